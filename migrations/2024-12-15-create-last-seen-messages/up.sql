@@ -0,0 +1,13 @@
+-- Your SQL goes here
+CREATE TABLE "last_seen_messages" (
+  "user_id" integer NOT NULL,
+  "group_id" integer NOT NULL,
+  "message_id" integer NOT NULL,
+  "updated_at" timestamp NOT NULL,
+  PRIMARY KEY ("user_id", "group_id")
+);
+
+COMMENT ON TABLE "last_seen_messages" IS 'Latest message id that an user has seen in a group';
+
+ALTER TABLE "last_seen_messages" ADD FOREIGN KEY ("user_id") REFERENCES "users" ("id");
+ALTER TABLE "last_seen_messages" ADD FOREIGN KEY ("group_id") REFERENCES "groups" ("id") ON DELETE CASCADE;
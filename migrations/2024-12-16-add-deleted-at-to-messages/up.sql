@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE messages ADD deleted_at timestamp NULL;
+COMMENT ON COLUMN messages.deleted_at IS 'Store time when a message was soft-deleted, e.g. recalled by its sender';
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE messages DROP CONSTRAINT messages_reply_to_fkey;
+ALTER TABLE messages DROP COLUMN reply_to;
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+ALTER TABLE messages ADD reply_to int4 NULL;
+ALTER TABLE messages ADD CONSTRAINT messages_reply_to_fkey FOREIGN KEY (reply_to) REFERENCES messages(id) ON DELETE SET NULL;
+COMMENT ON COLUMN messages.reply_to IS 'Store id of the message which this message replies to';
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE groups ADD slow_mode_seconds int4 NULL;
+COMMENT ON COLUMN groups.slow_mode_seconds IS 'Minimum time in seconds between two messages of a member, slow mode is disabled if it is NULL';
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE users DROP COLUMN user_code_expires_at;
+ALTER TABLE users DROP COLUMN is_guest;
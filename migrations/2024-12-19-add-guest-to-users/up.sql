@@ -0,0 +1,4 @@
+-- Your SQL goes here
+ALTER TABLE users ADD is_guest bool NOT NULL DEFAULT false;
+ALTER TABLE users ADD user_code_expires_at timestamp NULL;
+COMMENT ON COLUMN users.user_code_expires_at IS 'Expiration time of the user code of a guest, the guest and its data are purged after it';
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE groups ADD max_message_length int4 NULL;
+COMMENT ON COLUMN groups.max_message_length IS 'Maximum number of characters of a message content in the group, the global limit applies if it is NULL';
@@ -0,0 +1,12 @@
+-- Your SQL goes here
+CREATE TABLE "message_translations" (
+  "message_id" integer NOT NULL,
+  "lang" varchar(35) NOT NULL,
+  "content" text NOT NULL,
+  "created_at" timestamp NOT NULL,
+  PRIMARY KEY ("message_id", "lang")
+);
+
+COMMENT ON TABLE "message_translations" IS 'Cached translations of message contents';
+
+ALTER TABLE "message_translations" ADD FOREIGN KEY ("message_id") REFERENCES "messages" ("id") ON DELETE CASCADE;
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE attachments ADD size_bytes int8 NULL;
+COMMENT ON COLUMN attachments.size_bytes IS 'Size in bytes of the uploaded file when the attachment was created';
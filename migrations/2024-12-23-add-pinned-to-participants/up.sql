@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE participants ADD pinned bool NOT NULL DEFAULT false;
+COMMENT ON COLUMN participants.pinned IS 'The group is pinned to the top of the group list of the user';
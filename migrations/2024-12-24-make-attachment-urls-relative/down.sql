@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+-- The server url isn't known here, relative urls are resolved by the application either way
+SELECT 1;
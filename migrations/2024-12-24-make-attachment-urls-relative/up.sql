@@ -0,0 +1,4 @@
+-- Your SQL goes here
+UPDATE attachments
+SET url = regexp_replace(url, '^[a-zA-Z][a-zA-Z0-9+.-]*://[^/]+/files/', 'files/')
+WHERE url ~ '^[a-zA-Z][a-zA-Z0-9+.-]*://[^/]+/files/[^/]+$';
@@ -0,0 +1,13 @@
+-- Your SQL goes here
+CREATE TABLE "group_bans" (
+  "group_id" integer NOT NULL,
+  "user_id" integer NOT NULL,
+  "banned_until" timestamp NOT NULL,
+  "created_at" timestamp NOT NULL,
+  PRIMARY KEY ("group_id", "user_id")
+);
+
+COMMENT ON TABLE "group_bans" IS 'Users removed from a group and not allowed to rejoin it until banned_until';
+
+ALTER TABLE "group_bans" ADD FOREIGN KEY ("group_id") REFERENCES "groups" ("id") ON DELETE CASCADE;
+ALTER TABLE "group_bans" ADD FOREIGN KEY ("user_id") REFERENCES "users" ("id") ON DELETE CASCADE;
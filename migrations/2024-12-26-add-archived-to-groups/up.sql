@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE groups ADD archived bool NOT NULL DEFAULT false;
+COMMENT ON COLUMN groups.archived IS 'The group is read-only, members can read but not send messages';
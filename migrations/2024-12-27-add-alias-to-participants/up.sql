@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE participants ADD alias varchar(255);
+COMMENT ON COLUMN participants.alias IS 'Name shown for the user in the group instead of the username';
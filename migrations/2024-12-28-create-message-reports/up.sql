@@ -0,0 +1,14 @@
+-- Your SQL goes here
+CREATE TABLE "message_reports" (
+  "id" serial PRIMARY KEY,
+  "reporter_id" integer NOT NULL,
+  "message_id" integer NOT NULL,
+  "reason" text NOT NULL,
+  "created_at" timestamp NOT NULL,
+  UNIQUE ("reporter_id", "message_id")
+);
+
+COMMENT ON TABLE "message_reports" IS 'Messages reported by members for moderation';
+
+ALTER TABLE "message_reports" ADD FOREIGN KEY ("reporter_id") REFERENCES "users" ("id") ON DELETE CASCADE;
+ALTER TABLE "message_reports" ADD FOREIGN KEY ("message_id") REFERENCES "messages" ("id") ON DELETE CASCADE;
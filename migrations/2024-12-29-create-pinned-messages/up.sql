@@ -0,0 +1,15 @@
+-- Your SQL goes here
+CREATE TABLE "pinned_messages" (
+  "message_id" integer PRIMARY KEY,
+  "group_id" integer NOT NULL,
+  "pinned_by" integer NOT NULL,
+  "pinned_at" timestamp NOT NULL
+);
+
+COMMENT ON TABLE "pinned_messages" IS 'Messages pinned by the owner of a group';
+
+CREATE INDEX ON "pinned_messages" ("group_id");
+
+ALTER TABLE "pinned_messages" ADD FOREIGN KEY ("message_id") REFERENCES "messages" ("id") ON DELETE CASCADE;
+ALTER TABLE "pinned_messages" ADD FOREIGN KEY ("group_id") REFERENCES "groups" ("id") ON DELETE CASCADE;
+ALTER TABLE "pinned_messages" ADD FOREIGN KEY ("pinned_by") REFERENCES "users" ("id") ON DELETE CASCADE;
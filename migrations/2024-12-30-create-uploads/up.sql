@@ -0,0 +1,13 @@
+-- Your SQL goes here
+CREATE TABLE "uploads" (
+  "id" uuid PRIMARY KEY,
+  "user_id" integer NOT NULL,
+  "file_name" varchar,
+  "content_type" varchar,
+  "created_at" timestamp NOT NULL,
+  "completed_at" timestamp
+);
+
+COMMENT ON TABLE "uploads" IS 'Uploads with a client supplied id, a retried upload returns the completed file';
+
+ALTER TABLE "uploads" ADD FOREIGN KEY ("user_id") REFERENCES "users" ("id") ON DELETE CASCADE;
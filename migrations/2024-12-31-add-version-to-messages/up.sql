@@ -0,0 +1,4 @@
+-- Your SQL goes here
+ALTER TABLE "messages" ADD COLUMN "version" integer NOT NULL DEFAULT 1;
+
+COMMENT ON COLUMN "messages"."version" IS 'Incremented on each edit, an edit with an outdated version is rejected';
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE groups DROP COLUMN post_policy;
+DROP TYPE IF EXISTS PostPolicyType;
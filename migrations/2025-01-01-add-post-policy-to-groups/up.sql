@@ -0,0 +1,9 @@
+-- Your SQL goes here
+CREATE TYPE PostPolicyType AS ENUM (
+  'everyone',
+  'moderators_only',
+  'owner_only'
+);
+
+ALTER TABLE groups ADD post_policy PostPolicyType NOT NULL DEFAULT 'everyone';
+COMMENT ON COLUMN groups.post_policy IS 'Members allowed to send messages in the group';
@@ -0,0 +1,13 @@
+-- Your SQL goes here
+CREATE TABLE "drafts" (
+  "user_id" integer NOT NULL,
+  "group_id" integer NOT NULL,
+  "content" text NOT NULL,
+  "updated_at" timestamp NOT NULL,
+  PRIMARY KEY ("user_id", "group_id")
+);
+
+COMMENT ON TABLE "drafts" IS 'Unsent message of an user in a group, private to the user';
+
+ALTER TABLE "drafts" ADD FOREIGN KEY ("user_id") REFERENCES "users" ("id") ON DELETE CASCADE;
+ALTER TABLE "drafts" ADD FOREIGN KEY ("group_id") REFERENCES "groups" ("id") ON DELETE CASCADE;
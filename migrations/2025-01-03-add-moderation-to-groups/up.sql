@@ -0,0 +1,9 @@
+-- Your SQL goes here
+CREATE TYPE ModerationType AS ENUM (
+  'off',
+  'mask',
+  'reject'
+);
+
+ALTER TABLE groups ADD moderation ModerationType NOT NULL DEFAULT 'off';
+COMMENT ON COLUMN groups.moderation IS 'Handling of message contents containing words of the bad-word filter';
@@ -1,6 +1,8 @@
 use std::io::Write;
 
-use super::schema::sql_types::{Attachmenttype, Messagestatustype, Messagetype};
+use super::schema::sql_types::{
+  Attachmenttype, Messagestatustype, Messagetype, Moderationtype, Postpolicytype,
+};
 use chrono::NaiveDateTime;
 use diesel::{
   deserialize::{self, FromSql, FromSqlRow},
@@ -20,6 +22,8 @@ pub struct User {
   pub username: String,
   pub user_code: String,
   pub created_at: NaiveDateTime,
+  pub is_guest: bool,
+  pub user_code_expires_at: Option<NaiveDateTime>,
 }
 
 #[derive(Insertable)]
@@ -30,6 +34,16 @@ pub struct NewUser<'a> {
   pub created_at: NaiveDateTime,
 }
 
+#[derive(Insertable)]
+#[diesel(table_name = crate::database::schema::users)]
+pub struct NewGuestUser<'a> {
+  pub username: &'a str,
+  pub user_code: &'a str,
+  pub created_at: NaiveDateTime,
+  pub is_guest: bool,
+  pub user_code_expires_at: NaiveDateTime,
+}
+
 #[derive(Selectable, Queryable, Identifiable, Associations)]
 #[diesel(table_name = crate::database::schema::groups)]
 #[diesel(belongs_to(User))]
@@ -43,6 +57,11 @@ pub struct Group {
   pub maximum_members: Option<i32>,
   pub created_at: Option<NaiveDateTime>,
   pub expired_at: Option<NaiveDateTime>,
+  pub slow_mode_seconds: Option<i32>,
+  pub max_message_length: Option<i32>,
+  pub archived: bool,
+  pub post_policy: PostPolicy,
+  pub moderation: Moderation,
 }
 
 #[derive(Insertable)]
@@ -89,6 +108,8 @@ pub struct Participant {
   pub id: i32,
   pub user_id: i32,
   pub group_id: i32,
+  pub pinned: bool,
+  pub alias: Option<String>,
 }
 
 // Custom Message type
@@ -127,15 +148,34 @@ impl FromSql<Messagetype, diesel::pg::Pg> for MessageTypeEnum {
   }
 }
 
+/// Status of a message
+///
+/// The labels of the `MessageStatusType` database enum are the variant names, the same strings
+/// as the JSON serialization, e.g. `"Seen"`. Both are written from `as_str`
 #[derive(
   Debug, PartialEq, FromSqlRow, AsExpression, Eq, Clone, Serialize, Deserialize, ToSchema,
 )]
 #[diesel(sql_type = crate::database::schema::sql_types::Messagestatustype)]
 pub enum MessageStatus {
+  #[serde(rename = "NotSent")]
   NotSent,
+  #[serde(rename = "Sent")]
   Sent,
+  #[serde(rename = "Seen")]
   Seen,
 }
+impl MessageStatus {
+  const ALL: [MessageStatus; 3] = [MessageStatus::NotSent, MessageStatus::Sent, MessageStatus::Seen];
+
+  /// Label of the status in the database and in JSON
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      MessageStatus::NotSent => "NotSent",
+      MessageStatus::Sent => "Sent",
+      MessageStatus::Seen => "Seen",
+    }
+  }
+}
 impl Default for MessageStatus {
   fn default() -> Self {
     Self::Sent
@@ -143,22 +183,100 @@ impl Default for MessageStatus {
 }
 impl ToSql<Messagestatustype, diesel::pg::Pg> for MessageStatus {
   fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, diesel::pg::Pg>) -> serialize::Result {
-    let status_str = match *self {
-      MessageStatus::NotSent => "NotSent",
-      MessageStatus::Sent => "Sent",
-      MessageStatus::Seen => "Seen",
-    };
-    out.write_all(status_str.as_bytes())?;
+    out.write_all(self.as_str().as_bytes())?;
     Ok(serialize::IsNull::No)
   }
 }
 
 impl FromSql<Messagestatustype, diesel::pg::Pg> for MessageStatus {
+  fn from_sql(bytes: diesel::pg::PgValue) -> deserialize::Result<Self> {
+    MessageStatus::ALL
+      .into_iter()
+      .find(|status| status.as_str().as_bytes() == bytes.as_bytes())
+      .ok_or_else(|| "Unrecognized enum variant".into())
+  }
+}
+
+/// Members allowed to send messages in a group
+///
+/// There are no moderator roles yet, so `ModeratorsOnly` currently allows only the owner
+#[derive(
+  Debug, Default, PartialEq, FromSqlRow, AsExpression, Eq, Clone, Copy, Serialize, Deserialize,
+  ToSchema,
+)]
+#[diesel(sql_type = crate::database::schema::sql_types::Postpolicytype)]
+#[serde(rename_all = "snake_case")]
+pub enum PostPolicy {
+  #[default]
+  Everyone,
+  ModeratorsOnly,
+  OwnerOnly,
+}
+impl PostPolicy {
+  /// Whether a member can send messages, `is_owner` is whether the member owns the group
+  pub fn allows(&self, is_owner: bool) -> bool {
+    match self {
+      PostPolicy::Everyone => true,
+      PostPolicy::ModeratorsOnly | PostPolicy::OwnerOnly => is_owner,
+    }
+  }
+}
+impl ToSql<Postpolicytype, diesel::pg::Pg> for PostPolicy {
+  fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, diesel::pg::Pg>) -> serialize::Result {
+    let policy_str = match *self {
+      PostPolicy::Everyone => "everyone",
+      PostPolicy::ModeratorsOnly => "moderators_only",
+      PostPolicy::OwnerOnly => "owner_only",
+    };
+    out.write_all(policy_str.as_bytes())?;
+    Ok(serialize::IsNull::No)
+  }
+}
+
+impl FromSql<Postpolicytype, diesel::pg::Pg> for PostPolicy {
+  fn from_sql(bytes: diesel::pg::PgValue) -> deserialize::Result<Self> {
+    match bytes.as_bytes() {
+      b"everyone" => Ok(PostPolicy::Everyone),
+      b"moderators_only" => Ok(PostPolicy::ModeratorsOnly),
+      b"owner_only" => Ok(PostPolicy::OwnerOnly),
+      _ => Err("Unrecognized enum variant".into()),
+    }
+  }
+}
+
+/// Handling of message contents containing words of the bad-word filter
+#[derive(
+  Debug, Default, PartialEq, FromSqlRow, AsExpression, Eq, Clone, Copy, Serialize, Deserialize,
+  ToSchema,
+)]
+#[diesel(sql_type = crate::database::schema::sql_types::Moderationtype)]
+#[serde(rename_all = "snake_case")]
+pub enum Moderation {
+  #[default]
+  Off,
+  /// Flagged words are replaced by `*` before the message is saved
+  Mask,
+  /// Messages containing flagged words are rejected
+  Reject,
+}
+impl ToSql<Moderationtype, diesel::pg::Pg> for Moderation {
+  fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, diesel::pg::Pg>) -> serialize::Result {
+    let moderation_str = match *self {
+      Moderation::Off => "off",
+      Moderation::Mask => "mask",
+      Moderation::Reject => "reject",
+    };
+    out.write_all(moderation_str.as_bytes())?;
+    Ok(serialize::IsNull::No)
+  }
+}
+
+impl FromSql<Moderationtype, diesel::pg::Pg> for Moderation {
   fn from_sql(bytes: diesel::pg::PgValue) -> deserialize::Result<Self> {
     match bytes.as_bytes() {
-      b"NotSent" => Ok(MessageStatus::NotSent),
-      b"Sent" => Ok(MessageStatus::Sent),
-      b"Seen" => Ok(MessageStatus::Seen),
+      b"off" => Ok(Moderation::Off),
+      b"mask" => Ok(Moderation::Mask),
+      b"reject" => Ok(Moderation::Reject),
       _ => Err("Unrecognized enum variant".into()),
     }
   }
@@ -233,6 +351,10 @@ pub struct Message {
   pub updated_at: Option<NaiveDateTime>,
   pub user_id: i32,
   pub group_id: i32,
+  pub deleted_at: Option<NaiveDateTime>,
+  pub reply_to: Option<i32>,
+  /// Incremented on each edit, see `services::message::update_message`
+  pub version: i32,
 }
 
 #[derive(Insertable)]
@@ -246,6 +368,7 @@ pub struct NewMessage<'a> {
   pub created_at: NaiveDateTime,
   pub user_id: i32,
   pub group_id: i32,
+  pub reply_to: Option<i32>,
 }
 
 #[derive(Queryable, Selectable, Identifiable, Associations, Debug, Clone)]
@@ -257,13 +380,141 @@ pub struct Attachment {
   pub url: String,
   pub attachment_type: AttachmentTypeEnum,
   pub message_id: i32,
+  pub size_bytes: Option<i64>,
 }
 
 #[derive(Insertable)]
 #[diesel(table_name = crate::database::schema::attachments)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
-pub struct NewAttachment<'a> {
-  pub url: &'a str,
+pub struct NewAttachment {
+  pub url: String,
   pub message_id: i32,
   pub attachment_type: AttachmentTypeEnum,
+  pub size_bytes: Option<i64>,
+}
+
+#[derive(Selectable, Queryable, Associations, Insertable, Debug)]
+#[diesel(table_name = crate::database::schema::last_seen_messages)]
+#[diesel(belongs_to(User))]
+#[diesel(belongs_to(Group))]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct LastSeenMessage {
+  pub user_id: i32,
+  pub group_id: i32,
+  pub message_id: i32,
+  pub updated_at: NaiveDateTime,
+}
+
+#[derive(Selectable, Queryable, Associations, Insertable, Debug)]
+#[diesel(table_name = crate::database::schema::message_translations)]
+#[diesel(belongs_to(Message))]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct MessageTranslation {
+  pub message_id: i32,
+  pub lang: String,
+  pub content: String,
+  pub created_at: NaiveDateTime,
+}
+
+#[derive(Selectable, Queryable, Identifiable, Associations, Debug)]
+#[diesel(table_name = crate::database::schema::message_reports)]
+#[diesel(belongs_to(Message))]
+#[diesel(belongs_to(User, foreign_key = reporter_id))]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct MessageReport {
+  pub id: i32,
+  pub reporter_id: i32,
+  pub message_id: i32,
+  pub reason: String,
+  pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::database::schema::message_reports)]
+pub struct NewMessageReport<'a> {
+  pub reporter_id: i32,
+  pub message_id: i32,
+  pub reason: &'a str,
+  pub created_at: NaiveDateTime,
+}
+
+#[derive(Selectable, Queryable, Associations, Insertable, Debug)]
+#[diesel(table_name = crate::database::schema::group_bans)]
+#[diesel(belongs_to(User))]
+#[diesel(belongs_to(Group))]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct GroupBan {
+  pub group_id: i32,
+  pub user_id: i32,
+  pub banned_until: NaiveDateTime,
+  pub created_at: NaiveDateTime,
+}
+
+#[derive(Selectable, Queryable, Associations, Insertable, Debug)]
+#[diesel(table_name = crate::database::schema::pinned_messages)]
+#[diesel(belongs_to(Message))]
+#[diesel(belongs_to(Group))]
+#[diesel(belongs_to(User, foreign_key = pinned_by))]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct PinnedMessage {
+  pub message_id: i32,
+  pub group_id: i32,
+  pub pinned_by: i32,
+  pub pinned_at: NaiveDateTime,
+}
+
+#[derive(Selectable, Queryable, Associations, Insertable, Debug)]
+#[diesel(table_name = crate::database::schema::drafts)]
+#[diesel(belongs_to(User))]
+#[diesel(belongs_to(Group))]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Draft {
+  pub user_id: i32,
+  pub group_id: i32,
+  pub content: String,
+  pub updated_at: NaiveDateTime,
+}
+
+#[allow(dead_code)]
+#[derive(Selectable, Queryable, Associations, Debug)]
+#[diesel(table_name = crate::database::schema::uploads)]
+#[diesel(belongs_to(User))]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Upload {
+  pub id: Uuid,
+  pub user_id: i32,
+  /// Name of the stored file, set when the upload is completed
+  pub file_name: Option<String>,
+  pub content_type: Option<String>,
+  pub created_at: NaiveDateTime,
+  pub completed_at: Option<NaiveDateTime>,
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn everyone_policy_allows_every_member() {
+    assert!(PostPolicy::Everyone.allows(true));
+    assert!(PostPolicy::Everyone.allows(false));
+  }
+
+  #[test]
+  fn moderators_only_policy_allows_only_the_owner() {
+    // there are no moderator roles yet, the owner is the only moderator
+    assert!(PostPolicy::ModeratorsOnly.allows(true));
+    assert!(!PostPolicy::ModeratorsOnly.allows(false));
+  }
+
+  #[test]
+  fn owner_only_policy_allows_only_the_owner() {
+    assert!(PostPolicy::OwnerOnly.allows(true));
+    assert!(!PostPolicy::OwnerOnly.allows(false));
+  }
+
+  #[test]
+  fn default_policy_is_everyone() {
+    assert_eq!(PostPolicy::default(), PostPolicy::Everyone);
+  }
 }
@@ -12,6 +12,14 @@ pub mod sql_types {
     #[derive(diesel::query_builder::QueryId, Clone, diesel::sql_types::SqlType)]
     #[diesel(postgres_type(name = "messagetype"))]
     pub struct Messagetype;
+
+    #[derive(diesel::query_builder::QueryId, Clone, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "moderationtype"))]
+    pub struct Moderationtype;
+
+    #[derive(diesel::query_builder::QueryId, Clone, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "postpolicytype"))]
+    pub struct Postpolicytype;
 }
 
 diesel::table! {
@@ -24,10 +32,33 @@ diesel::table! {
         url -> Varchar,
         attachment_type -> Attachmenttype,
         message_id -> Int4,
+        size_bytes -> Nullable<Int8>,
+    }
+}
+
+diesel::table! {
+    drafts (user_id, group_id) {
+        user_id -> Int4,
+        group_id -> Int4,
+        content -> Text,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    group_bans (group_id, user_id) {
+        group_id -> Int4,
+        user_id -> Int4,
+        banned_until -> Timestamp,
+        created_at -> Timestamp,
     }
 }
 
 diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::Postpolicytype;
+    use super::sql_types::Moderationtype;
+
     groups (id) {
         id -> Int4,
         #[max_length = 255]
@@ -39,6 +70,40 @@ diesel::table! {
         maximum_members -> Nullable<Int4>,
         created_at -> Nullable<Timestamp>,
         expired_at -> Nullable<Timestamp>,
+        slow_mode_seconds -> Nullable<Int4>,
+        max_message_length -> Nullable<Int4>,
+        archived -> Bool,
+        post_policy -> Postpolicytype,
+        moderation -> Moderationtype,
+    }
+}
+
+diesel::table! {
+    last_seen_messages (user_id, group_id) {
+        user_id -> Int4,
+        group_id -> Int4,
+        message_id -> Int4,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    message_reports (id) {
+        id -> Int4,
+        reporter_id -> Int4,
+        message_id -> Int4,
+        reason -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    message_translations (message_id, lang) {
+        message_id -> Int4,
+        #[max_length = 35]
+        lang -> Varchar,
+        content -> Text,
+        created_at -> Timestamp,
     }
 }
 
@@ -58,6 +123,9 @@ diesel::table! {
         message_uuid -> Uuid,
         updated_at -> Nullable<Timestamp>,
         status -> Messagestatustype,
+        deleted_at -> Nullable<Timestamp>,
+        reply_to -> Nullable<Int4>,
+        version -> Int4,
     }
 }
 
@@ -66,6 +134,29 @@ diesel::table! {
         user_id -> Int4,
         group_id -> Int4,
         id -> Int4,
+        pinned -> Bool,
+        #[max_length = 255]
+        alias -> Nullable<Varchar>,
+    }
+}
+
+diesel::table! {
+    pinned_messages (message_id) {
+        message_id -> Int4,
+        group_id -> Int4,
+        pinned_by -> Int4,
+        pinned_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    uploads (id) {
+        id -> Uuid,
+        user_id -> Int4,
+        file_name -> Nullable<Varchar>,
+        content_type -> Nullable<Varchar>,
+        created_at -> Timestamp,
+        completed_at -> Nullable<Timestamp>,
     }
 }
 
@@ -77,6 +168,8 @@ diesel::table! {
         #[max_length = 255]
         user_code -> Varchar,
         created_at -> Timestamp,
+        is_guest -> Bool,
+        user_code_expires_at -> Nullable<Timestamp>,
     }
 }
 
@@ -92,19 +185,39 @@ diesel::table! {
 }
 
 diesel::joinable!(attachments -> messages (message_id));
+diesel::joinable!(drafts -> groups (group_id));
+diesel::joinable!(drafts -> users (user_id));
+diesel::joinable!(group_bans -> groups (group_id));
+diesel::joinable!(group_bans -> users (user_id));
 diesel::joinable!(groups -> users (user_id));
+diesel::joinable!(last_seen_messages -> groups (group_id));
+diesel::joinable!(last_seen_messages -> users (user_id));
+diesel::joinable!(message_reports -> messages (message_id));
+diesel::joinable!(message_reports -> users (reporter_id));
+diesel::joinable!(message_translations -> messages (message_id));
 diesel::joinable!(messages -> groups (group_id));
 diesel::joinable!(messages -> users (user_id));
 diesel::joinable!(participants -> groups (group_id));
 diesel::joinable!(participants -> users (user_id));
+diesel::joinable!(pinned_messages -> groups (group_id));
+diesel::joinable!(pinned_messages -> messages (message_id));
+diesel::joinable!(pinned_messages -> users (pinned_by));
+diesel::joinable!(uploads -> users (user_id));
 diesel::joinable!(waiting_list -> groups (group_id));
 diesel::joinable!(waiting_list -> users (user_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
     attachments,
+    drafts,
+    group_bans,
     groups,
+    last_seen_messages,
+    message_reports,
+    message_translations,
     messages,
     participants,
+    pinned_messages,
+    uploads,
     users,
     waiting_list,
 );
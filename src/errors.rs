@@ -1,7 +1,13 @@
-use axum::{http::StatusCode, response::IntoResponse};
-
+use axum::{
+  http::{header, StatusCode},
+  response::IntoResponse,
+  Json,
+};
+use serde::Serialize;
 use thiserror::Error;
 
+use crate::utils::validation::FieldError;
+
 #[derive(Error, Debug)]
 pub enum DBError {
   #[error("Failed to query from database {}", 0)]
@@ -10,6 +16,10 @@ pub enum DBError {
   #[error("Failed to get a connection: {}", 0.to_string())]
   ConnectionError(#[from] r2d2::Error),
 
+  /// The connection was closed while running a query
+  #[error("The database connection was lost: {0}")]
+  ConnectionLost(String),
+
   #[error("Constraint violation: {0}")]
   ConstraintViolation(String),
 
@@ -27,6 +37,9 @@ impl IntoResponse for DBError {
       Self::ConnectionError(err) => {
         (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response()
       }
+      Self::ConnectionLost(err) => {
+        (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response()
+      }
       Self::TransactionError(err) => {
         (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response()
       }
@@ -37,6 +50,10 @@ impl IntoResponse for DBError {
 impl From<diesel::result::Error> for DBError {
   fn from(error: diesel::result::Error) -> Self {
     match error {
+      diesel::result::Error::DatabaseError(
+        diesel::result::DatabaseErrorKind::ClosedConnection,
+        _,
+      ) => DBError::ConnectionLost(error.to_string()),
       diesel::result::Error::DatabaseError(_, _) => DBError::QueryError(error.to_string()),
       diesel::result::Error::NotFound => DBError::QueryError("Record not found".to_string()),
       _ => DBError::TransactionError(error.to_string()), // Use TransactionError as a fallback
@@ -44,6 +61,23 @@ impl From<diesel::result::Error> for DBError {
   }
 }
 
+/// Failure of resolving an user from a user code
+#[derive(Error, Debug)]
+pub enum AuthError {
+  #[error("The user code is missing")]
+  Missing,
+
+  #[error("The user code is invalid")]
+  Invalid,
+
+  /// The user code of a guest has passed its expiration time
+  #[error("The user code is expired")]
+  Expired,
+
+  #[error("Database error: cause {}", 0.to_string())]
+  DatabaseError(DBError),
+}
+
 #[allow(dead_code)]
 #[derive(Error, Debug)]
 pub enum ApiError {
@@ -53,37 +87,155 @@ pub enum ApiError {
   #[error("The resource is not found: {0}")]
   NotFound(String),
 
+  /// The resource existed but was deleted
+  #[error("The resource was deleted: {0}")]
+  Gone(String),
+
   #[error("{0}")]
   ExistedResource(String),
 
   #[error("The user already joined the group")]
   AlreadyJoined,
 
+  #[error("The group has expired")]
+  GroupExpired,
+
+  #[error("The user is banned from the group until {0}")]
+  BannedFromGroup(String),
+
+  #[error("The group is archived, new messages are not allowed")]
+  GroupArchived,
+
+  #[error("Only the owner can send messages in the group")]
+  PostNotAllowed,
+
+  #[error("The content contains words which are not allowed in the group")]
+  ContentRejected,
+
+  #[error("{0}")]
+  Conflict(String),
+
+  /// The resource was modified concurrently, the current version is given
+  #[error("The resource was modified, the current version is {0}")]
+  VersionConflict(i32),
+
+  /// The group has used up its storage quota of the given number of bytes
+  #[error("The storage quota of the group ({0} bytes) is exceeded")]
+  StorageQuotaExceeded(u64),
+
+  #[error("{0}")]
+  BadRequest(String),
+
   #[error("The current user doesn't have permission to access the resource")]
   Forbidden,
 
+  /// The current user is not a member of the given group
+  #[error("The current user is not a member of the group {0}")]
+  NotAMember(i32),
+
   #[error("The current user doesn't have right to access the resource")]
   Unauthorized,
 
   #[error("The request is missing {0}")]
   MissingField(String),
 
+  #[error("The request has invalid fields")]
+  ValidationFailed(Vec<FieldError>),
+
+  /// The server is saturated, the client should retry after the given number of seconds
+  #[error("The server is busy, please retry later")]
+  ServiceBusy(u64),
+
+  /// The client sent too many requests, it should retry after the given number of seconds
+  #[error("Too many requests, please retry later")]
+  TooManyRequests(u64),
+
   #[error("Unknown error")]
   Unknown,
 }
+impl From<AuthError> for ApiError {
+  fn from(error: AuthError) -> Self {
+    match error {
+      AuthError::Missing => Self::Forbidden,
+      AuthError::Invalid | AuthError::Expired => Self::NotFound("User".into()),
+      AuthError::DatabaseError(err) => Self::DatabaseError(err),
+    }
+  }
+}
+
+impl From<diesel::result::Error> for ApiError {
+  fn from(error: diesel::result::Error) -> Self {
+    Self::DatabaseError(DBError::from(error))
+  }
+}
+
 impl ApiError {
   pub fn new_database_query_err(cause: &str) -> Self {
     Self::DatabaseError(DBError::QueryError(cause.to_string()))
   }
 }
 
+/// Body of the response for a request based on an outdated version of a resource
+#[derive(Serialize)]
+struct VersionConflictResponse {
+  msg: String,
+  current_version: i32,
+}
+
+/// Body of the response for a request which failed validation
+#[derive(Serialize)]
+struct ValidationErrorResponse {
+  msg: String,
+  errors: Vec<FieldError>,
+}
+
 impl IntoResponse for ApiError {
   fn into_response(self) -> axum::response::Response {
+    if let Self::ValidationFailed(errors) = self {
+      let body = ValidationErrorResponse {
+        msg: "The request has invalid fields".into(),
+        errors,
+      };
+      return (StatusCode::BAD_REQUEST, Json(body)).into_response();
+    }
+    if let Self::VersionConflict(current_version) = self {
+      let body = VersionConflictResponse {
+        msg: self.to_string(),
+        current_version,
+      };
+      return (StatusCode::CONFLICT, Json(body)).into_response();
+    }
+    if let Self::ServiceBusy(retry_after) = self {
+      return (
+        StatusCode::SERVICE_UNAVAILABLE,
+        [(header::RETRY_AFTER, retry_after.to_string())],
+        self.to_string(),
+      )
+        .into_response();
+    }
+    if let Self::TooManyRequests(retry_after) = self {
+      return (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(header::RETRY_AFTER, retry_after.to_string())],
+        self.to_string(),
+      )
+        .into_response();
+    }
     return match self {
       Self::NotFound(_) => (StatusCode::NOT_FOUND, self.to_string()),
+      Self::Gone(_) => (StatusCode::GONE, self.to_string()),
       Self::AlreadyJoined => (StatusCode::BAD_REQUEST, self.to_string()),
+      Self::GroupExpired => (StatusCode::GONE, self.to_string()),
+      Self::BannedFromGroup(_) => (StatusCode::FORBIDDEN, self.to_string()),
+      Self::GroupArchived => (StatusCode::FORBIDDEN, self.to_string()),
+      Self::PostNotAllowed => (StatusCode::FORBIDDEN, self.to_string()),
+      Self::ContentRejected => (StatusCode::BAD_REQUEST, self.to_string()),
       Self::ExistedResource(_) => (StatusCode::BAD_REQUEST, self.to_string()),
+      Self::Conflict(_) => (StatusCode::CONFLICT, self.to_string()),
+      Self::StorageQuotaExceeded(_) => (StatusCode::PAYLOAD_TOO_LARGE, self.to_string()),
+      Self::BadRequest(_) => (StatusCode::BAD_REQUEST, self.to_string()),
       Self::Forbidden => (StatusCode::FORBIDDEN, self.to_string()),
+      Self::NotAMember(_) => (StatusCode::FORBIDDEN, self.to_string()),
       Self::Unauthorized => (StatusCode::UNAUTHORIZED, self.to_string()),
       Self::MissingField(_) => (StatusCode::BAD_REQUEST, self.to_string()),
       // Yes we want to hide internal message error from user
@@ -98,3 +250,44 @@ impl IntoResponse for ApiError {
     .into_response();
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn not_a_member_is_forbidden() {
+    assert_eq!(ApiError::NotAMember(1).into_response().status(), StatusCode::FORBIDDEN);
+    assert_eq!(ApiError::Forbidden.into_response().status(), StatusCode::FORBIDDEN);
+  }
+
+  #[test]
+  fn unauthorized_is_kept_for_credentials() {
+    assert_eq!(ApiError::Unauthorized.into_response().status(), StatusCode::UNAUTHORIZED);
+  }
+
+  #[test]
+  fn auth_errors_map_to_status() {
+    let missing = ApiError::from(AuthError::Missing).into_response();
+    assert_eq!(missing.status(), StatusCode::FORBIDDEN);
+    for error in [AuthError::Invalid, AuthError::Expired] {
+      assert_eq!(ApiError::from(error).into_response().status(), StatusCode::NOT_FOUND);
+    }
+  }
+
+  #[test]
+  fn validation_failed_is_bad_request() {
+    let error = ApiError::ValidationFailed(vec![FieldError::new("username", "must not be empty")]);
+    assert_eq!(error.into_response().status(), StatusCode::BAD_REQUEST);
+  }
+
+  #[test]
+  fn retry_errors_set_retry_after() {
+    let response = ApiError::TooManyRequests(30).into_response();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.headers()[header::RETRY_AFTER], "30");
+    let response = ApiError::ServiceBusy(5).into_response();
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(response.headers()[header::RETRY_AFTER], "5");
+  }
+}
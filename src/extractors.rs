@@ -1,8 +1,13 @@
 use axum::{
   async_trait,
-  extract::FromRequestParts,
+  extract::{FromRequest, FromRequestParts, Request},
   http::{request::Parts, StatusCode},
+  response::{IntoResponse, Response},
+  Json,
 };
+use serde::de::DeserializeOwned;
+
+use crate::{errors::ApiError, utils::validation::Validate};
 
 pub struct UserToken(pub Option<String>);
 
@@ -31,3 +36,28 @@ where
     Ok(UserToken(None))
   }
 }
+
+/// ### JSON extractor which validates the payload
+///
+/// Works like `Json<T>` but runs `Validate::validate` before the handler,
+/// an invalid payload is rejected with 400 and the list of all field errors
+pub struct ValidatedJson<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for ValidatedJson<T>
+where
+  T: DeserializeOwned + Validate,
+  S: Send + Sync,
+{
+  type Rejection = Response;
+
+  async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+    let Json(value) = Json::<T>::from_request(req, state)
+      .await
+      .map_err(IntoResponse::into_response)?;
+    value
+      .validate()
+      .map_err(|errors| ApiError::ValidationFailed(errors).into_response())?;
+    Ok(ValidatedJson(value))
+  }
+}
@@ -1,5 +1,24 @@
+use std::{
+  io,
+  sync::{atomic::Ordering, Arc},
+};
+
+use axum::{
+  body::{Body, Bytes},
+  extract::State,
+  http::header,
+  response::Response,
+  Json,
+};
+use serde::Serialize;
+use tokio::sync::mpsc;
+
 use crate::{
-  database::models::User, errors::ApiError, services::user::get_user_by_code, PoolPGConnectionType,
+  database::models::User,
+  errors::{ApiError, DBError},
+  payloads::common::HealthResponse,
+  services::auth::authenticate_user_code,
+  AppState, PoolPGConnectionType,
 };
 
 /// ### Handler for API "/"
@@ -9,6 +28,27 @@ pub async fn home() -> &'static str {
   "Let's quick chat with AnonymousChatBox"
 }
 
+/// ### Handler for API "/health"
+#[utoipa::path(
+  get,
+  path = "/health",
+  responses(
+      (status = 200, description = "The server is running", body = HealthResponse, content_type = "application/json",
+        example = json!(
+            {
+              "status": "ok",
+              "ws_connections": 12
+            }
+        )),
+  ),
+)]
+pub async fn health(State(app_state): State<Arc<AppState>>) -> Json<HealthResponse> {
+  Json(HealthResponse {
+    status: "ok".into(),
+    ws_connections: app_state.ws_connections.load(Ordering::Relaxed),
+  })
+}
+
 pub async fn fallback() -> &'static str {
   "The requested URL was not found on the server."
 }
@@ -17,14 +57,63 @@ pub async fn check_user_exists(
   conn: &mut PoolPGConnectionType,
   user_code: Option<String>,
 ) -> Result<User, ApiError> {
-  if user_code.is_none() {
-    return Err(ApiError::Forbidden);
-  }
-  let user = get_user_by_code(conn, &user_code.unwrap())
-    .map_err(|_| ApiError::new_database_query_err("Failed to retrieve user by code"))?;
-  if let Some(user) = user {
-    return Ok(user);
-  } else {
-    return Err(ApiError::NotFound("User".into()));
+  Ok(authenticate_user_code(conn, user_code.as_deref())?)
+}
+
+/// ### Writer of the items of a JSON array streamed by `stream_json_array`
+pub struct JsonArrayWriter {
+  chunk_sender: mpsc::Sender<Result<Bytes, io::Error>>,
+  is_first: bool,
+  is_failed: bool,
+}
+
+impl JsonArrayWriter {
+  /// Serialize and send one item of the array, `false` is returned when streaming must stop,
+  /// i.e. the item can't be serialized or the client went away
+  pub fn write<T: Serialize>(&mut self, item: &T) -> bool {
+    let mut chunk = if self.is_first { Vec::new() } else { b",".to_vec() };
+    if let Err(err) = serde_json::to_writer(&mut chunk, item) {
+      tracing::error!("Failed to serialize item of streamed array: {}", err.to_string());
+      self.is_failed = true;
+      return false;
+    }
+    self.is_first = false;
+    self.chunk_sender.blocking_send(Ok(Bytes::from(chunk))).is_ok()
   }
 }
+
+/// ### Stream a JSON body made of `head`, the items written by `write_items` and `tail`
+///
+/// `write_items` runs on a blocking thread since Diesel is synchronous. If it fails or one
+/// of the items can't be serialized, the body ends with an error instead of `tail`, so the
+/// client never receives a truncated document that looks complete
+pub fn stream_json_array<F>(head: Vec<u8>, tail: &'static [u8], write_items: F) -> Response
+where
+  F: FnOnce(&mut JsonArrayWriter) -> Result<(), DBError> + Send + 'static,
+{
+  let (chunk_sender, chunk_receiver) = mpsc::channel::<Result<Bytes, io::Error>>(32);
+  tokio::task::spawn_blocking(move || {
+    if chunk_sender.blocking_send(Ok(Bytes::from(head))).is_err() {
+      return;
+    }
+    let mut writer = JsonArrayWriter {
+      chunk_sender,
+      is_first: true,
+      is_failed: false,
+    };
+    let last_chunk = match write_items(&mut writer) {
+      Ok(_) if writer.is_failed => Err(io::Error::other("Failed to serialize the streamed array")),
+      Ok(_) => Ok(Bytes::from_static(tail)),
+      Err(err) => Err(io::Error::other(err.to_string())),
+    };
+    let _ = writer.chunk_sender.blocking_send(last_chunk);
+  });
+
+  let body_stream = futures::stream::unfold(chunk_receiver, |mut receiver| async move {
+    receiver.recv().await.map(|chunk| (chunk, receiver))
+  });
+  Response::builder()
+    .header(header::CONTENT_TYPE, "application/json")
+    .body(Body::from_stream(body_stream))
+    .unwrap()
+}
@@ -1,9 +1,13 @@
 use crate::{
   errors::{ApiError, DBError},
   extractors::UserToken,
-  payloads::minors::FileResponse,
-  utils::minors::{generate_file_name_with_timestamp, get_server_url, guess_mime_type_from_path},
-  AppState, UPLOADS_DIRECTORY,
+  payloads::{messages::AttachmentDetailResponse, minors::FileResponse},
+  services,
+  utils::{
+    minors::{generate_file_name_with_timestamp, get_server_url, guess_mime_type_from_path},
+    validation::FieldError,
+  },
+  AppState, UPLOADS_DIRECTORY, UPLOAD_RETRY_AFTER_SECS,
 };
 use axum::{
   body::{Body, Bytes},
@@ -12,7 +16,7 @@ use axum::{
   response::{IntoResponse, Response},
   BoxError, Json,
 };
-use axum_extra::extract::Multipart;
+use axum_extra::extract::{multipart::MultipartError, Multipart};
 use futures::{Stream, TryFutureExt, TryStreamExt};
 use std::{io, path::PathBuf, sync::Arc};
 use tokio::{
@@ -21,19 +25,71 @@ use tokio::{
 };
 use tokio_util::io::{ReaderStream, StreamReader};
 use utoipa::ToSchema;
+use uuid::Uuid;
 
 ///### Handler to serve static files efficiently with streaming
+///
+/// Unless `PUBLIC_FILES` is true, a file is only served to members of the group of a message
+/// having the file as attachment, so files not attached to any message are not served.
+/// A file only attached to deleted messages is never served, `410 Gone` is returned instead
 #[utoipa::path(
   get,
   path = "/files/{filename}",
   params(
+    (
+      "x-user-code" = Option<String>, Header, description = "user code for authentication, not required when files are public",
+      example = "6C70F6E0A888C1360AD532C66D8F1CD0ED48C1CC47FA1AE6665B1FC3DAABB468"
+    ),
     ("filename" = String, Path, description = "name of file"),
   ),
   responses(
-      (status = 200, description = "OK")
+      (status = 200, description = "OK"),
+      (status = 403, description = "The current user is not a member of the group of the file"),
+      (status = 404, description = "The file or the user is not found"),
+      (status = 410, description = "The file is only attached to deleted messages"),
+      (status = 500, description = "Database error")
   )
 )]
-pub async fn serve_file(Path(filename): Path<String>) -> Response {
+pub async fn serve_file(
+  State(state): State<Arc<AppState>>,
+  UserToken(token): UserToken,
+  Path(filename): Path<String>,
+) -> Result<Response, ApiError> {
+  let conn = &mut state
+    .db_pool
+    .get()
+    .map_err(|err| ApiError::DatabaseError(DBError::ConnectionError(err)))?;
+  let groups =
+    services::attachment::get_groups_of_url(conn, &services::storage::uploaded_file_url(&filename))
+      .map_err(ApiError::DatabaseError)?;
+  let group_ids = groups
+    .iter()
+    .filter(|(_, deleted)| !deleted)
+    .map(|(group_id, _)| *group_id)
+    .collect::<Vec<i32>>();
+  // content of deleted messages must not be retrievable by url, even when files are public
+  if group_ids.is_empty() && !groups.is_empty() {
+    return Err(ApiError::Gone("File".into()));
+  }
+  if !state.public_files {
+    let user = super::common::check_user_exists(conn, token).await?;
+    if group_ids.is_empty() {
+      return Err(ApiError::NotFound("File".into()));
+    }
+    let mut is_member = false;
+    for group_id in group_ids {
+      if services::group::check_user_join_group(conn, user.id, group_id)
+        .map_err(|_err| ApiError::new_database_query_err("Failed to check user joined group"))?
+      {
+        is_member = true;
+        break;
+      }
+    }
+    if !is_member {
+      return Err(ApiError::Forbidden);
+    }
+  }
+
   // Construct the path to the static file directory
   let base_path = PathBuf::from(UPLOADS_DIRECTORY);
   let file_path = base_path.join(filename);
@@ -48,26 +104,108 @@ pub async fn serve_file(Path(filename): Path<String>) -> Response {
       let content_type = guess_mime_type_from_path(file_path);
 
       // Build and return the response
-      Response::builder()
-        .header(header::CONTENT_TYPE, content_type)
-        .body(body)
-        .unwrap()
+      Ok(
+        Response::builder()
+          .header(header::CONTENT_TYPE, content_type)
+          .body(body)
+          .unwrap(),
+      )
     }
     Err(_) => {
       // Return a 404 response if the file doesn't exist
-      (StatusCode::NOT_FOUND, "404: File not found".to_string()).into_response()
+      Ok((StatusCode::NOT_FOUND, "404: File not found".to_string()).into_response())
     }
   }
 }
 
+/// ### Handler for GET /attachments/:attachment_id
+///
+/// Get type and size of an attachment without downloading its file
+#[utoipa::path(
+  get,
+  path = "/attachments/{attachment_id}",
+  params(
+    (
+      "x-user-code" = String, Header, description = "user code for authentication",
+      example = "6C70F6E0A888C1360AD532C66D8F1CD0ED48C1CC47FA1AE6665B1FC3DAABB468"
+    ),
+    ("attachment_id" = u32, Path, description = "id of the attachment"),
+  ),
+  responses(
+      (status = 200, description = "Get attachment successfully", body = AttachmentDetailResponse, content_type = "application/json",
+        example = json!(
+            {
+              "id": 3,
+              "url": "http://127.0.0.1:8080/files/avatar.png",
+              "attachment_type": "IMAGE",
+              "size": 48213,
+              "message_id": 2,
+              "group_id": 24
+            }
+        )),
+      (status = 403, description = "The current user is not a member of the group"),
+      (status = 404, description = "The attachment or its file is not found"),
+      (status = 500, description = "Database error")
+  ),
+)]
+pub async fn get_attachment(
+  State(state): State<Arc<AppState>>,
+  UserToken(token): UserToken,
+  Path(attachment_id): Path<i32>,
+) -> Result<Json<AttachmentDetailResponse>, ApiError> {
+  let conn = &mut state
+    .db_pool
+    .get()
+    .map_err(|err| ApiError::DatabaseError(DBError::ConnectionError(err)))?;
+  let user = super::common::check_user_exists(conn, token).await?;
+
+  let (attachment, group_id) = services::attachment::get_attachment_with_group(conn, attachment_id)
+    .map_err(ApiError::DatabaseError)?
+    .ok_or(ApiError::NotFound("Attachment".into()))?;
+  if !services::group::check_user_join_group(conn, user.id, group_id)
+    .map_err(|_err| ApiError::new_database_query_err("Failed to check user joined group"))?
+  {
+    return Err(ApiError::NotAMember(group_id));
+  }
+
+  // uploaded files are named by the last path segment of the attachment url
+  let file_name = services::storage::uploaded_file_name(&attachment.url)
+    .ok_or(ApiError::NotFound("File".into()))?;
+  let metadata = tokio::fs::metadata(PathBuf::from(UPLOADS_DIRECTORY).join(file_name))
+    .await
+    .ok()
+    .filter(|metadata| metadata.is_file())
+    .ok_or(ApiError::NotFound("File".into()))?;
+
+  Ok(Json(AttachmentDetailResponse {
+    id: attachment.id,
+    url: services::storage::to_public_url(&attachment.url),
+    attachment_type: attachment.attachment_type,
+    size: metadata.len(),
+    message_id: attachment.message_id,
+    group_id,
+  }))
+}
+
 #[allow(dead_code)]
 #[derive(ToSchema, Debug)]
 pub struct UploadFile {
+  /// Group the file is uploaded for, it must be sent before `file`
+  pub group_id: i32,
+  /// Optional id making the upload retry-safe, it must be sent before `file`.
+  /// Retrying with the id of a completed upload returns the stored file instead of storing it again
+  pub upload_id: Option<Uuid>,
   #[schema(value_type = String, format = Binary)]
   pub file: Vec<u8>,
 }
 
 /// ### Handler to upload a file to server
+///
+/// The file is uploaded for a group given by the `group_id` field, which must precede
+/// the `file` field, only members of the group can upload files.
+///
+/// A client may send an `upload_id` field before the `file` field to retry a failed upload
+/// safely, a retry of a completed upload returns the same file
 #[utoipa::path(
     post,
     params(
@@ -79,7 +217,12 @@ pub struct UploadFile {
     path = "/files",
     request_body(content_type = "multipart/form-data", content = inline(UploadFile), description = "File to upload"),
     responses(
-        (status = 200, description = "OK")
+        (status = 200, description = "OK"),
+        (status = 400, description = "The multipart body is malformed, or the list of invalid fields, e.g. a missing file, a group_id sent after the file or a file exceeding the maximum upload size"),
+        (status = 403, description = "The current user is not a member of the group"),
+        (status = 409, description = "The upload_id is used by an upload of another user"),
+        (status = 413, description = "The storage quota of the group is exceeded"),
+        (status = 503, description = "Too many uploads in progress, retry after the `Retry-After` header seconds")
     )
 )]
 pub async fn upload_file(
@@ -87,48 +230,119 @@ pub async fn upload_file(
   UserToken(token): UserToken,
   mut multipart: Multipart,
 ) -> Result<Json<FileResponse>, ApiError> {
+  // the permit is released when the upload completes or fails
+  let _permit = state
+    .upload_semaphore
+    .try_acquire()
+    .map_err(|_| ApiError::ServiceBusy(UPLOAD_RETRY_AFTER_SECS))?;
   let conn = &mut state
     .db_pool
     .get()
     .map_err(|err| ApiError::DatabaseError(DBError::ConnectionError(err)))?;
-  super::common::check_user_exists(conn, token).await?;
-  let mut file = None;
-  loop {
-    let next_field = multipart.next_field().await;
-    if let Err(ref err) = next_field {
-      tracing::debug!("No more next multipart field : {}", err.to_string());
-      break;
-    }
-    if let Some(field) = next_field.unwrap() {
-      let name = field.name().unwrap().to_string();
+  let user = super::common::check_user_exists(conn, token).await?;
+  let mut group_id = None;
+  let mut upload_id = None;
+  // problems of the fields are collected, so all of them are reported at once
+  let mut errors = Vec::new();
+  while let Some(field) = multipart.next_field().await.map_err(multipart_error)? {
+    let name = field.name().unwrap_or_default().to_string();
+    if name == "group_id" {
+      let value = field.text().await.map_err(multipart_error)?;
+      match value.trim().parse::<i32>() {
+        Ok(value) => group_id = Some(value),
+        Err(_) => errors.push(FieldError::new("group_id", "must be a number")),
+      }
+    } else if name == "upload_id" {
+      let value = field.text().await.map_err(multipart_error)?;
+      match Uuid::parse_str(value.trim()) {
+        Ok(value) => upload_id = Some(value),
+        Err(_) => errors.push(FieldError::new("upload_id", "must be a UUID")),
+      }
+    } else if name == "file" {
+      // the file is streamed to disk as it is received, so other fields are checked beforehand
+      if group_id.is_none() && !errors.iter().any(|error| error.field == "group_id") {
+        errors.push(FieldError::new("group_id", "must be sent before the file"));
+      }
       if field.content_type().is_none() {
-        return Err(ApiError::MissingField("Content-type header".to_owned()));
+        errors.push(FieldError::new("file", "must have a Content-Type header"));
+      }
+      let Some(group_id) = group_id.filter(|_| errors.is_empty()) else {
+        return Err(ApiError::ValidationFailed(errors));
+      };
+      if !services::group::check_user_join_group(conn, user.id, group_id)
+        .map_err(|_err| ApiError::new_database_query_err("Failed to check user joined group"))?
+      {
+        return Err(ApiError::NotAMember(group_id));
+      }
+      if let Some(upload_id) = upload_id {
+        match services::upload::get_upload(conn, upload_id).map_err(ApiError::DatabaseError)? {
+          Some(upload) if upload.user_id != user.id => {
+            return Err(ApiError::Conflict("The upload id is already used".into()));
+          }
+          Some(upload) => {
+            // the file of a completed upload may have been evicted, it is stored again then
+            if let (Some(name), Some(content_type)) = (upload.file_name, upload.content_type) {
+              if PathBuf::from(UPLOADS_DIRECTORY).join(&name).is_file() {
+                return Ok(Json(uploaded_file_response(name, &content_type)));
+              }
+            }
+          }
+          None => services::upload::start_upload(conn, upload_id, user.id)
+            .map_err(ApiError::DatabaseError)?,
+        }
       }
-      let content_type = field.content_type().unwrap();
+      let quota = state.group_storage_quota;
+      let used_bytes = services::attachment::get_storage_bytes_of_group(conn, group_id)
+        .map_err(ApiError::DatabaseError)?
+        .max(0) as u64;
+      if used_bytes >= quota {
+        return Err(ApiError::StorageQuotaExceeded(quota));
+      }
+      let content_type = field.content_type().unwrap_or_default().to_owned();
       tracing::debug!("File received with content type: {content_type}");
-
-      if name == "file" {
-        let file_name = field.file_name().unwrap().to_owned();
-        file = Some((file_name, content_type.to_owned(), field))
+      let file_name = field.file_name().unwrap_or_default().to_owned();
+      let (file_response, size) = stream_to_file(&file_name, &content_type, field).await?;
+      if used_bytes + size > quota {
+        let path = PathBuf::from(UPLOADS_DIRECTORY).join(&file_response.name);
+        if let Err(err) = tokio::fs::remove_file(path).await {
+          tracing::error!("Failed to remove file exceeding the storage quota: {}", err.to_string());
+        }
+        return Err(ApiError::StorageQuotaExceeded(quota));
+      }
+      if let Some(upload_id) = upload_id {
+        services::upload::complete_upload(conn, upload_id, &file_response.name, &content_type)
+          .map_err(ApiError::DatabaseError)?;
       }
-    } else {
-      break;
+      return Ok(Json(file_response));
     }
   }
 
-  if file.is_none() {
-    return Err(ApiError::MissingField("file".to_owned()));
+  if group_id.is_none() && !errors.iter().any(|error| error.field == "group_id") {
+    errors.push(FieldError::new("group_id", "is required"));
   }
+  errors.push(FieldError::new("file", "is required"));
+  Err(ApiError::ValidationFailed(errors))
+}
 
-  let file = file.unwrap();
-  stream_to_file(&file.0, &file.1, file.2).await
+/// Map a failure of parsing a multipart body, distinct from the end of the fields.
+/// A body exceeding the size limit is reported as an invalid `file` field
+fn multipart_error(err: MultipartError) -> ApiError {
+  tracing::debug!("Failed to parse multipart body: {}", err);
+  if err.status() == StatusCode::PAYLOAD_TOO_LARGE {
+    return ApiError::ValidationFailed(vec![FieldError::new(
+      "file",
+      "exceeds the maximum upload size",
+    )]);
+  }
+  ApiError::BadRequest(format!("Malformed multipart body: {}", err.body_text()))
 }
 
+/// Write an uploaded file to the uploads directory, return it with its size in bytes
 async fn stream_to_file<S, E>(
   file_name: &str,
   content_type: &str,
   stream: S,
-) -> Result<Json<FileResponse>, ApiError>
+) -> Result<(FileResponse, u64), ApiError>
 where
   S: Stream<Item = Result<Bytes, E>>,
   E: Into<BoxError>,
@@ -145,25 +359,32 @@ where
     let mut file = BufWriter::new(File::create(&path).await?);
 
     // Copy the body into the file.
-    tokio::io::copy(&mut body_reader, &mut file).await?;
-    let file_url = format!(
-      "{server_url}/files/{file_path}",
-      server_url = get_server_url(),
-      file_path = new_file_name
-    );
-    let file_response = FileResponse {
-      name: new_file_name,
-      content_type: content_type.into(),
-      file_path: file_url,
-    };
-    Ok(Json(file_response))
+    let size = tokio::io::copy(&mut body_reader, &mut file).await?;
+    Ok((uploaded_file_response(new_file_name, content_type), size))
   }
   .map_err(|err: io::Error| {
-    tracing::error!(
-      "An error occur when transmute stream to file: {}",
-      err.to_string()
-    );
+    // the body is cut off while streaming the file when it exceeds the size limit
+    if let Some(err) = err
+      .into_inner()
+      .and_then(|inner| inner.downcast::<MultipartError>().ok())
+    {
+      return multipart_error(*err);
+    }
+    tracing::error!("An error occur when transmute stream to file");
     ApiError::Unknown
   })
   .await
 }
+
+fn uploaded_file_response(file_name: String, content_type: &str) -> FileResponse {
+  let file_url = format!(
+    "{server_url}/files/{file_path}",
+    server_url = get_server_url(),
+    file_path = file_name
+  );
+  FileResponse {
+    name: file_name,
+    content_type: content_type.into(),
+    file_path: file_url,
+  }
+}
@@ -1,4 +1,4 @@
-use std::{borrow::Borrow, env, sync::Arc, time::Duration};
+use std::{borrow::Borrow, collections::HashSet, env, sync::Arc, time::Duration};
 use diesel::result::Error;
 use axum::{
   body::Body, extract::{Path, Query, State}, http::StatusCode, Json
@@ -6,8 +6,9 @@ use axum::{
 use axum::http::HeaderValue;
 use chrono::{NaiveDateTime, Utc};
 use diesel::{
-  r2d2::ConnectionManager, result::DatabaseErrorKind, Connection, ExpressionMethods, JoinOnDsl,
-  OptionalExtension, PgConnection, QueryDsl, RunQueryDsl, SelectableHelper,
+  r2d2::ConnectionManager, result::DatabaseErrorKind, sql_types::Bool, Connection,
+  ExpressionMethods, IntoSql, JoinOnDsl, OptionalExtension, PgConnection, QueryDsl, RunQueryDsl,
+  SelectableHelper,
 };
 use diesel::dsl::sql;
 use dotenvy::dotenv;
@@ -17,21 +18,27 @@ use crate::{
   database::{
     models::{self, Group, NewGroup, NewWaitingList, User, WaitingList},
     schema::{self},
-  }, errors::{ApiError, DBError}, extractors::UserToken, payloads::{
+  }, errors::{ApiError, AuthError, DBError}, extractors::{UserToken, ValidatedJson}, payloads::{
     self,
     common::{ListResponse, PageRequest},
-    groups::{GroupResult, JoinGroupForm, NewGroupForm, ProcessWaitingRequest, WaitingListResponse},
+    groups::{GroupCodeValidationResponse, GroupPreviewQuery, GroupPreviewResponse, GroupResult, GroupSummariesRequest, GroupSummariesResponse, SlowModeRequest, SlowModeResponse, PinGroupRequest, PinGroupResponse, MyPermissionsResponse, ArchiveGroupResponse, DraftRequest, DraftResponse, ModerationRequest, ModerationResponse, PostPolicyRequest, PostPolicyResponse, RemoveMemberResult, RemoveMembersRequest, RemoveMembersResponse, validate_ban_minutes, MaxMessageLengthRequest, MaxMessageLengthResponse, JoinGroupForm, NewGroupForm, ProcessWaitingRequest, RenameGroupRequest, WaitingListResponse},
+    messages::{MessageReportResponse, MessageStatsResponse, PinnedMessageResponse},
+    socket::message::{GroupData, GroupUpdatedData, MemberLeftData, SMessageType},
   }, services::{
-    self, group::{check_owner_of_group, check_user_join_group, get_count_waiting_list, get_waiting_list_object}, user::{create_user, get_user_by_code}
+    self, group::{check_owner_of_group, check_user_join_group, get_count_waiting_list, get_waiting_list_object, LeaveGroupOutcome}, user::create_user
   }, utils::{
     crypto::generate_secret_code,
     minors::{calculate_offset_from_page, calculate_total_pages},
+    validation::FieldError,
   }, AppState, DEFAULT_PAGE_SIZE, DEFAULT_PAGE_START
 };
 use md5;
 use super::common::check_user_exists;
+use super::socket::connections::{
+  revoke_group_members, send_message_event_to_group, send_message_event_to_user,
+};
 
-use crate::payloads::groups::{DelGroupRequest, DelGroupResponse, GrDetailSettingResponse, GroupInfo, GroupListResponse, LeaveGroupRequest, LeaveGroupResponse, NewUserAndGroupRequest, NewUserAndGroupResponse, RmRfGroupsRequest, RmRfGroupsResponse, RmUserRequest, RmUserResponse, UserSettingInfo};
+use crate::payloads::groups::{DelGroupRequest, DelGroupResponse, GrDetailSettingResponse, GrSettingPageRequest, GroupInfo, GroupListResponse, LeaveGroupRequest, LeaveGroupResponse, NewUserAndGroupRequest, NewUserAndGroupResponse, RmRfGroupsRequest, RmRfGroupsResponse, RmUserRequest, RmUserResponse, UserSettingInfo};
 use crate::database::schema::{attachments, groups, messages, participants, users, waiting_list};
 use crate::payloads::common::CommonResponse;
 use crate::payloads::groups::{GroupResponse, NewGroupWithUserIdRequest, GroupDetailResponse};
@@ -40,30 +47,71 @@ use crate::payloads::groups::{GroupResponse, NewGroupWithUserIdRequest, GroupDet
 /// ### Create new or get existing user from user_code token
 ///
 /// This function will return a new or existing user depend on user's existence:
-/// - If user_code doesn't provide or if having but not valid a new user will be created.
+/// - If user_code doesn't provide or if having but not valid a new user will be created,
+///   `username` is then required and `ApiError::ValidationFailed` is returned when it is empty.
 /// - If user existed in database return existing user.
+/// - If the user code of a guest is expired, `AuthError::Expired` is returned.
+///
+/// Usernames are unique, `ApiError::ExistedResource` is returned when the username of
+/// the new user is already taken
 fn get_or_create_user_from_user_code(
   conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
   user_code: &Option<String>,
   username: &str,
-) -> Result<(User, bool), diesel::result::Error> {
-  let user;
-  let mut is_new = true;
-  if user_code.is_none() {
-    tracing::debug!("");
-    user = create_user(conn, username)?;
-  } else {
-    let user_code = user_code.as_ref().unwrap();
-    tracing::debug!("user_code: {}", user_code);
-    if let Some(found_user) = get_user_by_code(conn, user_code)? {
-      tracing::debug!("Found user from database via user_code");
-      user = found_user;
-      is_new = false;
-    } else {
-      user = create_user(conn, username)?;
-    }
+) -> Result<(User, bool), ApiError> {
+  if let Some(found_user) = services::auth::find_user_by_code(conn, user_code.as_deref())? {
+    tracing::debug!("Found user from database via user_code");
+    return Ok((found_user, false));
+  }
+  if username.trim().is_empty() {
+    return Err(ApiError::ValidationFailed(vec![FieldError::new("username", "must not be empty")]));
+  }
+  let user = create_user_with_unique_username(conn, username)?;
+  Ok((user, true))
+}
+
+/// ### Create a new user, failing with a clear error if the username is already taken
+///
+/// The user is inserted in a nested transaction (savepoint), so a duplicated username
+/// doesn't abort the transaction of the caller
+fn create_user_with_unique_username(
+  conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+  username: &str,
+) -> Result<User, ApiError> {
+  conn
+    .transaction(|conn| create_user(conn, username))
+    .map_err(|err| match err {
+      Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+        ApiError::ExistedResource(format!("Username '{}' is already taken", username))
+      }
+      _ => ApiError::from(err),
+    })
+}
+
+/// ### Check the user can own one more group
+///
+/// Must run in the transaction inserting the group: the row of the user is locked until the
+/// transaction ends, so concurrent creations of the same user are counted one after another.
+/// Return `ApiError::Conflict` when the user already owns `max_groups` active groups
+fn check_owned_groups_limit(
+  conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+  user_id: i32,
+  max_groups: i64,
+) -> Result<(), ApiError> {
+  users::table
+    .find(user_id)
+    .select(users::id)
+    .for_update()
+    .first::<i32>(conn)?;
+  let owned_groups = services::group::get_count_active_groups_of_owner(conn, user_id)
+    .map_err(ApiError::DatabaseError)?;
+  if owned_groups >= max_groups {
+    return Err(ApiError::Conflict(format!(
+      "User already owns the maximum of {} active groups",
+      max_groups
+    )));
   }
-  Ok((user, is_new))
+  Ok(())
 }
 
 /// ### Handler for API `/add-user-group`
@@ -98,19 +146,26 @@ fn get_or_create_user_from_user_code(
  ),
   responses(
       (status = 200, description = "Create a group successfully", body = GroupResult, content_type = "application/json"),
-      (status = 400, description = "Username already existed"),
+      (status = 400, description = "Username already existed or the request has invalid fields"),
+      (status = 409, description = "User already owns the maximum number of active groups"),
       (status = 500, description = "Database error")
   ),
 )]
 pub async fn create_user_and_group(
   State(app_state): State<Arc<AppState>>,
   UserToken(user_token): UserToken,
-  Json(new_group_form): Json<NewGroupForm>,
-) -> Result<Json<GroupResult>, DBError> {
+  ValidatedJson(new_group_form): ValidatedJson<NewGroupForm>,
+) -> Result<Json<GroupResult>, ApiError> {
   tracing::debug!("POST: /add-user-group");
-  let conn = &mut app_state.db_pool.get().map_err(DBError::ConnectionError)?;
-  let transaction_rs: Result<(User, Group), diesel::result::Error> = conn.transaction(|conn| {
-    let (user, _) = get_or_create_user_from_user_code(conn, user_token.borrow(), &new_group_form.username)?;
+  let conn = &mut app_state
+    .db_pool
+    .get()
+    .map_err(|err| ApiError::DatabaseError(DBError::ConnectionError(err)))?;
+  let transaction_rs: Result<(User, Group), ApiError> = conn.transaction(|conn| {
+    let (user, is_new) = get_or_create_user_from_user_code(conn, user_token.borrow(), &new_group_form.username)?;
+    if !is_new {
+      check_owned_groups_limit(conn, user.id, app_state.max_groups_per_user)?;
+    }
 
     let current = Utc::now();
     let expired_at = current + Duration::from_secs((new_group_form.duration * 60) as u64);
@@ -141,12 +196,7 @@ pub async fn create_user_and_group(
     Ok((user, group_result))
   });
 
-  let (user, group) = transaction_rs.map_err(|err| match err {
-    diesel::result::Error::DatabaseError(diesel::result::DatabaseErrorKind::UniqueViolation, _) => {
-      DBError::ConstraintViolation(err.to_string())
-    }
-    _ => DBError::QueryError(err.to_string()),
-  })?;
+  let (user, group) = transaction_rs?;
 
   let group_rs = payloads::groups::GroupResult {
     user_id: user.id,
@@ -155,7 +205,7 @@ pub async fn create_user_and_group(
     group_id: group.id,
     group_name: group.name,
     group_code: group.group_code,
-    expired_at: group.expired_at.unwrap().and_utc().to_string(),
+    expired_at: group.expired_at.unwrap().and_utc(),
     is_waiting: false,
   };
   Ok(Json(group_rs))
@@ -164,7 +214,7 @@ pub async fn create_user_and_group(
 pub async fn create_user_and_group_v1(
     State(app_state): State<Arc<AppState>>,
     UserToken(user_token): UserToken,
-    Json(request): Json<NewUserAndGroupRequest>,
+    ValidatedJson(request): ValidatedJson<NewUserAndGroupRequest>,
 ) -> Result<Json<CommonResponse<NewUserAndGroupResponse>>, ApiError> {
     tracing::debug!("POST: /v1/add-user-group");
 
@@ -191,11 +241,13 @@ pub async fn create_user_and_group_v1(
         )));
     }
 
-
     // Step 2: Begin transaction to create user and group
-    let transaction_rs: Result<NewUserAndGroupResponse, Error> = conn.transaction(|conn| {
+    let transaction_rs: Result<NewUserAndGroupResponse, ApiError> = conn.transaction(|conn| {
         // Retrieve or create the user
-        let (user, _) = get_or_create_user_from_user_code(conn, user_token.borrow(), &request.username)?;
+        let (user, is_new) = get_or_create_user_from_user_code(conn, user_token.borrow(), &request.username)?;
+        if !is_new {
+            check_owned_groups_limit(conn, user.id, app_state.max_groups_per_user)?;
+        }
 
         // Calculate current and expiration times
         let current = Utc::now();
@@ -232,7 +284,7 @@ pub async fn create_user_and_group_v1(
             group_id: group_result.id,
             group_name: group_result.name,
             group_code: group_result.group_code,
-            expired_at: group_result.expired_at.unwrap().and_utc().to_string(),
+            expired_at: group_result.expired_at.unwrap().and_utc(),
             is_waiting: false,
         };
 
@@ -246,12 +298,13 @@ pub async fn create_user_and_group_v1(
     // Map the result into a common JSON response format
     match transaction_rs {
         Ok(response) => Ok(Json(CommonResponse::success(response))),
-        Err(err) => {
+        Err(ApiError::DatabaseError(err)) => {
             error!("Transaction error: {:?}", err);
             Err(ApiError::DatabaseError(DBError::TransactionError(
                 "Failed to create user and group".to_string(),
             )))
         }
+        Err(err) => Err(err),
     }
 }
 
@@ -265,6 +318,7 @@ pub async fn create_user_and_group_v1(
 /// 2. **Group Joining Process**:
 ///    - **Pending Approval**: If the group requires owner approval, the user is added to a waiting list.
 ///    - **Direct Join**: If no owner approval is required, the user is added to the group immediately.
+///    - **Owner Join**: The owner of the group is always added directly, the waiting list is bypassed.
 #[utoipa::path(
   post,
   path = "/join-group",
@@ -288,25 +342,93 @@ pub async fn create_user_and_group_v1(
  ),
   responses(
       (status = 200, description = "Join group successfully", body = GroupResult, content_type = "application/json"),
-      (status = 400, description = "User already join the group"),
+      (status = 400, description = "User already join the group, the username is already taken or the request has invalid fields"),
       (status = 401, description = "User was already in waiting list"),
+      (status = 403, description = "The user is banned from the group"),
+      (status = 404, description = "Group not found"),
+      (status = 410, description = "The group has expired"),
       (status = 500, description = "Database error")
   ),
 )]
 pub async fn join_group(
   State(app_state): State<Arc<AppState>>,
   UserToken(user_token): UserToken,
-  Json(join_group_form): Json<JoinGroupForm>,
+  ValidatedJson(join_group_form): ValidatedJson<JoinGroupForm>,
 ) -> Result<Json<GroupResult>, ApiError> {
   tracing::debug!("POST: /join-group");
   let conn = &mut app_state
     .db_pool
     .get()
     .map_err(|err| ApiError::DatabaseError(DBError::ConnectionError(err)))?;
+  let group_rs = process_join_group(conn, &user_token, &join_group_form)?;
+  Ok(Json(group_rs))
+}
+
+/// ### Handler for the `/v1/join-group`
+///
+/// Same as `/join-group`, but the result is wrapped in a `CommonResponse`.
+/// Expected failures are returned with status 200 and an error code:
+/// - 1: The group is not found
+/// - 2: The user already joined the group
+/// - 3: The user was already in waiting list or the username is already taken
+/// - 4: The group has expired
+/// - 5: The user is banned from the group
+#[utoipa::path(
+  post,
+  path = "/v1/join-group",
+  params(
+    (
+      "x-user-code" = Option<String>, Header, description = "user code for authentication",
+      example = "6C70F6E0A888C1360AD532C66D8F1CD0ED48C1CC47FA1AE6665B1FC3DAABB468"
+    ),
+  ),
+  request_body = JoinGroupForm,
+  responses(
+      (status = 200, description = "Join group successfully or failed with an error code", body = CommonResponse<GroupResult>, content_type = "application/json"),
+      (status = 400, description = "The request has invalid fields"),
+      (status = 500, description = "Database error")
+  ),
+)]
+pub async fn join_group_v1(
+  State(app_state): State<Arc<AppState>>,
+  UserToken(user_token): UserToken,
+  ValidatedJson(join_group_form): ValidatedJson<JoinGroupForm>,
+) -> Result<Json<CommonResponse<GroupResult>>, ApiError> {
+  tracing::debug!("POST: /v1/join-group");
+  let conn = &mut app_state
+    .db_pool
+    .get()
+    .map_err(|err| ApiError::DatabaseError(DBError::ConnectionError(err)))?;
+  match process_join_group(conn, &user_token, &join_group_form) {
+    Ok(group_rs) => Ok(Json(CommonResponse::success(group_rs))),
+    Err(err @ ApiError::NotFound(_)) => Ok(Json(CommonResponse::error(1, &err.to_string()))),
+    Err(err @ ApiError::AlreadyJoined) => Ok(Json(CommonResponse::error(2, &err.to_string()))),
+    Err(err @ ApiError::ExistedResource(_)) => {
+      Ok(Json(CommonResponse::error(3, &err.to_string())))
+    }
+    Err(err @ ApiError::GroupExpired) => Ok(Json(CommonResponse::error(4, &err.to_string()))),
+    Err(err @ ApiError::BannedFromGroup(_)) => {
+      Ok(Json(CommonResponse::error(5, &err.to_string())))
+    }
+    Err(err) => Err(err),
+  }
+}
+
+/// ### Join a group from its code, shared by `/join-group` and `/v1/join-group`
+///
+/// The user is created if `user_token` doesn't match any user
+fn process_join_group(
+  conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+  user_token: &Option<String>,
+  join_group_form: &JoinGroupForm,
+) -> Result<GroupResult, ApiError> {
   let transaction_rs: Result<Result<(User, Group, bool), ApiError>, diesel::result::Error> = conn
     .transaction(|conn| {
       let (user, _) =
-        get_or_create_user_from_user_code(conn, &user_token, &join_group_form.username)?;
+        match get_or_create_user_from_user_code(conn, user_token, &join_group_form.username) {
+          Ok(user) => user,
+          Err(err) => return Ok(Err(err)),
+        };
 
       use schema::groups::dsl::{group_code, groups};
       let group = groups
@@ -321,6 +443,19 @@ pub async fn join_group(
         ))));
       }
       let group = group.unwrap();
+      if group
+        .expired_at
+        .is_some_and(|expired_at| expired_at <= Utc::now().naive_utc())
+      {
+        return Ok(Err(ApiError::GroupExpired));
+      }
+      match services::group::get_active_ban(conn, group.id, user.id) {
+        Ok(Some(banned_until)) => {
+          return Ok(Err(ApiError::BannedFromGroup(banned_until.and_utc().to_rfc3339())))
+        }
+        Ok(None) => {}
+        Err(err) => return Ok(Err(ApiError::DatabaseError(err))),
+      }
 
       // checking user already joined the group
       let check_result = check_user_join_group(conn, user.id, group.id);
@@ -330,10 +465,12 @@ pub async fn join_group(
       if let Ok(true) = check_result{
         return Ok(Err(ApiError::AlreadyJoined));
       }
-      // check group approval_require property to consider add directly to group or waiting list
+      // check group approval_require property to consider add directly to group or waiting list,
+      // the owner of the group is always added directly
       let mut is_waiting = false;
+      let is_owner = group.user_id == user.id;
 
-      if group.approval_require.unwrap() {
+      if group.approval_require.unwrap_or_default() && !is_owner {
         let waiting_list = NewWaitingList {
           user_id: user.id,
           group_id: group.id,
@@ -385,11 +522,11 @@ pub async fn join_group(
     group_id: group.id,
     group_name: group.name,
     group_code: group.group_code,
-    expired_at: group.expired_at.unwrap().and_utc().to_string(),
+    expired_at: group.expired_at.unwrap().and_utc(),
     is_waiting,
   };
 
-  Ok(Json(group_rs))
+  Ok(group_rs)
 }
 
 /// ### Handler for the `/gr/list/{user_id}`
@@ -420,26 +557,31 @@ pub async fn get_list_groups_by_user_id(
         DBError::ConnectionError(err)
     })?;
 
-    // Fetch user info
-    let user = users::table
-        .find(user_id)
-        .first::<models::User>(conn)
-        .map_err(|err| {
-            tracing::error!("Failed to find user with id {}: {:?}", user_id, err);
-            DBError::QueryError(format!("User not found: {:?}", err))
-        })?;
-
-    tracing::info!(
-        "User found: user_id = {}, user_code = {}",
-        user.id,
-        user.user_code
-    );
+    let (user, group_list, group_waiting_list) =
+        services::db::with_retry(conn, &app_state.db_pool, &app_state.db_retry, |conn| {
+            // Fetch user info
+            let user = users::table
+                .find(user_id)
+                .first::<models::User>(conn)
+                .map_err(|err| {
+                    tracing::error!("Failed to find user with id {}: {:?}", user_id, err);
+                    services::db::query_error(&err, format!("User not found: {:?}", err))
+                })?;
+
+            tracing::info!(
+                "User found: user_id = {}, user_code = {}",
+                user.id,
+                user.user_code
+            );
 
-    // Fetch user groups
-    let group_list = fetch_user_groups(conn, user_id).await?;
+            // Fetch user groups
+            let group_list = fetch_user_groups(conn, user_id)?;
 
-    // Fetch waiting groups
-    let group_waiting_list = fetch_waiting_groups(conn, user_id).await?;
+            // Fetch waiting groups
+            let group_waiting_list = fetch_waiting_groups(conn, user_id)?;
+            Ok((user, group_list, group_waiting_list))
+        })
+        .await?;
 
     let response = GroupListResponse {
         user_id: user.id,
@@ -452,8 +594,11 @@ pub async fn get_list_groups_by_user_id(
     Ok((StatusCode::OK, Json(response)))
 }
 
+/// Group of the group list: id, name, code, expiration time, creation time, pinned and archived
+type GroupRow = (i32, String, String, Option<NaiveDateTime>, Option<NaiveDateTime>, bool, bool);
+
 // Fetch groups that the user is part of
-async fn fetch_user_groups(
+fn fetch_user_groups(
     conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
     user_id: i32,
 ) -> Result<Vec<GroupInfo>, DBError> {
@@ -466,18 +611,20 @@ async fn fetch_user_groups(
             groups::group_code,
             groups::expired_at,
             groups::created_at,
+            participants::pinned,
+            groups::archived,
         ))
-        .load::<(i32, String, String, Option<NaiveDateTime>, Option<NaiveDateTime>)>(conn)
+        .load::<GroupRow>(conn)
         .map_err(|err| {
             tracing::error!("Failed to load groups for user_id {}: {:?}", user_id, err);
-            DBError::QueryError(format!("Error loading groups: {:?}", err))
+            services::db::query_error(&err, format!("Error loading groups: {:?}", err))
         })?;
 
-    Ok(process_group_list(conn, user_groups).await?)
+    Ok(process_group_list(conn, user_groups)?)
 }
 
 // Fetch groups where the user is waiting for approval
-async fn fetch_waiting_groups(
+fn fetch_waiting_groups(
     conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
     user_id: i32,
 ) -> Result<Vec<GroupInfo>, DBError> {
@@ -490,175 +637,1313 @@ async fn fetch_waiting_groups(
             groups::group_code,
             groups::expired_at,
             groups::created_at,
+            false.into_sql::<Bool>(),
+            groups::archived,
         ))
-        .load::<(i32, String, String, Option<NaiveDateTime>, Option<NaiveDateTime>)>(conn)
+        .load::<GroupRow>(conn)
         .map_err(|err| {
             tracing::error!("Failed to load waiting groups for user_id {}: {:?}", user_id, err);
-            DBError::QueryError(format!("Error loading waiting groups: {:?}", err))
+            services::db::query_error(&err, format!("Error loading waiting groups: {:?}", err))
         })?;
 
-    Ok(process_group_list(conn, waiting_groups).await?)
+    Ok(process_group_list(conn, waiting_groups)?)
+}
+
+// Process a list of groups and retrieve the latest message for each
+fn process_group_list(
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+    groups: Vec<GroupRow>,
+) -> Result<Vec<GroupInfo>, DBError> {
+    let mut group_list = Vec::new();
+
+    for (group_id, group_name, group_code, expired_at, created_at, pinned, archived) in groups {
+        tracing::info!(
+            "Processing group: id = {}, name = {}, code = {}",
+            group_id, group_name, group_code
+        );
+
+        use diesel::sql_types::{Nullable, Text, Timestamp};
+
+        let latest_message = messages::table
+            .inner_join(users::table.on(messages::user_id.eq(users::id)))
+            .filter(messages::group_id.eq(group_id))
+            .order((messages::created_at.desc(), messages::id.desc()))
+            .select((
+                sql::<Nullable<Text>>("messages.content"),
+                sql::<Timestamp>("messages.created_at"),
+                sql::<Nullable<Text>>("users.username"),
+            ))
+            .first::<(Option<String>, NaiveDateTime, Option<String>)>(conn)
+            .optional()
+            .map_err(|err| {
+                tracing::error!("Failed to get latest message for group_id {}: {:?}", group_id, err);
+                services::db::query_error(&err, format!("Error loading latest message: {:?}", err))
+            })?;
+
+        let (latest_ms_content, latest_ms_time, latest_ms_username) = latest_message
+            .map(|(content, time, username)| {
+                (
+                    content.unwrap_or_default(),
+                    Some(time),
+                    username.unwrap_or_default(),
+                )
+            })
+            .unwrap_or_default();
+
+        // The last activity is the latest message, or the creation of a group without messages
+        let last_activity = latest_ms_time.unwrap_or_default().max(created_at.unwrap_or_default());
+        group_list.push((last_activity, GroupInfo {
+            group_id,
+            group_name,
+            group_code,
+            expired_at: expired_at.map(|dt| dt.and_utc()),
+            latest_ms_content,
+            latest_ms_time: latest_ms_time.map(|dt| dt.and_utc()),
+            latest_ms_username,
+            created_at: created_at.map(|dt| dt.and_utc()),
+            pinned,
+            archived,
+        }));
+    }
+
+    // Sort pinned groups first, then groups by last activity (descending)
+    group_list.sort_by(|(a_activity, a), (b_activity, b)| {
+        b.pinned.cmp(&a.pinned).then(b_activity.cmp(a_activity))
+    });
+    Ok(group_list.into_iter().map(|(_, group)| group).collect())
+}
+
+
+
+/**
+   Create a new group with exists user by user_id
+*/
+pub async fn create_group_with_user(
+  State(app_state): State<Arc<AppState>>,
+  ValidatedJson(new_group_req): ValidatedJson<NewGroupWithUserIdRequest>,
+) -> Result<Json<CommonResponse<GroupResponse>>, ApiError> {
+  tracing::debug!("POST: /create-group");
+  let conn = &mut app_state
+    .db_pool
+    .get()
+    .map_err(|err| ApiError::DatabaseError(DBError::ConnectionError(err)))?;
+
+  // Check if the user exists
+  let user_exists = users::table
+    .find(new_group_req.user_id)
+    .first::<models::User>(conn)
+    .optional()
+    .map_err(|err| {
+      tracing::error!(
+        "Error checking user_id {}: {:?}",
+        new_group_req.user_id,
+        err
+      );
+      ApiError::DatabaseError(DBError::QueryError("Error checking user".to_string()))
+    })?;
+
+  if user_exists.is_none() {
+    return Ok(Json(CommonResponse::error(1, "User does not exist")));
+  }
+
+  let group_result = conn.transaction(|conn| {
+    check_owned_groups_limit(conn, new_group_req.user_id, app_state.max_groups_per_user)?;
+
+    let current_time = Utc::now();
+    let expired_at = current_time + chrono::Duration::minutes(new_group_req.duration.into());
+
+    // Create the new group
+    let new_group = models::NewGroup {
+      name: &new_group_req.group_name,
+      group_code: &generate_secret_code(&new_group_req.group_name),
+      user_id: new_group_req.user_id,
+      approval_require: new_group_req.approval_require,
+      created_at: current_time.naive_utc(),
+      expired_at: expired_at.naive_utc(),
+      maximum_members: new_group_req.maximum_members,
+    };
+
+    let group_result = diesel::insert_into(groups::table)
+      .values(&new_group)
+      .returning(models::Group::as_returning())
+      .get_result::<models::Group>(conn)
+      .map_err(|err| {
+        tracing::error!("Error inserting group: {:?}", err);
+        ApiError::DatabaseError(DBError::QueryError("Error inserting group".to_string()))
+      })?;
+
+    // Insert into participants table
+    diesel::insert_into(participants::table)
+      .values((
+        participants::user_id.eq(new_group_req.user_id),
+        participants::group_id.eq(group_result.id),
+      ))
+      .execute(conn)
+      .map_err(|err| {
+        tracing::error!("Error inserting into participants: {:?}", err);
+        ApiError::DatabaseError(DBError::QueryError("Error inserting into participants".to_string()))
+      })?;
+    Ok::<_, ApiError>(group_result)
+  })?;
+
+  // Prepare the response
+  let group_response = GroupResponse {
+    group_id: group_result.id,
+    group_name: group_result.name,
+    group_code: group_result.group_code,
+    expired_at: group_result.expired_at.map(|expired_at| expired_at.and_utc()),
+  };
+
+  Ok(Json(CommonResponse::success(group_response)))
+}
+
+///### Validate user is an owner of the group_id or not
+///
+/// If user is not an owner an api error will be propagated
+fn validate_owner_of_group(
+  conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+  user_token: &Option<String>,
+  group_id: i32,
+) -> Result<(), ApiError> {
+  let User { id: user_id, .. } = services::auth::authenticate_user_code(conn, user_token.as_deref())
+    .map_err(|err| match err {
+      AuthError::DatabaseError(err) => ApiError::DatabaseError(err),
+      _ => ApiError::Forbidden,
+    })?;
+
+  if !check_owner_of_group(conn, user_id, group_id)
+    .map_err(|_| ApiError::new_database_query_err("Failed to check owner of group"))?
+  {
+    return Err(ApiError::Unauthorized);
+  }
+  Ok(())
+}
+
+/// ### Handler for GET /groups/preview?code=
+///
+/// Get public information of a group from its code, so users can confirm before joining.
+/// Authentication is not required and members of the group are not revealed
+#[utoipa::path(
+  get,
+  path = "/groups/preview",
+  params(
+    ("code" = String, Query, description = "code of the group"),
+  ),
+  responses(
+      (status = 200, description = "Get group preview successfully", body = GroupPreviewResponse, content_type = "application/json"),
+      (status = 404, description = "Group not found or expired"),
+      (status = 500, description = "Database error")
+  ),
+)]
+pub async fn preview_group(
+  State(app_state): State<Arc<AppState>>,
+  Query(GroupPreviewQuery { code }): Query<GroupPreviewQuery>,
+) -> Result<Json<GroupPreviewResponse>, ApiError> {
+  let conn = &mut app_state
+    .db_pool
+    .get()
+    .map_err(|err| ApiError::DatabaseError(DBError::ConnectionError(err)))?;
+
+  let group = services::group::get_active_group_by_code(conn, &code)
+    .map_err(ApiError::DatabaseError)?
+    .ok_or(ApiError::NotFound("Group not found or expired".into()))?;
+  let member_count =
+    services::group::get_count_participants(conn, group.id).map_err(ApiError::DatabaseError)?;
+
+  Ok(Json(GroupPreviewResponse {
+    group_name: group.name,
+    member_count,
+    approval_require: group.approval_require.unwrap_or_default(),
+    expired_at: group.expired_at.map(|expired_at| expired_at.and_utc()),
+    is_full: group
+      .maximum_members
+      .is_some_and(|maximum_members| member_count >= maximum_members as i64),
+  }))
+}
+
+/// ### Handler for GET /groups/validate-code?code=
+///
+/// Check a group code before showing a join form, nothing is created.
+/// Authentication is not required, `valid` is false and the group fields are missing
+/// when the code doesn't exist, an expired or full group is reported by `expired` and `is_full`
+#[utoipa::path(
+  get,
+  path = "/groups/validate-code",
+  params(
+    ("code" = String, Query, description = "code of the group"),
+  ),
+  responses(
+      (status = 200, description = "Check group code successfully", body = GroupCodeValidationResponse, content_type = "application/json",
+        example = json!(
+            {
+              "valid": false,
+              "group_name": "Weekend trip",
+              "approval_require": false,
+              "is_full": true,
+              "expired": false
+            }
+        )),
+      (status = 500, description = "Database error")
+  ),
+)]
+pub async fn validate_group_code(
+  State(app_state): State<Arc<AppState>>,
+  Query(GroupPreviewQuery { code }): Query<GroupPreviewQuery>,
+) -> Result<Json<GroupCodeValidationResponse>, ApiError> {
+  let conn = &mut app_state
+    .db_pool
+    .get()
+    .map_err(|err| ApiError::DatabaseError(DBError::ConnectionError(err)))?;
+
+  let Some(group) =
+    services::group::get_group_by_code(conn, &code).map_err(ApiError::DatabaseError)?
+  else {
+    return Ok(Json(GroupCodeValidationResponse {
+      valid: false,
+      group_name: None,
+      approval_require: None,
+      is_full: None,
+      expired: None,
+    }));
+  };
+  let member_count =
+    services::group::get_count_participants(conn, group.id).map_err(ApiError::DatabaseError)?;
+  let is_full = group
+    .maximum_members
+    .is_some_and(|maximum_members| member_count >= maximum_members as i64);
+  let expired = group
+    .expired_at
+    .is_some_and(|expired_at| expired_at <= Utc::now().naive_utc());
+
+  Ok(Json(GroupCodeValidationResponse {
+    valid: !is_full && !expired,
+    group_name: Some(group.name),
+    approval_require: Some(group.approval_require.unwrap_or_default()),
+    is_full: Some(is_full),
+    expired: Some(expired),
+  }))
 }
 
-// Process a list of groups and retrieve the latest message for each
-async fn process_group_list(
-    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
-    groups: Vec<(i32, String, String, Option<NaiveDateTime>, Option<NaiveDateTime>)>,
-) -> Result<Vec<GroupInfo>, DBError> {
-    let mut group_list = Vec::new();
+/// ### Handler for PATCH /groups/:group_id/name
+///
+/// Rename the group then inform connected members by a `GroupUpdatedEvent`
+///
+/// **Notice**: User must be an owner of the group
+#[utoipa::path(
+  patch,
+  path = "/groups/{group_id}/name",
+  params(
+    (
+      "x-user-code" = String, Header, description = "user code for authentication",
+      example = "6C70F6E0A888C1360AD532C66D8F1CD0ED48C1CC47FA1AE6665B1FC3DAABB468"
+    ),
+    ("group_id" = u32, Path, description = "id of the group"),
+  ),
+  request_body = RenameGroupRequest,
+  responses(
+      (status = 200, description = "Rename group successfully", body = CommonResponse<GroupResponse>, content_type = "application/json"),
+      (status = 400, description = "Group name is empty or too long"),
+      (status = 404, description = "Group not found"),
+      (status = 403, description = "The current user doesn't have permission to access the resource"),
+      (status = 401, description = "The current user doesn't have right to access the resource"),
+      (status = 500, description = "Database error")
+  ),
+)]
+pub async fn rename_group(
+  State(app_state): State<Arc<AppState>>,
+  UserToken(user_token): UserToken,
+  Path(group_id): Path<i32>,
+  ValidatedJson(request): ValidatedJson<RenameGroupRequest>,
+) -> Result<Json<CommonResponse<GroupResponse>>, ApiError> {
+  let conn = &mut app_state
+    .db_pool
+    .get()
+    .map_err(|err| ApiError::DatabaseError(DBError::ConnectionError(err)))?;
+
+  if services::group::get_group_info(conn, group_id)
+    .map_err(ApiError::DatabaseError)?
+    .is_none()
+  {
+    return Err(ApiError::NotFound("Group not found".into()));
+  }
+  validate_owner_of_group(conn, &user_token, group_id)?;
+
+  let group = services::group::update_group_name(conn, group_id, request.name.trim())
+    .map_err(ApiError::DatabaseError)?;
+
+  let _ = send_message_event_to_group(
+    conn,
+    SMessageType::GroupUpdatedEvent(GroupUpdatedData {
+      group_id,
+      group_name: group.name.clone(),
+      archived: group.archived,
+      post_policy: group.post_policy,
+    }),
+    group_id,
+  );
+
+  Ok(Json(CommonResponse::success(GroupResponse {
+    group_id: group.id,
+    group_name: group.name,
+    group_code: group.group_code,
+    expired_at: group.expired_at.map(|expired_at| expired_at.and_utc()),
+  })))
+}
+
+/// ### Handler for API `POST /groups/:group_id/remove-members`
+///
+/// Remove several members from a group at once, the bulk counterpart of `/rm-u-from-gr`.
+/// The owner can't be removed, the result of each user tells whether they were removed.
+/// `MemberLeftEvent` is sent to the remaining members and to each removed member
+///
+/// **Notice**: User must be an owner of the group
+#[utoipa::path(
+  post,
+  path = "/groups/{group_id}/remove-members",
+  params(
+    (
+      "x-user-code" = String, Header, description = "user code for authentication",
+      example = "6C70F6E0A888C1360AD532C66D8F1CD0ED48C1CC47FA1AE6665B1FC3DAABB468"
+    ),
+    ("group_id" = u32, Path, description = "id of the group"),
+  ),
+  request_body = RemoveMembersRequest,
+  responses(
+      (status = 200, description = "Result of removing each member", body = CommonResponse<RemoveMembersResponse>, content_type = "application/json",
+        example = json!(
+            {
+              "code": 0,
+              "msg": "Success",
+              "data": {
+                "group_id": 24,
+                "results": [
+                  { "user_id": 38, "removed": true, "reason": null },
+                  { "user_id": 2, "removed": false, "reason": "The owner cannot be removed from the group" },
+                  { "user_id": 41, "removed": false, "reason": "User is not a member of the group" }
+                ]
+              }
+            }
+        )),
+      (status = 400, description = "The list of user ids is empty or too long"),
+      (status = 404, description = "Group not found"),
+      (status = 403, description = "The current user doesn't have permission to access the resource"),
+      (status = 401, description = "The current user doesn't have right to access the resource"),
+      (status = 500, description = "Database error")
+  ),
+)]
+pub async fn remove_members(
+  State(app_state): State<Arc<AppState>>,
+  UserToken(user_token): UserToken,
+  Path(group_id): Path<i32>,
+  ValidatedJson(request): ValidatedJson<RemoveMembersRequest>,
+) -> Result<Json<CommonResponse<RemoveMembersResponse>>, ApiError> {
+  let conn = &mut app_state
+    .db_pool
+    .get()
+    .map_err(|err| ApiError::DatabaseError(DBError::ConnectionError(err)))?;
+
+  let Some(group) = services::group::get_group_info(conn, group_id).map_err(ApiError::DatabaseError)?
+  else {
+    return Err(ApiError::NotFound("Group not found".into()));
+  };
+  validate_owner_of_group(conn, &user_token, group_id)?;
+
+  let user_ids = request
+    .user_ids
+    .iter()
+    .copied()
+    .filter(|user_id| *user_id != group.user_id)
+    .collect::<Vec<i32>>();
+  let removed_ids = conn
+    .transaction::<_, DBError, _>(|conn| {
+      let removed_ids = services::group::remove_members(conn, group_id, &user_ids)?;
+      if let Some(ban_minutes) = request.ban_minutes {
+        let banned_until = Utc::now().naive_utc() + chrono::Duration::minutes(ban_minutes.into());
+        services::group::ban_users(conn, group_id, &removed_ids, banned_until)?;
+      }
+      Ok(removed_ids)
+    })
+    .map_err(ApiError::DatabaseError)?;
+
+  revoke_group_members(group_id, &removed_ids);
+  for removed_id in &removed_ids {
+    let event = SMessageType::MemberLeftEvent(MemberLeftData {
+      group_id,
+      user_id: *removed_id,
+    });
+    let _ = send_message_event_to_group(conn, event.clone(), group_id);
+    send_message_event_to_user(*removed_id, event);
+  }
+
+  let mut seen_ids = HashSet::new();
+  let results = request
+    .user_ids
+    .into_iter()
+    .filter(|user_id| seen_ids.insert(*user_id))
+    .map(|user_id| {
+      let reason = if user_id == group.user_id {
+        Some("The owner cannot be removed from the group")
+      } else if !removed_ids.contains(&user_id) {
+        Some("User is not a member of the group")
+      } else {
+        None
+      };
+      RemoveMemberResult {
+        user_id,
+        removed: reason.is_none(),
+        reason: reason.map(String::from),
+      }
+    })
+    .collect();
+
+  Ok(Json(CommonResponse::success(RemoveMembersResponse {
+    group_id,
+    results,
+  })))
+}
+
+/// ### Handler for API `DELETE /groups/:group_id/bans/:user_id`
+///
+/// Lift the ban of an user, allowing them to rejoin the group before the ban expires
+///
+/// **Notice**: User must be an owner of the group
+#[utoipa::path(
+  delete,
+  path = "/groups/{group_id}/bans/{user_id}",
+  params(
+    (
+      "x-user-code" = String, Header, description = "user code for authentication",
+      example = "6C70F6E0A888C1360AD532C66D8F1CD0ED48C1CC47FA1AE6665B1FC3DAABB468"
+    ),
+    ("group_id" = u32, Path, description = "id of the group"),
+    ("user_id" = u32, Path, description = "id of the banned user"),
+  ),
+  responses(
+      (status = 204, description = "Unban the user successfully"),
+      (status = 404, description = "Group not found or the user is not banned"),
+      (status = 401, description = "The current user is not the owner of the group"),
+      (status = 500, description = "Database error")
+  ),
+)]
+pub async fn unban_user(
+  State(app_state): State<Arc<AppState>>,
+  UserToken(user_token): UserToken,
+  Path((group_id, user_id)): Path<(i32, i32)>,
+) -> Result<(StatusCode, Body), ApiError> {
+  let conn = &mut app_state
+    .db_pool
+    .get()
+    .map_err(|err| ApiError::DatabaseError(DBError::ConnectionError(err)))?;
+
+  if services::group::get_group_info(conn, group_id)
+    .map_err(ApiError::DatabaseError)?
+    .is_none()
+  {
+    return Err(ApiError::NotFound("Group not found".into()));
+  }
+  validate_owner_of_group(conn, &user_token, group_id)?;
+
+  if !services::group::unban_user(conn, group_id, user_id).map_err(ApiError::DatabaseError)? {
+    return Err(ApiError::NotFound("The user is not banned from the group".into()));
+  }
+  Ok((StatusCode::NO_CONTENT, Body::empty()))
+}
+
+/// ### Handler for API `GET /groups/:group_id/reports`
+///
+/// Get a page of reports of messages of the group, newest first
+///
+/// **Notice**: User must be an owner of the group
+#[utoipa::path(
+  get,
+  path = "/groups/{group_id}/reports",
+  params(
+    (
+      "x-user-code" = String, Header, description = "user code for authentication",
+      example = "6C70F6E0A888C1360AD532C66D8F1CD0ED48C1CC47FA1AE6665B1FC3DAABB468"
+    ),
+    ("group_id" = u32, Path, description = "id of the group"),
+    ("page" = Option<u32>, Query, description = "page index" ),
+    ("limit" = Option<u32>, Query, description = "the number of items per a page")
+  ),
+  responses(
+      (status = 200, description = "Get reports of group successfully", body = ListResponse<MessageReportResponse>, content_type = "application/json"),
+      (status = 404, description = "Group not found"),
+      (status = 401, description = "The current user is not the owner of the group"),
+      (status = 500, description = "Database error")
+  ),
+)]
+pub async fn get_group_reports(
+  State(app_state): State<Arc<AppState>>,
+  UserToken(user_token): UserToken,
+  Path(group_id): Path<i32>,
+  Query(page_request): Query<PageRequest>,
+) -> Result<ListResponse<MessageReportResponse>, ApiError> {
+  let conn = &mut app_state
+    .db_pool
+    .get()
+    .map_err(|err| ApiError::DatabaseError(DBError::ConnectionError(err)))?;
+
+  if services::group::get_group_info(conn, group_id)
+    .map_err(ApiError::DatabaseError)?
+    .is_none()
+  {
+    return Err(ApiError::NotFound("Group not found".into()));
+  }
+  validate_owner_of_group(conn, &user_token, group_id)?;
+
+  let reports = services::report::get_reports_of_group(conn, group_id, &page_request)
+    .map_err(ApiError::DatabaseError)?;
+  let report_count = services::report::get_count_reports_of_group(conn, group_id)
+    .map_err(ApiError::DatabaseError)?;
+  let total_pages =
+    calculate_total_pages(report_count as u64, page_request.get_per_page() as u64) as u16;
+  Ok(ListResponse {
+    count: reports.len() as i32,
+    objects: reports
+      .into_iter()
+      .map(|(report, reporter_name, message_content)| MessageReportResponse {
+        id: report.id,
+        message_id: report.message_id,
+        message_content,
+        reporter_id: report.reporter_id,
+        reporter_name,
+        reason: report.reason,
+        created_at: report.created_at,
+      })
+      .collect(),
+    total_pages,
+  })
+}
+
+/// ### Handler for API `GET /groups/:group_id/message-stats`
+///
+/// Get the number of messages of the group with the creation time of the earliest and
+/// latest ones, e.g. to render a jump-to-date scrollbar without paging through the history
+///
+/// **Notice**: User must be a member of the group
+#[utoipa::path(
+  get,
+  path = "/groups/{group_id}/message-stats",
+  params(
+    (
+      "x-user-code" = String, Header, description = "user code for authentication",
+      example = "6C70F6E0A888C1360AD532C66D8F1CD0ED48C1CC47FA1AE6665B1FC3DAABB468"
+    ),
+    ("group_id" = u32, Path, description = "id of the group"),
+  ),
+  responses(
+      (status = 200, description = "Get message stats of group successfully", body = MessageStatsResponse, content_type = "application/json"),
+      (status = 403, description = "The current user is not a member of the group"),
+      (status = 404, description = "User not found"),
+      (status = 500, description = "Database error")
+  ),
+)]
+pub async fn get_message_stats(
+  State(app_state): State<Arc<AppState>>,
+  UserToken(user_token): UserToken,
+  Path(group_id): Path<i32>,
+) -> Result<Json<MessageStatsResponse>, ApiError> {
+  let conn = &mut app_state
+    .db_pool
+    .get()
+    .map_err(|err| ApiError::DatabaseError(DBError::ConnectionError(err)))?;
+  let user = check_user_exists(conn, user_token).await?;
+
+  if !services::group::check_user_join_group(conn, user.id, group_id)
+    .map_err(|_err| ApiError::new_database_query_err("Failed to check user joined group"))?
+  {
+    return Err(ApiError::NotAMember(group_id));
+  }
+
+  let (count, earliest, latest) = services::message::get_message_stats(conn, group_id)
+    .map_err(ApiError::DatabaseError)?;
+  Ok(Json(MessageStatsResponse {
+    group_id,
+    count,
+    earliest,
+    latest,
+  }))
+}
+
+/// ### Handler for API `GET /groups/:group_id/pinned-messages`
+///
+/// Get pinned messages of the group, latest pinned first. Clients keep the list up to date
+/// with `PinEvent` websocket messages
+///
+/// **Notice**: User must be a member of the group
+#[utoipa::path(
+  get,
+  path = "/groups/{group_id}/pinned-messages",
+  params(
+    (
+      "x-user-code" = String, Header, description = "user code for authentication",
+      example = "6C70F6E0A888C1360AD532C66D8F1CD0ED48C1CC47FA1AE6665B1FC3DAABB468"
+    ),
+    ("group_id" = u32, Path, description = "id of the group"),
+  ),
+  responses(
+      (status = 200, description = "Get pinned messages of group successfully", body = Vec<PinnedMessageResponse>, content_type = "application/json"),
+      (status = 403, description = "The current user is not a member of the group"),
+      (status = 404, description = "User not found"),
+      (status = 500, description = "Database error")
+  ),
+)]
+pub async fn get_pinned_messages(
+  State(app_state): State<Arc<AppState>>,
+  UserToken(user_token): UserToken,
+  Path(group_id): Path<i32>,
+) -> Result<Json<Vec<PinnedMessageResponse>>, ApiError> {
+  let conn = &mut app_state
+    .db_pool
+    .get()
+    .map_err(|err| ApiError::DatabaseError(DBError::ConnectionError(err)))?;
+  let user = check_user_exists(conn, user_token).await?;
+
+  if !services::group::check_user_join_group(conn, user.id, group_id)
+    .map_err(|_err| ApiError::new_database_query_err("Failed to check user joined group"))?
+  {
+    return Err(ApiError::NotAMember(group_id));
+  }
+
+  let pinned_messages = services::pin::get_pinned_messages_of_group(conn, group_id)
+    .map_err(ApiError::DatabaseError)?;
+  Ok(Json(
+    pinned_messages
+      .into_iter()
+      .map(|(pinned_message, message_content)| PinnedMessageResponse {
+        message_id: pinned_message.message_id,
+        message_content,
+        pinned_by: pinned_message.pinned_by,
+        pinned_at: pinned_message.pinned_at,
+      })
+      .collect(),
+  ))
+}
+
+/// ### Handler for API `GET /groups/:group_id/my-permissions`
+///
+/// Get the effective permissions of the current user in the group, computed from the owner of
+/// the group, its post policy and whether it is archived or expired. There are no moderator
+/// roles yet, so only the owner can moderate and pin messages
+///
+/// **Notice**: User must be a member of the group
+#[utoipa::path(
+  get,
+  path = "/groups/{group_id}/my-permissions",
+  params(
+    (
+      "x-user-code" = String, Header, description = "user code for authentication",
+      example = "6C70F6E0A888C1360AD532C66D8F1CD0ED48C1CC47FA1AE6665B1FC3DAABB468"
+    ),
+    ("group_id" = u32, Path, description = "id of the group"),
+  ),
+  responses(
+      (status = 200, description = "Get permissions of the current user successfully", body = MyPermissionsResponse, content_type = "application/json"),
+      (status = 403, description = "The current user is not a member of the group"),
+      (status = 404, description = "User or group not found"),
+      (status = 500, description = "Database error")
+  ),
+)]
+pub async fn get_my_permissions(
+  State(app_state): State<Arc<AppState>>,
+  UserToken(user_token): UserToken,
+  Path(group_id): Path<i32>,
+) -> Result<Json<MyPermissionsResponse>, ApiError> {
+  let conn = &mut app_state
+    .db_pool
+    .get()
+    .map_err(|err| ApiError::DatabaseError(DBError::ConnectionError(err)))?;
+  let user = check_user_exists(conn, user_token).await?;
+
+  let Some(group) =
+    services::group::get_group_info(conn, group_id).map_err(ApiError::DatabaseError)?
+  else {
+    return Err(ApiError::NotFound("Group not found".into()));
+  };
+  if !services::group::check_user_join_group(conn, user.id, group_id)
+    .map_err(|_err| ApiError::new_database_query_err("Failed to check user joined group"))?
+  {
+    return Err(ApiError::NotAMember(group_id));
+  }
+
+  let is_owner = group.user_id == user.id;
+  let expired = group
+    .expired_at
+    .is_some_and(|expired_at| expired_at <= Utc::now().naive_utc());
+  Ok(Json(MyPermissionsResponse {
+    group_id,
+    is_owner,
+    can_post: !group.archived && !expired && group.post_policy.allows(is_owner),
+    can_moderate: is_owner,
+    can_pin: is_owner,
+  }))
+}
+
+/// ### Handler for API `GET /groups/:group_id/draft`
+///
+/// Get the unsent message of the current user in the group, so the draft follows the user
+/// across devices. A draft is private to its user
+///
+/// **Notice**: User must be a member of the group
+#[utoipa::path(
+  get,
+  path = "/groups/{group_id}/draft",
+  params(
+    (
+      "x-user-code" = String, Header, description = "user code for authentication",
+      example = "6C70F6E0A888C1360AD532C66D8F1CD0ED48C1CC47FA1AE6665B1FC3DAABB468"
+    ),
+    ("group_id" = u32, Path, description = "id of the group"),
+  ),
+  responses(
+      (status = 200, description = "Get draft successfully", body = DraftResponse, content_type = "application/json"),
+      (status = 403, description = "The current user is not a member of the group"),
+      (status = 404, description = "User not found or the user has no draft in the group"),
+      (status = 500, description = "Database error")
+  ),
+)]
+pub async fn get_draft(
+  State(app_state): State<Arc<AppState>>,
+  UserToken(user_token): UserToken,
+  Path(group_id): Path<i32>,
+) -> Result<Json<DraftResponse>, ApiError> {
+  let conn = &mut app_state
+    .db_pool
+    .get()
+    .map_err(|err| ApiError::DatabaseError(DBError::ConnectionError(err)))?;
+  let user = check_user_exists(conn, user_token).await?;
+
+  if !services::group::check_user_join_group(conn, user.id, group_id)
+    .map_err(|_err| ApiError::new_database_query_err("Failed to check user joined group"))?
+  {
+    return Err(ApiError::NotAMember(group_id));
+  }
+
+  let draft = services::draft::get_draft(conn, user.id, group_id)
+    .map_err(ApiError::DatabaseError)?
+    .ok_or(ApiError::NotFound("Draft".into()))?;
+  Ok(Json(DraftResponse::from(draft)))
+}
+
+/// ### Handler for API `PUT /groups/:group_id/draft`
+///
+/// Save the unsent message of the current user in the group, replacing the previous draft
+///
+/// **Notice**: User must be a member of the group
+#[utoipa::path(
+  put,
+  path = "/groups/{group_id}/draft",
+  params(
+    (
+      "x-user-code" = String, Header, description = "user code for authentication",
+      example = "6C70F6E0A888C1360AD532C66D8F1CD0ED48C1CC47FA1AE6665B1FC3DAABB468"
+    ),
+    ("group_id" = u32, Path, description = "id of the group"),
+  ),
+  request_body = DraftRequest,
+  responses(
+      (status = 200, description = "Save draft successfully", body = DraftResponse, content_type = "application/json"),
+      (status = 400, description = "The content is too long"),
+      (status = 403, description = "The current user is not a member of the group"),
+      (status = 404, description = "User not found"),
+      (status = 500, description = "Database error")
+  ),
+)]
+pub async fn save_draft(
+  State(app_state): State<Arc<AppState>>,
+  UserToken(user_token): UserToken,
+  Path(group_id): Path<i32>,
+  ValidatedJson(request): ValidatedJson<DraftRequest>,
+) -> Result<Json<DraftResponse>, ApiError> {
+  let conn = &mut app_state
+    .db_pool
+    .get()
+    .map_err(|err| ApiError::DatabaseError(DBError::ConnectionError(err)))?;
+  let user = check_user_exists(conn, user_token).await?;
+
+  if !services::group::check_user_join_group(conn, user.id, group_id)
+    .map_err(|_err| ApiError::new_database_query_err("Failed to check user joined group"))?
+  {
+    return Err(ApiError::NotAMember(group_id));
+  }
+
+  let draft = services::draft::save_draft(conn, user.id, group_id, request.content)
+    .map_err(ApiError::DatabaseError)?;
+  Ok(Json(DraftResponse::from(draft)))
+}
+
+/// ### Handler for API `DELETE /groups/:group_id/draft`
+///
+/// Delete the draft of the current user in the group, e.g. after the message was sent
+///
+/// **Notice**: User must be a member of the group
+#[utoipa::path(
+  delete,
+  path = "/groups/{group_id}/draft",
+  params(
+    (
+      "x-user-code" = String, Header, description = "user code for authentication",
+      example = "6C70F6E0A888C1360AD532C66D8F1CD0ED48C1CC47FA1AE6665B1FC3DAABB468"
+    ),
+    ("group_id" = u32, Path, description = "id of the group"),
+  ),
+  responses(
+      (status = 204, description = "Delete draft successfully, or the user had no draft"),
+      (status = 403, description = "The current user is not a member of the group"),
+      (status = 404, description = "User not found"),
+      (status = 500, description = "Database error")
+  ),
+)]
+pub async fn delete_draft(
+  State(app_state): State<Arc<AppState>>,
+  UserToken(user_token): UserToken,
+  Path(group_id): Path<i32>,
+) -> Result<(StatusCode, Body), ApiError> {
+  let conn = &mut app_state
+    .db_pool
+    .get()
+    .map_err(|err| ApiError::DatabaseError(DBError::ConnectionError(err)))?;
+  let user = check_user_exists(conn, user_token).await?;
+
+  if !services::group::check_user_join_group(conn, user.id, group_id)
+    .map_err(|_err| ApiError::new_database_query_err("Failed to check user joined group"))?
+  {
+    return Err(ApiError::NotAMember(group_id));
+  }
+
+  services::draft::delete_draft(conn, user.id, group_id).map_err(ApiError::DatabaseError)?;
+  Ok((StatusCode::NO_CONTENT, Body::empty()))
+}
+
+/// ### Handler for API `POST /groups/:group_id/archive`
+///
+/// Archive the group, members can still read messages but new messages are rejected.
+/// Connected members are informed by a `GroupUpdatedEvent`
+///
+/// **Notice**: User must be an owner of the group
+#[utoipa::path(
+  post,
+  path = "/groups/{group_id}/archive",
+  params(
+    (
+      "x-user-code" = String, Header, description = "user code for authentication",
+      example = "6C70F6E0A888C1360AD532C66D8F1CD0ED48C1CC47FA1AE6665B1FC3DAABB468"
+    ),
+    ("group_id" = u32, Path, description = "id of the group"),
+  ),
+  responses(
+      (status = 200, description = "Archive group successfully", body = CommonResponse<ArchiveGroupResponse>, content_type = "application/json"),
+      (status = 404, description = "Group not found"),
+      (status = 401, description = "The current user is not the owner of the group"),
+      (status = 500, description = "Database error")
+  ),
+)]
+pub async fn archive_group(
+  State(app_state): State<Arc<AppState>>,
+  UserToken(user_token): UserToken,
+  Path(group_id): Path<i32>,
+) -> Result<Json<CommonResponse<ArchiveGroupResponse>>, ApiError> {
+  set_group_archived(&app_state, user_token, group_id, true)
+}
+
+/// ### Handler for API `POST /groups/:group_id/unarchive`
+///
+/// Unarchive the group, members can send messages again.
+/// Connected members are informed by a `GroupUpdatedEvent`
+///
+/// **Notice**: User must be an owner of the group
+#[utoipa::path(
+  post,
+  path = "/groups/{group_id}/unarchive",
+  params(
+    (
+      "x-user-code" = String, Header, description = "user code for authentication",
+      example = "6C70F6E0A888C1360AD532C66D8F1CD0ED48C1CC47FA1AE6665B1FC3DAABB468"
+    ),
+    ("group_id" = u32, Path, description = "id of the group"),
+  ),
+  responses(
+      (status = 200, description = "Unarchive group successfully", body = CommonResponse<ArchiveGroupResponse>, content_type = "application/json"),
+      (status = 404, description = "Group not found"),
+      (status = 401, description = "The current user is not the owner of the group"),
+      (status = 500, description = "Database error")
+  ),
+)]
+pub async fn unarchive_group(
+  State(app_state): State<Arc<AppState>>,
+  UserToken(user_token): UserToken,
+  Path(group_id): Path<i32>,
+) -> Result<Json<CommonResponse<ArchiveGroupResponse>>, ApiError> {
+  set_group_archived(&app_state, user_token, group_id, false)
+}
+
+/// Archive or unarchive a group, shared by `/groups/:group_id/archive` and `/groups/:group_id/unarchive`
+fn set_group_archived(
+  app_state: &AppState,
+  user_token: Option<String>,
+  group_id: i32,
+  archived: bool,
+) -> Result<Json<CommonResponse<ArchiveGroupResponse>>, ApiError> {
+  let conn = &mut app_state
+    .db_pool
+    .get()
+    .map_err(|err| ApiError::DatabaseError(DBError::ConnectionError(err)))?;
+
+  if services::group::get_group_info(conn, group_id)
+    .map_err(ApiError::DatabaseError)?
+    .is_none()
+  {
+    return Err(ApiError::NotFound("Group not found".into()));
+  }
+  validate_owner_of_group(conn, &user_token, group_id)?;
+
+  let group = services::group::update_archived(conn, group_id, archived)
+    .map_err(ApiError::DatabaseError)?;
+
+  let _ = send_message_event_to_group(
+    conn,
+    SMessageType::GroupUpdatedEvent(GroupUpdatedData {
+      group_id,
+      group_name: group.name,
+      archived: group.archived,
+      post_policy: group.post_policy,
+    }),
+    group_id,
+  );
+
+  Ok(Json(CommonResponse::success(ArchiveGroupResponse {
+    group_id,
+    archived: group.archived,
+  })))
+}
+
+/// ### Handler for API `PATCH /groups/:group_id/pin`
+///
+/// Pin or unpin a group, pinned groups are listed first in the group list of the current user
+///
+/// **Notice**: User must be a member of the group
+#[utoipa::path(
+  patch,
+  path = "/groups/{group_id}/pin",
+  params(
+    (
+      "x-user-code" = String, Header, description = "user code for authentication",
+      example = "6C70F6E0A888C1360AD532C66D8F1CD0ED48C1CC47FA1AE6665B1FC3DAABB468"
+    ),
+    ("group_id" = u32, Path, description = "id of the group"),
+  ),
+  request_body = PinGroupRequest,
+  responses(
+      (status = 200, description = "Pin or unpin the group successfully", body = CommonResponse<PinGroupResponse>, content_type = "application/json"),
+      (status = 403, description = "The current user is not a member of the group"),
+      (status = 404, description = "User not found"),
+      (status = 500, description = "Database error")
+  ),
+)]
+pub async fn pin_group(
+  State(app_state): State<Arc<AppState>>,
+  UserToken(user_token): UserToken,
+  Path(group_id): Path<i32>,
+  Json(request): Json<PinGroupRequest>,
+) -> Result<Json<CommonResponse<PinGroupResponse>>, ApiError> {
+  let conn = &mut app_state
+    .db_pool
+    .get()
+    .map_err(|err| ApiError::DatabaseError(DBError::ConnectionError(err)))?;
+  let user = check_user_exists(conn, user_token).await?;
+
+  if !services::group::update_pinned(conn, user.id, group_id, request.pinned)
+    .map_err(ApiError::DatabaseError)?
+  {
+    return Err(ApiError::NotAMember(group_id));
+  }
+
+  Ok(Json(CommonResponse::success(PinGroupResponse {
+    group_id,
+    pinned: request.pinned,
+  })))
+}
+
+/// ### Handler for API `PATCH /groups/:group_id/slow-mode`
+///
+/// Set the minimum time between two messages of each member, the owner is not limited
+///
+/// **Notice**: User must be an owner of the group
+#[utoipa::path(
+  patch,
+  path = "/groups/{group_id}/slow-mode",
+  params(
+    (
+      "x-user-code" = String, Header, description = "user code for authentication",
+      example = "6C70F6E0A888C1360AD532C66D8F1CD0ED48C1CC47FA1AE6665B1FC3DAABB468"
+    ),
+    ("group_id" = u32, Path, description = "id of the group"),
+  ),
+  request_body = SlowModeRequest,
+  responses(
+      (status = 200, description = "Set slow mode successfully", body = CommonResponse<SlowModeResponse>, content_type = "application/json"),
+      (status = 400, description = "Slow mode interval is out of range"),
+      (status = 404, description = "Group not found"),
+      (status = 403, description = "The current user doesn't have permission to access the resource"),
+      (status = 401, description = "The current user doesn't have right to access the resource"),
+      (status = 500, description = "Database error")
+  ),
+)]
+pub async fn set_slow_mode(
+  State(app_state): State<Arc<AppState>>,
+  UserToken(user_token): UserToken,
+  Path(group_id): Path<i32>,
+  ValidatedJson(request): ValidatedJson<SlowModeRequest>,
+) -> Result<Json<CommonResponse<SlowModeResponse>>, ApiError> {
+  let conn = &mut app_state
+    .db_pool
+    .get()
+    .map_err(|err| ApiError::DatabaseError(DBError::ConnectionError(err)))?;
+
+  if services::group::get_group_info(conn, group_id)
+    .map_err(ApiError::DatabaseError)?
+    .is_none()
+  {
+    return Err(ApiError::NotFound("Group not found".into()));
+  }
+  validate_owner_of_group(conn, &user_token, group_id)?;
 
-    for (group_id, group_name, group_code, expired_at, created_at) in groups {
-        tracing::info!(
-            "Processing group: id = {}, name = {}, code = {}",
-            group_id, group_name, group_code
-        );
+  let slow_mode_seconds = request.slow_mode_seconds.filter(|seconds| *seconds > 0);
+  let group = services::group::update_slow_mode(conn, group_id, slow_mode_seconds)
+    .map_err(ApiError::DatabaseError)?;
 
-        use diesel::sql_types::{Nullable, Text, Timestamp};
+  Ok(Json(CommonResponse::success(SlowModeResponse {
+    group_id: group.id,
+    slow_mode_seconds: group.slow_mode_seconds,
+  })))
+}
 
-        let latest_message = messages::table
-            .inner_join(users::table.on(messages::user_id.eq(users::id)))
-            .filter(messages::group_id.eq(group_id))
-            .order(messages::created_at.desc())
-            .select((
-                sql::<Nullable<Text>>("messages.content"),
-                sql::<Timestamp>("messages.created_at"),
-                sql::<Nullable<Text>>("users.username"),
-            ))
-            .first::<(Option<String>, NaiveDateTime, Option<String>)>(conn)
-            .optional()
-            .map_err(|err| {
-                tracing::error!("Failed to get latest message for group_id {}: {:?}", group_id, err);
-                DBError::QueryError(format!("Error loading latest message: {:?}", err))
-            })?;
+/// ### Handler for API `PATCH /groups/:group_id/post-policy`
+///
+/// Set which members can send messages in the group, e.g. only the owner in an announcement
+/// group. Connected members are informed by a `GroupUpdatedEvent`
+///
+/// **Notice**: User must be an owner of the group
+#[utoipa::path(
+  patch,
+  path = "/groups/{group_id}/post-policy",
+  params(
+    (
+      "x-user-code" = String, Header, description = "user code for authentication",
+      example = "6C70F6E0A888C1360AD532C66D8F1CD0ED48C1CC47FA1AE6665B1FC3DAABB468"
+    ),
+    ("group_id" = u32, Path, description = "id of the group"),
+  ),
+  request_body = PostPolicyRequest,
+  responses(
+      (status = 200, description = "Set post policy successfully", body = CommonResponse<PostPolicyResponse>, content_type = "application/json"),
+      (status = 404, description = "Group not found"),
+      (status = 401, description = "The current user is not the owner of the group"),
+      (status = 500, description = "Database error")
+  ),
+)]
+pub async fn set_post_policy(
+  State(app_state): State<Arc<AppState>>,
+  UserToken(user_token): UserToken,
+  Path(group_id): Path<i32>,
+  Json(request): Json<PostPolicyRequest>,
+) -> Result<Json<CommonResponse<PostPolicyResponse>>, ApiError> {
+  let conn = &mut app_state
+    .db_pool
+    .get()
+    .map_err(|err| ApiError::DatabaseError(DBError::ConnectionError(err)))?;
 
-        let (latest_ms_content, latest_ms_time, latest_ms_username) = latest_message
-            .map(|(content, time, username)| {
-                (
-                    content.unwrap_or_default(),
-                    time,
-                    username.unwrap_or_default(),
-                )
-            })
-            .unwrap_or_default();
+  if services::group::get_group_info(conn, group_id)
+    .map_err(ApiError::DatabaseError)?
+    .is_none()
+  {
+    return Err(ApiError::NotFound("Group not found".into()));
+  }
+  validate_owner_of_group(conn, &user_token, group_id)?;
 
-        group_list.push(GroupInfo {
-            group_id,
-            group_name,
-            group_code,
-            expired_at: expired_at.unwrap_or_default().and_utc().to_rfc3339(),
-            latest_ms_content,
-            latest_ms_time: latest_ms_time.and_utc().to_rfc3339(),
-            latest_ms_username,
-            created_at: created_at.unwrap_or_default().and_utc().to_rfc3339(),
-        });
-    }
+  let group = services::group::update_post_policy(conn, group_id, request.post_policy)
+    .map_err(ApiError::DatabaseError)?;
 
-    // Sort groups by creation date (descending)
-    group_list.sort_by(|a, b| b.created_at.cmp(&a.created_at));
-    Ok(group_list)
+  let _ = send_message_event_to_group(
+    conn,
+    SMessageType::GroupUpdatedEvent(GroupUpdatedData {
+      group_id,
+      group_name: group.name,
+      archived: group.archived,
+      post_policy: group.post_policy,
+    }),
+    group_id,
+  );
+
+  Ok(Json(CommonResponse::success(PostPolicyResponse {
+    group_id,
+    post_policy: group.post_policy,
+  })))
 }
 
-
-
-/**
-   Create a new group with exists user by user_id
-*/
-pub async fn create_group_with_user(
+/// ### Handler for API `PATCH /groups/:group_id/moderation`
+///
+/// Set how messages containing words of the bad-word filter are handled in the group:
+/// `off`, `mask` the words before saving, or `reject` the messages.
+/// Moderation has no effect if the server has no word list
+///
+/// **Notice**: User must be an owner of the group
+#[utoipa::path(
+  patch,
+  path = "/groups/{group_id}/moderation",
+  params(
+    (
+      "x-user-code" = String, Header, description = "user code for authentication",
+      example = "6C70F6E0A888C1360AD532C66D8F1CD0ED48C1CC47FA1AE6665B1FC3DAABB468"
+    ),
+    ("group_id" = u32, Path, description = "id of the group"),
+  ),
+  request_body = ModerationRequest,
+  responses(
+      (status = 200, description = "Set moderation successfully", body = CommonResponse<ModerationResponse>, content_type = "application/json"),
+      (status = 404, description = "Group not found"),
+      (status = 401, description = "The current user is not the owner of the group"),
+      (status = 500, description = "Database error")
+  ),
+)]
+pub async fn set_moderation(
   State(app_state): State<Arc<AppState>>,
-  Json(new_group_req): Json<NewGroupWithUserIdRequest>,
-) -> Result<Json<CommonResponse<GroupResponse>>, DBError> {
-  tracing::debug!("POST: /create-group");
-  let conn = &mut app_state.db_pool.get().map_err(DBError::ConnectionError)?;
-
-  // Check if the user exists
-  let user_exists = users::table
-    .find(new_group_req.user_id)
-    .first::<models::User>(conn)
-    .optional()
-    .map_err(|err| {
-      tracing::error!(
-        "Error checking user_id {}: {:?}",
-        new_group_req.user_id,
-        err
-      );
-      DBError::QueryError("Error checking user".to_string())
-    })?;
+  UserToken(user_token): UserToken,
+  Path(group_id): Path<i32>,
+  Json(request): Json<ModerationRequest>,
+) -> Result<Json<CommonResponse<ModerationResponse>>, ApiError> {
+  let conn = &mut app_state
+    .db_pool
+    .get()
+    .map_err(|err| ApiError::DatabaseError(DBError::ConnectionError(err)))?;
 
-  if user_exists.is_none() {
-    return Ok(Json(CommonResponse::error(1, "User does not exist")));
+  if services::group::get_group_info(conn, group_id)
+    .map_err(ApiError::DatabaseError)?
+    .is_none()
+  {
+    return Err(ApiError::NotFound("Group not found".into()));
   }
+  validate_owner_of_group(conn, &user_token, group_id)?;
 
-  let current_time = Utc::now();
-  let expired_at = current_time + chrono::Duration::minutes(new_group_req.duration.into());
-
-  // Create the new group
-  let new_group = models::NewGroup {
-    name: &new_group_req.group_name,
-    group_code: &generate_secret_code(&new_group_req.group_name),
-    user_id: new_group_req.user_id,
-    approval_require: new_group_req.approval_require,
-    created_at: current_time.naive_utc(),
-    expired_at: expired_at.naive_utc(),
-    maximum_members: new_group_req.maximum_members,
-  };
+  let group = services::moderation::update_moderation(conn, group_id, request.moderation)
+    .map_err(ApiError::DatabaseError)?;
 
-  let group_result = diesel::insert_into(groups::table)
-    .values(&new_group)
-    .returning(models::Group::as_returning())
-    .get_result::<models::Group>(conn)
-    .map_err(|err| {
-      tracing::error!("Error inserting group: {:?}", err);
-      DBError::QueryError("Error inserting group".to_string())
-    })?;
+  Ok(Json(CommonResponse::success(ModerationResponse {
+    group_id,
+    moderation: group.moderation,
+  })))
+}
 
-  // Insert into participants table
-  diesel::insert_into(participants::table)
-    .values((
-      participants::user_id.eq(new_group_req.user_id),
-      participants::group_id.eq(group_result.id),
-    ))
-    .execute(conn)
-    .map_err(|err| {
-      tracing::error!("Error inserting into participants: {:?}", err);
-      DBError::QueryError("Error inserting into participants".to_string())
-    })?;
+/// ### Handler for API `PATCH /groups/:group_id/max-message-length`
+///
+/// Set the maximum number of characters of a message content in the group,
+/// `null` falls back to the global limit
+///
+/// **Notice**: User must be an owner of the group
+#[utoipa::path(
+  patch,
+  path = "/groups/{group_id}/max-message-length",
+  params(
+    (
+      "x-user-code" = String, Header, description = "user code for authentication",
+      example = "6C70F6E0A888C1360AD532C66D8F1CD0ED48C1CC47FA1AE6665B1FC3DAABB468"
+    ),
+    ("group_id" = u32, Path, description = "id of the group"),
+  ),
+  request_body = MaxMessageLengthRequest,
+  responses(
+      (status = 200, description = "Set max message length successfully", body = CommonResponse<MaxMessageLengthResponse>, content_type = "application/json"),
+      (status = 400, description = "Max message length is out of range"),
+      (status = 404, description = "Group not found"),
+      (status = 403, description = "The current user doesn't have permission to access the resource"),
+      (status = 500, description = "Database error")
+  ),
+)]
+pub async fn set_max_message_length(
+  State(app_state): State<Arc<AppState>>,
+  UserToken(user_token): UserToken,
+  Path(group_id): Path<i32>,
+  ValidatedJson(request): ValidatedJson<MaxMessageLengthRequest>,
+) -> Result<Json<CommonResponse<MaxMessageLengthResponse>>, ApiError> {
+  let conn = &mut app_state
+    .db_pool
+    .get()
+    .map_err(|err| ApiError::DatabaseError(DBError::ConnectionError(err)))?;
 
-  // Prepare the response
-  let group_response = GroupResponse {
-    group_id: group_result.id,
-    group_name: group_result.name,
-    group_code: group_result.group_code,
-    expired_at: group_result.expired_at.unwrap().and_utc().to_string(),
-  };
+  if services::group::get_group_info(conn, group_id)
+    .map_err(ApiError::DatabaseError)?
+    .is_none()
+  {
+    return Err(ApiError::NotFound("Group not found".into()));
+  }
+  validate_owner_of_group(conn, &user_token, group_id)?;
 
-  Ok(Json(CommonResponse::success(group_response)))
+  let group =
+    services::group::update_max_message_length(conn, group_id, request.max_message_length)
+      .map_err(ApiError::DatabaseError)?;
+
+  Ok(Json(CommonResponse::success(MaxMessageLengthResponse {
+    group_id: group.id,
+    max_message_length: group.max_message_length,
+  })))
 }
 
-///### Validate user is an owner of the group_id or not
+/// ### Handler for API `POST /groups/summaries`
 ///
-/// If user is not an owner an api error will be propagated
-fn validate_owner_of_group(
-  conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
-  user_token: &Option<String>,
-  group_id: i32,
-) -> Result<(), ApiError> {
-  if user_token.is_none() {
-    return Err(ApiError::Forbidden);
-  }
-  let current_user = get_user_by_code(conn, user_token.as_ref().unwrap())
-    .map_err(|_| ApiError::new_database_query_err("Unable to get current user from database"))?;
-
-  if current_user.is_none() {
-    return Err(ApiError::Forbidden);
-  }
-  let User { id: user_id, .. } = current_user.unwrap();
+/// Get member count, unread count and last activity of several groups at once,
+/// only groups which the current user joined are returned
+#[utoipa::path(
+  post,
+  path = "/groups/summaries",
+  params(
+    (
+      "x-user-code" = String, Header, description = "user code for authentication",
+      example = "6C70F6E0A888C1360AD532C66D8F1CD0ED48C1CC47FA1AE6665B1FC3DAABB468"
+    ),
+  ),
+  request_body = GroupSummariesRequest,
+  responses(
+      (status = 200, description = "Get group summaries successfully",
+      body = GroupSummariesResponse, content_type = "application/json",
+        example = json!(
+            {
+              "summaries": {
+                "24": {
+                  "member_count": 3,
+                  "unread_count": 5,
+                  "last_activity": "2024-12-08T07:34:57.120623+00:00",
+                  "storage_bytes": 48213
+                },
+                "25": {
+                  "member_count": 1,
+                  "unread_count": 0,
+                  "last_activity": null,
+                  "storage_bytes": 0
+                }
+              }
+            }
+        )),
+      (status = 400, description = "The list of group ids is empty or too long"),
+      (status = 403, description = "The current user doesn't have permission to access the resource"),
+      (status = 404, description = "User not found"),
+      (status = 500, description = "Database error")
+  ),
+)]
+pub async fn get_group_summaries(
+  State(app_state): State<Arc<AppState>>,
+  UserToken(user_token): UserToken,
+  ValidatedJson(request): ValidatedJson<GroupSummariesRequest>,
+) -> Result<Json<GroupSummariesResponse>, ApiError> {
+  let conn = &mut app_state
+    .db_pool
+    .get()
+    .map_err(|err| ApiError::DatabaseError(DBError::ConnectionError(err)))?;
+  let user = check_user_exists(conn, user_token).await?;
 
-  if !check_owner_of_group(conn, user_id, group_id)
-    .map_err(|_| ApiError::new_database_query_err("Failed to check owner of group"))?
-  {
-    return Err(ApiError::Unauthorized);
-  }
-  Ok(())
+  let summaries = services::group::get_group_summaries(conn, user.id, &request.group_ids)
+    .map_err(ApiError::DatabaseError)?;
+  Ok(Json(GroupSummariesResponse { summaries }))
 }
 
 /// ### Handler for API `/group/:group_id/waiting-list`
@@ -795,7 +2080,7 @@ pub async fn get_waiting_list(
   responses(
       (status = 200, description = "Processes waiting list item successfully"),
       (status = 404, description = "Not found joining request"),
-      (status = 403, description = "The current user doesn't have permission to access the resource"),
+      (status = 403, description = "The current user doesn't have permission to access the resource or the requesting user is banned from the group"),
       (status = 401, description = "The current user doesn't have right to access the resource"),
       (status = 500, description = "Database error")
   ),
@@ -817,6 +2102,14 @@ pub async fn process_joining_request(
     .ok_or(ApiError::NotFound("Not found joining request".into()))?;
   
   validate_owner_of_group(conn, &user_token, join_request.group_id)?;
+  if process_form.is_approved {
+    if let Some(banned_until) =
+      services::group::get_active_ban(conn, join_request.group_id, join_request.user_id)
+        .map_err(ApiError::DatabaseError)?
+    {
+      return Err(ApiError::BannedFromGroup(banned_until.and_utc().to_rfc3339()));
+    }
+  }
   
   services::group::process_joining_request(conn, join_request, process_form.is_approved)
   .map_err(|_|ApiError::new_database_query_err("Unable to process joining request"))?;
@@ -882,6 +2175,10 @@ pub async fn del_gr_req(
             return Err(ApiError::Unauthorized);
         }
 
+        // Capture members before their participant rows are deleted, they are informed afterward
+        let member_ids = services::user::get_user_ids_from_group(conn, req.gr_id)
+            .map_err(|_| ApiError::new_database_query_err("Failed to get members of group"))?;
+
         // Step 1: Delete attachments linked to messages in this group
         diesel::delete(attachments::table.filter(
             attachments::message_id.eq_any(
@@ -936,6 +2233,10 @@ pub async fn del_gr_req(
                 ApiError::DatabaseError(DBError::QueryError("Failed to delete group".to_string()))
             })?;
 
+        let event = SMessageType::GroupDeletedEvent(GroupData { group_id: req.gr_id });
+        for member_id in member_ids {
+            send_message_event_to_user(member_id, event.clone());
+        }
 
         // Return successful deletion response
         let response = DelGroupResponse {
@@ -975,7 +2276,7 @@ pub async fn del_gr_req(
   ),
   responses(
       (status = 200, description = "Get group detail successfully", body = GroupDetailResponse, content_type = "application/json"),
-      (status = 401, description = "The current user doesn't have right to access the resource"),
+      (status = 403, description = "The current user is not a member of the group"),
       (status = 404, description = "User not found"),
       (status = 500, description = "Database error")
   ),
@@ -995,7 +2296,7 @@ pub async fn get_group_detail_with_extra_info(
   if !services::group::check_user_join_group(conn, user.id, group_id)
     .map_err(|_err| ApiError::new_database_query_err("Failed to check user joined group"))?
   {
-    return Err(ApiError::Unauthorized);
+    return Err(ApiError::NotAMember(group_id));
   }
 
   let group_info = services::group::get_group_info(conn, group_id)
@@ -1023,6 +2324,10 @@ pub async fn get_group_detail_with_extra_info(
   let latest_message = services::message::get_latest_messages_from_group(conn, group_id)
     .map_err(ApiError::DatabaseError)?;
 
+  let draft = services::draft::get_draft(conn, user.id, group_id)
+    .map_err(ApiError::DatabaseError)?
+    .map(DraftResponse::from);
+
   // Build response with max_member included
   let response = GroupDetailResponse {
     group_name,
@@ -1030,9 +2335,10 @@ pub async fn get_group_detail_with_extra_info(
     max_member: max_member.unwrap_or_default(), // Use default if max_member is None
     joined_member: joined_member as i32,
     waiting_member: waiting_member as i32,
-    created_at: created_at.map(|dt| dt.and_utc().to_rfc3339()).unwrap_or_default(),
-    expired_at: expired_at.map(|dt| dt.and_utc().to_rfc3339()).unwrap_or_default(),
+    created_at: created_at.map(|dt| dt.and_utc()),
+    expired_at: expired_at.map(|dt| dt.and_utc()),
     messages: latest_message,
+    draft,
   };
 
   Ok(Json(response))
@@ -1044,6 +2350,10 @@ pub async fn get_group_detail_with_extra_info(
   path = "/group-detail/setting/{gr_id}",
   params(
   ("gr_id" = i32, Path, description = "id of the group"),
+  ("joined_page" = Option<u16>, Query, description = "page index of joined members"),
+  ("joined_limit" = Option<u32>, Query, description = "the number of joined members per a page"),
+  ("waiting_page" = Option<u16>, Query, description = "page index of waiting members"),
+  ("waiting_limit" = Option<u32>, Query, description = "the number of waiting members per a page"),
   ),
   responses(
       (status = 200, description = "Get Group Detail Setting successfully", body = CommonResponse<GrDetailSettingResponse>),
@@ -1058,6 +2368,7 @@ pub async fn get_group_detail_with_extra_info(
 pub async fn get_gr_setting_v1(
     State(app_state): State<Arc<AppState>>,
     Path(gr_id): Path<i32>,
+    Query(page_request): Query<GrSettingPageRequest>,
 ) -> Result<Json<CommonResponse<GrDetailSettingResponse>>, ApiError> {
     let conn = &mut app_state
         .db_pool
@@ -1087,10 +2398,15 @@ pub async fn get_gr_setting_v1(
                 ApiError::DatabaseError(DBError::QueryError("Failed to count joined members".to_string()))
             })? as i32;
 
-        // Query to get list of joined members
+        // Query to get current page of joined members
+        let joined_page_request = page_request.joined();
+        let (joined_offset, joined_limit) = joined_page_request.get_offset_and_limit();
         let list_joined_member: Vec<UserSettingInfo> = participants::table
             .inner_join(users::table.on(users::id.eq(participants::user_id)))
             .filter(participants::group_id.eq(gr_id))
+            .order(users::id.asc())
+            .offset(joined_offset as i64)
+            .limit(joined_limit)
             .select((users::id, users::username, users::user_code))
             .load::<(i32, String, String)>(conn)
             .map_err(|err| {
@@ -1115,10 +2431,15 @@ pub async fn get_gr_setting_v1(
                 ApiError::DatabaseError(DBError::QueryError("Failed to count waiting members".to_string()))
             })? as i32;
 
-        // Query to get list of waiting members
+        // Query to get current page of waiting members
+        let waiting_page_request = page_request.waiting();
+        let (waiting_offset, waiting_limit) = waiting_page_request.get_offset_and_limit();
         let list_waiting_member: Vec<UserSettingInfo> = waiting_list::table
             .inner_join(users::table.on(users::id.eq(waiting_list::user_id)))
             .filter(waiting_list::group_id.eq(gr_id))
+            .order(waiting_list::id.asc())
+            .offset(waiting_offset as i64)
+            .limit(waiting_limit)
             .select((users::id, users::username, users::user_code))
             .load::<(i32, String, String)>(conn)
             .map_err(|err| {
@@ -1138,12 +2459,14 @@ pub async fn get_gr_setting_v1(
             owner_id: group.user_id,
             group_name: group.name,
             group_code: group.group_code,
-            expired_at: group.expired_at.map_or("N/A".to_string(), |ts| ts.and_utc().to_rfc3339()),
-            created_at: group.created_at.map_or("N/A".to_string(), |ts| ts.and_utc().to_rfc3339()),
+            expired_at: group.expired_at.map(|ts| ts.and_utc()),
+            created_at: group.created_at.map(|ts| ts.and_utc()),
             maximum_members: group.maximum_members.unwrap_or_default(),
             total_joined_member,
+            joined_page: joined_page_request.get_page(),
             list_joined_member,
             total_waiting_member,
+            waiting_page: waiting_page_request.get_page(),
             list_waiting_member,
         };
 
@@ -1160,6 +2483,7 @@ pub async fn get_gr_setting_v1(
     request_body = RmUserRequest,
     responses(
         (status = 200, description = "Group deleted successfully", body = RmUserResponse),
+        (status = 400, description = "The owner cannot be removed from the group", body = RmUserResponse),
         (status = 404, description = "User or group not found", body = RmUserResponse),
         (status = 401, description = "User not authorized to delete this group", body = RmUserResponse),
         (status = 500, description = "Database error", body = RmUserResponse)
@@ -1193,9 +2517,9 @@ pub async fn rm_user_from_gr(
         })?;
 
     // Return error if group does not exist
-    if group.is_none() {
+    let Some(group) = group else {
         return Err(ApiError::NotFound("Group not found".to_string()));
-    }
+    };
 
     // Check if the requesting user is the group owner
     if !check_owner_of_group(conn, req.gr_owner_id, req.gr_id)
@@ -1204,6 +2528,18 @@ pub async fn rm_user_from_gr(
         return Err(ApiError::Unauthorized);
     }
 
+    // The owner must stay a participant, the group should be deleted instead
+    if req.rm_user_id == group.user_id {
+        return Err(ApiError::BadRequest(
+            "The owner cannot be removed from the group, delete the group instead".to_string(),
+        ));
+    }
+    let mut errors = Vec::new();
+    validate_ban_minutes(&mut errors, req.ban_minutes);
+    if !errors.is_empty() {
+        return Err(ApiError::ValidationFailed(errors));
+    }
+
     use schema::participants::dsl::{participants, user_id, group_id};
     let delete_result = diesel::delete(participants.filter(user_id.eq(req.rm_user_id)).filter(group_id.eq(req.gr_id)))
         .execute(conn)
@@ -1217,6 +2553,21 @@ pub async fn rm_user_from_gr(
         return Err(ApiError::NotFound("User not found in the specified group".to_string()));
     }
 
+    if let Some(ban_minutes) = req.ban_minutes {
+        let banned_until = Utc::now().naive_utc() + chrono::Duration::minutes(ban_minutes.into());
+        services::group::ban_users(conn, req.gr_id, &[req.rm_user_id], banned_until)
+            .map_err(ApiError::DatabaseError)?;
+    }
+
+    // The removed user stops receiving events of the group, its clients drop the group
+    revoke_group_members(req.gr_id, &[req.rm_user_id]);
+    let event = SMessageType::MemberLeftEvent(MemberLeftData {
+        group_id: req.gr_id,
+        user_id: req.rm_user_id,
+    });
+    let _ = send_message_event_to_group(conn, event.clone(), req.gr_id);
+    send_message_event_to_user(req.rm_user_id, event);
+
     // Return success response
     Ok(Json(RmUserResponse {
         res_code: 200,
@@ -1231,6 +2582,7 @@ pub async fn rm_user_from_gr(
     request_body = LeaveGroupRequest,
     responses(
         (status = 200, description = "Group deleted successfully", body = LeaveGroupResponse),
+        (status = 400, description = "The owner cannot leave the group", body = LeaveGroupResponse),
         (status = 404, description = "User or group not found", body = LeaveGroupResponse),
         (status = 500, description = "Database error", body = LeaveGroupResponse)
     ),
@@ -1250,35 +2602,29 @@ pub async fn user_leave_gr(
         .get()
         .map_err(|err| ApiError::DatabaseError(DBError::ConnectionError(err)))?;
 
-    // Check if the group exists
-    use schema::groups::dsl::groups;
-    let group = groups
-        .find(req.gr_id)
-        .select(Group::as_select()) // Explicitly selecting the fields
-        .first::<Group>(conn)
-        .optional()
-        .map_err(|err| {
-            tracing::debug!("Error checking group_id {}: {:?}", req.gr_id, err);
-            ApiError::DatabaseError(DBError::QueryError("Error checking group existence".to_string()))
-        })?;
-
-    // Return error if group does not exist
-    if group.is_none() {
-        return Err(ApiError::NotFound("Group not found".to_string()));
+    match services::group::leave_group(conn, req.u_id, req.gr_id).map_err(ApiError::DatabaseError)? {
+        LeaveGroupOutcome::Left => {}
+        LeaveGroupOutcome::GroupNotFound => {
+            return Err(ApiError::NotFound("Group not found".to_string()));
+        }
+        LeaveGroupOutcome::IsOwner => {
+            return Err(ApiError::BadRequest(
+                "The owner cannot leave the group, delete the group instead".to_string(),
+            ));
+        }
+        LeaveGroupOutcome::NotAMember => {
+            return Err(ApiError::NotFound("User not found in the specified group".to_string()));
+        }
     }
 
-    use schema::participants::dsl::{participants, user_id, group_id};
-    let delete_result = diesel::delete(participants.filter(user_id.eq(req.u_id)).filter(group_id.eq(req.gr_id)))
-        .execute(conn)
-        .map_err(|err| {
-            tracing::debug!("Error removing user {} from group {}: {:?}", req.u_id, req.gr_id, err);
-            ApiError::DatabaseError(DBError::QueryError("Error removing user from group".to_string()))
-        })?;
-
-    // If no rows were deleted, the user was not part of the group
-    if delete_result == 0 {
-        return Err(ApiError::NotFound("User not found in the specified group".to_string()));
-    }
+    // The user stops receiving events of the group, its other clients drop the group
+    revoke_group_members(req.gr_id, &[req.u_id]);
+    let event = SMessageType::MemberLeftEvent(MemberLeftData {
+        group_id: req.gr_id,
+        user_id: req.u_id,
+    });
+    let _ = send_message_event_to_group(conn, event.clone(), req.gr_id);
+    send_message_event_to_user(req.u_id, event);
 
     // Return success response
     Ok(Json(LeaveGroupResponse {
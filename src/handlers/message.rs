@@ -1,19 +1,26 @@
 use crate::database::models::{ MessageStatus, MessageTypeEnum, NewMessage};
 use crate::errors::{ApiError, DBError};
-use crate::extractors::UserToken;
+use crate::extractors::{UserToken, ValidatedJson};
 use crate::payloads::common::{ListResponse, PageRequest, OrderBy};
-use crate::payloads::messages::{ AttachmentPayload, MessageFilterParams, MessageResponse, MessageSortParams, MessageWithUser, UpdateMessage};
+use crate::handlers::socket::connections::{send_message_event_to_group, send_message_event_to_user};
+use crate::payloads::messages::{ AttachmentPayload, MessageContextQuery, MessageContextResponse, MessageFilterParams, MessageIdItem, MessageIdsQuery, MessageIdsResponse, MessageResponse, MessageSortParams, MessageReportResponse, MessageWithUser, PinMessageRequest, PinMessageResponse, ReadAllResponse, ReportMessageRequest, TranslateQuery, TranslationResponse, UpdateMessage};
+use crate::payloads::socket::message::{LastSeenData, PinEventData, SMessageType};
 use crate::payloads::messages::{SendMessageRequest, SendMessageResponse};
 use crate::utils::minors::calculate_total_pages;
-use crate::{services, AppState};
+use crate::utils::validation::{FieldError, Validate};
+use crate::services::moderation::ModerationOutcome;
+use crate::services::translation::TranslationError;
+use crate::{services, AppState, DEFAULT_MESSAGE_CONTEXT_RADIUS, MAX_MESSAGE_CONTEXT_RADIUS, MAX_MESSAGE_IDS_LIMIT};
 use axum::body::Body;
 use axum::extract::{Path, Query};
 use axum::http::StatusCode;
+use axum::response::Response;
 use axum::{extract::State, Json};
 use chrono::Utc;
 use std::sync::Arc;
+use uuid::Uuid;
 
-use super::common::check_user_exists;
+use super::common::{check_user_exists, stream_json_array};
 
 /// ### Handler for API POST `/messages`
 ///
@@ -51,16 +58,18 @@ use super::common::check_user_exists;
     )
   ),
   responses(
-      (status = 200, description = "Send a message successfully", body = SendMessageResponse, content_type = "application/json"),
-      (status = 401, description = "The current user doesn't have right to access the resource"),
+      (status = 200, description = "Send a message successfully, the response has the message uuid generated by the server when the request has none", body = SendMessageResponse, content_type = "application/json"),
+      (status = 400, description = "The request has invalid fields, e.g. the content is longer than the maximum message length of the group, or the message uuid is nil or already used. Also returned when the moderation of the group rejects the content"),
+      (status = 403, description = "The current user is not a member of the group, the group is archived or its post policy doesn't allow the user to send messages"),
       (status = 404, description = "User not found"),
+      (status = 429, description = "Slow mode is enabled in the group, retry after the number of seconds in the `Retry-After` header"),
       (status = 500, description = "Database error")
   ),
 )]
 pub async fn send_msg(
   State(app_state): State<Arc<AppState>>,
   UserToken(user_token): UserToken,
-  Json(msg_request): Json<SendMessageRequest>,
+  ValidatedJson(msg_request): ValidatedJson<SendMessageRequest>,
 ) -> Result<Json<SendMessageResponse>, ApiError> {
   let conn = &mut app_state
     .db_pool
@@ -71,30 +80,86 @@ pub async fn send_msg(
   if !services::group::check_user_join_group(conn, user.id, msg_request.group_id)
     .map_err(|_err| ApiError::new_database_query_err("Failed to check user joined group"))?
   {
-    return Err(ApiError::Unauthorized);
+    return Err(ApiError::NotAMember(msg_request.group_id));
+  }
+  if services::group::is_group_archived(conn, msg_request.group_id)
+    .map_err(ApiError::DatabaseError)?
+  {
+    return Err(ApiError::GroupArchived);
+  }
+  if !services::group::can_post_in_group(conn, user.id, msg_request.group_id)
+    .map_err(ApiError::DatabaseError)?
+  {
+    return Err(ApiError::PostNotAllowed);
+  }
+  if let Some(remaining) =
+    services::group::get_slow_mode_remaining(conn, user.id, msg_request.group_id)
+      .map_err(ApiError::DatabaseError)?
+  {
+    return Err(ApiError::TooManyRequests(remaining as u64));
+  }
+  if let Some(content) = &msg_request.content {
+    let max_message_length =
+      services::group::get_max_message_length(conn, msg_request.group_id)
+        .map_err(ApiError::DatabaseError)?;
+    if content.chars().count() > max_message_length {
+      return Err(ApiError::ValidationFailed(vec![FieldError::new(
+        "content",
+        &format!("must be at most {} characters in the group", max_message_length),
+      )]));
+    }
+  }
+  let masked_content = match services::moderation::moderate_content(
+    conn,
+    app_state.word_filter.as_ref(),
+    msg_request.group_id,
+    msg_request.content.as_deref(),
+  )
+  .map_err(ApiError::DatabaseError)?
+  {
+    ModerationOutcome::Allowed => None,
+    ModerationOutcome::Masked(masked) => Some(masked),
+    ModerationOutcome::Rejected => return Err(ApiError::ContentRejected),
+  };
+  // only client supplied uuids can be duplicated, e.g. when a request is retried
+  if let Some(message_uuid) = msg_request.message_uuid {
+    if services::message::check_message_uuid_exists(conn, message_uuid)
+      .map_err(ApiError::DatabaseError)?
+    {
+      return Err(ApiError::BadRequest(format!("Message {} already exists", message_uuid)));
+    }
+  }
+  if let Some(reply_to) = msg_request.reply_to {
+    if !services::message::check_message_in_group(conn, reply_to, msg_request.group_id)
+      .map_err(ApiError::DatabaseError)?
+    {
+      return Err(ApiError::BadRequest(format!(
+        "Message {} does not exist in the group",
+        reply_to
+      )));
+    }
   }
 
   // Insert the text message into `messages`
   let new_message = NewMessage {
-    message_uuid: msg_request.message_uuid,
-    content: msg_request.content.as_ref(), // Convert String to &str
+    message_uuid: msg_request.message_uuid.unwrap_or_else(Uuid::new_v4),
+    content: masked_content.as_ref().or(msg_request.content.as_ref()),
     message_type: msg_request.message_type,
     status: MessageStatus::Sent,
     created_at: Utc::now().naive_utc(),
     user_id: user.id,
     group_id: msg_request.group_id,
+    reply_to: msg_request.reply_to,
   };
 
-  let inserted_message = services::message::create_new_message(conn, new_message)
-    .map_err(|_| ApiError::new_database_query_err("Failed to insert new message"))?;
-  let message_id = inserted_message.id;
+  // Insert the message and its attachments at once
+  let attachments = msg_request.attachments.as_deref().unwrap_or_default();
+  let (inserted_message, inserted_attachments) =
+    services::message::create_message_with_attachments(conn, new_message, attachments)
+      .map_err(ApiError::DatabaseError)?;
   let mut response = SendMessageResponse::from(inserted_message);
-  // Insert attachment if the message payload has attachments
-  if let Some(attachments) = msg_request.attachments {
-    let new_attachments = attachments.iter()
-    .map(|e|AttachmentPayload::into_new(e, message_id)).collect();
-    let inserted_attachments = services::attachment::create_attachments(conn, new_attachments).map_err(ApiError::DatabaseError)?;
-    response.set_attachment(inserted_attachments.iter().map(|e| AttachmentPayload::from(e.clone())).collect());
+  if msg_request.attachments.is_some() {
+    response.set_attachment(inserted_attachments.into_iter().map(AttachmentPayload::from).collect());
   }
   // Prepare the response
   Ok(Json(response))
@@ -115,6 +180,7 @@ pub async fn send_msg(
     ("status" = Option<MessageStatus>, Query,description = "message status filter"),
     ("from_date" = Option<String>, Query, description = "from created date filter"),
     ("to_date" = Option<String>, Query, description = "to created date filter"),
+    ("has_attachments" = Option<bool>, Query, description = "true for only messages with attachments, false for only messages without attachments"),
     ("created_at_sort" = Option<OrderBy>, Query, description = "created at sort by ASC or DESC"),
     ("page" = Option<u32>, Query, description = "page index" ),
     ("limit" = Option<u32>, Query, description = "the number of items per a page")
@@ -133,6 +199,7 @@ pub async fn send_msg(
                     "content": "This is test message 1",
                     "message_type": "TEXT",
                     "attachments": [],
+                    "has_more_attachments": false,
                     "status": "Sent",
                     "created_at": "2012-12-12 12:12:12",
                     "user_id": 44,
@@ -155,6 +222,7 @@ pub async fn send_msg(
                         "attachment_type": "IMAGE"
                       }
                     ],
+                    "has_more_attachments": false,
                     "status": "Sent",
                     "created_at": "2024-12-08T07:34:57.120623+00:00",
                     "updated_at": null,
@@ -167,18 +235,26 @@ pub async fn send_msg(
                     "content": "This is update message 3",
                     "message_type": "TEXT",
                     "attachments": [],
+                    "has_more_attachments": false,
                     "status": "Sent",
                     "created_at": "2024-11-16T06:51:52.784529+00:00",
                     "updated_at": "2024-11-16T06:59:47.420978+00:00",
                     "user_id": 1,
-                    "user_name": "linhnguyen"
+                    "user_name": "linhnguyen",
+                    "reply_to": 1,
+                    "quoted": {
+                      "message_id": 1,
+                      "user_id": 44,
+                      "user_name": "Linus Torvalds",
+                      "content": "This is test message 1",
+                      "message_type": "TEXT"
+                    }
                   },
                 ]
               }
               
         )),
-      (status = 403, description = "The current user doesn't have permission to access the resource"),
-      (status = 401, description = "The current user doesn't have right to access the resource"),
+      (status = 403, description = "The current user is not a member of the group"),
       (status = 500, description = "Database error")
   ),
 )]
@@ -199,14 +275,23 @@ pub async fn get_messages(
   if !services::group::check_user_join_group(conn, user.id, group_id)
     .map_err(|_err| ApiError::new_database_query_err("Failed to check user joined group"))?
   {
-    return Err(ApiError::Unauthorized);
+    return Err(ApiError::NotAMember(group_id));
   }
   // Query the latest messages using group_code
-  let messages =
-    services::message::get_messages(conn, group_id, &page_request, &message_filters, message_sorts)
-      .map_err(ApiError::DatabaseError)?;
-  
-  let message_count = services::message::get_count_messages(conn, group_id, message_filters).map_err(ApiError::DatabaseError)?;
+  let (messages, message_count) =
+    services::db::with_retry(conn, &app_state.db_pool, &app_state.db_retry, |conn| {
+      let messages = services::message::get_messages(
+        conn,
+        group_id,
+        &page_request,
+        &message_filters,
+        &message_sorts,
+      )?;
+      let message_count = services::message::get_count_messages(conn, group_id, &message_filters)?;
+      Ok((messages, message_count))
+    })
+    .await
+    .map_err(ApiError::DatabaseError)?;
   let total_pages = calculate_total_pages(message_count as u64, page_request.get_per_page() as u64) as u16;
   let list_response = ListResponse {
     count: messages.len() as i32,
@@ -217,6 +302,347 @@ pub async fn get_messages(
 }
 
 
+/// ### Handler for GET /messages/:message_id/attachments
+///
+/// Get a page of attachments of a message, message lists only return the first
+/// attachments of each message
+#[utoipa::path(
+  get,
+  path = "/messages/{message_id}/attachments",
+  params(
+    (
+      "x-user-code" = String, Header, description = "user code for authentication",
+      example = "6C70F6E0A888C1360AD532C66D8F1CD0ED48C1CC47FA1AE6665B1FC3DAABB468"
+    ),
+    ("message_id" = u32, Path, description = "id of the message"),
+    ("page" = Option<u32>, Query, description = "page index" ),
+    ("limit" = Option<u32>, Query, description = "the number of items per a page")
+  ),
+  responses(
+      (status = 200, description = "Get attachments of message successfully",
+      body = ListResponse<AttachmentPayload>, content_type = "application/json"),
+      (status = 404, description = "Message not found"),
+      (status = 403, description = "The current user is not a member of the group"),
+      (status = 500, description = "Database error")
+  ),
+)]
+pub async fn get_message_attachments(
+  State(app_state): State<Arc<AppState>>,
+  Path(message_id): Path<i32>,
+  UserToken(user_token): UserToken,
+  Query(page_request): Query<PageRequest>,
+) -> Result<ListResponse<AttachmentPayload>, ApiError> {
+  let conn = &mut app_state
+    .db_pool
+    .get()
+    .map_err(|err| ApiError::DatabaseError(DBError::ConnectionError(err)))?;
+  let user = check_user_exists(conn, user_token).await?;
+
+  let message = services::message::get_message(conn, message_id)
+    .map_err(ApiError::DatabaseError)?
+    .ok_or(ApiError::NotFound("Message".into()))?;
+
+  if !services::group::check_user_join_group(conn, user.id, message.group_id)
+    .map_err(|_err| ApiError::new_database_query_err("Failed to check user joined group"))?
+  {
+    return Err(ApiError::NotAMember(message.group_id));
+  }
+
+  let attachments =
+    services::attachment::get_attachments_of_message(conn, message_id, &page_request)
+      .map_err(ApiError::DatabaseError)?;
+  let attachment_count = services::attachment::get_count_attachments_of_message(conn, message_id)
+    .map_err(ApiError::DatabaseError)?;
+  let total_pages =
+    calculate_total_pages(attachment_count as u64, page_request.get_per_page() as u64) as u16;
+  Ok(ListResponse {
+    count: attachments.len() as i32,
+    objects: attachments.into_iter().map(AttachmentPayload::from).collect(),
+    total_pages,
+  })
+}
+
+/// ### Handler for GET /messages/:message_id/translate
+///
+/// Translate the content of a message by the configured translation provider,
+/// translations are cached until the message is edited
+///
+/// **Notice**: Only available if translation is enabled by `TRANSLATION_ENABLED`
+#[utoipa::path(
+  get,
+  path = "/messages/{message_id}/translate",
+  params(
+    (
+      "x-user-code" = String, Header, description = "user code for authentication",
+      example = "6C70F6E0A888C1360AD532C66D8F1CD0ED48C1CC47FA1AE6665B1FC3DAABB468"
+    ),
+    ("message_id" = u32, Path, description = "id of the message"),
+    ("lang" = String, Query, description = "language tag of the target language, e.g. en or pt-BR"),
+  ),
+  responses(
+      (status = 200, description = "Translate message successfully", body = TranslationResponse, content_type = "application/json"),
+      (status = 400, description = "The language tag is invalid or not supported"),
+      (status = 403, description = "The current user is not a member of the group"),
+      (status = 404, description = "Message not found or translation is disabled"),
+      (status = 500, description = "Database error")
+  ),
+)]
+pub async fn translate_message(
+  State(app_state): State<Arc<AppState>>,
+  Path(message_id): Path<i32>,
+  UserToken(user_token): UserToken,
+  Query(query): Query<TranslateQuery>,
+) -> Result<Json<TranslationResponse>, ApiError> {
+  if app_state.translation_provider.is_none() {
+    return Err(ApiError::NotFound("Translation is disabled".into()));
+  }
+  query.validate().map_err(ApiError::ValidationFailed)?;
+  let conn = &mut app_state
+    .db_pool
+    .get()
+    .map_err(|err| ApiError::DatabaseError(DBError::ConnectionError(err)))?;
+  let user = check_user_exists(conn, user_token).await?;
+
+  let message = services::message::get_message(conn, message_id)
+    .map_err(ApiError::DatabaseError)?
+    .ok_or(ApiError::NotFound("Message".into()))?;
+  if !services::group::check_user_join_group(conn, user.id, message.group_id)
+    .map_err(|_err| ApiError::new_database_query_err("Failed to check user joined group"))?
+  {
+    return Err(ApiError::NotAMember(message.group_id));
+  }
+
+  // a cached translation is stale once the message was edited after it
+  let last_changed_at = message.updated_at.unwrap_or(message.created_at);
+  if let Some(translation) =
+    services::translation::get_cached_translation(conn, message_id, &query.lang)
+      .map_err(ApiError::DatabaseError)?
+      .filter(|translation| translation.created_at >= last_changed_at)
+  {
+    return Ok(Json(TranslationResponse {
+      message_id,
+      lang: translation.lang,
+      content: translation.content,
+      cached: true,
+    }));
+  }
+
+  let content = message.content.unwrap_or_default();
+  let lang = query.lang.clone();
+  let provider_state = app_state.clone();
+  let translation_rs = tokio::task::spawn_blocking(move || {
+    provider_state
+      .translation_provider
+      .as_ref()
+      .map(|provider| provider.translate(&content, &lang))
+  })
+  .await
+  .map_err(|err| {
+    tracing::error!("Translation task panicked: {}", err.to_string());
+    ApiError::Unknown
+  })?;
+  let translated = match translation_rs {
+    Some(Ok(translated)) => translated,
+    Some(Err(err @ TranslationError::UnsupportedLanguage(_))) => {
+      return Err(ApiError::BadRequest(err.to_string()));
+    }
+    Some(Err(err)) => {
+      tracing::error!("Failed to translate message {}: {}", message_id, err.to_string());
+      return Err(ApiError::Unknown);
+    }
+    None => return Err(ApiError::NotFound("Translation is disabled".into())),
+  };
+
+  let translation =
+    services::translation::save_translation(conn, message_id, &query.lang, translated)
+      .map_err(ApiError::DatabaseError)?;
+  Ok(Json(TranslationResponse {
+    message_id,
+    lang: translation.lang,
+    content: translation.content,
+    cached: false,
+  }))
+}
+
+/// ### Handler for GET /messages/by-uuid/:message_uuid
+///
+/// Get a message by the uuid its sender assigned to it, so a client can resolve an optimistic
+/// message to the saved message without knowing its id
+#[utoipa::path(
+  get,
+  path = "/messages/by-uuid/{message_uuid}",
+  params(
+    (
+      "x-user-code" = String, Header, description = "user code for authentication",
+      example = "6C70F6E0A888C1360AD532C66D8F1CD0ED48C1CC47FA1AE6665B1FC3DAABB468"
+    ),
+    ("message_uuid" = Uuid, Path, description = "uuid of the message"),
+  ),
+  responses(
+      (status = 200, description = "Get message successfully", body = MessageWithUser, content_type = "application/json"),
+      (status = 403, description = "The current user is not a member of the group"),
+      (status = 404, description = "User or message not found"),
+      (status = 500, description = "Database error")
+  ),
+)]
+pub async fn get_message_by_uuid(
+  State(app_state): State<Arc<AppState>>,
+  Path(message_uuid): Path<Uuid>,
+  UserToken(user_token): UserToken,
+) -> Result<Json<MessageWithUser>, ApiError> {
+  let conn = &mut app_state
+    .db_pool
+    .get()
+    .map_err(|err| ApiError::DatabaseError(DBError::ConnectionError(err)))?;
+  let user = check_user_exists(conn, user_token).await?;
+
+  let (group_id, message) = services::message::get_message_by_uuid(conn, message_uuid)
+    .map_err(ApiError::DatabaseError)?
+    .ok_or(ApiError::NotFound("Message".into()))?;
+  if !services::group::check_user_join_group(conn, user.id, group_id)
+    .map_err(|_err| ApiError::new_database_query_err("Failed to check user joined group"))?
+  {
+    return Err(ApiError::NotAMember(group_id));
+  }
+  Ok(Json(message))
+}
+
+/// ### Handler for POST /messages/:message_id/report
+///
+/// Report a message for moderation, reports are reviewed by the owner of the group.
+/// A member can report a message only once
+#[utoipa::path(
+  post,
+  path = "/messages/{message_id}/report",
+  params(
+    (
+      "x-user-code" = String, Header, description = "user code for authentication",
+      example = "6C70F6E0A888C1360AD532C66D8F1CD0ED48C1CC47FA1AE6665B1FC3DAABB468"
+    ),
+    ("message_id" = u32, Path, description = "id of the message"),
+  ),
+  request_body = ReportMessageRequest,
+  responses(
+      (status = 200, description = "Report message successfully", body = MessageReportResponse, content_type = "application/json"),
+      (status = 400, description = "The reason is empty or too long"),
+      (status = 403, description = "The current user is not a member of the group"),
+      (status = 404, description = "Message not found"),
+      (status = 409, description = "The current user already reported the message"),
+      (status = 500, description = "Database error")
+  ),
+)]
+pub async fn report_message(
+  State(app_state): State<Arc<AppState>>,
+  Path(message_id): Path<i32>,
+  UserToken(user_token): UserToken,
+  ValidatedJson(request): ValidatedJson<ReportMessageRequest>,
+) -> Result<Json<MessageReportResponse>, ApiError> {
+  let conn = &mut app_state
+    .db_pool
+    .get()
+    .map_err(|err| ApiError::DatabaseError(DBError::ConnectionError(err)))?;
+  let user = check_user_exists(conn, user_token).await?;
+
+  let message = services::message::get_message(conn, message_id)
+    .map_err(ApiError::DatabaseError)?
+    .ok_or(ApiError::NotFound("Message".into()))?;
+  if !services::group::check_user_join_group(conn, user.id, message.group_id)
+    .map_err(|_err| ApiError::new_database_query_err("Failed to check user joined group"))?
+  {
+    return Err(ApiError::NotAMember(message.group_id));
+  }
+
+  let report = services::report::create_report(conn, user.id, message_id, request.reason.trim())
+    .map_err(ApiError::DatabaseError)?
+    .ok_or(ApiError::Conflict("The message was already reported".into()))?;
+  Ok(Json(MessageReportResponse {
+    id: report.id,
+    message_id,
+    message_content: message.content,
+    reporter_id: user.id,
+    reporter_name: user.username,
+    reason: report.reason,
+    created_at: report.created_at,
+  }))
+}
+
+/// ### Handler for PATCH /messages/:message_id/pin
+///
+/// Pin or unpin a message, members of the group are notified with a `PinEvent`
+///
+/// **Notice**: User must be an owner of the group
+#[utoipa::path(
+  patch,
+  path = "/messages/{message_id}/pin",
+  params(
+    (
+      "x-user-code" = String, Header, description = "user code for authentication",
+      example = "6C70F6E0A888C1360AD532C66D8F1CD0ED48C1CC47FA1AE6665B1FC3DAABB468"
+    ),
+    ("message_id" = u32, Path, description = "id of the message"),
+  ),
+  request_body = PinMessageRequest,
+  responses(
+      (status = 200, description = "Pin or unpin the message successfully", body = PinMessageResponse, content_type = "application/json"),
+      (status = 401, description = "The current user is not the owner of the group"),
+      (status = 404, description = "Message not found"),
+      (status = 500, description = "Database error")
+  ),
+)]
+pub async fn pin_message(
+  State(app_state): State<Arc<AppState>>,
+  Path(message_id): Path<i32>,
+  UserToken(user_token): UserToken,
+  Json(request): Json<PinMessageRequest>,
+) -> Result<Json<PinMessageResponse>, ApiError> {
+  let conn = &mut app_state
+    .db_pool
+    .get()
+    .map_err(|err| ApiError::DatabaseError(DBError::ConnectionError(err)))?;
+  let user = check_user_exists(conn, user_token).await?;
+
+  let message = services::message::get_message(conn, message_id)
+    .map_err(ApiError::DatabaseError)?
+    .ok_or(ApiError::NotFound("Message".into()))?;
+  if !services::group::check_owner_of_group(conn, user.id, message.group_id)
+    .map_err(|_err| ApiError::new_database_query_err("Failed to check owner of group"))?
+  {
+    return Err(ApiError::Unauthorized);
+  }
+
+  let event = if request.pinned {
+    services::pin::pin_message(conn, message_id, message.group_id, user.id)
+      .map_err(ApiError::DatabaseError)?
+      .map(|pinned_message| PinEventData {
+        group_id: message.group_id,
+        message_id,
+        pinned: true,
+        by_user_id: user.id,
+        pinned_at: Some(pinned_message.pinned_at.and_utc()),
+      })
+  } else {
+    services::pin::unpin_message(conn, message_id)
+      .map_err(ApiError::DatabaseError)?
+      .then_some(PinEventData {
+        group_id: message.group_id,
+        message_id,
+        pinned: false,
+        by_user_id: user.id,
+        pinned_at: None,
+      })
+  };
+  // Pinning an already pinned message changes nothing, members are not notified again
+  if let Some(event) = event {
+    let _ = send_message_event_to_group(conn, SMessageType::PinEvent(event), message.group_id);
+  }
+
+  Ok(Json(PinMessageResponse {
+    message_id,
+    group_id: message.group_id,
+    pinned: request.pinned,
+  }))
+}
+
 /// ### Handler for DELETE /messages/:message_id
 #[utoipa::path(
   delete,
@@ -265,6 +691,9 @@ pub async fn delete_message(
 }
 
 /// ### Handler for PUT /messages/:message_id
+///
+/// An edit giving the `version` of the message it is based on is rejected with 409 if the
+/// message was edited since, the response body then carries the `current_version`
 #[utoipa::path(
   put,
   path = "/messages/{message_id}",
@@ -282,14 +711,18 @@ pub async fn delete_message(
           {
             "content": "This is new message",
             "message_type": "TEXT",
+            "version": 1
           }
         )),
     )
   ),
   responses(
       (status = 200, description = "Update the message successfully", body = MessageResponse, content_type = "application/json"),
+      (status = 400, description = "The message type is ATTACHMENT but the message has no attachments, the content is too long or contains words which are not allowed in the group"),
       (status = 403, description = "The current user doesn't have permission to access the resource"),
       (status = 401, description = "The current user doesn't have right to access the resource"),
+      (status = 404, description = "Message not found"),
+      (status = 409, description = "The message was edited since the given version"),
       (status = 500, description = "Database error")
   ),
 )]
@@ -297,7 +730,7 @@ pub async fn update_message(
   State(app_state): State<Arc<AppState>>,
   Path(message_id): Path<i32>,
   UserToken(user_token): UserToken,
-  Json(update_data): Json<UpdateMessage>,
+  Json(mut update_data): Json<UpdateMessage>,
 ) -> Result<Json<MessageResponse>, ApiError> {
   let conn = &mut app_state
   .db_pool
@@ -309,12 +742,285 @@ let message = services::message::get_message(conn, message_id).map_err(ApiError:
 if message.is_none(){
   return Err(ApiError::NotFound("Message".into()));
 }
+let message = message.unwrap();
 
-if message.unwrap().user_id != user.id{
+if message.user_id != user.id{
   return Err(ApiError::Unauthorized);
 }
+  if let Some(message_type) = &update_data.message_type {
+    if !services::message::is_valid_type_transition(conn, &message, message_type)
+      .map_err(ApiError::DatabaseError)?
+    {
+      return Err(ApiError::ValidationFailed(vec![FieldError::new(
+        "message_type",
+        "must not be ATTACHMENT for a message without attachments",
+      )]));
+    }
+  }
+
+  // the new content is checked like the content of a sent message
+  if let Some(content) = &update_data.content {
+    let max_message_length = services::group::get_max_message_length(conn, message.group_id)
+      .map_err(ApiError::DatabaseError)?;
+    if content.chars().count() > max_message_length {
+      return Err(ApiError::ValidationFailed(vec![FieldError::new(
+        "content",
+        &format!("must be at most {} characters in the group", max_message_length),
+      )]));
+    }
+  }
+  match services::moderation::moderate_content(
+    conn,
+    app_state.word_filter.as_ref(),
+    message.group_id,
+    update_data.content.as_deref(),
+  )
+  .map_err(ApiError::DatabaseError)?
+  {
+    ModerationOutcome::Allowed => {}
+    ModerationOutcome::Masked(masked) => update_data.content = Some(masked),
+    ModerationOutcome::Rejected => return Err(ApiError::ContentRejected),
+  }
 
-  let message = services::message::update_message(conn, message_id, update_data)
-  .map_err(ApiError::DatabaseError)?;
+  let message = services::message::update_message(conn, user.id, message, update_data)
+    .map_err(ApiError::DatabaseError)?;
+  let Some(message) = message else {
+    // the message was edited or deleted after it was loaded
+    let current = services::message::get_message(conn, message_id)
+      .map_err(ApiError::DatabaseError)?
+      .ok_or(ApiError::NotFound("Message".into()))?;
+    return Err(ApiError::VersionConflict(current.version));
+  };
   Ok(Json(MessageResponse::from(message)))
-}
\ No newline at end of file
+}
+
+/// ### Handler for POST /groups/:group_id/read-all
+///
+/// Mark all messages of the group as read by moving the last seen pointer of
+/// the current user to the latest message of the group.
+/// Other connections of the current user are informed by a `LastSeenEvent`
+#[utoipa::path(
+  post,
+  path = "/groups/{group_id}/read-all",
+  params(
+    (
+      "x-user-code" = String, Header, description = "user code for authentication",
+      example = "6C70F6E0A888C1360AD532C66D8F1CD0ED48C1CC47FA1AE6665B1FC3DAABB468"
+    ),
+    ("group_id" = u32, Path, description = "id of the group"),
+  ),
+  responses(
+      (status = 200, description = "Mark all messages as read successfully", body = ReadAllResponse, content_type = "application/json"),
+      (status = 403, description = "The current user is not a member of the group"),
+      (status = 500, description = "Database error")
+  ),
+)]
+pub async fn read_all_messages(
+  State(app_state): State<Arc<AppState>>,
+  Path(group_id): Path<i32>,
+  UserToken(user_token): UserToken,
+) -> Result<Json<ReadAllResponse>, ApiError> {
+  let conn = &mut app_state
+    .db_pool
+    .get()
+    .map_err(|err| ApiError::DatabaseError(DBError::ConnectionError(err)))?;
+  let user = check_user_exists(conn, user_token).await?;
+
+  if !services::group::check_user_join_group(conn, user.id, group_id)
+    .map_err(|_err| ApiError::new_database_query_err("Failed to check user joined group"))?
+  {
+    return Err(ApiError::NotAMember(group_id));
+  }
+
+  let latest_message_id =
+    services::message::get_latest_message_id(conn, group_id).map_err(ApiError::DatabaseError)?;
+
+  let mut last_seen_message_id = None;
+  if let Some(message_id) = latest_message_id {
+    let last_seen =
+      services::message::update_last_seen_message(conn, user.id, group_id, message_id)
+        .map_err(ApiError::DatabaseError)?;
+    send_message_event_to_user(
+      user.id,
+      SMessageType::LastSeenEvent(LastSeenData {
+        group_id,
+        message_id: last_seen.message_id,
+      }),
+    );
+    last_seen_message_id = Some(last_seen.message_id);
+  }
+
+  Ok(Json(ReadAllResponse {
+    group_id,
+    last_seen_message_id,
+  }))
+}
+
+
+/// ### Handler for GET /groups/:group_id/message-ids
+///
+/// Get only the ids of messages of the group after `since_id`, so a client syncing its local
+/// cache can diff them and fetch the missing messages only. At most 1000 ids are returned
+///
+/// **Notice**: User must be a member of the group
+#[utoipa::path(
+  get,
+  path = "/groups/{group_id}/message-ids",
+  params(
+    (
+      "x-user-code" = String, Header, description = "user code for authentication",
+      example = "6C70F6E0A888C1360AD532C66D8F1CD0ED48C1CC47FA1AE6665B1FC3DAABB468"
+    ),
+    ("group_id" = u32, Path, description = "id of the group"),
+    ("since_id" = Option<i32>, Query, description = "only ids greater than this id are returned"),
+    ("limit" = Option<u32>, Query, description = "maximum number of ids, at most 1000"),
+  ),
+  responses(
+      (status = 200, description = "Get message ids successfully", body = MessageIdsResponse, content_type = "application/json"),
+      (status = 403, description = "The current user is not a member of the group"),
+      (status = 404, description = "User not found"),
+      (status = 500, description = "Database error")
+  ),
+)]
+pub async fn get_message_ids(
+  State(app_state): State<Arc<AppState>>,
+  UserToken(user_token): UserToken,
+  Path(group_id): Path<i32>,
+  Query(query): Query<MessageIdsQuery>,
+) -> Result<Json<MessageIdsResponse>, ApiError> {
+  let conn = &mut app_state
+    .db_pool
+    .get()
+    .map_err(|err| ApiError::DatabaseError(DBError::ConnectionError(err)))?;
+  let user = check_user_exists(conn, user_token).await?;
+
+  if !services::group::check_user_join_group(conn, user.id, group_id)
+    .map_err(|_err| ApiError::new_database_query_err("Failed to check user joined group"))?
+  {
+    return Err(ApiError::NotAMember(group_id));
+  }
+
+  let limit = query
+    .limit
+    .map_or(MAX_MESSAGE_IDS_LIMIT, |limit| (limit as i64).clamp(1, MAX_MESSAGE_IDS_LIMIT));
+  // one more message is loaded to know whether there are more messages
+  let mut messages =
+    services::message::get_message_ids_since(conn, group_id, query.since_id, limit + 1)
+      .map_err(ApiError::DatabaseError)?;
+  let has_more = messages.len() as i64 > limit;
+  messages.truncate(limit as usize);
+
+  Ok(Json(MessageIdsResponse {
+    group_id,
+    messages: messages
+      .into_iter()
+      .map(|(id, updated_at)| MessageIdItem { id, updated_at })
+      .collect(),
+    has_more,
+  }))
+}
+
+/// ### Handler for GET /groups/:group_id/messages/:message_id/context
+///
+/// Get a message with up to `radius` messages sent before and after it, oldest first,
+/// so a client can jump to a search result or a quoted message without paging from the start
+#[utoipa::path(
+  get,
+  path = "/groups/{group_id}/messages/{message_id}/context",
+  params(
+    (
+      "x-user-code" = String, Header, description = "user code for authentication",
+      example = "6C70F6E0A888C1360AD532C66D8F1CD0ED48C1CC47FA1AE6665B1FC3DAABB468"
+    ),
+    ("group_id" = u32, Path, description = "id of the group"),
+    ("message_id" = u32, Path, description = "id of the target message"),
+    ("radius" = Option<u32>, Query, description = "number of messages on each side of the target message, 10 by default and at most 50"),
+  ),
+  responses(
+      (status = 200, description = "Get context of message successfully", body = MessageContextResponse, content_type = "application/json"),
+      (status = 403, description = "The current user is not a member of the group"),
+      (status = 404, description = "User not found or the message is not in the group"),
+      (status = 500, description = "Database error")
+  ),
+)]
+pub async fn get_message_context(
+  State(app_state): State<Arc<AppState>>,
+  UserToken(user_token): UserToken,
+  Path((group_id, message_id)): Path<(i32, i32)>,
+  Query(query): Query<MessageContextQuery>,
+) -> Result<Json<MessageContextResponse>, ApiError> {
+  let conn = &mut app_state
+    .db_pool
+    .get()
+    .map_err(|err| ApiError::DatabaseError(DBError::ConnectionError(err)))?;
+  let user = check_user_exists(conn, user_token).await?;
+
+  if !services::group::check_user_join_group(conn, user.id, group_id)
+    .map_err(|_err| ApiError::new_database_query_err("Failed to check user joined group"))?
+  {
+    return Err(ApiError::NotAMember(group_id));
+  }
+  if !services::message::check_message_in_group(conn, message_id, group_id)
+    .map_err(ApiError::DatabaseError)?
+  {
+    return Err(ApiError::NotFound(format!(
+      "Message {} not found in group {}",
+      message_id, group_id
+    )));
+  }
+
+  let radius = query.radius.map_or(DEFAULT_MESSAGE_CONTEXT_RADIUS, |radius| {
+    (radius as i64).min(MAX_MESSAGE_CONTEXT_RADIUS)
+  });
+  let messages = services::message::get_messages_around(conn, group_id, message_id, radius)
+    .map_err(ApiError::DatabaseError)?;
+
+  Ok(Json(MessageContextResponse {
+    group_id,
+    message_id,
+    messages,
+  }))
+}
+
+/// ### Handler for GET /groups/:group_id/messages/export
+///
+/// Export all messages of the group as a JSON array.
+/// Unlike `/groups/:group_id/messages`, the response is not paginated, messages are streamed
+/// from the database and written to a chunked response body one by one
+#[utoipa::path(
+  get,
+  path = "/groups/{group_id}/messages/export",
+  params(
+    (
+      "x-user-code" = String, Header, description = "user code for authentication",
+      example = "6C70F6E0A888C1360AD532C66D8F1CD0ED48C1CC47FA1AE6665B1FC3DAABB468"
+    ),
+    ("group_id" = u32, Path, description = "id of the group"),
+  ),
+  responses(
+      (status = 200, description = "Export messages successfully", body = Vec<MessageWithUser>, content_type = "application/json"),
+      (status = 403, description = "The current user is not a member of the group"),
+      (status = 500, description = "Database error")
+  ),
+)]
+pub async fn export_messages(
+  State(app_state): State<Arc<AppState>>,
+  Path(group_id): Path<i32>,
+  UserToken(user_token): UserToken,
+) -> Result<Response, ApiError> {
+  let mut conn = app_state
+    .db_pool
+    .get()
+    .map_err(|err| ApiError::DatabaseError(DBError::ConnectionError(err)))?;
+  let user = check_user_exists(&mut conn, user_token).await?;
+
+  if !services::group::check_user_join_group(&mut conn, user.id, group_id)
+    .map_err(|_err| ApiError::new_database_query_err("Failed to check user joined group"))?
+  {
+    return Err(ApiError::NotAMember(group_id));
+  }
+
+  Ok(stream_json_array(b"[".to_vec(), b"]", move |writer| {
+    services::message::stream_messages(&mut conn, group_id, |message| writer.write(&message))
+  }))
+}
@@ -1,14 +1,183 @@
-use std::{collections::HashMap, sync::Mutex};
+use std::{
+  collections::{BTreeSet, HashMap, HashSet},
+  net::SocketAddr,
+  sync::{atomic::Ordering, Arc, Mutex},
+  time::Duration,
+};
 
 use once_cell::sync::Lazy;
-use tokio::sync::broadcast::Sender;
+use tokio::sync::{
+  broadcast::{error::RecvError, Receiver, Sender},
+  Notify,
+};
 
-use crate::{payloads::socket::message::SMessageType, services, PoolPGConnectionType};
+use crate::{
+  payloads::socket::message::{MessagesData, ReadyData, SMessageType, UserOfflineData},
+  services, AppState, PoolPGConnectionType, MAX_EVENTS_BEFORE_READY, SEEN_EVENTS_COALESCE_MILLIS,
+  SOCKET_READY_TIMEOUT_SECS,
+};
 
-pub type ClientSessionsType = Lazy<Mutex<HashMap<i32, Sender<SMessageType>>>>;
+/// Active connections of a single user, keyed by the remote address of each connection
+pub type UserSessionsType = HashMap<SocketAddr, Sender<SMessageType>>;
+
+pub type ClientSessionsType = Lazy<Mutex<HashMap<i32, UserSessionsType>>>;
 
 pub static CLIENT_SESSIONS: ClientSessionsType =
-  Lazy::new(|| Mutex::new(HashMap::<i32, Sender<SMessageType>>::new()));
+  Lazy::new(|| Mutex::new(HashMap::<i32, UserSessionsType>::new()));
+
+/// Seen message ids of a group waiting to be propagated as a single `SeenMessagesEvent`
+struct PendingSeenEvent {
+  user_ids: Vec<i32>,
+  message_ids: BTreeSet<i32>,
+}
+
+static PENDING_SEEN_EVENTS: Lazy<Mutex<HashMap<i32, PendingSeenEvent>>> =
+  Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Registration of a connection in `CLIENT_SESSIONS`
+///
+/// Dropping the guard is the single cleanup path of a connection, whether it is closed by the
+/// client, fails or its task is aborted: the session is removed, which closes the group events
+/// subscription of the connection, `AppState::ws_connections` is decremented and
+/// `UserOfflineEvent` is sent to users sharing a group with the user when it was the last
+/// connection of the user
+pub struct ClientSessionGuard {
+  user_id: i32,
+  addr: SocketAddr,
+  app_state: Arc<AppState>,
+}
+
+impl Drop for ClientSessionGuard {
+  fn drop(&mut self) {
+    self.app_state.ws_connections.fetch_sub(1, Ordering::Relaxed);
+    if !remove_client_session(self.user_id, self.addr) {
+      return;
+    }
+    let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+      return;
+    };
+    let user_id = self.user_id;
+    let db_pool = self.app_state.db_pool.clone();
+    runtime.spawn_blocking(move || {
+      // The user might have reconnected in the meantime
+      if !get_online_user_ids(&[user_id]).is_empty() {
+        return;
+      }
+      let user_ids = db_pool
+        .get()
+        .map_err(|err| err.to_string())
+        .and_then(|mut conn| {
+          services::user::get_user_ids_sharing_group(&mut conn, user_id)
+            .map_err(|err| err.to_string())
+        });
+      match user_ids {
+        Ok(user_ids) => {
+          let event = SMessageType::UserOfflineEvent(UserOfflineData { user_id });
+          send_to_connections(&user_ids, event);
+        }
+        Err(err) => tracing::error!("Failed to send offline event of user {user_id}: {err}"),
+      }
+    });
+  }
+}
+
+/// Register a new connection of an user, other connections of the same user are kept
+///
+/// The connection stays registered and counted in `AppState::ws_connections` until the
+/// returned guard is dropped
+pub fn add_client_session(
+  user_id: i32,
+  addr: SocketAddr,
+  sender: Sender<SMessageType>,
+  app_state: Arc<AppState>,
+) -> ClientSessionGuard {
+  if let Ok(mut client_sessions) = CLIENT_SESSIONS.lock() {
+    client_sessions
+      .entry(user_id)
+      .or_default()
+      .insert(addr, sender);
+  }
+  app_state.ws_connections.fetch_add(1, Ordering::Relaxed);
+  ClientSessionGuard {
+    user_id,
+    addr,
+    app_state,
+  }
+}
+
+/// Remove a connection of an user, return true if the user has no connection left
+///
+/// The connection might already have been pruned by `send_to_connections`
+fn remove_client_session(user_id: i32, addr: SocketAddr) -> bool {
+  let Ok(mut client_sessions) = CLIENT_SESSIONS.lock() else {
+    return false;
+  };
+  let Some(user_sessions) = client_sessions.get_mut(&user_id) else {
+    return true;
+  };
+  user_sessions.remove(&addr);
+  if user_sessions.is_empty() {
+    client_sessions.remove(&user_id);
+    return true;
+  }
+  false
+}
+
+/// Forward group events of a connection from `events` to `output` once the client is ready
+///
+/// Events received before `ready` is notified are buffered, then sent right after a `ReadyResult`.
+/// Beyond `MAX_EVENTS_BEFORE_READY` events the buffer is dropped and the client is asked to resync.
+/// A client never sending `Ready` becomes live after `SOCKET_READY_TIMEOUT_SECS`
+pub fn spawn_ready_gate(
+  mut events: Receiver<SMessageType>,
+  ready: Arc<Notify>,
+  output: Sender<SMessageType>,
+) {
+  tokio::spawn(async move {
+    let mut buffered = Vec::new();
+    let mut resync_required = false;
+    let ready_timeout = tokio::time::sleep(Duration::from_secs(SOCKET_READY_TIMEOUT_SECS));
+    tokio::pin!(ready_timeout);
+    loop {
+      tokio::select! {
+        _ = ready.notified() => break,
+        _ = &mut ready_timeout => break,
+        event = events.recv() => match event {
+          Ok(_) if resync_required => {}
+          Ok(event) if buffered.len() < MAX_EVENTS_BEFORE_READY => buffered.push(event),
+          Ok(_) | Err(RecvError::Lagged(_)) => {
+            buffered.clear();
+            resync_required = true;
+          }
+          Err(RecvError::Closed) => return,
+        }
+      }
+    }
+
+    let ready_result = SMessageType::ReadyResult(ReadyData {
+      buffered_count: buffered.len(),
+      resync_required,
+    });
+    for event in std::iter::once(ready_result).chain(buffered) {
+      if output.send(event).is_err() {
+        return;
+      }
+    }
+    loop {
+      match events.recv().await {
+        Ok(event) => {
+          if output.send(event).is_err() {
+            return;
+          }
+        }
+        Err(RecvError::Lagged(count)) => {
+          tracing::warn!("Connection lagged, {count} events were dropped")
+        }
+        Err(RecvError::Closed) => return,
+      }
+    }
+  });
+}
 
 pub fn send_message_event_to_group(
   conn: &mut PoolPGConnectionType,
@@ -24,26 +193,130 @@ pub fn send_message_event_to_group(
     return Ok(0);
   }
 
+  Ok(send_to_connections(&user_ids, new_message))
+}
+
+/// Propagate seen messages to a group, coalescing bursts of seen events
+///
+/// The first call for a group opens a window of `SEEN_EVENTS_COALESCE_MILLIS`, message ids of
+/// following calls within that window are merged, then a single `SeenMessagesEvent` is sent
+/// to the members of the group when the window is closed
+pub fn send_seen_event_to_group(
+  conn: &mut PoolPGConnectionType,
+  group_id: i32,
+  message_ids: Vec<i32>,
+) -> Result<(), ()> {
+  if let Some(pending) = PENDING_SEEN_EVENTS.lock().map_err(|_| ())?.get_mut(&group_id) {
+    pending.message_ids.extend(message_ids);
+    return Ok(());
+  }
+  let user_ids = services::user::get_user_ids_from_group(conn, group_id).map_err(|_| ())?;
+
+  let mut pending_events = PENDING_SEEN_EVENTS.lock().map_err(|_| ())?;
+  // Another seen event might open the window while querying members of the group
+  if let Some(pending) = pending_events.get_mut(&group_id) {
+    pending.message_ids.extend(message_ids);
+    return Ok(());
+  }
+  pending_events.insert(
+    group_id,
+    PendingSeenEvent {
+      user_ids,
+      message_ids: message_ids.into_iter().collect(),
+    },
+  );
+  drop(pending_events);
+
+  tokio::spawn(async move {
+    tokio::time::sleep(Duration::from_millis(SEEN_EVENTS_COALESCE_MILLIS)).await;
+    let count = flush_seen_event(group_id);
+    tracing::debug!("Send coalesced seen event of group {group_id} to {count} clients");
+  });
+  Ok(())
+}
+
+/// Send the pending seen event of a group, return the number of connections that received it
+fn flush_seen_event(group_id: i32) -> usize {
+  let pending = match PENDING_SEEN_EVENTS.lock() {
+    Ok(mut pending_events) => pending_events.remove(&group_id),
+    Err(_) => None,
+  };
+  let Some(PendingSeenEvent {
+    user_ids,
+    message_ids,
+  }) = pending
+  else {
+    return 0;
+  };
+
+  let event = SMessageType::SeenMessagesEvent(MessagesData {
+    group_id,
+    message_ids: message_ids.into_iter().collect(),
+  });
+  send_to_connections(&user_ids, event)
+}
+
+/// Stop sending events of a group to members removed from it
+///
+/// Group events are sent to the participants queried when sending, so removed members stop
+/// receiving them once their participant rows are deleted. Only a pending seen event still holds
+/// the members of the group when its window was opened, the removed members are dropped from it
+pub fn revoke_group_members(group_id: i32, user_ids: &[i32]) {
+  if let Ok(mut pending_events) = PENDING_SEEN_EVENTS.lock() {
+    if let Some(pending) = pending_events.get_mut(&group_id) {
+      pending.user_ids.retain(|user_id| !user_ids.contains(user_id));
+    }
+  }
+}
+
+/// Send an event to every active connection of a single user
+///
+/// Return the number of connections that received the event
+pub fn send_message_event_to_user(user_id: i32, new_message: SMessageType) -> usize {
+  send_to_connections(&[user_id], new_message)
+}
+
+/// Send an event to all active connections of the users, including every session of each user
+///
+/// Sending only fails when the connection has no receiver left, such dead connections are
+/// removed from `CLIENT_SESSIONS` so the map doesn't grow when a disconnect is missed.
+/// Return the number of connections that received the event
+fn send_to_connections(user_ids: &[i32], event: SMessageType) -> usize {
+  let Ok(mut client_sessions) = CLIENT_SESSIONS.lock() else {
+    return 0;
+  };
   let mut count = 0;
-  if let Some(active_connections) = get_connected_connections(user_ids) {
-    for active_connection in active_connections {
-      if active_connection.send(new_message.clone()).is_ok() {
+  for user_id in user_ids {
+    let Some(user_sessions) = client_sessions.get_mut(user_id) else {
+      continue;
+    };
+    user_sessions.retain(|addr, sender| {
+      if sender.send(event.clone()).is_ok() {
         count += 1;
+        return true;
       }
+      tracing::debug!("Prune dead connection {addr} of user {user_id}");
+      false
+    });
+    if user_sessions.is_empty() {
+      client_sessions.remove(user_id);
     }
   }
-  Ok(count)
+  count
 }
 
-fn get_connected_connections(user_ids: Vec<i32>) -> Option<Vec<Sender<SMessageType>>> {
-  // let mut result = Vec::new();
+/// Get ids of users, among `user_ids`, having at least one active connection
+pub fn get_online_user_ids(user_ids: &[i32]) -> HashSet<i32> {
   if let Ok(client_sessions) = CLIENT_SESSIONS.lock() {
-    let result = client_sessions
+    return user_ids
       .iter()
-      .filter(|session| user_ids.contains(&session.0))
-      .map(|session| session.1.clone())
-      .collect::<Vec<Sender<SMessageType>>>();
-    return Some(result);
+      .filter(|user_id| {
+        client_sessions
+          .get(user_id)
+          .is_some_and(|sessions| !sessions.is_empty())
+      })
+      .copied()
+      .collect();
   }
-  None
+  HashSet::new()
 }
@@ -1,43 +1,68 @@
 use crate::{
-  database::models::MessageStatus,
-  errors::ApiError,
+  database::models::{self, MessageStatus},
+  errors::{ApiError, AuthError, DBError},
   handlers::socket::{
-    connections::{self, send_message_event_to_group, CLIENT_SESSIONS},
-    structs::ClientSession,
+    connections::{
+      self, add_client_session, get_online_user_ids, revoke_group_members, send_message_event_to_group,
+      send_message_event_to_user, spawn_ready_gate,
+    },
+    structs::{ClientSession, SocketEncoding, SocketParams},
+    throttle::{acquire_ip_connection, IpConnectionGuard},
   },
   payloads::{
     messages::AttachmentPayload,
     socket::{
       common::ResultMessage,
       message::{
-        AuthenticationStatusCode, MessagesData, SMessageContent, SMessageEdit, SMessageType,
+        AliasChangedData, AuthenticatedUser, AuthenticationStatusCode, BatchItemResultData, FetchHistoryData, GroupData, HistoryPageData, MemberInfo, MemberLeftData,
+        MembersData, MessageStatusData, MessagesData, QueryStatusData, RecallMessageData, ReplayRangeData,
+        RecalledMessageData, SMessageContent, SMessageEdit, SMessageStatus, SMessageType,
+        SNewMessage, SendResultData, SetAliasData, UnreadCountData, UnreadMessagesData,
       },
     },
   },
   services::{
-    self, group::check_user_join_group, message::create_new_message, user::get_user_by_code,
+    self,
+    auth::authenticate_user_code,
+    group::{check_user_join_group, LeaveGroupOutcome},
+    moderation::{ModerationOutcome, WordFilter},
   },
-  AppState, PoolPGConnectionType,
+  utils::crypto::{decode_cursor, encode_cursor},
+  AppState, PoolPGConnectionType, DEFAULT_PAGE_SIZE, MAX_QUERY_STATUS_IDS, MAX_SEND_BATCH_SIZE, MAX_SOCKET_HISTORY_LIMIT, MAX_UNREAD_MESSAGE_IDS,
+  MAX_ALIAS_LENGTH, MAX_REPLAY_RANGE, MAX_SOCKET_MEMBERS_LIST, MESSAGE_RECALL_WINDOW_MINUTES,
+  WS_IP_LIMIT_RETRY_SECS,
 };
 use axum::{
   extract::{
     ws::{Message, WebSocket},
-    ConnectInfo, State, WebSocketUpgrade,
+    ConnectInfo, Query, State, WebSocketUpgrade,
   },
   response::IntoResponse,
 };
 use axum_extra::{headers::UserAgent, TypedHeader};
+use chrono::Utc;
+use diesel::Connection;
 use futures::{sink::SinkExt, stream::StreamExt};
 
-use std::{net::SocketAddr, ops::ControlFlow, sync::Arc, time::Duration};
+use std::{
+  collections::{HashMap, HashSet},
+  net::SocketAddr,
+  ops::ControlFlow,
+  sync::Arc,
+  time::Duration,
+};
 use tokio::{
-  sync::broadcast::{self, Sender},
+  sync::{
+    broadcast::{self, Sender},
+    Notify,
+  },
   time::timeout,
 };
 
 pub async fn ws_handler(
   ws: WebSocketUpgrade,
   State(state): State<Arc<AppState>>,
+  Query(params): Query<SocketParams>,
   user_agent: Option<TypedHeader<UserAgent>>,
   ConnectInfo(addr): ConnectInfo<SocketAddr>,
 ) -> Result<impl IntoResponse, ApiError> {
@@ -48,9 +73,26 @@ pub async fn ws_handler(
     "unknown".into()
   };
   tracing::debug!("User agent: {user_agent} at {addr} connected");
-  Ok(ws.on_upgrade(move |socket| handle_socket(socket, addr, state)))
+  if let Some(blocked_for) = state.ws_auth_throttle.blocked_for(addr.ip()) {
+    tracing::info!("Reject connection from {addr}, too many failed authentications");
+    return Err(ApiError::TooManyRequests(blocked_for.as_secs() + 1));
+  }
+  // The connection is counted until the socket is closed
+  let Some(ip_connection) = acquire_ip_connection(state.ws_ip_connections.clone(), addr.ip()) else {
+    tracing::info!("Reject connection from {addr}, too many open connections");
+    return Err(ApiError::TooManyRequests(WS_IP_LIMIT_RETRY_SECS));
+  };
+  Ok(ws.on_upgrade(move |socket| {
+    handle_socket(socket, addr, state, params.encoding, ip_connection)
+  }))
 }
-pub async fn handle_socket(socket: WebSocket, addr: SocketAddr, app_state: Arc<AppState>) {
+pub async fn handle_socket(
+  socket: WebSocket,
+  addr: SocketAddr,
+  app_state: Arc<AppState>,
+  encoding: SocketEncoding,
+  _ip_connection: IpConnectionGuard,
+) {
   let (mut socket_sender, mut socket_receiver) = socket.split();
   // Shared channel for receiving data from other channel then sending to current connection
   let (shared_tx, mut shared_rx) = broadcast::channel::<SMessageType>(1003);
@@ -59,10 +101,14 @@ pub async fn handle_socket(socket: WebSocket, addr: SocketAddr, app_state: Arc<A
   let mut sending_task = tokio::spawn(async move {
     while let Ok(msg) = shared_rx.recv().await {
       // tracing::debug!("Propagate message from group {group_id} to client");
-      if let Err(err) = socket_sender
-        .send(Message::Text(serde_json::to_string(&msg).unwrap()))
-        .await
-      {
+      let frame = match encoding.encode(&msg) {
+        Ok(frame) => frame,
+        Err(err) => {
+          tracing::error!("Failed to encode message to client {}: {}", addr, err);
+          continue;
+        }
+      };
+      if let Err(err) = socket_sender.send(frame).await {
         tracing::info!("Stop handling propagate message to client {addr}");
         tracing::error!(
           "Failed to send message to client {}, cause: {}",
@@ -108,20 +154,33 @@ pub async fn handle_socket(socket: WebSocket, addr: SocketAddr, app_state: Arc<A
   }
   let first_message = first_message_rs.unwrap();
 
-  let authenticated_rs = authenticate(first_message, app_state.clone(), &mut current_sender, addr);
+  let authenticated_rs = authenticate(
+    first_message,
+    app_state.clone(),
+    &mut current_sender,
+    addr,
+    encoding,
+  );
 
   if authenticated_rs.is_err() {
     tracing::info!("Client {addr} authenticated failed");
     return;
   }
   let mut client_session = authenticated_rs.unwrap();
-  CLIENT_SESSIONS
-    .lock()
-    .unwrap()
-    .insert(client_session.user_id, shared_tx.clone());
+  // Group events pass through a gate holding them back until the client sends `Ready`
+  let (events_tx, events_rx) = broadcast::channel::<SMessageType>(1003);
+  spawn_ready_gate(events_rx, client_session.ready.clone(), shared_tx.clone());
+  let session_guard = add_client_session(
+    client_session.user_id,
+    addr,
+    events_tx,
+    app_state.clone(),
+  );
 
   // Received message from client and process message
+  // The session is cleaned up when this task ends, including when it is aborted
   let mut receiving_task = tokio::spawn(async move {
+    let _session_guard = session_guard;
     while let Some(Ok(msg)) = socket_receiver.next().await {
       if process_message(
         msg,
@@ -156,11 +215,12 @@ fn authenticate(
   state: Arc<AppState>,
   current_sender: &mut Sender<SMessageType>,
   addr: SocketAddr,
+  encoding: SocketEncoding,
 ) -> Result<ClientSession, ()> {
   match msg {
-    Message::Text(raw_str) => {
+    Message::Text(_) | Message::Binary(_) => {
       let conn = &mut state.db_pool.get().unwrap();
-      let rs = serde_json::from_slice::<SMessageType>(raw_str.as_bytes());
+      let rs = encoding.decode(&msg);
       if let Err(err) = rs {
         tracing::debug!("Not support socket message type: {}", err.to_string());
         if current_sender
@@ -173,48 +233,66 @@ fn authenticate(
         }
         return Err(());
       }
-      match rs.unwrap() {
+      let s_message = rs.unwrap();
+      if !s_message.is_client_message() {
+        tracing::debug!("Client {addr} sent a server only message type");
+        let _ = current_sender.send(SMessageType::UnSupportMessage(
+          "Server only message type".into(),
+        ));
+        return Err(());
+      }
+      match s_message {
         SMessageType::Authenticate(user_code) => {
-          // Validate user authentication and authorization
-          let user_rs = get_user_by_code(conn, &user_code);
-          if let Err(_err) = user_rs {
-            if current_sender
-              .send(SMessageType::AuthenticateResponse(
-                AuthenticationStatusCode::Other.into(),
-              ))
-              .is_err()
-            {
-              tracing::error!("Failed to send authenticate result message");
-            };
+          // The address may have been blocked by other connections opened at the same time
+          if state.ws_auth_throttle.blocked_for(addr.ip()).is_some() {
+            let _ = current_sender.send(SMessageType::AuthenticateResponse(
+              AuthenticationStatusCode::TooManyFailedAttempts.into(),
+            ));
             return Err(());
           }
-          let user_op = user_rs.unwrap();
-          if let None = user_op {
-            if current_sender
-              .send(SMessageType::AuthenticateResponse(
-                AuthenticationStatusCode::ExpireOrNotFound.into(),
-              ))
-              .is_err()
-            {
-              tracing::error!("Failed to send authenticate result message");
+          // Validate user authentication and authorization
+          let user = match authenticate_user_code(conn, Some(&user_code)) {
+            Ok(user) => user,
+            Err(err) => {
+              let status_code = match err {
+                AuthError::DatabaseError(_) => AuthenticationStatusCode::Other,
+                _ => {
+                  if state.ws_auth_throttle.record_failure(addr.ip()) {
+                    tracing::info!("Block {addr} after too many failed authentications");
+                  }
+                  AuthenticationStatusCode::ExpireOrNotFound
+                }
+              };
+              if current_sender
+                .send(SMessageType::AuthenticateResponse(status_code.into()))
+                .is_err()
+              {
+                tracing::error!("Failed to send authenticate result message");
+              }
+              return Err(());
             }
-            return Err(());
-          }
-          let user = user_op.unwrap();
+          };
 
           if current_sender
             .send(SMessageType::AuthenticateResponse(
-              AuthenticationStatusCode::Success.into(),
+              AuthenticationStatusCode::Success(AuthenticatedUser {
+                user_id: user.id,
+                username: user.username.clone(),
+              })
+              .into(),
             ))
             .is_err()
           {
             tracing::error!("Failed to send authenticate successfully message");
           };
+          state.ws_auth_throttle.reset(addr.ip());
           tracing::debug!("Client {addr} authenticated successfully");
           return Ok(ClientSession {
             user_id: user.id,
             username: user.username,
             addr,
+            ready: Arc::new(Notify::new()),
+            encoding,
           });
         }
 
@@ -222,10 +300,10 @@ fn authenticate(
           tracing::debug!("Cannot handle message ");
         }
       }
-      tracing::debug!(">> {addr} send text message {raw_str:?}");
+      tracing::debug!(">> {addr} send message {msg:?}");
     }
     _ => {
-      tracing::debug!("Only supports authenticated text message type");
+      tracing::debug!("Only supports authenticated text or binary message type");
       let _ = current_sender.send(SMessageType::AuthenticateResponse(
         AuthenticationStatusCode::UnsupportedMessageType.into(),
       ));
@@ -243,14 +321,16 @@ async fn process_message(
   let conn = &mut app_state.db_pool.get().unwrap();
   tracing::debug!(">> Client {} SEND message", client_session.addr);
   match msg {
+    // the websocket answers a ping with a pong of the same payload by itself, the pong is
+    // written with the next frame sent or received by the connection
     Message::Ping(v) => {
       tracing::debug!(">> {} send ping message {v:?}", client_session.addr)
     }
     Message::Pong(v) => {
       tracing::debug!(">> {} send pong message {v:?}", client_session.addr)
     }
-    Message::Text(raw_str) => {
-      let rs = serde_json::from_slice::<SMessageType>(raw_str.as_bytes());
+    Message::Text(_) | Message::Binary(_) => {
+      let rs = client_session.encoding.decode(&msg);
       if let Err(err) = rs {
         tracing::debug!("Not support socket message type: {}", err.to_string());
         if current_sender
@@ -263,10 +343,37 @@ async fn process_message(
         }
         return ControlFlow::Break(());
       }
-      match rs.unwrap() {
+      let s_message = rs.unwrap();
+      if !s_message.is_client_message() {
+        tracing::debug!("Client {} sent a server only message type", client_session.addr);
+        let _ = current_sender.send(SMessageType::UnSupportMessage(
+          "Server only message type".into(),
+        ));
+        return ControlFlow::Continue(());
+      }
+      match s_message {
+        SMessageType::Ready => {
+          tracing::debug!("Client {} is ready to receive group events", client_session.addr);
+          client_session.ready.notify_one();
+        }
+        SMessageType::SendBatch(s_new_messages) => {
+          process_send_batch(
+            conn,
+            client_session,
+            current_sender,
+            s_new_messages,
+            app_state.word_filter.as_ref(),
+          );
+        }
         SMessageType::Send(s_new_message) => {
           if let Some(value) =
-            process_send_message(conn, client_session, s_new_message, current_sender)
+            process_send_message(
+              conn,
+              client_session,
+              s_new_message,
+              current_sender,
+              app_state.word_filter.as_ref(),
+            )
           {
             return value;
           }
@@ -274,20 +381,59 @@ async fn process_message(
         SMessageType::DeleteMessage(delete_message_data) => {
           process_delete_message(conn, client_session, current_sender, delete_message_data);
         }
+        SMessageType::RecallMessage(recall_message_data) => {
+          process_recall_message(conn, client_session, current_sender, recall_message_data);
+        }
         SMessageType::EditMessage(edit_message) => {
-          process_update_message(conn, current_sender, edit_message);
+          process_update_message(
+            conn,
+            client_session,
+            current_sender,
+            app_state.word_filter.as_ref(),
+            edit_message,
+          );
         }
         SMessageType::SeenMessages(messages_request) => {
           process_seen_messages(conn, client_session, current_sender, messages_request);
         }
+        SMessageType::GetMembers(group_data) => {
+          process_get_members(conn, client_session, current_sender, group_data);
+        }
+        SMessageType::LeaveGroup(group_data) => {
+          process_leave_group(conn, client_session, current_sender, group_data);
+        }
+        SMessageType::SetAlias(set_alias_data) => {
+          process_set_alias(conn, client_session, current_sender, set_alias_data);
+        }
+        SMessageType::UnreadCount(group_data) => {
+          process_unread_count(conn, client_session, current_sender, group_data);
+        }
+        SMessageType::UnreadMessages(group_data) => {
+          process_unread_messages(conn, client_session, current_sender, group_data);
+        }
+        SMessageType::QueryStatus(query_status_data) => {
+          process_query_status(conn, client_session, current_sender, query_status_data);
+        }
+        SMessageType::ReplayRange(replay_range_data) => {
+          process_replay_range(conn, client_session, current_sender, replay_range_data);
+        }
+        SMessageType::Ping(ping_data) => {
+          let _ = current_sender.send(SMessageType::Pong(ping_data));
+        }
+        SMessageType::FetchHistory(fetch_history_data) => {
+          process_fetch_history(
+            conn,
+            client_session,
+            current_sender,
+            &app_state.cursor_secret,
+            fetch_history_data,
+          );
+        }
         _ => {
           tracing::debug!("Cannot handle message type");
         }
       }
-      tracing::debug!(">> {} send text message {:?}", client_session.addr, raw_str);
-    }
-    Message::Binary(data) => {
-      tracing::debug!(">> {} send binary message {:?}", client_session.addr, data)
+      tracing::debug!(">> {} send message {:?}", client_session.addr, msg);
     }
     Message::Close(frame) => {
       if let Some(cf) = frame {
@@ -311,24 +457,167 @@ async fn process_message(
 
 fn process_update_message(
   conn: &mut PoolPGConnectionType,
+  client_session: &mut ClientSession,
   current_sender: &mut Sender<SMessageType>,
-  edit_message: SMessageEdit,
+  word_filter: Option<&WordFilter>,
+  mut edit_message: SMessageEdit,
 ) {
-  let SMessageEdit {
-    message_id,
+  let message_id = edit_message.message_id;
+  let current_message = match services::message::get_message(conn, message_id) {
+    Ok(Some(message)) => message,
+    Ok(None) => {
+      let _ = current_sender.send(SMessageType::EditMessageResponse(ResultMessage::new(
+        4,
+        "The message is not found",
+      )));
+      return;
+    }
+    Err(err) => {
+      let _ = current_sender.send(SMessageType::EditMessageResponse(ResultMessage::new(
+        1,
+        &format!("Failed to update message, {}", err),
+      )));
+      return;
+    }
+  };
+  if current_message.user_id != client_session.user_id {
+    let _ = current_sender.send(SMessageType::EditMessageResponse(ResultMessage::new(
+      6,
+      "Only the author can edit the message",
+    )));
+    return;
+  }
+  // the message is broadcast to its own group, never to the group given by the client
+  let group_id = current_message.group_id;
+  // the new content is checked like the content of a sent message
+  match exceeded_message_length(conn, group_id, edit_message.content.as_deref()) {
+    Ok(None) => {}
+    Ok(Some(max_message_length)) => {
+      let _ = current_sender.send(SMessageType::EditMessageResponse(ResultMessage::new(
+        7,
+        &format!(
+          "Message content must be at most {} characters in the group",
+          max_message_length
+        ),
+      )));
+      return;
+    }
+    Err(err) => {
+      let _ = current_sender.send(SMessageType::EditMessageResponse(ResultMessage::new(
+        1,
+        &format!("Failed to update message, {}", err),
+      )));
+      return;
+    }
+  }
+  match services::moderation::moderate_content(
+    conn,
+    word_filter,
     group_id,
-    ..
-  } = edit_message.clone();
-  let message_rs = services::message::update_message(conn, message_id, edit_message.into());
+    edit_message.content.as_deref(),
+  ) {
+    Ok(ModerationOutcome::Allowed) => {}
+    Ok(ModerationOutcome::Masked(masked)) => edit_message.content = Some(masked),
+    Ok(ModerationOutcome::Rejected) => {
+      let _ = current_sender.send(SMessageType::EditMessageResponse(ResultMessage::new(
+        10,
+        "The content contains words which are not allowed in the group",
+      )));
+      return;
+    }
+    Err(err) => {
+      let _ = current_sender.send(SMessageType::EditMessageResponse(ResultMessage::new(
+        1,
+        &format!("Failed to update message, {}", err),
+      )));
+      return;
+    }
+  }
+  if let Some(message_type) = &edit_message.message_type {
+    match services::message::is_valid_type_transition(conn, &current_message, message_type) {
+      Ok(true) => {}
+      Ok(false) => {
+        let _ = current_sender.send(SMessageType::EditMessageResponse(ResultMessage::new(
+          5,
+          "A message without attachments can not be an ATTACHMENT message",
+        )));
+        return;
+      }
+      Err(err) => {
+        let _ = current_sender.send(SMessageType::EditMessageResponse(ResultMessage::new(
+          1,
+          &format!("Failed to update message, {}", err),
+        )));
+        return;
+      }
+    }
+  }
+  let previous_version = current_message.version;
+  let message_rs = services::message::update_message(
+    conn,
+    client_session.user_id,
+    current_message,
+    edit_message.into(),
+  );
   if let Err(ref err) = message_rs {
     let _ = current_sender.send(SMessageType::EditMessageResponse(ResultMessage::new(
       1,
       &format!("Failed to update message, {}", err.to_string()),
     )));
+  } else if let Ok(None) = message_rs {
+    // the message was edited since the given version, or deleted
+    let current_version = services::message::get_message(conn, message_id)
+      .ok()
+      .flatten()
+      .map(|message| message.version);
+    let result = match current_version {
+      Some(version) => ResultMessage::new(
+        3,
+        &format!("The message was modified, the current version is {}", version),
+      ),
+      None => ResultMessage::new(4, "The message is not found"),
+    };
+    let _ = current_sender.send(SMessageType::EditMessageResponse(result));
   } else {
+    let message = message_rs.unwrap().unwrap();
+    // reload attachments, author and quoted message so the event is as complete as `Receive`
+    let attachments = services::attachment::get_attachments_of_messages(conn, &[message.id])
+      .map(|attachments| {
+        attachments
+          .into_iter()
+          .map(AttachmentPayload::from)
+          .collect::<Vec<AttachmentPayload>>()
+      })
+      .inspect_err(|err| {
+        tracing::error!(
+          "Failed to get attachments of message {}: {}",
+          message.id,
+          err.to_string()
+        )
+      })
+      .ok();
+    let username = services::user::get_username(conn, message.user_id)
+      .ok()
+      .flatten();
+    let quoted = message.reply_to.and_then(|reply_to| {
+      services::message::get_quoted_messages(conn, &[reply_to])
+        .ok()
+        .and_then(|mut quoted_messages| quoted_messages.remove(&reply_to))
+    });
+    let mut message_content = SMessageContent::from(message);
+    message_content.attachments = attachments;
+    message_content.username = username;
+    message_content.quoted = quoted;
+    // Nothing changed, the version is the same, only the editor gets the message back
+    if message_content.version == previous_version {
+      let _ = current_sender.send(SMessageType::EditMessageData(message_content));
+      return;
+    }
+    // Every session of the editor is a group member connection, so all of them
+    // (including the one which initiated the edit) receive the edited message
     let _ = send_message_event_to_group(
       conn,
-      SMessageType::EditMessageData(SMessageContent::from(message_rs.unwrap())),
+      SMessageType::EditMessageData(message_content),
       group_id,
     );
   }
@@ -383,11 +672,73 @@ fn process_delete_message(
   }
 }
 
+fn process_recall_message(
+  conn: &mut PoolPGConnectionType,
+  client_session: &mut ClientSession,
+  current_sender: &mut Sender<SMessageType>,
+  RecallMessageData { message_id }: RecallMessageData,
+) {
+  tracing::debug!(">> Client {} RECALL message", client_session.addr);
+  let message = match services::message::get_message(conn, message_id) {
+    Ok(Some(message)) => message,
+    Ok(None) => {
+      let _ = current_sender.send(SMessageType::RecallMessageResponse(ResultMessage::new(
+        1,
+        "Message not found or already recalled",
+      )));
+      return;
+    }
+    Err(_err) => {
+      let _ = current_sender.send(SMessageType::RecallMessageResponse(ResultMessage::new(
+        4,
+        "Failed to recall message, try again later",
+      )));
+      return;
+    }
+  };
+  if message.user_id != client_session.user_id {
+    let _ = current_sender.send(SMessageType::RecallMessageResponse(ResultMessage::new(
+      2,
+      "User is not the owner of the message",
+    )));
+    return;
+  }
+  let recall_deadline =
+    message.created_at + chrono::Duration::minutes(MESSAGE_RECALL_WINDOW_MINUTES);
+  if Utc::now().naive_utc() > recall_deadline {
+    let _ = current_sender.send(SMessageType::RecallMessageResponse(ResultMessage::new(
+      3,
+      &format!(
+        "Message can only be recalled within {} minutes after sending",
+        MESSAGE_RECALL_WINDOW_MINUTES
+      ),
+    )));
+    return;
+  }
+
+  if services::message::recall_message(conn, message_id).is_err() {
+    let _ = current_sender.send(SMessageType::RecallMessageResponse(ResultMessage::new(
+      4,
+      "Failed to recall message, try again later",
+    )));
+    return;
+  }
+  let _ = send_message_event_to_group(
+    conn,
+    SMessageType::RecallMessageEvent(RecalledMessageData {
+      group_id: message.group_id,
+      message_id,
+    }),
+    message.group_id,
+  );
+}
+
 fn process_send_message(
   conn: &mut PoolPGConnectionType,
   client_session: &mut ClientSession,
-  s_new_message: crate::payloads::socket::message::SNewMessage,
+  mut s_new_message: SNewMessage,
   current_sender: &mut Sender<SMessageType>,
+  word_filter: Option<&WordFilter>,
 ) -> Option<ControlFlow<()>> {
   tracing::debug!(
     ">> Client {} SEND message: {:?}",
@@ -396,51 +747,185 @@ fn process_send_message(
   );
   if let Ok(rs) = check_user_join_group(conn, client_session.user_id, s_new_message.group_id) {
     if rs {
-      let insert_message = s_new_message.build_new_message(client_session.user_id);
-      let insertion_rs = create_new_message(conn, insert_message);
-
-      if insertion_rs.is_err() {
-        return Some(ControlFlow::Break(()));
+      match services::group::is_group_archived(conn, s_new_message.group_id) {
+        Ok(false) => {}
+        Ok(true) => {
+          let _ = current_sender.send(SMessageType::SendMessageResponse(ResultMessage::new(
+            8,
+            "The group is archived, new messages are not allowed",
+          )));
+          return None;
+        }
+        Err(_) => {
+          let _ = current_sender.send(SMessageType::SendMessageResponse(ResultMessage::new(
+            5,
+            "Failed to send message, try again later",
+          )));
+          return None;
+        }
+      }
+      match services::group::can_post_in_group(
+        conn,
+        client_session.user_id,
+        s_new_message.group_id,
+      ) {
+        Ok(true) => {}
+        Ok(false) => {
+          let _ = current_sender.send(SMessageType::SendMessageResponse(ResultMessage::new(
+            9,
+            "Only the owner can send messages in the group",
+          )));
+          return None;
+        }
+        Err(_) => {
+          let _ = current_sender.send(SMessageType::SendMessageResponse(ResultMessage::new(
+            5,
+            "Failed to send message, try again later",
+          )));
+          return None;
+        }
+      }
+      match services::group::get_slow_mode_remaining(
+        conn,
+        client_session.user_id,
+        s_new_message.group_id,
+      ) {
+        Ok(None) => {}
+        Ok(Some(remaining)) => {
+          let _ = current_sender.send(SMessageType::SendMessageResponse(ResultMessage::new(
+            6,
+            &format!("Slow mode is enabled, retry in {} seconds", remaining),
+          )));
+          return None;
+        }
+        Err(_) => {
+          let _ = current_sender.send(SMessageType::SendMessageResponse(ResultMessage::new(
+            5,
+            "Failed to send message, try again later",
+          )));
+          return None;
+        }
       }
-      let inserted_message = insertion_rs.unwrap();
-      let mut inserted_attachment_payloads = None;
-      if let Some(attachments) = s_new_message.attachments {
-        let new_attachments = attachments
-          .iter()
-          .map(|e| AttachmentPayload::into_new(e, inserted_message.id))
-          .collect();
-
-        match services::attachment::create_attachments(conn, new_attachments) {
-          Ok(inserted_attachments) => {
-            inserted_attachment_payloads = Some(
-              inserted_attachments
-                .iter()
-                .map(|e| AttachmentPayload::from(e.clone()))
-                .collect::<Vec<AttachmentPayload>>(),
-            );
+      match exceeded_message_length(
+        conn,
+        s_new_message.group_id,
+        s_new_message.content.as_deref(),
+      ) {
+        Ok(None) => {}
+        Ok(Some(max_message_length)) => {
+          let _ = current_sender.send(SMessageType::SendMessageResponse(ResultMessage::new(
+            7,
+            &format!(
+              "Message content must be at most {} characters in the group",
+              max_message_length
+            ),
+          )));
+          return None;
+        }
+        Err(_) => {
+          let _ = current_sender.send(SMessageType::SendMessageResponse(ResultMessage::new(
+            5,
+            "Failed to send message, try again later",
+          )));
+          return None;
+        }
+      }
+      match services::moderation::moderate_content(
+        conn,
+        word_filter,
+        s_new_message.group_id,
+        s_new_message.content.as_deref(),
+      ) {
+        Ok(ModerationOutcome::Allowed) => {}
+        Ok(ModerationOutcome::Masked(masked)) => s_new_message.content = Some(masked),
+        Ok(ModerationOutcome::Rejected) => {
+          let _ = current_sender.send(SMessageType::SendMessageResponse(ResultMessage::new(
+            10,
+            "The content contains words which are not allowed in the group",
+          )));
+          return None;
+        }
+        Err(_) => {
+          let _ = current_sender.send(SMessageType::SendMessageResponse(ResultMessage::new(
+            5,
+            "Failed to send message, try again later",
+          )));
+          return None;
+        }
+      }
+      // the replied message must belong to the same group
+      let mut quoted = None;
+      if let Some(reply_to) = s_new_message.reply_to {
+        match services::message::check_message_in_group(conn, reply_to, s_new_message.group_id) {
+          Ok(true) => {
+            quoted = services::message::get_quoted_messages(conn, &[reply_to])
+              .ok()
+              .and_then(|mut quoted_messages| quoted_messages.remove(&reply_to));
           }
-          Err(err) => {
-            tracing::error!(
-              "Failed to create new attachments of message id {}: {} ",
-              inserted_message.id,
-              err.to_string()
-            )
+          Ok(false) => {
+            let _ = current_sender.send(SMessageType::SendMessageResponse(ResultMessage::new(
+              3,
+              "The replied message does not exist in the group",
+            )));
+            return None;
+          }
+          Err(_) => {
+            let _ = current_sender.send(SMessageType::SendMessageResponse(ResultMessage::new(
+              4,
+              "Failed to check the replied message",
+            )));
+            return None;
           }
         }
       }
+      let insert_message = s_new_message.build_new_message(client_session.user_id);
+      let insertion_rs = services::message::create_message_with_attachments(
+        conn,
+        insert_message,
+        s_new_message.attachments.as_deref().unwrap_or_default(),
+      );
+      let Ok((inserted_message, inserted_attachments)) = insertion_rs else {
+        let _ = current_sender.send(SMessageType::SendMessageResponse(ResultMessage::new(
+          5,
+          "Failed to send message, try again later",
+        )));
+        return None;
+      };
+      let inserted_attachment_payloads = s_new_message.attachments.as_ref().map(|_| {
+        inserted_attachments
+          .into_iter()
+          .map(AttachmentPayload::from)
+          .collect::<Vec<AttachmentPayload>>()
+      });
+      let message_id = inserted_message.id;
       let mut message_content = SMessageContent::from(inserted_message);
       message_content.attachments = inserted_attachment_payloads;
       message_content.username = Some(client_session.username.clone());
+      message_content.quoted = quoted;
+      // The sender is a member, its connections get their single `Receive` from this fan-out,
+      // so it must not be echoed to `current_sender`
       let send_rs = connections::send_message_event_to_group(
         conn,
         SMessageType::Receive(message_content),
         s_new_message.group_id,
       );
-      if send_rs.is_err() {
-        tracing::error!("Failed to send message event to group");
-      } else {
-        tracing::debug!("Send new message to {} clients", send_rs.unwrap());
-      }
+      let delivered_count = match send_rs {
+        Ok(count) => {
+          tracing::debug!("Send new message to {} clients", count);
+          count
+        }
+        Err(_) => {
+          tracing::error!("Failed to send message event to group");
+          0
+        }
+      };
+      // inform the sender how many connections received the message
+      let _ = current_sender.send(SMessageType::SendResponse(SendResultData {
+        message_uuid: s_new_message.message_uuid,
+        message_id,
+        group_id: s_new_message.group_id,
+        delivered_count,
+      }));
     } else {
       tracing::debug!(
         "Client {} did  not joined group {}",
@@ -448,13 +933,13 @@ fn process_send_message(
         s_new_message.group_id
       );
       if current_sender
-        .send(SMessageType::AuthenticateResponse(
-          AuthenticationStatusCode::NoPermission.into(),
+        .send(SMessageType::SendMessageResponse(
+          ResultMessage::not_a_member(s_new_message.group_id),
         ))
         .is_err()
       {
         tracing::error!(
-          "Failed to send AuthenticateResponse to client {}",
+          "Failed to send SendMessageResponse to client {}",
           client_session.addr
         );
       }
@@ -477,6 +962,174 @@ fn process_send_message(
   None
 }
 
+/// Get the maximum message length of the group if `content` is longer than it
+fn exceeded_message_length(
+  conn: &mut PoolPGConnectionType,
+  group_id: i32,
+  content: Option<&str>,
+) -> Result<Option<usize>, DBError> {
+  let Some(content) = content else {
+    return Ok(None);
+  };
+  let max_message_length = services::group::get_max_message_length(conn, group_id)?;
+  Ok((content.chars().count() > max_message_length).then_some(max_message_length))
+}
+
+/// Insert messages of a batch in a single transaction, then propagate each of them
+///
+/// Messages which can not be sent, e.g. to a group the user didn't join, are rejected
+/// without failing the whole batch
+fn process_send_batch(
+  conn: &mut PoolPGConnectionType,
+  client_session: &mut ClientSession,
+  current_sender: &mut Sender<SMessageType>,
+  s_new_messages: Vec<SNewMessage>,
+  word_filter: Option<&WordFilter>,
+) {
+  if s_new_messages.len() > MAX_SEND_BATCH_SIZE {
+    let _ = current_sender.send(SMessageType::SendBatchResponse(ResultMessage::new(
+      1,
+      &format!("At most {} messages are allowed", MAX_SEND_BATCH_SIZE),
+    )));
+    return;
+  }
+
+  // check membership once per distinct group
+  let mut joined_groups: HashMap<i32, bool> = HashMap::new();
+  for s_new_message in &s_new_messages {
+    if joined_groups.contains_key(&s_new_message.group_id) {
+      continue;
+    }
+    let Ok(joined) = check_user_join_group(conn, client_session.user_id, s_new_message.group_id)
+    else {
+      let _ = current_sender.send(SMessageType::SendBatchResponse(ResultMessage::new(
+        2,
+        "Failed to check user joined group, try again later",
+      )));
+      return;
+    };
+    joined_groups.insert(s_new_message.group_id, joined);
+  }
+
+  let user_id = client_session.user_id;
+  let transaction_rs = conn.transaction::<_, DBError, _>(|conn| {
+    let mut results = Vec::with_capacity(s_new_messages.len());
+    let mut inserted = Vec::new();
+    for s_new_message in &s_new_messages {
+      let group_id = s_new_message.group_id;
+      // earlier messages of the batch are visible inside the transaction, so slow mode
+      // and replies to them are checked as if the messages were sent one by one
+      let rejection = if !joined_groups.get(&group_id).copied().unwrap_or(false) {
+        Some(ApiError::NotAMember(group_id).to_string())
+      } else if services::group::is_group_archived(conn, group_id)? {
+        Some("The group is archived, new messages are not allowed".to_string())
+      } else if !services::group::can_post_in_group(conn, user_id, group_id)? {
+        Some("Only the owner can send messages in the group".to_string())
+      } else if let Some(remaining) =
+        services::group::get_slow_mode_remaining(conn, user_id, group_id)?
+      {
+        Some(format!("Slow mode is enabled, retry in {} seconds", remaining))
+      } else if let Some(max_message_length) =
+        exceeded_message_length(conn, group_id, s_new_message.content.as_deref())?
+      {
+        Some(format!(
+          "Message content must be at most {} characters in the group",
+          max_message_length
+        ))
+      } else if let Some(reply_to) = s_new_message.reply_to {
+        (!services::message::check_message_in_group(conn, reply_to, group_id)?)
+          .then(|| "The replied message does not exist in the group".to_string())
+      } else {
+        None
+      };
+      let mut masked_content = None;
+      let rejection = match rejection {
+        Some(error) => Some(error),
+        None => match services::moderation::moderate_content(
+          conn,
+          word_filter,
+          group_id,
+          s_new_message.content.as_deref(),
+        )? {
+          ModerationOutcome::Allowed => None,
+          ModerationOutcome::Masked(masked) => {
+            masked_content = Some(masked);
+            None
+          }
+          ModerationOutcome::Rejected => Some(ApiError::ContentRejected.to_string()),
+        },
+      };
+      if let Some(error) = rejection {
+        results.push(BatchItemResultData {
+          message_uuid: s_new_message.message_uuid,
+          group_id,
+          message_id: None,
+          error: Some(error),
+        });
+        continue;
+      }
+
+      let mut new_message = s_new_message.build_new_message(user_id);
+      if masked_content.is_some() {
+        new_message.content = masked_content.as_ref();
+      }
+      let (message, attachments) = services::message::create_message_with_attachments(
+        conn,
+        new_message,
+        s_new_message.attachments.as_deref().unwrap_or_default(),
+      )?;
+      results.push(BatchItemResultData {
+        message_uuid: s_new_message.message_uuid,
+        group_id,
+        message_id: Some(message.id),
+        error: None,
+      });
+      let attachments = s_new_message.attachments.as_ref().map(|_| {
+        attachments
+          .into_iter()
+          .map(AttachmentPayload::from)
+          .collect::<Vec<AttachmentPayload>>()
+      });
+      inserted.push((message, attachments));
+    }
+    Ok((results, inserted))
+  });
+  let Ok((results, inserted)) = transaction_rs else {
+    let _ = current_sender.send(SMessageType::SendBatchResponse(ResultMessage::new(
+      3,
+      "Failed to send messages, try again later",
+    )));
+    return;
+  };
+
+  let reply_ids = inserted
+    .iter()
+    .filter_map(|(message, _)| message.reply_to)
+    .collect::<Vec<i32>>();
+  let quoted_messages =
+    services::message::get_quoted_messages(conn, &reply_ids).unwrap_or_default();
+  for (message, attachments) in inserted {
+    let group_id = message.group_id;
+    let mut message_content = SMessageContent::from(message);
+    message_content.attachments = attachments;
+    message_content.username = Some(client_session.username.clone());
+    message_content.quoted = message_content
+      .reply_to
+      .and_then(|id| quoted_messages.get(&id).cloned());
+    // The sender gets its single `Receive` of each message from the fan-out, like in `Send`
+    if connections::send_message_event_to_group(
+      conn,
+      SMessageType::Receive(message_content),
+      group_id,
+    )
+    .is_err()
+    {
+      tracing::error!("Failed to send message event to group {}", group_id);
+    }
+  }
+  let _ = current_sender.send(SMessageType::SendBatchResult(results));
+}
+
 fn process_seen_messages(
   conn: &mut PoolPGConnectionType,
   client_session: &mut ClientSession,
@@ -489,10 +1142,9 @@ fn process_seen_messages(
   // check current user joined the group
   if let Ok(joined) = check_user_join_group(conn, client_session.user_id, group_id) {
     if !joined {
-      let _ = current_sender.send(SMessageType::SeenMessagesResponse(ResultMessage::new(
-        1,
-        "User hasn't joined the group",
-      )));
+      let _ = current_sender.send(SMessageType::SeenMessagesResponse(
+        ResultMessage::not_a_member(group_id),
+      ));
       return;
     }
   } else {
@@ -516,6 +1168,15 @@ fn process_seen_messages(
 
   let messages = messages_rs.unwrap();
 
+  let requested_ids: HashSet<&i32> = message_ids.iter().collect();
+  if messages.len() != requested_ids.len() {
+    let _ = current_sender.send(SMessageType::SeenMessagesResponse(ResultMessage::new(
+      6,
+      "One of messages doesn't exist",
+    )));
+    return;
+  }
+
   if messages.iter().any(|message| message.group_id != group_id) {
     let _ = current_sender.send(SMessageType::SeenMessagesResponse(ResultMessage::new(
       4,
@@ -534,13 +1195,482 @@ fn process_seen_messages(
     return;
   }
 
+  // advance last seen pointer of current user, only to a message that exists
+  if let Some(latest_seen_id) = messages.iter().map(|message| message.id).max() {
+    if let Err(err) = services::message::update_last_seen_message(
+      conn,
+      client_session.user_id,
+      group_id,
+      latest_seen_id,
+    ) {
+      tracing::error!("Failed to update last seen message: {}", err.to_string());
+    }
+  }
+
+  // propagate seen message to active client connections, bursts of seen events are merged
+  if connections::send_seen_event_to_group(conn, group_id, message_ids).is_err() {
+    tracing::error!("Failed to send seen messages event to group {group_id}");
+  }
+}
+
+fn process_get_members(
+  conn: &mut PoolPGConnectionType,
+  client_session: &mut ClientSession,
+  current_sender: &mut Sender<SMessageType>,
+  GroupData { group_id }: GroupData,
+) {
+  // check current user joined the group
+  if let Ok(joined) = check_user_join_group(conn, client_session.user_id, group_id) {
+    if !joined {
+      let _ = current_sender.send(SMessageType::GetMembersResponse(
+        ResultMessage::not_a_member(group_id),
+      ));
+      return;
+    }
+  } else {
+    let _ = current_sender.send(SMessageType::GetMembersResponse(ResultMessage::new(
+      2,
+      "Failed to check user joined group, try again later",
+    )));
+    return;
+  }
+
+  let group_rs = services::group::get_group_info(conn, group_id);
+  let members_rs = services::user::get_members_of_group(conn, group_id, MAX_SOCKET_MEMBERS_LIST);
+  let (Ok(Some(group)), Ok(members)) = (group_rs, members_rs) else {
+    let _ = current_sender.send(SMessageType::GetMembersResponse(ResultMessage::new(
+      3,
+      "Failed to get members of group, try again later",
+    )));
+    return;
+  };
+
+  let member_ids = members.iter().map(|(user_id, ..)| *user_id).collect::<Vec<i32>>();
+  let online_user_ids = get_online_user_ids(&member_ids);
+  let members = members
+    .into_iter()
+    .map(|(user_id, username, alias)| MemberInfo {
+      user_id,
+      username,
+      alias,
+      is_owner: user_id == group.user_id,
+      is_online: online_user_ids.contains(&user_id),
+    })
+    .collect();
+  let _ = current_sender.send(SMessageType::MembersList(MembersData { group_id, members }));
+}
+
+/// Remove the current user from a group, then inform the remaining members and the other
+/// clients of the user by a `MemberLeftEvent`
+fn process_leave_group(
+  conn: &mut PoolPGConnectionType,
+  client_session: &mut ClientSession,
+  current_sender: &mut Sender<SMessageType>,
+  GroupData { group_id }: GroupData,
+) {
+  let user_id = client_session.user_id;
+  let result = match services::group::leave_group(conn, user_id, group_id) {
+    Ok(LeaveGroupOutcome::Left) => ResultMessage::new(0, "Left the group"),
+    Ok(LeaveGroupOutcome::GroupNotFound) => ResultMessage::new(1, "Group not found"),
+    Ok(LeaveGroupOutcome::IsOwner) => ResultMessage::new(
+      2,
+      "The owner cannot leave the group, delete the group instead",
+    ),
+    Ok(LeaveGroupOutcome::NotAMember) => ResultMessage::not_a_member(group_id),
+    Err(_) => ResultMessage::new(3, "Failed to leave the group, try again later"),
+  };
+  if result.status_code != 0 {
+    let _ = current_sender.send(SMessageType::LeaveGroupResponse(result));
+    return;
+  }
+
+  // The user stops receiving events of the group
+  revoke_group_members(group_id, &[user_id]);
+  let event = SMessageType::MemberLeftEvent(MemberLeftData { group_id, user_id });
+  let _ = send_message_event_to_group(conn, event.clone(), group_id);
+  send_message_event_to_user(user_id, event);
+  let _ = current_sender.send(SMessageType::LeaveGroupResponse(result));
+}
+
+/// Set the alias of the current user in a group, then inform connected members by an `AliasChangedEvent`
+fn process_set_alias(
+  conn: &mut PoolPGConnectionType,
+  client_session: &mut ClientSession,
+  current_sender: &mut Sender<SMessageType>,
+  SetAliasData { group_id, alias }: SetAliasData,
+) {
+  let alias = alias.trim();
+  if alias.is_empty() || alias.chars().count() > MAX_ALIAS_LENGTH {
+    let _ = current_sender.send(SMessageType::SetAliasResponse(ResultMessage::new(
+      3,
+      &format!("Alias must have between 1 and {} characters", MAX_ALIAS_LENGTH),
+    )));
+    return;
+  }
+
+  match services::group::update_alias(conn, client_session.user_id, group_id, alias) {
+    Ok(true) => {}
+    Ok(false) => {
+      let _ = current_sender.send(SMessageType::SetAliasResponse(
+        ResultMessage::not_a_member(group_id),
+      ));
+      return;
+    }
+    Err(_) => {
+      let _ = current_sender.send(SMessageType::SetAliasResponse(ResultMessage::new(
+        2,
+        "Failed to set alias, try again later",
+      )));
+      return;
+    }
+  }
   let _ = send_message_event_to_group(
     conn,
-    SMessageType::SeenMessagesEvent(MessagesData {
+    SMessageType::AliasChangedEvent(AliasChangedData {
       group_id,
-      message_ids,
+      user_id: client_session.user_id,
+      alias: alias.to_string(),
     }),
     group_id,
   );
-  // propagate seen message to active client connections
+}
+
+fn process_unread_count(
+  conn: &mut PoolPGConnectionType,
+  client_session: &mut ClientSession,
+  current_sender: &mut Sender<SMessageType>,
+  GroupData { group_id }: GroupData,
+) {
+  // check current user joined the group
+  if let Ok(joined) = check_user_join_group(conn, client_session.user_id, group_id) {
+    if !joined {
+      let _ = current_sender.send(SMessageType::UnreadCountResponse(
+        ResultMessage::not_a_member(group_id),
+      ));
+      return;
+    }
+  } else {
+    let _ = current_sender.send(SMessageType::UnreadCountResponse(ResultMessage::new(
+      2,
+      "Failed to check user joined group, try again later",
+    )));
+    return;
+  }
+
+  match services::message::count_unseen_messages(conn, client_session.user_id, group_id) {
+    Ok(count) => {
+      let _ = current_sender.send(SMessageType::UnreadCountResult(UnreadCountData {
+        group_id,
+        count,
+      }));
+    }
+    Err(_err) => {
+      let _ = current_sender.send(SMessageType::UnreadCountResponse(ResultMessage::new(
+        3,
+        "Failed to count unseen messages, try again later",
+      )));
+    }
+  }
+}
+
+fn process_unread_messages(
+  conn: &mut PoolPGConnectionType,
+  client_session: &mut ClientSession,
+  current_sender: &mut Sender<SMessageType>,
+  GroupData { group_id }: GroupData,
+) {
+  // check current user joined the group
+  if let Ok(joined) = check_user_join_group(conn, client_session.user_id, group_id) {
+    if !joined {
+      let _ = current_sender.send(SMessageType::UnreadMessagesResponse(
+        ResultMessage::not_a_member(group_id),
+      ));
+      return;
+    }
+  } else {
+    let _ = current_sender.send(SMessageType::UnreadMessagesResponse(ResultMessage::new(
+      2,
+      "Failed to check user joined group, try again later",
+    )));
+    return;
+  }
+
+  // load one more id to know if there are more unseen messages than the maximum
+  match services::message::get_unseen_message_ids(
+    conn,
+    client_session.user_id,
+    group_id,
+    MAX_UNREAD_MESSAGE_IDS + 1,
+  ) {
+    Ok(mut message_ids) => {
+      let has_more = message_ids.len() as i64 > MAX_UNREAD_MESSAGE_IDS;
+      message_ids.truncate(MAX_UNREAD_MESSAGE_IDS as usize);
+      let _ = current_sender.send(SMessageType::UnreadMessagesResult(UnreadMessagesData {
+        group_id,
+        message_ids,
+        has_more,
+      }));
+    }
+    Err(_err) => {
+      let _ = current_sender.send(SMessageType::UnreadMessagesResponse(ResultMessage::new(
+        3,
+        "Failed to get unseen messages, try again later",
+      )));
+    }
+  }
+}
+
+fn process_query_status(
+  conn: &mut PoolPGConnectionType,
+  client_session: &mut ClientSession,
+  current_sender: &mut Sender<SMessageType>,
+  QueryStatusData { message_ids }: QueryStatusData,
+) {
+  if message_ids.len() > MAX_QUERY_STATUS_IDS {
+    let _ = current_sender.send(SMessageType::QueryStatusResponse(ResultMessage::new(
+      1,
+      &format!("At most {} message ids are allowed", MAX_QUERY_STATUS_IDS),
+    )));
+    return;
+  }
+
+  match services::message::get_status_of_sent_messages(conn, client_session.user_id, &message_ids)
+  {
+    Ok(statuses) => {
+      let statuses = statuses
+        .into_iter()
+        .map(|(message_id, status)| MessageStatusData {
+          message_id,
+          status: SMessageStatus::from(status),
+        })
+        .collect();
+      let _ = current_sender.send(SMessageType::StatusResult(statuses));
+    }
+    Err(_err) => {
+      let _ = current_sender.send(SMessageType::QueryStatusResponse(ResultMessage::new(
+        2,
+        "Failed to get status of messages, try again later",
+      )));
+    }
+  }
+}
+
+/// Build message contents of a history page with their attachments, author and quoted message
+fn build_history_messages(
+  conn: &mut PoolPGConnectionType,
+  messages: Vec<(models::Message, String)>,
+) -> Result<Vec<SMessageContent>, DBError> {
+  let message_ids = messages.iter().map(|(message, _)| message.id).collect::<Vec<i32>>();
+  let attachments = services::attachment::get_attachments_of_messages(conn, &message_ids)?;
+  let mut grouped_attachments: HashMap<i32, Vec<AttachmentPayload>> = HashMap::new();
+  for attachment in attachments {
+    grouped_attachments
+      .entry(attachment.message_id)
+      .or_default()
+      .push(AttachmentPayload::from(attachment));
+  }
+  let reply_ids = messages
+    .iter()
+    .filter_map(|(message, _)| message.reply_to)
+    .collect::<Vec<i32>>();
+  let quoted_messages = services::message::get_quoted_messages(conn, &reply_ids)?;
+
+  Ok(
+    messages
+      .into_iter()
+      .map(|(message, username)| {
+        let attachments = grouped_attachments.remove(&message.id);
+        let mut message_content = SMessageContent::from(message);
+        message_content.attachments = attachments;
+        message_content.username = Some(username);
+        message_content.quoted = message_content
+          .reply_to
+          .and_then(|id| quoted_messages.get(&id).cloned());
+        message_content
+      })
+      .collect(),
+  )
+}
+
+/// Send messages of a group whose id is in `from_id..=to_id`, oldest first
+///
+/// Used by clients to recover events missed while lagging. At most `MAX_SOCKET_HISTORY_LIMIT`
+/// messages are sent, `has_more` tells the client to request the rest of the range
+/// Whether `from_id..=to_id` is not empty and covers at most `MAX_REPLAY_RANGE` ids,
+/// the ids are sent by the client so the span is computed without overflow
+fn is_valid_replay_range(from_id: i32, to_id: i32) -> bool {
+  to_id
+    .checked_sub(from_id)
+    .is_some_and(|span| (0..MAX_REPLAY_RANGE).contains(&span))
+}
+
+fn process_replay_range(
+  conn: &mut PoolPGConnectionType,
+  client_session: &mut ClientSession,
+  current_sender: &mut Sender<SMessageType>,
+  ReplayRangeData {
+    group_id,
+    from_id,
+    to_id,
+  }: ReplayRangeData,
+) {
+  if !is_valid_replay_range(from_id, to_id) {
+    let _ = current_sender.send(SMessageType::ReplayRangeResponse(ResultMessage::new(
+      4,
+      &format!(
+        "The range must not be empty and must cover at most {} ids",
+        MAX_REPLAY_RANGE
+      ),
+    )));
+    return;
+  }
+  // check current user joined the group
+  if let Ok(joined) = check_user_join_group(conn, client_session.user_id, group_id) {
+    if !joined {
+      let _ = current_sender.send(SMessageType::ReplayRangeResponse(
+        ResultMessage::not_a_member(group_id),
+      ));
+      return;
+    }
+  } else {
+    let _ = current_sender.send(SMessageType::ReplayRangeResponse(ResultMessage::new(
+      2,
+      "Failed to check user joined group, try again later",
+    )));
+    return;
+  }
+
+  // load one more message to know if the range has more messages
+  let messages_rs = services::message::get_messages_in_range(
+    conn,
+    group_id,
+    from_id,
+    to_id,
+    MAX_SOCKET_HISTORY_LIMIT + 1,
+  );
+  let Ok(mut messages) = messages_rs else {
+    let _ = current_sender.send(SMessageType::ReplayRangeResponse(ResultMessage::new(
+      3,
+      "Failed to get messages, try again later",
+    )));
+    return;
+  };
+  let has_more = messages.len() as i64 > MAX_SOCKET_HISTORY_LIMIT;
+  messages.truncate(MAX_SOCKET_HISTORY_LIMIT as usize);
+  let Ok(messages) = build_history_messages(conn, messages) else {
+    let _ = current_sender.send(SMessageType::ReplayRangeResponse(ResultMessage::new(
+      3,
+      "Failed to get messages, try again later",
+    )));
+    return;
+  };
+  let _ = current_sender.send(SMessageType::HistoryPage(HistoryPageData {
+    group_id,
+    messages,
+    has_more,
+    next_cursor: None,
+  }));
+}
+
+fn process_fetch_history(
+  conn: &mut PoolPGConnectionType,
+  client_session: &mut ClientSession,
+  current_sender: &mut Sender<SMessageType>,
+  cursor_secret: &str,
+  FetchHistoryData {
+    group_id,
+    cursor,
+    limit,
+  }: FetchHistoryData,
+) {
+  // check current user joined the group
+  if let Ok(joined) = check_user_join_group(conn, client_session.user_id, group_id) {
+    if !joined {
+      let _ = current_sender.send(SMessageType::FetchHistoryResponse(
+        ResultMessage::not_a_member(group_id),
+      ));
+      return;
+    }
+  } else {
+    let _ = current_sender.send(SMessageType::FetchHistoryResponse(ResultMessage::new(
+      2,
+      "Failed to check user joined group, try again later",
+    )));
+    return;
+  }
+
+  // the cursor must be issued by the server for the same group
+  let before_id = match cursor {
+    Some(cursor) => match decode_cursor(cursor_secret, group_id, &cursor) {
+      Some(message_id) => Some(message_id),
+      None => {
+        let _ = current_sender.send(SMessageType::FetchHistoryResponse(ResultMessage::new(
+          4,
+          "Invalid cursor",
+        )));
+        return;
+      }
+    },
+    None => None,
+  };
+  let limit = limit
+    .unwrap_or(DEFAULT_PAGE_SIZE as i64)
+    .clamp(1, MAX_SOCKET_HISTORY_LIMIT);
+  // load one more message to know if there are older messages
+  let messages_rs = services::message::get_messages_before(conn, group_id, before_id, limit + 1);
+  let Ok(mut messages) = messages_rs else {
+    let _ = current_sender.send(SMessageType::FetchHistoryResponse(ResultMessage::new(
+      3,
+      "Failed to get messages, try again later",
+    )));
+    return;
+  };
+  let has_more = messages.len() as i64 > limit;
+  messages.truncate(limit as usize);
+  let next_cursor = if has_more {
+    messages
+      .last()
+      .map(|(message, _)| encode_cursor(cursor_secret, group_id, message.id))
+  } else {
+    None
+  };
+
+  let Ok(messages) = build_history_messages(conn, messages) else {
+    let _ = current_sender.send(SMessageType::FetchHistoryResponse(ResultMessage::new(
+      3,
+      "Failed to get messages, try again later",
+    )));
+    return;
+  };
+  let _ = current_sender.send(SMessageType::HistoryPage(HistoryPageData {
+    group_id,
+    messages,
+    has_more,
+    next_cursor,
+  }));
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn replay_range_must_not_be_empty() {
+    assert!(is_valid_replay_range(5, 5));
+    assert!(is_valid_replay_range(1, 42));
+    assert!(!is_valid_replay_range(42, 1));
+  }
+
+  #[test]
+  fn replay_range_is_limited() {
+    assert!(is_valid_replay_range(1, MAX_REPLAY_RANGE));
+    assert!(!is_valid_replay_range(1, MAX_REPLAY_RANGE + 1));
+  }
+
+  #[test]
+  fn replay_range_rejects_overflowing_span() {
+    assert!(!is_valid_replay_range(i32::MIN, i32::MAX));
+    assert!(!is_valid_replay_range(-1, i32::MAX));
+    assert!(!is_valid_replay_range(i32::MAX, i32::MIN));
+  }
 }
@@ -1,3 +1,4 @@
 pub mod connections;
 pub mod handler;
 pub mod structs;
+pub mod throttle;
@@ -1,8 +1,62 @@
-use std::net::SocketAddr;
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::extract::ws::Message;
+use serde::Deserialize;
+use tokio::sync::Notify;
+
+use crate::payloads::socket::message::SMessageType;
 
 #[derive(Clone)]
 pub struct ClientSession {
   pub user_id: i32,
   pub username: String,
   pub addr: SocketAddr,
+  /// Notified when the client sends `Ready`, group events are held back until then
+  pub ready: Arc<Notify>,
+  pub encoding: SocketEncoding,
+}
+
+/// Query parameters of the websocket endpoint
+#[derive(Deserialize)]
+pub struct SocketParams {
+  #[serde(default)]
+  pub encoding: SocketEncoding,
+}
+
+/// Encoding of websocket messages, chosen by the client with the `encoding` query parameter
+/// when connecting. JSON messages are sent in text frames, MessagePack messages in binary frames
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SocketEncoding {
+  #[default]
+  Json,
+  #[serde(alias = "messagepack")]
+  Msgpack,
+}
+
+impl SocketEncoding {
+  pub fn encode(&self, message: &SMessageType) -> Result<Message, String> {
+    match self {
+      SocketEncoding::Json => serde_json::to_string(message)
+        .map(Message::Text)
+        .map_err(|err| err.to_string()),
+      // structs are encoded as maps, so messages have the same shape as in JSON
+      SocketEncoding::Msgpack => rmp_serde::to_vec_named(message)
+        .map(Message::Binary)
+        .map_err(|err| err.to_string()),
+    }
+  }
+
+  /// Decode a text frame of a JSON connection or a binary frame of a MessagePack connection
+  pub fn decode(&self, frame: &Message) -> Result<SMessageType, String> {
+    match (self, frame) {
+      (SocketEncoding::Json, Message::Text(raw_str)) => {
+        serde_json::from_str::<SMessageType>(raw_str).map_err(|err| err.to_string())
+      }
+      (SocketEncoding::Msgpack, Message::Binary(data)) => {
+        rmp_serde::from_slice::<SMessageType>(data).map_err(|err| err.to_string())
+      }
+      _ => Err(format!("{:?} connections do not support this frame type", self)),
+    }
+  }
 }
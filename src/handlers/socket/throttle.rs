@@ -0,0 +1,215 @@
+use std::{
+  collections::HashMap,
+  net::IpAddr,
+  sync::{Arc, Mutex},
+  time::{Duration, Instant},
+};
+
+/// Failed authentications of a single remote address
+struct FailedAuthentications {
+  count: u32,
+  last_failure: Instant,
+  blocked_until: Option<Instant>,
+}
+
+/// Throttle websocket authentication per remote ip address
+///
+/// After `max_failures` consecutive failures the address is blocked for `cooldown`,
+/// so user codes can not be brute-forced by opening many connections
+pub struct AuthThrottle {
+  max_failures: u32,
+  cooldown: Duration,
+  failures: Mutex<HashMap<IpAddr, FailedAuthentications>>,
+}
+
+impl AuthThrottle {
+  pub fn new(max_failures: u32, cooldown: Duration) -> Self {
+    Self {
+      max_failures,
+      cooldown,
+      failures: Mutex::new(HashMap::new()),
+    }
+  }
+
+  /// Get the remaining time of the block of `ip`, `None` if it is allowed to authenticate
+  pub fn blocked_for(&self, ip: IpAddr) -> Option<Duration> {
+    let failures = self.failures.lock().ok()?;
+    let blocked_until = failures.get(&ip)?.blocked_until?;
+    blocked_until.checked_duration_since(Instant::now())
+  }
+
+  /// Record a failed authentication of `ip`, return true if the address is blocked from now
+  pub fn record_failure(&self, ip: IpAddr) -> bool {
+    let Ok(mut failures) = self.failures.lock() else {
+      return false;
+    };
+    let now = Instant::now();
+    // forget addresses which stopped failing and are not blocked anymore
+    failures.retain(|_, entry| {
+      entry.blocked_until.is_some_and(|blocked_until| blocked_until > now)
+        || now.duration_since(entry.last_failure) < self.cooldown
+    });
+
+    let entry = failures.entry(ip).or_insert(FailedAuthentications {
+      count: 0,
+      last_failure: now,
+      blocked_until: None,
+    });
+    if entry.blocked_until.is_some_and(|blocked_until| blocked_until <= now) {
+      entry.count = 0;
+      entry.blocked_until = None;
+    }
+    entry.count += 1;
+    entry.last_failure = now;
+    if entry.count >= self.max_failures {
+      entry.blocked_until = Some(now + self.cooldown);
+      return true;
+    }
+    false
+  }
+
+  /// Clear failures of `ip` after a successful authentication
+  pub fn reset(&self, ip: IpAddr) {
+    if let Ok(mut failures) = self.failures.lock() {
+      failures.remove(&ip);
+    }
+  }
+}
+
+/// Limit the number of open websocket connections per remote ip address
+///
+/// A single client can not exhaust the channels and sessions of the server by opening
+/// many connections
+pub struct IpConnectionLimit {
+  max_connections: usize,
+  connections: Mutex<HashMap<IpAddr, usize>>,
+}
+
+impl IpConnectionLimit {
+  pub fn new(max_connections: usize) -> Self {
+    Self {
+      max_connections,
+      connections: Mutex::new(HashMap::new()),
+    }
+  }
+
+  /// Count a new connection of `ip`, return false if the address reached the limit
+  fn try_acquire(&self, ip: IpAddr) -> bool {
+    let Ok(mut connections) = self.connections.lock() else {
+      return true;
+    };
+    let count = connections.entry(ip).or_insert(0);
+    if *count >= self.max_connections {
+      return false;
+    }
+    *count += 1;
+    true
+  }
+
+  fn release(&self, ip: IpAddr) {
+    if let Ok(mut connections) = self.connections.lock() {
+      if let Some(count) = connections.get_mut(&ip) {
+        *count = count.saturating_sub(1);
+        if *count == 0 {
+          connections.remove(&ip);
+        }
+      }
+    }
+  }
+}
+
+/// A counted connection of an ip address, released when the guard is dropped
+pub struct IpConnectionGuard {
+  ip: IpAddr,
+  limit: Arc<IpConnectionLimit>,
+}
+
+impl Drop for IpConnectionGuard {
+  fn drop(&mut self) {
+    self.limit.release(self.ip);
+  }
+}
+
+/// Count a new connection of `ip` in `limit`, usually `AppState::ws_ip_connections`,
+/// `None` if the address already has the maximum number of connections
+pub fn acquire_ip_connection(limit: Arc<IpConnectionLimit>, ip: IpAddr) -> Option<IpConnectionGuard> {
+  if !limit.try_acquire(ip) {
+    return None;
+  }
+  Some(IpConnectionGuard { ip, limit })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::{net::Ipv4Addr, thread};
+
+  const IP: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+  const OTHER_IP: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2));
+
+  #[test]
+  fn auth_throttle_blocks_after_max_failures() {
+    let throttle = AuthThrottle::new(3, Duration::from_secs(60));
+    assert!(!throttle.record_failure(IP));
+    assert!(!throttle.record_failure(IP));
+    assert_eq!(throttle.blocked_for(IP), None);
+    assert!(throttle.record_failure(IP));
+    let blocked_for = throttle.blocked_for(IP).unwrap();
+    assert!(blocked_for > Duration::from_secs(59) && blocked_for <= Duration::from_secs(60));
+    assert_eq!(throttle.blocked_for(OTHER_IP), None);
+  }
+
+  #[test]
+  fn auth_throttle_reset_clears_block() {
+    let throttle = AuthThrottle::new(2, Duration::from_secs(60));
+    throttle.record_failure(IP);
+    assert!(throttle.record_failure(IP));
+    throttle.reset(IP);
+    assert_eq!(throttle.blocked_for(IP), None);
+    // failures are counted from zero again
+    assert!(!throttle.record_failure(IP));
+  }
+
+  #[test]
+  fn auth_throttle_block_expires_after_cooldown() {
+    let throttle = AuthThrottle::new(2, Duration::from_millis(50));
+    throttle.record_failure(IP);
+    assert!(throttle.record_failure(IP));
+    assert!(throttle.blocked_for(IP).is_some());
+    thread::sleep(Duration::from_millis(60));
+    assert_eq!(throttle.blocked_for(IP), None);
+    // the expired block doesn't count toward the next block
+    assert!(!throttle.record_failure(IP));
+  }
+
+  fn open_connections(limit: &IpConnectionLimit, ip: IpAddr) -> Option<usize> {
+    limit.connections.lock().unwrap().get(&ip).copied()
+  }
+
+  #[test]
+  fn ip_connection_limit_rejects_connections_past_limit() {
+    let limit = Arc::new(IpConnectionLimit::new(2));
+    let first = acquire_ip_connection(limit.clone(), IP);
+    let second = acquire_ip_connection(limit.clone(), IP);
+    assert!(first.is_some() && second.is_some());
+    assert!(acquire_ip_connection(limit.clone(), IP).is_none());
+    assert!(acquire_ip_connection(limit.clone(), OTHER_IP).is_some());
+    assert_eq!(open_connections(&limit, IP), Some(2));
+  }
+
+  #[test]
+  fn ip_connection_is_released_when_guard_is_dropped() {
+    let limit = Arc::new(IpConnectionLimit::new(2));
+    let first = acquire_ip_connection(limit.clone(), IP).unwrap();
+    let second = acquire_ip_connection(limit.clone(), IP).unwrap();
+    drop(first);
+    assert_eq!(open_connections(&limit, IP), Some(1));
+    let third = acquire_ip_connection(limit.clone(), IP);
+    assert!(third.is_some());
+    drop(second);
+    drop(third);
+    // the entry of an address without connection is removed
+    assert_eq!(open_connections(&limit, IP), None);
+    assert!(limit.connections.lock().unwrap().is_empty());
+  }
+}
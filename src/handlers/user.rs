@@ -2,14 +2,30 @@ use std::sync::Arc;
 
 use crate::database::models;
 use crate::database::schema::users;
-use crate::errors::DBError;
-use crate::payloads::common::CommonResponse;
-use crate::payloads::user::{NewUserRequest, UserResponse};
+use crate::errors::{ApiError, DBError};
+use crate::extractors::{UserToken, ValidatedJson};
+use crate::handlers::common::{check_user_exists, stream_json_array};
+use crate::payloads::common::{CommonResponse, ListResponse, PageRequest};
+use crate::payloads::messages::FeedItem;
+use crate::payloads::user::{
+  ExportMessage, ExportProfile, GuestResponse, NewUserRequest, PublicUserResponse,
+  UpdateUsernameRequest, UpgradeGuestRequest, UserExportResponse, UserResponse, UserStatsResponse,
+  UsersBatchRequest,
+};
+use crate::services;
 use crate::utils::crypto::generate_secret_code;
 use crate::AppState;
-use axum::{extract::State, Json};
+use crate::utils::minors::calculate_total_pages;
+use axum::{
+  extract::{Query, State},
+  response::Response,
+  Json,
+};
 
-use diesel::{ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl, SelectableHelper};
+use diesel::{
+  result::{DatabaseErrorKind, Error},
+  ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl, SelectableHelper,
+};
 
 /// Add User
 #[utoipa::path(
@@ -117,3 +133,343 @@ pub async fn add_user(
 
   Ok(Json(CommonResponse::success(user_response)))
 }
+
+/// ### Handler for API `GET /me/stats`
+///
+/// Get the number of joined groups, owned groups, authored messages and uploaded attachments
+/// of the current user
+#[utoipa::path(
+  get,
+  path = "/me/stats",
+  params(
+    (
+      "x-user-code" = String, Header, description = "user code for authentication",
+      example = "6C70F6E0A888C1360AD532C66D8F1CD0ED48C1CC47FA1AE6665B1FC3DAABB468"
+    ),
+  ),
+  responses(
+      (status = 200, description = "Get stats successfully", body = UserStatsResponse, content_type = "application/json",
+        example = json!(
+            {
+              "joined_groups": 4,
+              "owned_groups": 2,
+              "messages": 128,
+              "attachments": 9
+            }
+        )),
+      (status = 403, description = "The user code is missing"),
+      (status = 404, description = "User not found"),
+      (status = 500, description = "Database error")
+  ),
+)]
+pub async fn get_my_stats(
+  State(app_state): State<Arc<AppState>>,
+  UserToken(user_token): UserToken,
+) -> Result<Json<UserStatsResponse>, ApiError> {
+  let conn = &mut app_state
+    .db_pool
+    .get()
+    .map_err(|err| ApiError::DatabaseError(DBError::ConnectionError(err)))?;
+  let user = check_user_exists(conn, user_token).await?;
+
+  let stats = services::user::get_user_stats(conn, user.id)?;
+  Ok(Json(stats))
+}
+
+/// ### Handler for API `GET /me/feed`
+///
+/// Get a page of the latest messages across all groups joined by the current user,
+/// newest first
+#[utoipa::path(
+  get,
+  path = "/me/feed",
+  params(
+    (
+      "x-user-code" = String, Header, description = "user code for authentication",
+      example = "6C70F6E0A888C1360AD532C66D8F1CD0ED48C1CC47FA1AE6665B1FC3DAABB468"
+    ),
+    ("page" = Option<u32>, Query, description = "page index" ),
+    ("limit" = Option<u32>, Query, description = "the number of items per a page")
+  ),
+  responses(
+      (status = 200, description = "Get feed successfully", body = ListResponse<FeedItem>, content_type = "application/json",
+        example = json!(
+            {
+              "count": 1,
+              "total_pages": 4,
+              "objects": [
+                {
+                  "group_id": 24,
+                  "group_name": "Linux fundamentals",
+                  "message_uuid": "ff0e32e2-ab5e-4ef7-8dec-93668270ab8c",
+                  "id": 51,
+                  "content": "Hello world",
+                  "message_type": "TEXT",
+                  "status": "Sent",
+                  "created_at": "2024-12-08T07:34:57.120623+00:00",
+                  "updated_at": null,
+                  "user_id": 2,
+                  "user_name": "tienphuc"
+                }
+              ]
+            }
+        )),
+      (status = 403, description = "The user code is missing"),
+      (status = 404, description = "User not found"),
+      (status = 500, description = "Database error")
+  ),
+)]
+pub async fn get_my_feed(
+  State(app_state): State<Arc<AppState>>,
+  UserToken(user_token): UserToken,
+  Query(page_request): Query<PageRequest>,
+) -> Result<ListResponse<FeedItem>, ApiError> {
+  let conn = &mut app_state
+    .db_pool
+    .get()
+    .map_err(|err| ApiError::DatabaseError(DBError::ConnectionError(err)))?;
+  let user = check_user_exists(conn, user_token).await?;
+
+  let items = services::message::get_feed_of_user(conn, user.id, &page_request)
+    .map_err(ApiError::DatabaseError)?;
+  let item_count =
+    services::message::get_count_feed_of_user(conn, user.id).map_err(ApiError::DatabaseError)?;
+  let total_pages =
+    calculate_total_pages(item_count as u64, page_request.get_per_page() as u64) as u16;
+  Ok(ListResponse {
+    count: items.len() as i32,
+    objects: items,
+    total_pages,
+  })
+}
+
+/// ### Handler for API `POST /guest`
+///
+/// Create a guest with a random username, its user code expires after a short time and
+/// the guest is purged with its authored messages and owned groups afterward
+#[utoipa::path(
+  post,
+  path = "/guest",
+  responses(
+      (status = 200, description = "Guest successfully created", body = CommonResponse<GuestResponse>),
+      (status = 500, description = "Database error")
+  ),
+)]
+pub async fn create_guest(
+  State(app_state): State<Arc<AppState>>,
+) -> Result<Json<CommonResponse<GuestResponse>>, ApiError> {
+  let conn = &mut app_state
+    .db_pool
+    .get()
+    .map_err(|err| ApiError::DatabaseError(DBError::ConnectionError(err)))?;
+
+  let guest = services::user::create_guest_user(conn)?;
+  Ok(Json(CommonResponse::success(GuestResponse {
+    user_id: guest.id,
+    username: guest.username,
+    user_code: guest.user_code,
+    expires_at: guest.user_code_expires_at.map(|expires_at| expires_at.and_utc()),
+  })))
+}
+
+/// ### Handler for API `POST /me/upgrade`
+///
+/// Turn the current guest into a permanent user, the user code is kept and no longer expires
+///
+/// Error codes:
+/// - 1: Username already exists
+/// - 2: The current user is not a guest
+#[utoipa::path(
+  post,
+  path = "/me/upgrade",
+  params(
+    (
+      "x-user-code" = String, Header, description = "user code for authentication",
+      example = "6C70F6E0A888C1360AD532C66D8F1CD0ED48C1CC47FA1AE6665B1FC3DAABB468"
+    ),
+  ),
+  request_body = UpgradeGuestRequest,
+  responses(
+      (status = 200, description = "Guest successfully upgraded", body = CommonResponse<UserResponse>),
+      (status = 400, description = "Username is empty or too long"),
+      (status = 403, description = "The user code is missing"),
+      (status = 404, description = "User not found or the guest session has expired"),
+      (status = 500, description = "Database error")
+  ),
+)]
+pub async fn upgrade_guest(
+  State(app_state): State<Arc<AppState>>,
+  UserToken(user_token): UserToken,
+  ValidatedJson(request): ValidatedJson<UpgradeGuestRequest>,
+) -> Result<Json<CommonResponse<UserResponse>>, ApiError> {
+  let conn = &mut app_state
+    .db_pool
+    .get()
+    .map_err(|err| ApiError::DatabaseError(DBError::ConnectionError(err)))?;
+  let user = check_user_exists(conn, user_token).await?;
+
+  if !user.is_guest {
+    return Ok(Json(CommonResponse::error(2, "The current user is not a guest")));
+  }
+  let username = request.username.trim();
+  if services::user::username_exists(conn, username)? {
+    return Ok(Json(CommonResponse::error(1, "Username already exists")));
+  }
+
+  let user = services::user::upgrade_guest_user(conn, user.id, username)?;
+  Ok(Json(CommonResponse::success(UserResponse {
+    user_id: user.id,
+    username: user.username,
+    user_code: user.user_code,
+  })))
+}
+
+/// ### Handler for API `PATCH /me/username`
+///
+/// Change the username of the current user, the username is the handle used to mention the user
+#[utoipa::path(
+  patch,
+  path = "/me/username",
+  params(
+    (
+      "x-user-code" = String, Header, description = "user code for authentication",
+      example = "6C70F6E0A888C1360AD532C66D8F1CD0ED48C1CC47FA1AE6665B1FC3DAABB468"
+    ),
+  ),
+  request_body = UpdateUsernameRequest,
+  responses(
+      (status = 200, description = "Username successfully changed", body = UserResponse),
+      (status = 400, description = "Username is empty or too long"),
+      (status = 403, description = "The user code is missing"),
+      (status = 404, description = "User not found"),
+      (status = 409, description = "Username already exists"),
+      (status = 500, description = "Database error")
+  ),
+)]
+pub async fn update_username(
+  State(app_state): State<Arc<AppState>>,
+  UserToken(user_token): UserToken,
+  ValidatedJson(request): ValidatedJson<UpdateUsernameRequest>,
+) -> Result<Json<UserResponse>, ApiError> {
+  let conn = &mut app_state
+    .db_pool
+    .get()
+    .map_err(|err| ApiError::DatabaseError(DBError::ConnectionError(err)))?;
+  let user = check_user_exists(conn, user_token).await?;
+
+  let user = services::user::update_username(conn, user.id, request.username.trim()).map_err(
+    |err| match err {
+      Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+        ApiError::Conflict("Username already exists".into())
+      }
+      err => ApiError::from(err),
+    },
+  )?;
+  Ok(Json(UserResponse {
+    user_id: user.id,
+    username: user.username,
+    user_code: user.user_code,
+  }))
+}
+
+/// ### Handler for API `GET /me/export`
+///
+/// Download all data of the current user as a JSON document: the profile, the joined groups
+/// and the messages sent by the user with the manifest of their attachments.
+/// Messages are streamed from the database and written to a chunked response body one by one
+#[utoipa::path(
+  get,
+  path = "/me/export",
+  params(
+    (
+      "x-user-code" = String, Header, description = "user code for authentication",
+      example = "6C70F6E0A888C1360AD532C66D8F1CD0ED48C1CC47FA1AE6665B1FC3DAABB468"
+    ),
+  ),
+  responses(
+      (status = 200, description = "Export data of the current user successfully", body = UserExportResponse, content_type = "application/json"),
+      (status = 403, description = "The user code is missing"),
+      (status = 404, description = "User not found"),
+      (status = 500, description = "Database error")
+  ),
+)]
+pub async fn export_my_data(
+  State(app_state): State<Arc<AppState>>,
+  UserToken(user_token): UserToken,
+) -> Result<Response, ApiError> {
+  let mut conn = app_state
+    .db_pool
+    .get()
+    .map_err(|err| ApiError::DatabaseError(DBError::ConnectionError(err)))?;
+  let user = check_user_exists(&mut conn, user_token).await?;
+  let groups = services::user::get_memberships_of_user(&mut conn, user.id)?;
+
+  // the profile and the groups are small, only messages are streamed
+  let profile = ExportProfile {
+    user_id: user.id,
+    username: user.username,
+    created_at: user.created_at.and_utc(),
+    is_guest: user.is_guest,
+  };
+  let mut head = b"{\"profile\":".to_vec();
+  serde_json::to_writer(&mut head, &profile)
+    .and_then(|_| {
+      head.extend_from_slice(b",\"groups\":");
+      serde_json::to_writer(&mut head, &groups)
+    })
+    .map_err(|err| {
+      tracing::error!("Failed to serialize export of user {}: {}", user.id, err);
+      ApiError::Unknown
+    })?;
+  head.extend_from_slice(b",\"messages\":[");
+
+  let user_id = user.id;
+  Ok(stream_json_array(head, b"]}", move |writer| {
+    services::message::stream_messages_of_user(&mut conn, user_id, |group_id, message| {
+      writer.write(&ExportMessage { group_id, message })
+    })
+  }))
+}
+
+/// ### Handler for API `POST /users/batch`
+///
+/// Get the usernames of several users at once, e.g. to render authors of messages without
+/// one request per user. Only the current user and users sharing at least one group with
+/// the current user are returned, other ids are ignored
+#[utoipa::path(
+  post,
+  path = "/users/batch",
+  params(
+    (
+      "x-user-code" = String, Header, description = "user code for authentication",
+      example = "6C70F6E0A888C1360AD532C66D8F1CD0ED48C1CC47FA1AE6665B1FC3DAABB468"
+    ),
+  ),
+  request_body = UsersBatchRequest,
+  responses(
+      (status = 200, description = "Get users successfully", body = Vec<PublicUserResponse>, content_type = "application/json"),
+      (status = 400, description = "The list of user ids is empty or too long"),
+      (status = 403, description = "The user code is missing"),
+      (status = 404, description = "User not found"),
+      (status = 500, description = "Database error")
+  ),
+)]
+pub async fn get_users_batch(
+  State(app_state): State<Arc<AppState>>,
+  UserToken(user_token): UserToken,
+  ValidatedJson(request): ValidatedJson<UsersBatchRequest>,
+) -> Result<Json<Vec<PublicUserResponse>>, ApiError> {
+  let conn = &mut app_state
+    .db_pool
+    .get()
+    .map_err(|err| ApiError::DatabaseError(DBError::ConnectionError(err)))?;
+  let user = check_user_exists(conn, user_token).await?;
+
+  let users = services::user::get_visible_users(conn, user.id, &request.user_ids)?;
+  Ok(Json(
+    users
+      .into_iter()
+      .map(|(user_id, username)| PublicUserResponse { user_id, username })
+      .collect(),
+  ))
+}
@@ -1,4 +1,9 @@
-use std::{env, net::SocketAddr, sync::Arc};
+use std::{
+  env,
+  net::SocketAddr,
+  sync::{atomic::AtomicUsize, Arc},
+  time::Duration,
+};
 mod database;
 mod errors;
 mod extractors;
@@ -14,28 +19,61 @@ use diesel::{
 
 use ::r2d2::PooledConnection;
 use dotenvy::dotenv;
-use tokio::{net::TcpListener, signal};
-use tracing::level_filters::LevelFilter;
+use handlers::socket::throttle::{AuthThrottle, IpConnectionLimit};
+use services::{
+  db::RetryPolicy,
+  moderation::WordFilter,
+  translation::{NoopTranslationProvider, TranslationProvider},
+};
+use utils::crypto::generate_random_salt;
+use tokio::{net::TcpListener, signal, sync::Semaphore};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
 use utils::constants::*;
 
 pub(crate) type PoolPGConnectionType = PooledConnection<ConnectionManager<PgConnection>>;
 
+/// Configure logging from `RUST_LOG`, or `LOG_LEVEL` if `RUST_LOG` isn't set
+///
+/// Both accept `EnvFilter` directives, e.g. `info,anony_box_backbone::handlers=debug`
 fn config_logging() {
-  let directives = format!("{level}", level = LevelFilter::DEBUG);
-  let filter = EnvFilter::new(directives);
+  let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| {
+    let directives = env::var("LOG_LEVEL").unwrap_or(DEFAULT_LOG_LEVEL.to_string());
+    EnvFilter::new(directives)
+  });
   let registry = tracing_subscriber::registry().with(filter);
   registry.with(tracing_subscriber::fmt::layer()).init();
 }
 
 pub struct AppState {
   pub db_pool: Pool<ConnectionManager<PgConnection>>,
+  /// Maximum number of active (non-expired) groups an user can own
+  pub max_groups_per_user: i64,
+  /// Bound the number of files uploaded at the same time
+  pub upload_semaphore: Semaphore,
+  /// Maximum total size in bytes of attachments of a group
+  pub group_storage_quota: u64,
+  /// Failed websocket authentications per ip address
+  pub ws_auth_throttle: AuthThrottle,
+  /// Secret signing pagination cursors
+  pub cursor_secret: String,
+  /// Provider of message translations, `None` if translation is disabled
+  pub translation_provider: Option<Box<dyn TranslationProvider>>,
+  /// Retry of read queries failing with a transient database error
+  pub db_retry: RetryPolicy,
+  /// Serve uploaded files to anyone, otherwise only to members of the group of the file
+  pub public_files: bool,
+  /// Number of authenticated websocket connections
+  pub ws_connections: AtomicUsize,
+  /// Open websocket connections per ip address
+  pub ws_ip_connections: Arc<IpConnectionLimit>,
+  /// Bad-word filter applied to messages of groups with moderation, `None` if no word list is set
+  pub word_filter: Option<WordFilter>,
 }
 
 #[tokio::main]
 async fn main() {
-  config_logging();
   dotenv().ok();
+  config_logging();
   let database_url = env::var("DATABASE_URL").expect("Database URL must be set");
   let server_address = env::var("SERVER_ADDRESS").unwrap_or(DEFAULT_SERVER_ADDRESS.to_string());
   let server_port = if let Ok(value) = env::var("SERVER_PORT") {
@@ -50,13 +88,135 @@ async fn main() {
     DEFAULT_POOL_SIZE
   };
 
+  let max_groups_per_user = if let Ok(value) = env::var("MAXIMUM_GROUPS_PER_USER") {
+    value
+      .parse::<i64>()
+      .expect("Maximum groups per user must be a number")
+  } else {
+    DEFAULT_MAXIMUM_GROUPS_PER_USER
+  };
+
+  let max_concurrent_uploads = if let Ok(value) = env::var("MAXIMUM_CONCURRENT_UPLOADS") {
+    value
+      .parse::<usize>()
+      .expect("Maximum concurrent uploads must be a number")
+  } else {
+    DEFAULT_MAXIMUM_CONCURRENT_UPLOADS
+  };
+
+  let uploads_max_size = if let Ok(value) = env::var("UPLOADS_MAX_SIZE") {
+    value
+      .parse::<u64>()
+      .expect("Maximum size of uploads directory must be a number")
+  } else {
+    DEFAULT_UPLOADS_MAX_SIZE
+  };
+
+  let group_storage_quota = if let Ok(value) = env::var("GROUP_STORAGE_QUOTA") {
+    value
+      .parse::<u64>()
+      .expect("Storage quota of groups must be a number")
+  } else {
+    DEFAULT_GROUP_STORAGE_QUOTA
+  };
+
+  let max_ws_auth_failures = if let Ok(value) = env::var("MAXIMUM_WS_AUTH_FAILURES") {
+    value
+      .parse::<u32>()
+      .expect("Maximum websocket authentication failures must be a number")
+  } else {
+    DEFAULT_MAXIMUM_WS_AUTH_FAILURES
+  };
+
+  let max_ws_connections_per_ip = if let Ok(value) = env::var("MAXIMUM_WS_CONNECTIONS_PER_IP") {
+    value
+      .parse::<usize>()
+      .expect("Maximum websocket connections per ip must be a number")
+  } else {
+    DEFAULT_MAXIMUM_WS_CONNECTIONS_PER_IP
+  };
+
+  let db_retries = if let Ok(value) = env::var("DB_RETRY_ATTEMPTS") {
+    value
+      .parse::<u32>()
+      .expect("Database retry attempts must be a number")
+  } else {
+    DEFAULT_DB_RETRY_ATTEMPTS
+  };
+
+  let db_retry_backoff = if let Ok(value) = env::var("DB_RETRY_BACKOFF_MILLIS") {
+    value
+      .parse::<u64>()
+      .expect("Database retry backoff must be a number of milliseconds")
+  } else {
+    DEFAULT_DB_RETRY_BACKOFF_MILLIS
+  };
+
+  let cursor_secret = env::var("CURSOR_SECRET").unwrap_or_else(|_| {
+    tracing::warn!("CURSOR_SECRET is not set, cursors will be invalid after restarting");
+    generate_random_salt(32)
+  });
+
+  let translation_enabled = if let Ok(value) = env::var("TRANSLATION_ENABLED") {
+    value
+      .parse::<bool>()
+      .expect("Translation enabled flag must be true or false")
+  } else {
+    false
+  };
+  // Replace the no-op provider to plug in a real translation provider
+  let translation_provider: Option<Box<dyn TranslationProvider>> = if translation_enabled {
+    Some(Box::new(NoopTranslationProvider))
+  } else {
+    None
+  };
+
+  let public_files = if let Ok(value) = env::var("PUBLIC_FILES") {
+    value
+      .parse::<bool>()
+      .expect("Public files flag must be true or false")
+  } else {
+    false
+  };
+
+  let word_filter = match env::var("BAD_WORDS_FILE") {
+    Ok(path) if !path.trim().is_empty() => {
+      Some(WordFilter::load(path.trim()).expect("Failed to read the bad words file"))
+    }
+    _ => None,
+  };
+
   let manager = ConnectionManager::<PgConnection>::new(database_url);
   let db_pool = r2d2::Pool::builder()
     .max_size(pool_size)
     .build(manager)
     .expect("Failed to create connection pool");
 
-  let app_state = Arc::new(AppState { db_pool });
+  services::storage::spawn_uploads_eviction_task(db_pool.clone(), uploads_max_size);
+  services::user::spawn_guest_purge_task(db_pool.clone());
+  services::upload::spawn_uploads_cleanup_task(db_pool.clone());
+  services::storage::spawn_attachment_size_backfill_task(db_pool.clone());
+
+  let app_state = Arc::new(AppState {
+    db_pool,
+    max_groups_per_user,
+    upload_semaphore: Semaphore::new(max_concurrent_uploads),
+    group_storage_quota,
+    ws_auth_throttle: AuthThrottle::new(
+      max_ws_auth_failures,
+      Duration::from_secs(WS_AUTH_COOLDOWN_SECS),
+    ),
+    cursor_secret,
+    translation_provider,
+    db_retry: RetryPolicy {
+      retries: db_retries,
+      backoff: Duration::from_millis(db_retry_backoff),
+    },
+    public_files,
+    ws_connections: AtomicUsize::new(0),
+    ws_ip_connections: Arc::new(IpConnectionLimit::new(max_ws_connections_per_ip)),
+    word_filter,
+  });
 
   let app = router::init_router().with_state(app_state);
 
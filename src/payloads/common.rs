@@ -90,3 +90,11 @@ where
     (StatusCode::OK, Json(self)).into_response()
   }
 }
+
+/// Api: state of the server
+#[derive(Serialize, ToSchema)]
+pub struct HealthResponse {
+  pub status: String,
+  /// Number of authenticated websocket connections
+  pub ws_connections: usize,
+}
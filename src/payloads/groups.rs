@@ -1,6 +1,16 @@
+use crate::database::models::{Draft, Moderation, PostPolicy};
+use crate::payloads::common::PageRequest;
 use crate::payloads::messages::MessageWithUser;
 use crate::utils::custom_serde::*;
-use chrono::{DateTime, Duration, Utc};
+use crate::utils::validation::{
+  into_validation_result, validate_required_text, validate_text_length, FieldError, Validate,
+};
+use crate::{
+  MAX_GROUP_CODE_LENGTH, MAX_GROUP_SUMMARIES_IDS, MAX_REMOVE_MEMBERS_IDS, MAX_GROUP_BAN_MINUTES, MAX_MESSAGE_CONTENT_LENGTH, MAX_SLOW_MODE_SECONDS, MAX_GROUP_DURATION, MAX_GROUP_NAME_LENGTH, MAX_JOIN_MESSAGE_LENGTH,
+  MAX_USERNAME_LENGTH,
+};
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
@@ -20,6 +30,24 @@ impl NewGroupForm {
   }
 }
 
+impl Validate for NewGroupForm {
+  fn validate(&self) -> Result<(), Vec<FieldError>> {
+    let mut errors = Vec::new();
+    validate_text_length(&mut errors, "username", &self.username, MAX_USERNAME_LENGTH);
+    validate_required_text(&mut errors, "group_name", &self.group_name, MAX_GROUP_NAME_LENGTH);
+    if self.duration == 0 || self.duration > MAX_GROUP_DURATION {
+      errors.push(FieldError::new(
+        "duration",
+        &format!("must be between 1 and {} minutes", MAX_GROUP_DURATION),
+      ));
+    }
+    if self.maximum_members.is_some_and(|maximum| maximum < 1) {
+      errors.push(FieldError::new("maximum_members", "must be at least 1"));
+    }
+    into_validation_result(errors)
+  }
+}
+
 #[derive(Serialize, Deserialize, Default, ToSchema)]
 pub struct GroupResult {
   pub user_id: i32,
@@ -28,16 +56,259 @@ pub struct GroupResult {
   pub group_id: i32,
   pub group_name: String,
   pub group_code: String,
-  pub expired_at: String,
+  #[serde(
+    serialize_with = "serialize_with_date_time_utc",
+    deserialize_with = "deserialize_with_date_time_utc"
+  )]
+  pub expired_at: DateTime<Utc>,
   pub is_waiting: bool,
 }
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct JoinGroupForm {
   pub group_code: String,
   pub username: String,
   pub message: String,
 }
 
+impl Validate for JoinGroupForm {
+  fn validate(&self) -> Result<(), Vec<FieldError>> {
+    let mut errors = Vec::new();
+    validate_required_text(&mut errors, "group_code", &self.group_code, MAX_GROUP_CODE_LENGTH);
+    validate_text_length(&mut errors, "username", &self.username, MAX_USERNAME_LENGTH);
+    validate_text_length(&mut errors, "message", &self.message, MAX_JOIN_MESSAGE_LENGTH);
+    into_validation_result(errors)
+  }
+}
+
+#[derive(Deserialize)]
+pub struct GroupPreviewQuery {
+  pub code: String,
+}
+
+/// Public information of a group shown before joining it
+#[derive(Serialize, ToSchema)]
+pub struct GroupPreviewResponse {
+  pub group_name: String,
+  pub member_count: i64,
+  pub approval_require: bool,
+  #[serde(serialize_with = "serialize_with_date_time_utc_option")]
+  pub expired_at: Option<DateTime<Utc>>,
+  pub is_full: bool,
+}
+
+/// Result of checking a group code before joining, the group fields are missing when
+/// no group has the code
+#[derive(Serialize, ToSchema)]
+pub struct GroupCodeValidationResponse {
+  /// The group exists, is not expired and is not full
+  pub valid: bool,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub group_name: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub approval_require: Option<bool>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub is_full: Option<bool>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub expired: Option<bool>,
+}
+
+/// Api: rename a group
+#[derive(Deserialize, ToSchema)]
+pub struct RenameGroupRequest {
+  pub name: String,
+}
+
+impl Validate for RenameGroupRequest {
+  fn validate(&self) -> Result<(), Vec<FieldError>> {
+    let mut errors = Vec::new();
+    validate_required_text(&mut errors, "name", &self.name, MAX_GROUP_NAME_LENGTH);
+    into_validation_result(errors)
+  }
+}
+
+/// Api: set slow mode of a group
+#[derive(Deserialize, ToSchema)]
+pub struct SlowModeRequest {
+  /// Minimum time in seconds between two messages of a member, `null` or 0 disables slow mode
+  pub slow_mode_seconds: Option<i32>,
+}
+
+impl Validate for SlowModeRequest {
+  fn validate(&self) -> Result<(), Vec<FieldError>> {
+    let mut errors = Vec::new();
+    if let Some(seconds) = self.slow_mode_seconds {
+      if !(0..=MAX_SLOW_MODE_SECONDS).contains(&seconds) {
+        errors.push(FieldError::new(
+          "slow_mode_seconds",
+          &format!("must be between 0 and {}", MAX_SLOW_MODE_SECONDS),
+        ));
+      }
+    }
+    into_validation_result(errors)
+  }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SlowModeResponse {
+  pub group_id: i32,
+  pub slow_mode_seconds: Option<i32>,
+}
+
+/// Api: effective permissions of the current user in a group
+#[derive(Serialize, ToSchema)]
+pub struct MyPermissionsResponse {
+  pub group_id: i32,
+  pub is_owner: bool,
+  /// The group is not archived nor expired and its post policy allows the user to post
+  pub can_post: bool,
+  /// Delete messages of other members and remove members
+  pub can_moderate: bool,
+  pub can_pin: bool,
+}
+
+/// Api: pin or unpin a group in the group list of the current user
+#[derive(Deserialize, ToSchema)]
+pub struct PinGroupRequest {
+  pub pinned: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct PinGroupResponse {
+  pub group_id: i32,
+  pub pinned: bool,
+}
+
+/// Api: result of archiving or unarchiving a group
+#[derive(Serialize, ToSchema)]
+pub struct ArchiveGroupResponse {
+  pub group_id: i32,
+  /// Members of an archived group can read but not send messages
+  pub archived: bool,
+}
+
+/// Api: set which members can send messages in a group
+#[derive(Deserialize, ToSchema)]
+pub struct PostPolicyRequest {
+  pub post_policy: PostPolicy,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct PostPolicyResponse {
+  pub group_id: i32,
+  pub post_policy: PostPolicy,
+}
+
+/// Api: set how messages with words of the bad-word filter are handled in a group
+#[derive(Deserialize, ToSchema)]
+pub struct ModerationRequest {
+  pub moderation: Moderation,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ModerationResponse {
+  pub group_id: i32,
+  pub moderation: Moderation,
+}
+
+/// Api: set the maximum message length of a group
+#[derive(Deserialize, ToSchema)]
+pub struct MaxMessageLengthRequest {
+  /// Maximum number of characters of a message content, `null` falls back to the global limit
+  pub max_message_length: Option<i32>,
+}
+
+impl Validate for MaxMessageLengthRequest {
+  fn validate(&self) -> Result<(), Vec<FieldError>> {
+    let mut errors = Vec::new();
+    if let Some(length) = self.max_message_length {
+      if !(1..=MAX_MESSAGE_CONTENT_LENGTH as i32).contains(&length) {
+        errors.push(FieldError::new(
+          "max_message_length",
+          &format!("must be between 1 and {}", MAX_MESSAGE_CONTENT_LENGTH),
+        ));
+      }
+    }
+    into_validation_result(errors)
+  }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct MaxMessageLengthResponse {
+  pub group_id: i32,
+  pub max_message_length: Option<i32>,
+}
+
+/// Api: save the unsent message of the current user in a group
+#[derive(Deserialize, ToSchema)]
+pub struct DraftRequest {
+  pub content: String,
+}
+
+impl Validate for DraftRequest {
+  fn validate(&self) -> Result<(), Vec<FieldError>> {
+    let mut errors = Vec::new();
+    validate_text_length(&mut errors, "content", &self.content, MAX_MESSAGE_CONTENT_LENGTH);
+    into_validation_result(errors)
+  }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DraftResponse {
+  pub group_id: i32,
+  pub content: String,
+  #[serde(serialize_with = "serialize_naive_datetime")]
+  pub updated_at: NaiveDateTime,
+}
+
+impl From<Draft> for DraftResponse {
+  fn from(draft: Draft) -> Self {
+    Self {
+      group_id: draft.group_id,
+      content: draft.content,
+      updated_at: draft.updated_at,
+    }
+  }
+}
+
+/// Api: get summaries of several groups at once
+#[derive(Deserialize, ToSchema)]
+pub struct GroupSummariesRequest {
+  pub group_ids: Vec<i32>,
+}
+
+impl Validate for GroupSummariesRequest {
+  fn validate(&self) -> Result<(), Vec<FieldError>> {
+    let mut errors = Vec::new();
+    if self.group_ids.is_empty() {
+      errors.push(FieldError::new("group_ids", "must not be empty"));
+    } else if self.group_ids.len() > MAX_GROUP_SUMMARIES_IDS {
+      errors.push(FieldError::new(
+        "group_ids",
+        &format!("must contain at most {} ids", MAX_GROUP_SUMMARIES_IDS),
+      ));
+    }
+    into_validation_result(errors)
+  }
+}
+
+#[derive(Serialize, ToSchema, Debug, Clone, Default)]
+pub struct GroupSummary {
+  pub member_count: i64,
+  /// Number of messages from other members after the last seen message of the current user
+  pub unread_count: i64,
+  /// Creation time of the latest message, missing when the group has no message
+  #[serde(serialize_with = "serialize_naive_datetime_option")]
+  pub last_activity: Option<NaiveDateTime>,
+  /// Total size in bytes of attachments of the group, counted against the group storage quota
+  pub storage_bytes: i64,
+}
+
+/// Summaries keyed by group id, groups which the current user did not join are left out
+#[derive(Serialize, ToSchema)]
+pub struct GroupSummariesResponse {
+  pub summaries: HashMap<i32, GroupSummary>,
+}
+
 /**
  for api get list gr by user id
 */
@@ -46,11 +317,19 @@ pub struct GroupInfo {
   pub group_id: i32,
   pub group_name: String,
   pub group_code: String,
-  pub expired_at: String,
+  #[serde(serialize_with = "serialize_with_date_time_utc_option")]
+  pub expired_at: Option<DateTime<Utc>>,
   pub latest_ms_content: String,
-  pub latest_ms_time: String,
+  /// Creation time of the latest message, missing when the group has no message
+  #[serde(serialize_with = "serialize_with_date_time_utc_option")]
+  pub latest_ms_time: Option<DateTime<Utc>>,
   pub latest_ms_username: String,
-  pub created_at: String,
+  #[serde(serialize_with = "serialize_with_date_time_utc_option")]
+  pub created_at: Option<DateTime<Utc>>,
+  /// The group is pinned to the top of the list by the user
+  pub pinned: bool,
+  /// The group is read-only, new messages are rejected
+  pub archived: bool,
 }
 
 #[derive(Serialize, ToSchema)]
@@ -75,12 +354,30 @@ pub struct NewGroupWithUserIdRequest {
   pub approval_require: Option<bool>,
 }
 
-#[derive(Serialize)]
+impl Validate for NewGroupWithUserIdRequest {
+  fn validate(&self) -> Result<(), Vec<FieldError>> {
+    let mut errors = Vec::new();
+    validate_required_text(&mut errors, "group_name", &self.group_name, MAX_GROUP_NAME_LENGTH);
+    if self.duration == 0 || self.duration > MAX_GROUP_DURATION {
+      errors.push(FieldError::new(
+        "duration",
+        &format!("must be between 1 and {} minutes", MAX_GROUP_DURATION),
+      ));
+    }
+    if self.maximum_members.is_some_and(|maximum| maximum < 1) {
+      errors.push(FieldError::new("maximum_members", "must be at least 1"));
+    }
+    into_validation_result(errors)
+  }
+}
+
+#[derive(Serialize, ToSchema)]
 pub struct GroupResponse {
   pub group_id: i32,
   pub group_name: String,
   pub group_code: String,
-  pub expired_at: String,
+  #[serde(serialize_with = "serialize_with_date_time_utc_option")]
+  pub expired_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Serialize, ToSchema)]
@@ -133,15 +430,50 @@ pub struct GrDetailSettingResponse {
   pub owner_id: i32,
   pub group_name: String,
   pub group_code: String,
-  pub expired_at: String,
-  pub created_at: String,
+  #[serde(
+    serialize_with = "serialize_with_date_time_utc_option",
+    deserialize_with = "deserialize_with_date_time_utc_option"
+  )]
+  pub expired_at: Option<DateTime<Utc>>,
+  #[serde(
+    serialize_with = "serialize_with_date_time_utc_option",
+    deserialize_with = "deserialize_with_date_time_utc_option"
+  )]
+  pub created_at: Option<DateTime<Utc>>,
   pub maximum_members: i32,
   pub total_joined_member: i32,
+  pub joined_page: u16,
   pub list_joined_member: Vec<UserSettingInfo>,
   pub total_waiting_member: i32,
+  pub waiting_page: u16,
   pub list_waiting_member: Vec<UserSettingInfo>,
 }
 
+/// Separate page params for joined and waiting member lists of the group setting api
+#[derive(Debug, Deserialize)]
+pub struct GrSettingPageRequest {
+  pub joined_page: Option<u16>,
+  pub joined_limit: Option<u32>,
+  pub waiting_page: Option<u16>,
+  pub waiting_limit: Option<u32>,
+}
+
+impl GrSettingPageRequest {
+  pub fn joined(&self) -> PageRequest {
+    PageRequest {
+      page: self.joined_page,
+      limit: self.joined_limit,
+    }
+  }
+
+  pub fn waiting(&self) -> PageRequest {
+    PageRequest {
+      page: self.waiting_page,
+      limit: self.waiting_limit,
+    }
+  }
+}
+
 #[derive(Serialize, Deserialize, ToSchema)]
 pub struct UserSettingInfo {
   pub user_id: i32,
@@ -159,6 +491,24 @@ pub struct NewUserAndGroupRequest {
   pub approval_require: Option<bool>,
 }
 
+impl Validate for NewUserAndGroupRequest {
+  fn validate(&self) -> Result<(), Vec<FieldError>> {
+    let mut errors = Vec::new();
+    validate_text_length(&mut errors, "username", &self.username, MAX_USERNAME_LENGTH);
+    validate_required_text(&mut errors, "group_name", &self.group_name, MAX_GROUP_NAME_LENGTH);
+    if self.duration == 0 || self.duration > MAX_GROUP_DURATION {
+      errors.push(FieldError::new(
+        "duration",
+        &format!("must be between 1 and {} minutes", MAX_GROUP_DURATION),
+      ));
+    }
+    if self.maximum_members.is_some_and(|maximum| maximum < 1) {
+      errors.push(FieldError::new("maximum_members", "must be at least 1"));
+    }
+    into_validation_result(errors)
+  }
+}
+
 #[derive(Serialize, Deserialize, ToSchema)]
 pub struct NewUserAndGroupResponse {
   pub msg: String,
@@ -171,9 +521,13 @@ pub struct GroupDetailResponse {
   pub max_member: i32,
   pub joined_member: i32,
   pub waiting_member: i32,
-  pub created_at: String,
-  pub expired_at: String,
+  #[serde(serialize_with = "serialize_with_date_time_utc_option")]
+  pub created_at: Option<DateTime<Utc>>,
+  #[serde(serialize_with = "serialize_with_date_time_utc_option")]
+  pub expired_at: Option<DateTime<Utc>>,
   pub messages: Vec<MessageWithUser>,
+  /// Unsent message of the current user in the group, restored when opening the group
+  pub draft: Option<DraftResponse>,
 }
 
 /// Api: remove an user from a griup
@@ -182,6 +536,9 @@ pub struct RmUserRequest {
   pub gr_owner_id: i32,
   pub gr_id: i32,
   pub rm_user_id: i32,
+  /// Ban the removed user from rejoining the group for this number of minutes
+  #[serde(default)]
+  pub ban_minutes: Option<u32>,
 }
 
 #[derive(Serialize, Deserialize, ToSchema)]
@@ -190,6 +547,55 @@ pub struct RmUserResponse {
   pub res_msg: String,
 }
 
+/// Api: remove several members from a group at once
+#[derive(Deserialize, ToSchema)]
+pub struct RemoveMembersRequest {
+  pub user_ids: Vec<i32>,
+  /// Ban the removed members from rejoining the group for this number of minutes
+  #[serde(default)]
+  pub ban_minutes: Option<u32>,
+}
+
+impl Validate for RemoveMembersRequest {
+  fn validate(&self) -> Result<(), Vec<FieldError>> {
+    let mut errors = Vec::new();
+    if self.user_ids.is_empty() {
+      errors.push(FieldError::new("user_ids", "must not be empty"));
+    } else if self.user_ids.len() > MAX_REMOVE_MEMBERS_IDS {
+      errors.push(FieldError::new(
+        "user_ids",
+        &format!("must contain at most {} ids", MAX_REMOVE_MEMBERS_IDS),
+      ));
+    }
+    validate_ban_minutes(&mut errors, self.ban_minutes);
+    into_validation_result(errors)
+  }
+}
+
+/// Check a ban lasts between 1 minute and `MAX_GROUP_BAN_MINUTES`
+pub fn validate_ban_minutes(errors: &mut Vec<FieldError>, ban_minutes: Option<u32>) {
+  if ban_minutes.is_some_and(|minutes| minutes == 0 || minutes > MAX_GROUP_BAN_MINUTES) {
+    errors.push(FieldError::new(
+      "ban_minutes",
+      &format!("must be between 1 and {} minutes", MAX_GROUP_BAN_MINUTES),
+    ));
+  }
+}
+
+/// Result of removing a single member, `reason` explains why the member was not removed
+#[derive(Serialize, ToSchema)]
+pub struct RemoveMemberResult {
+  pub user_id: i32,
+  pub removed: bool,
+  pub reason: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct RemoveMembersResponse {
+  pub group_id: i32,
+  pub results: Vec<RemoveMemberResult>,
+}
+
 #[derive(Serialize, Deserialize, ToSchema)]
 pub struct RmRfGroupsRequest {
   pub cmd: String,
@@ -199,3 +605,167 @@ pub struct RmRfGroupsRequest {
 pub struct RmRfGroupsResponse {
   pub msg: String,
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn invalid_fields(result: Result<(), Vec<FieldError>>) -> Vec<String> {
+    result
+      .unwrap_err()
+      .into_iter()
+      .map(|error| error.field)
+      .collect()
+  }
+
+  fn new_group_form(username: &str, group_name: &str, duration: u32) -> NewGroupForm {
+    NewGroupForm {
+      username: username.into(),
+      group_name: group_name.into(),
+      duration,
+      maximum_members: None,
+      approval_require: None,
+    }
+  }
+
+  #[test]
+  fn new_group_form_accepts_valid_form() {
+    assert!(new_group_form("alice", "friends", 60).validate().is_ok());
+  }
+
+  #[test]
+  fn new_group_form_allows_empty_username() {
+    // the username is only required when no existing user is found from the user code
+    assert!(new_group_form("", "friends", 60).validate().is_ok());
+  }
+
+  #[test]
+  fn new_group_form_reports_every_invalid_field() {
+    let mut form = new_group_form(&"a".repeat(MAX_USERNAME_LENGTH + 1), " ", 0);
+    form.maximum_members = Some(0);
+    assert_eq!(
+      invalid_fields(form.validate()),
+      ["username", "group_name", "duration", "maximum_members"]
+    );
+  }
+
+  #[test]
+  fn new_group_form_limits_duration() {
+    assert!(new_group_form("alice", "friends", MAX_GROUP_DURATION).validate().is_ok());
+    assert_eq!(
+      invalid_fields(new_group_form("alice", "friends", MAX_GROUP_DURATION + 1).validate()),
+      ["duration"]
+    );
+  }
+
+  #[test]
+  fn new_user_and_group_request_requires_group_name() {
+    let request = NewUserAndGroupRequest {
+      username: "alice".into(),
+      group_name: "".into(),
+      duration: 60,
+      maximum_members: Some(2),
+      approval_require: None,
+    };
+    assert_eq!(invalid_fields(request.validate()), ["group_name"]);
+  }
+
+  #[test]
+  fn new_group_with_user_id_request_requires_group_name() {
+    let request = NewGroupWithUserIdRequest {
+      user_id: 1,
+      group_name: "".into(),
+      duration: 60,
+      maximum_members: None,
+      approval_require: None,
+    };
+    assert_eq!(invalid_fields(request.validate()), ["group_name"]);
+  }
+
+  #[test]
+  fn join_group_form_requires_group_code() {
+    let mut form = JoinGroupForm {
+      group_code: "CODE".into(),
+      username: "".into(),
+      message: "".into(),
+    };
+    assert!(form.validate().is_ok());
+    form.group_code = "".into();
+    form.message = "a".repeat(MAX_JOIN_MESSAGE_LENGTH + 1);
+    assert_eq!(invalid_fields(form.validate()), ["group_code", "message"]);
+  }
+
+  #[test]
+  fn rename_group_request_requires_name() {
+    assert!(RenameGroupRequest { name: "friends".into() }.validate().is_ok());
+    assert_eq!(
+      invalid_fields(RenameGroupRequest { name: " ".into() }.validate()),
+      ["name"]
+    );
+  }
+
+  #[test]
+  fn slow_mode_request_checks_range() {
+    for seconds in [None, Some(0), Some(MAX_SLOW_MODE_SECONDS)] {
+      assert!(SlowModeRequest { slow_mode_seconds: seconds }.validate().is_ok());
+    }
+    for seconds in [Some(-1), Some(MAX_SLOW_MODE_SECONDS + 1)] {
+      assert!(SlowModeRequest { slow_mode_seconds: seconds }.validate().is_err());
+    }
+  }
+
+  #[test]
+  fn max_message_length_request_checks_range() {
+    let maximum = MAX_MESSAGE_CONTENT_LENGTH as i32;
+    for length in [None, Some(1), Some(maximum)] {
+      assert!(MaxMessageLengthRequest { max_message_length: length }.validate().is_ok());
+    }
+    for length in [Some(0), Some(maximum + 1)] {
+      assert!(MaxMessageLengthRequest { max_message_length: length }.validate().is_err());
+    }
+  }
+
+  #[test]
+  fn draft_request_limits_content() {
+    assert!(DraftRequest { content: "".into() }.validate().is_ok());
+    let content = "a".repeat(MAX_MESSAGE_CONTENT_LENGTH + 1);
+    assert_eq!(invalid_fields(DraftRequest { content }.validate()), ["content"]);
+  }
+
+  #[test]
+  fn group_summaries_request_limits_ids() {
+    assert!(GroupSummariesRequest { group_ids: vec![1] }.validate().is_ok());
+    assert!(GroupSummariesRequest { group_ids: vec![] }.validate().is_err());
+    let group_ids = vec![1; MAX_GROUP_SUMMARIES_IDS + 1];
+    assert!(GroupSummariesRequest { group_ids }.validate().is_err());
+  }
+
+  #[test]
+  fn ban_minutes_must_be_in_range() {
+    for ban_minutes in [None, Some(1), Some(MAX_GROUP_BAN_MINUTES)] {
+      let mut errors = Vec::new();
+      validate_ban_minutes(&mut errors, ban_minutes);
+      assert!(errors.is_empty(), "{:?} should be valid", ban_minutes);
+    }
+    for ban_minutes in [Some(0), Some(MAX_GROUP_BAN_MINUTES + 1)] {
+      let mut errors = Vec::new();
+      validate_ban_minutes(&mut errors, ban_minutes);
+      assert_eq!(errors.len(), 1, "{:?} should be invalid", ban_minutes);
+      assert_eq!(errors[0].field, "ban_minutes");
+    }
+  }
+
+  #[test]
+  fn remove_members_request_checks_ids_and_ban() {
+    let request = RemoveMembersRequest {
+      user_ids: vec![2, 3],
+      ban_minutes: Some(10),
+    };
+    assert!(request.validate().is_ok());
+    let request = RemoveMembersRequest {
+      user_ids: vec![2; MAX_REMOVE_MEMBERS_IDS + 1],
+      ban_minutes: Some(0),
+    };
+    assert_eq!(invalid_fields(request.validate()), ["user_ids", "ban_minutes"]);
+  }
+}
@@ -2,7 +2,12 @@ use crate::database::models::{
   Attachment, AttachmentTypeEnum, Message, MessageStatus, MessageTypeEnum, NewAttachment,
 };
 use crate::services::message::MessageWithAttachmentRaw;
+use crate::services::storage::{to_public_url, to_stored_url, uploaded_file_size};
 use crate::utils::custom_serde::*;
+use crate::utils::validation::{
+  into_validation_result, validate_required_text, validate_text_length, FieldError, Validate,
+};
+use crate::{MAX_LANGUAGE_TAG_LENGTH, MAX_MESSAGE_CONTENT_LENGTH, MAX_REPORT_REASON_LENGTH};
 use chrono::{DateTime, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
@@ -15,37 +20,107 @@ pub struct AttachmentPayload {
   pub url: String,
   #[serde(default = "AttachmentTypeEnum::default")]
   pub attachment_type: AttachmentTypeEnum,
+  /// Size of the uploaded file in bytes, measured by the server, unknown if the file is missing
+  #[serde(default, skip_deserializing)]
+  pub size_bytes: Option<i64>,
 }
 
 impl From<Attachment> for AttachmentPayload {
   fn from(value: Attachment) -> Self {
     Self {
       id: value.id,
-      url: value.url,
+      url: to_public_url(&value.url),
       attachment_type: value.attachment_type,
+      size_bytes: value.size_bytes,
     }
   }
 }
 
-impl<'a> AttachmentPayload {
-  pub fn into_new(&'a self, message_id: i32) -> NewAttachment<'a> {
+impl AttachmentPayload {
+  pub fn into_new(&self, message_id: i32) -> NewAttachment {
     NewAttachment {
-      url: &self.url,
+      url: to_stored_url(&self.url),
       message_id,
       attachment_type: self.attachment_type.clone(),
+      size_bytes: uploaded_file_size(&self.url).map(|size| size as i64),
     }
   }
 }
 
+/// Api: metadata of an attachment and its uploaded file
+#[derive(Serialize, ToSchema)]
+pub struct AttachmentDetailResponse {
+  pub id: i32,
+  pub url: String,
+  pub attachment_type: AttachmentTypeEnum,
+  /// Size of the uploaded file in bytes
+  pub size: u64,
+  pub message_id: i32,
+  pub group_id: i32,
+}
+
+/// Compact preview of the message replied by another message
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone)]
+pub struct QuotedMessage {
+  pub message_id: i32,
+  pub user_id: i32,
+  pub user_name: String,
+  /// Content truncated to `MAX_QUOTED_CONTENT_LENGTH` characters
+  pub content: Option<String>,
+  pub message_type: MessageTypeEnum,
+}
+
 // Request structure for sending a message
 #[derive(Deserialize, ToSchema)]
 pub struct SendMessageRequest {
-  pub message_uuid: Uuid,
+  /// Client generated id of the message, a message with an already used id is rejected.
+  /// The server generates one when it is missing
+  pub message_uuid: Option<Uuid>,
   pub group_id: i32,
   pub content: Option<String>,
   #[serde(default = "MessageTypeEnum::default")]
   pub message_type: MessageTypeEnum,
   pub attachments: Option<Vec<AttachmentPayload>>,
+  /// id of the replied message in the same group
+  pub reply_to: Option<i32>,
+}
+
+impl Validate for SendMessageRequest {
+  fn validate(&self) -> Result<(), Vec<FieldError>> {
+    let mut errors = Vec::new();
+    let has_content = self
+      .content
+      .as_ref()
+      .is_some_and(|content| !content.trim().is_empty());
+    let has_attachments = self
+      .attachments
+      .as_ref()
+      .is_some_and(|attachments| !attachments.is_empty());
+    if self.message_uuid.is_some_and(|message_uuid| message_uuid.is_nil()) {
+      errors.push(FieldError::new("message_uuid", "must not be the nil uuid"));
+    }
+    if let Some(content) = &self.content {
+      validate_text_length(&mut errors, "content", content, MAX_MESSAGE_CONTENT_LENGTH);
+    }
+    if !has_content && !has_attachments {
+      errors.push(FieldError::new(
+        "content",
+        "must not be empty when the message has no attachments",
+      ));
+    }
+    if self.message_type == MessageTypeEnum::ATTACHMENT && !has_attachments {
+      errors.push(FieldError::new(
+        "attachments",
+        "must not be empty for an ATTACHMENT message",
+      ));
+    }
+    if let Some(attachments) = &self.attachments {
+      if attachments.iter().any(|attachment| attachment.url.trim().is_empty()) {
+        errors.push(FieldError::new("attachments", "url must not be empty"));
+      }
+    }
+    into_validation_result(errors)
+  }
 }
 
 impl SendMessageResponse {
@@ -65,6 +140,7 @@ pub struct SendMessageResponse {
   #[serde(serialize_with = "serialize_with_date_time_utc")]
   pub created_at: DateTime<Utc>,
   pub attachments: Option<Vec<AttachmentPayload>>,
+  pub reply_to: Option<i32>,
 }
 
 impl From<Message> for SendMessageResponse {
@@ -77,6 +153,7 @@ impl From<Message> for SendMessageResponse {
       status: value.status,
       created_at: value.created_at.and_utc(),
       attachments: None,
+      reply_to: value.reply_to,
     }
   }
 }
@@ -98,6 +175,8 @@ pub struct MessageResponse {
   pub created_at: NaiveDateTime,
   #[serde(serialize_with = "serialize_naive_datetime_option")]
   pub updated_at: Option<NaiveDateTime>,
+  /// Version to send back when editing the message
+  pub version: i32,
   pub user_id: i32,
   pub user_name: String,
 }
@@ -112,6 +191,7 @@ impl From<Message> for MessageResponse {
       status: value.status,
       created_at: value.created_at,
       updated_at: value.updated_at,
+      version: value.version,
       user_id: value.user_id,
       user_name: "".into(),
     }
@@ -131,6 +211,32 @@ pub struct MessageWithUser {
   pub content: Option<String>,
   pub message_type: MessageTypeEnum,
   pub attachments: Option<Vec<AttachmentPayload>>,
+  /// The message has more attachments than the returned ones,
+  /// get all of them from `/messages/{message_id}/attachments`
+  pub has_more_attachments: bool,
+  pub status: MessageStatus,
+  #[serde(serialize_with = "serialize_naive_datetime")]
+  pub created_at: NaiveDateTime,
+  #[serde(serialize_with = "serialize_naive_datetime_option")]
+  pub updated_at: Option<NaiveDateTime>,
+  /// Version to send back when editing the message
+  pub version: i32,
+  pub user_id: i32,
+  pub user_name: String,
+  pub reply_to: Option<i32>,
+  /// Preview of the replied message, missing when it was deleted
+  pub quoted: Option<QuotedMessage>,
+}
+
+/// A message of the activity feed of an user, with the group it was sent to
+#[derive(Queryable, Serialize, Debug, Clone, ToSchema)]
+pub struct FeedItem {
+  pub group_id: i32,
+  pub group_name: String,
+  pub message_uuid: Uuid,
+  pub id: i32,
+  pub content: Option<String>,
+  pub message_type: MessageTypeEnum,
   pub status: MessageStatus,
   #[serde(serialize_with = "serialize_naive_datetime")]
   pub created_at: NaiveDateTime,
@@ -148,11 +254,15 @@ impl From<MessageWithAttachmentRaw> for MessageWithUser {
       content: value.content,
       message_type: value.message_type,
       attachments: None,
+      has_more_attachments: false,
       status: value.status,
       created_at: value.created_at,
       updated_at: value.updated_at,
+      version: value.version,
       user_id: value.user_id,
       user_name: value.user_name,
+      reply_to: value.reply_to,
+      quoted: None,
     }
   }
 }
@@ -172,6 +282,8 @@ pub struct MessageFilterParams {
     default = "Option::default"
   )]
   pub to_date: Option<NaiveDate>,
+  /// `true` keeps only messages having attachments, `false` only messages without attachments
+  pub has_attachments: Option<bool>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -183,4 +295,244 @@ pub struct MessageSortParams {
 pub struct UpdateMessage {
   pub content: Option<String>,
   pub message_type: Option<MessageTypeEnum>,
+  /// Version of the message the edit is based on, the edit is rejected if the message
+  /// was edited since. Without a version the edit always applies
+  pub version: Option<i32>,
+}
+
+#[derive(Deserialize)]
+pub struct TranslateQuery {
+  /// BCP 47 language tag of the target language, e.g. `en` or `pt-BR`
+  pub lang: String,
+}
+
+impl Validate for TranslateQuery {
+  fn validate(&self) -> Result<(), Vec<FieldError>> {
+    let mut errors = Vec::new();
+    if self.lang.is_empty()
+      || self.lang.len() > MAX_LANGUAGE_TAG_LENGTH
+      || !self
+        .lang
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-')
+    {
+      errors.push(FieldError::new("lang", "must be a valid language tag"));
+    }
+    into_validation_result(errors)
+  }
+}
+
+/// Api: translated content of a message
+#[derive(Serialize, ToSchema)]
+pub struct TranslationResponse {
+  pub message_id: i32,
+  pub lang: String,
+  pub content: String,
+  /// Whether the translation was taken from the cache
+  pub cached: bool,
+}
+
+/// Api: report a message for moderation
+#[derive(Deserialize, ToSchema)]
+pub struct ReportMessageRequest {
+  pub reason: String,
+}
+
+impl Validate for ReportMessageRequest {
+  fn validate(&self) -> Result<(), Vec<FieldError>> {
+    let mut errors = Vec::new();
+    validate_required_text(&mut errors, "reason", &self.reason, MAX_REPORT_REASON_LENGTH);
+    into_validation_result(errors)
+  }
+}
+
+/// Api: a report of a message, reviewed by the owner of the group
+#[derive(Serialize, ToSchema)]
+pub struct MessageReportResponse {
+  pub id: i32,
+  pub message_id: i32,
+  /// Content of the reported message, missing for messages without content
+  pub message_content: Option<String>,
+  pub reporter_id: i32,
+  pub reporter_name: String,
+  pub reason: String,
+  #[serde(serialize_with = "serialize_naive_datetime")]
+  pub created_at: NaiveDateTime,
+}
+
+/// Api: number of messages of a group and the time range they were sent in,
+/// `earliest` and `latest` are null for a group without messages
+#[derive(Serialize, ToSchema)]
+pub struct MessageStatsResponse {
+  pub group_id: i32,
+  pub count: i64,
+  #[serde(serialize_with = "serialize_naive_datetime_option")]
+  pub earliest: Option<NaiveDateTime>,
+  #[serde(serialize_with = "serialize_naive_datetime_option")]
+  pub latest: Option<NaiveDateTime>,
+}
+
+#[derive(Deserialize)]
+pub struct MessageIdsQuery {
+  /// Only messages with a greater id are returned, all messages if missing
+  pub since_id: Option<i32>,
+  pub limit: Option<u32>,
+}
+
+/// Api: id of a message of a group, `updated_at` is null for a message never edited
+#[derive(Serialize, ToSchema)]
+pub struct MessageIdItem {
+  pub id: i32,
+  #[serde(serialize_with = "serialize_naive_datetime_option")]
+  pub updated_at: Option<NaiveDateTime>,
+}
+
+/// Api: ids of messages of a group in ascending order, `has_more` is true when more messages
+/// follow the last one, request them again with `since_id` being the last id
+#[derive(Serialize, ToSchema)]
+pub struct MessageIdsResponse {
+  pub group_id: i32,
+  pub messages: Vec<MessageIdItem>,
+  pub has_more: bool,
+}
+
+#[derive(Deserialize)]
+pub struct MessageContextQuery {
+  /// Number of messages on each side of the target message
+  pub radius: Option<u32>,
+}
+
+/// Api: a message with the messages sent around it, oldest first
+#[derive(Serialize, ToSchema)]
+pub struct MessageContextResponse {
+  pub group_id: i32,
+  pub message_id: i32,
+  pub messages: Vec<MessageWithUser>,
+}
+
+/// Api: pin or unpin a message of a group
+#[derive(Deserialize, ToSchema)]
+pub struct PinMessageRequest {
+  pub pinned: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct PinMessageResponse {
+  pub message_id: i32,
+  pub group_id: i32,
+  pub pinned: bool,
+}
+
+/// Api: a pinned message of a group
+#[derive(Serialize, ToSchema)]
+pub struct PinnedMessageResponse {
+  pub message_id: i32,
+  /// Content of the pinned message, missing for messages without content
+  pub message_content: Option<String>,
+  pub pinned_by: i32,
+  #[serde(serialize_with = "serialize_naive_datetime")]
+  pub pinned_at: NaiveDateTime,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ReadAllResponse {
+  pub group_id: i32,
+  pub last_seen_message_id: Option<i32>,
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+
+  fn invalid_fields(result: Result<(), Vec<FieldError>>) -> Vec<String> {
+    result
+      .unwrap_err()
+      .into_iter()
+      .map(|error| error.field)
+      .collect()
+  }
+
+  fn send_message_request(body: serde_json::Value) -> SendMessageRequest {
+    serde_json::from_value(body).unwrap()
+  }
+
+  #[test]
+  fn send_message_request_accepts_text_message() {
+    let request = send_message_request(json!({ "group_id": 1, "content": "hello" }));
+    assert!(request.validate().is_ok());
+  }
+
+  #[test]
+  fn send_message_request_requires_content_or_attachments() {
+    let request = send_message_request(json!({ "group_id": 1, "content": "  " }));
+    assert_eq!(invalid_fields(request.validate()), ["content"]);
+    let request = send_message_request(json!({
+      "group_id": 1,
+      "attachments": [{ "url": "files/photo.png" }],
+    }));
+    assert!(request.validate().is_ok());
+  }
+
+  #[test]
+  fn send_message_request_rejects_nil_uuid() {
+    let request = send_message_request(json!({
+      "group_id": 1,
+      "content": "hello",
+      "message_uuid": Uuid::nil(),
+    }));
+    assert_eq!(invalid_fields(request.validate()), ["message_uuid"]);
+    let request = send_message_request(json!({
+      "group_id": 1,
+      "content": "hello",
+      "message_uuid": Uuid::new_v4(),
+    }));
+    assert!(request.validate().is_ok());
+  }
+
+  #[test]
+  fn send_message_request_limits_content_length() {
+    let request = send_message_request(json!({
+      "group_id": 1,
+      "content": "a".repeat(MAX_MESSAGE_CONTENT_LENGTH + 1),
+    }));
+    assert_eq!(invalid_fields(request.validate()), ["content"]);
+  }
+
+  #[test]
+  fn send_message_request_checks_attachments() {
+    let request = send_message_request(json!({
+      "group_id": 1,
+      "message_type": "ATTACHMENT",
+      "content": "hello",
+    }));
+    assert_eq!(invalid_fields(request.validate()), ["attachments"]);
+    let request = send_message_request(json!({
+      "group_id": 1,
+      "attachments": [{ "url": " " }],
+    }));
+    assert_eq!(invalid_fields(request.validate()), ["attachments"]);
+  }
+
+  #[test]
+  fn translate_query_requires_language_tag() {
+    for lang in ["en", "pt-BR", "zh-Hant"] {
+      assert!(TranslateQuery { lang: lang.into() }.validate().is_ok(), "{}", lang);
+    }
+    let too_long = "a".repeat(MAX_LANGUAGE_TAG_LENGTH + 1);
+    for lang in ["", "en_US", "en us", "日本", too_long.as_str()] {
+      assert!(TranslateQuery { lang: lang.into() }.validate().is_err(), "{}", lang);
+    }
+  }
+
+  #[test]
+  fn report_message_request_requires_reason() {
+    assert!(ReportMessageRequest { reason: "spam".into() }.validate().is_ok());
+    assert_eq!(
+      invalid_fields(ReportMessageRequest { reason: "".into() }.validate()),
+      ["reason"]
+    );
+    let reason = "a".repeat(MAX_REPORT_REASON_LENGTH + 1);
+    assert_eq!(invalid_fields(ReportMessageRequest { reason }.validate()), ["reason"]);
+  }
 }
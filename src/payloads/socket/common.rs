@@ -6,10 +6,21 @@ pub struct ResultMessage {
   pub message: String,
 }
 impl ResultMessage {
+  /// Status code of every response rejecting a request of an user who is not a member of the group,
+  /// the counterpart of `ApiError::NotAMember`
+  pub const NOT_A_MEMBER: i32 = 403;
+
   pub fn new(status_code: i32, message: &str) -> Self {
     Self {
       status_code,
       message: message.into(),
     }
   }
+
+  pub fn not_a_member(group_id: i32) -> Self {
+    Self::new(
+      Self::NOT_A_MEMBER,
+      &format!("The current user is not a member of the group {}", group_id),
+    )
+  }
 }
@@ -1,6 +1,6 @@
-use crate::database::models::{Message, MessageStatus, MessageTypeEnum, NewMessage};
+use crate::database::models::{Message, MessageStatus, MessageTypeEnum, NewMessage, PostPolicy};
 
-use crate::payloads::messages::{AttachmentPayload, UpdateMessage};
+use crate::payloads::messages::{AttachmentPayload, QuotedMessage, UpdateMessage};
 use crate::utils::custom_serde::*;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
@@ -20,20 +20,27 @@ use super::common::ResultMessage;
 ///   - 5 : Failed to get user from user code
 ///
 /// - `message`: short message for result
+/// - `user`: profile of the authenticated user, only on success
 ///
 #[allow(unused)]
 pub enum AuthenticationStatusCode {
-  Success,
+  Success(AuthenticatedUser),
   Timeout,
   UnsupportedMessageType,
   NoPermission,
   ExpireOrNotFound,
   Other,
+  TooManyFailedAttempts,
 }
-impl Into<ResultMessage> for AuthenticationStatusCode {
-  fn into(self) -> ResultMessage {
-    match self {
-      Self::Success => ResultMessage::new(0, "Authenticated Successfully"),
+impl Into<AuthenticateResultData> for AuthenticationStatusCode {
+  fn into(self) -> AuthenticateResultData {
+    let result = match self {
+      Self::Success(user) => {
+        return AuthenticateResultData {
+          result: ResultMessage::new(0, "Authenticated Successfully"),
+          user: Some(user),
+        }
+      }
       Self::Timeout => ResultMessage::new(1, "Authentication Timeout"),
       Self::UnsupportedMessageType => {
         ResultMessage::new(2, "Only supports authenticated text message type")
@@ -43,25 +50,226 @@ impl Into<ResultMessage> for AuthenticationStatusCode {
       }
       Self::ExpireOrNotFound => ResultMessage::new(4, "User token is expired or not found"),
       Self::Other => ResultMessage::new(5, "Failed to get user from user code"),
-    }
+      Self::TooManyFailedAttempts => {
+        ResultMessage::new(6, "Too many failed authentications, retry later")
+      }
+    };
+    AuthenticateResultData { result, user: None }
   }
 }
+
+/// Profile of the authenticated user, saving clients a request after connecting
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AuthenticatedUser {
+  pub user_id: i32,
+  pub username: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AuthenticateResultData {
+  #[serde(flatten)]
+  pub result: ResultMessage,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub user: Option<AuthenticatedUser>,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct MessagesData {
   pub group_id: i32,
   pub message_ids: Vec<i32>,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RecallMessageData {
+  pub message_id: i32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RecalledMessageData {
+  pub group_id: i32,
+  pub message_id: i32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct QueryStatusData {
+  pub message_ids: Vec<i32>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MessageStatusData {
+  pub message_id: i32,
+  pub status: SMessageStatus,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GroupData {
+  pub group_id: i32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MemberInfo {
+  pub user_id: i32,
+  pub username: String,
+  /// Name shown for the member in the group instead of the username
+  pub alias: Option<String>,
+  pub is_owner: bool,
+  pub is_online: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SetAliasData {
+  pub group_id: i32,
+  pub alias: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AliasChangedData {
+  pub group_id: i32,
+  pub user_id: i32,
+  pub alias: String,
+}
+
+/// A message was pinned or unpinned by the owner of the group, `pinned_at` is missing
+/// when the message was unpinned
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PinEventData {
+  pub group_id: i32,
+  pub message_id: i32,
+  pub pinned: bool,
+  pub by_user_id: i32,
+  #[serde(
+    serialize_with = "serialize_with_date_time_utc_option",
+    deserialize_with = "deserialize_with_date_time_utc_option"
+  )]
+  pub pinned_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MemberLeftData {
+  pub group_id: i32,
+  pub user_id: i32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct UserOfflineData {
+  pub user_id: i32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MembersData {
+  pub group_id: i32,
+  pub members: Vec<MemberInfo>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LastSeenData {
+  pub group_id: i32,
+  pub message_id: i32,
+}
+
+/// Delivery result of a sent message, `delivered_count` is the number of active
+/// connections of group members which received the message
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SendResultData {
+  pub message_uuid: Uuid,
+  pub message_id: i32,
+  pub group_id: i32,
+  pub delivered_count: usize,
+}
+
+/// Result of a message of a batch, `message_id` is missing if the message was rejected
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BatchItemResultData {
+  pub message_uuid: Uuid,
+  pub group_id: i32,
+  pub message_id: Option<i32>,
+  pub error: Option<String>,
+}
+
+/// Request a page of messages older than `cursor`, the latest messages if it is omitted
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FetchHistoryData {
+  pub group_id: i32,
+  /// Opaque cursor taken from `HistoryPageData::next_cursor`
+  pub cursor: Option<String>,
+  pub limit: Option<i64>,
+}
+
+/// Request messages of a group whose id is in `from_id..=to_id`, e.g. after missing events
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ReplayRangeData {
+  pub group_id: i32,
+  pub from_id: i32,
+  pub to_id: i32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct HistoryPageData {
+  pub group_id: i32,
+  pub messages: Vec<SMessageContent>,
+  pub has_more: bool,
+  /// Cursor of the next page, missing when there is no older message
+  pub next_cursor: Option<String>,
+}
+
+/// Result of the `Ready` handshake, group events buffered before it are sent right after it
+///
+/// If too many events were buffered they are dropped and `resync_required` is true,
+/// the client should reload the history of its groups
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ReadyData {
+  pub buffered_count: usize,
+  pub resync_required: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct UnreadCountData {
+  pub group_id: i32,
+  pub count: i64,
+}
+
+/// Ids of unseen messages of a group in ascending order, `has_more` is true when there are
+/// more unseen messages than the returned ids
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct UnreadMessagesData {
+  pub group_id: i32,
+  pub message_ids: Vec<i32>,
+  pub has_more: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GroupUpdatedData {
+  pub group_id: i32,
+  pub group_name: String,
+  pub archived: bool,
+  pub post_policy: PostPolicy,
+}
+
+/// Opaque value of a `Ping` echoed by the server in the `Pong`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PingData {
+  pub nonce: String,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub enum SMessageType {
   Authenticate(String),
-  AuthenticateResponse(ResultMessage),
+  AuthenticateResponse(AuthenticateResultData),
+
+  Ready,
+  ReadyResult(ReadyData),
 
   SubscribeGroup(i32),
   SubscribeGroupResponse(ResultMessage),
 
   Send(SNewMessage),
   Receive(SMessageContent),
+  SendResponse(SendResultData),
+  SendMessageResponse(ResultMessage),
+
+  SendBatch(Vec<SNewMessage>),
+  SendBatchResult(Vec<BatchItemResultData>),
+  SendBatchResponse(ResultMessage),
 
   EditMessage(SMessageEdit),
   EditMessageResponse(ResultMessage),
@@ -71,13 +279,90 @@ pub enum SMessageType {
   DeleteMessageEvent(MessagesData),
   DeleteMessageResponse(ResultMessage),
 
+  RecallMessage(RecallMessageData),
+  RecallMessageEvent(RecalledMessageData),
+  RecallMessageResponse(ResultMessage),
+
   SeenMessages(MessagesData),
   SeenMessagesEvent(MessagesData),
   SeenMessagesResponse(ResultMessage),
 
+  LastSeenEvent(LastSeenData),
+
+  GetMembers(GroupData),
+  MembersList(MembersData),
+  GetMembersResponse(ResultMessage),
+
+  UserOfflineEvent(UserOfflineData),
+
+  GroupUpdatedEvent(GroupUpdatedData),
+  GroupDeletedEvent(GroupData),
+  MemberLeftEvent(MemberLeftData),
+
+  LeaveGroup(GroupData),
+  LeaveGroupResponse(ResultMessage),
+
+  SetAlias(SetAliasData),
+  AliasChangedEvent(AliasChangedData),
+  SetAliasResponse(ResultMessage),
+
+  PinEvent(PinEventData),
+
+  UnreadCount(GroupData),
+  UnreadCountResult(UnreadCountData),
+  UnreadCountResponse(ResultMessage),
+
+  UnreadMessages(GroupData),
+  UnreadMessagesResult(UnreadMessagesData),
+  UnreadMessagesResponse(ResultMessage),
+
+  FetchHistory(FetchHistoryData),
+  HistoryPage(HistoryPageData),
+  FetchHistoryResponse(ResultMessage),
+
+  ReplayRange(ReplayRangeData),
+  ReplayRangeResponse(ResultMessage),
+
+  QueryStatus(QueryStatusData),
+  StatusResult(Vec<MessageStatusData>),
+  QueryStatusResponse(ResultMessage),
+
+  Ping(PingData),
+  Pong(PingData),
+
   UnSupportMessage(String),
 }
 
+impl SMessageType {
+  /// Whether a client is allowed to send the message type to the server.
+  ///
+  /// Events, results and responses are only sent from the server, a client sending them
+  /// could spoof events to other clients
+  pub fn is_client_message(&self) -> bool {
+    matches!(
+      self,
+      SMessageType::Authenticate(_)
+        | SMessageType::Ready
+        | SMessageType::SubscribeGroup(_)
+        | SMessageType::Send(_)
+        | SMessageType::SendBatch(_)
+        | SMessageType::EditMessage(_)
+        | SMessageType::DeleteMessage(_)
+        | SMessageType::RecallMessage(_)
+        | SMessageType::SeenMessages(_)
+        | SMessageType::GetMembers(_)
+        | SMessageType::LeaveGroup(_)
+        | SMessageType::SetAlias(_)
+        | SMessageType::UnreadCount(_)
+        | SMessageType::UnreadMessages(_)
+        | SMessageType::FetchHistory(_)
+        | SMessageType::ReplayRange(_)
+        | SMessageType::QueryStatus(_)
+        | SMessageType::Ping(_)
+    )
+  }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct SMessageContent {
   pub message_uuid: Uuid,
@@ -98,7 +383,10 @@ pub struct SMessageContent {
     deserialize_with = "deserialize_with_date_time_utc_option"
   )]
   pub updated_at: Option<DateTime<Utc>>,
+  pub version: i32,
   pub status: SMessageStatus,
+  pub reply_to: Option<i32>,
+  pub quoted: Option<QuotedMessage>,
 }
 impl From<Message> for SMessageContent {
   fn from(value: Message) -> Self {
@@ -113,7 +401,10 @@ impl From<Message> for SMessageContent {
       content: value.content.unwrap_or_default(),
       created_at: value.created_at.and_utc(),
       updated_at: value.updated_at.map(|data| data.and_utc()),
+      version: value.version,
       status: SMessageStatus::from(value.status),
+      reply_to: value.reply_to,
+      quoted: None,
     }
   }
 }
@@ -125,6 +416,7 @@ pub struct SNewMessage {
   pub message_type: Option<MessageTypeEnum>,
   pub content: Option<String>,
   pub attachments: Option<Vec<AttachmentPayload>>,
+  pub reply_to: Option<i32>,
 }
 
 impl<'a> SNewMessage {
@@ -142,6 +434,7 @@ impl<'a> SNewMessage {
       status: MessageStatus::Sent,
       created_at: Utc::now().naive_utc(),
       message_type,
+      reply_to: self.reply_to,
     }
   }
 }
@@ -152,12 +445,14 @@ pub struct SMessageEdit {
   pub group_id: i32,
   pub content: Option<String>,
   pub message_type: Option<MessageTypeEnum>,
+  pub version: Option<i32>,
 }
 impl Into<UpdateMessage> for SMessageEdit {
   fn into(self) -> UpdateMessage {
     UpdateMessage {
       content: self.content,
       message_type: self.message_type,
+      version: self.version,
     }
   }
 }
@@ -187,3 +482,172 @@ impl From<MessageStatus> for SMessageStatus {
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Decode a frame the way a JSON connection does
+  fn decode_client_frame(frame: &str) -> SMessageType {
+    serde_json::from_str::<SMessageType>(frame).unwrap()
+  }
+
+  fn message_content() -> SMessageContent {
+    SMessageContent {
+      message_uuid: Uuid::new_v4(),
+      message_id: 42,
+      user_id: 7,
+      group_id: 24,
+      content: "hello".into(),
+      username: Some("alice".into()),
+      message_type: MessageTypeEnum::TEXT,
+      attachments: None,
+      created_at: Utc::now(),
+      updated_at: None,
+      version: 1,
+      status: SMessageStatus::Sent,
+      reply_to: None,
+      quoted: None,
+    }
+  }
+
+  fn server_only_messages() -> Vec<SMessageType> {
+    let result = || ResultMessage::new(1, "result");
+    let messages_data = || MessagesData {
+      group_id: 24,
+      message_ids: vec![42],
+    };
+    vec![
+      SMessageType::AuthenticateResponse(AuthenticateResultData {
+        result: result(),
+        user: None,
+      }),
+      SMessageType::ReadyResult(ReadyData {
+        buffered_count: 0,
+        resync_required: false,
+      }),
+      SMessageType::SubscribeGroupResponse(result()),
+      SMessageType::Receive(message_content()),
+      SMessageType::SendResponse(SendResultData {
+        message_uuid: Uuid::new_v4(),
+        message_id: 42,
+        group_id: 24,
+        delivered_count: 1,
+      }),
+      SMessageType::SendMessageResponse(result()),
+      SMessageType::SendBatchResult(vec![BatchItemResultData {
+        message_uuid: Uuid::new_v4(),
+        group_id: 24,
+        message_id: Some(42),
+        error: None,
+      }]),
+      SMessageType::SendBatchResponse(result()),
+      SMessageType::EditMessageResponse(result()),
+      SMessageType::EditMessageData(message_content()),
+      SMessageType::DeleteMessageEvent(messages_data()),
+      SMessageType::DeleteMessageResponse(result()),
+      SMessageType::RecallMessageEvent(RecalledMessageData {
+        group_id: 24,
+        message_id: 42,
+      }),
+      SMessageType::RecallMessageResponse(result()),
+      SMessageType::SeenMessagesEvent(messages_data()),
+      SMessageType::SeenMessagesResponse(result()),
+      SMessageType::LastSeenEvent(LastSeenData {
+        group_id: 24,
+        message_id: 42,
+      }),
+      SMessageType::MembersList(MembersData {
+        group_id: 24,
+        members: vec![],
+      }),
+      SMessageType::GetMembersResponse(result()),
+      SMessageType::UserOfflineEvent(UserOfflineData { user_id: 7 }),
+      SMessageType::GroupUpdatedEvent(GroupUpdatedData {
+        group_id: 24,
+        group_name: "friends".into(),
+        archived: false,
+        post_policy: PostPolicy::Everyone,
+      }),
+      SMessageType::GroupDeletedEvent(GroupData { group_id: 24 }),
+      SMessageType::MemberLeftEvent(MemberLeftData {
+        group_id: 24,
+        user_id: 7,
+      }),
+      SMessageType::LeaveGroupResponse(result()),
+      SMessageType::AliasChangedEvent(AliasChangedData {
+        group_id: 24,
+        user_id: 7,
+        alias: "al".into(),
+      }),
+      SMessageType::SetAliasResponse(result()),
+      SMessageType::PinEvent(PinEventData {
+        group_id: 24,
+        message_id: 42,
+        pinned: true,
+        by_user_id: 7,
+        pinned_at: Some(Utc::now()),
+      }),
+      SMessageType::UnreadCountResult(UnreadCountData {
+        group_id: 24,
+        count: 3,
+      }),
+      SMessageType::UnreadCountResponse(result()),
+      SMessageType::UnreadMessagesResult(UnreadMessagesData {
+        group_id: 24,
+        message_ids: vec![42],
+        has_more: false,
+      }),
+      SMessageType::UnreadMessagesResponse(result()),
+      SMessageType::HistoryPage(HistoryPageData {
+        group_id: 24,
+        messages: vec![message_content()],
+        has_more: false,
+        next_cursor: None,
+      }),
+      SMessageType::FetchHistoryResponse(result()),
+      SMessageType::ReplayRangeResponse(result()),
+      SMessageType::StatusResult(vec![MessageStatusData {
+        message_id: 42,
+        status: SMessageStatus::Seen,
+      }]),
+      SMessageType::QueryStatusResponse(result()),
+      SMessageType::Pong(PingData {
+        nonce: "nonce".into(),
+      }),
+      SMessageType::UnSupportMessage("unsupported".into()),
+    ]
+  }
+
+  #[test]
+  fn client_sent_delete_message_event_is_rejected() {
+    let frame = r#"{"DeleteMessageEvent":{"group_id":24,"message_ids":[41,42]}}"#;
+    let message = decode_client_frame(frame);
+    assert!(matches!(message, SMessageType::DeleteMessageEvent(_)));
+    assert!(!message.is_client_message());
+  }
+
+  #[test]
+  fn client_sent_server_only_messages_are_rejected() {
+    for message in server_only_messages() {
+      let frame = serde_json::to_string(&message).unwrap();
+      assert!(!decode_client_frame(&frame).is_client_message(), "{}", frame);
+    }
+  }
+
+  #[test]
+  fn client_messages_are_accepted() {
+    let frames = [
+      r#"{"Authenticate":"CODE"}"#,
+      r#""Ready""#,
+      r#"{"SubscribeGroup":24}"#,
+      r#"{"DeleteMessage":{"group_id":24,"message_ids":[42]}}"#,
+      r#"{"SeenMessages":{"group_id":24,"message_ids":[42]}}"#,
+      r#"{"ReplayRange":{"group_id":24,"from_id":1,"to_id":42}}"#,
+      r#"{"Ping":{"nonce":"nonce"}}"#,
+    ];
+    for frame in frames {
+      assert!(decode_client_frame(frame).is_client_message(), "{}", frame);
+    }
+  }
+}
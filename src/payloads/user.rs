@@ -1,6 +1,14 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
+use crate::{
+    payloads::messages::MessageWithUser,
+    utils::custom_serde::{serialize_with_date_time_utc, serialize_with_date_time_utc_option},
+    utils::validation::{into_validation_result, validate_required_text, FieldError, Validate},
+    MAX_USERNAME_LENGTH, MAX_USERS_BATCH_IDS,
+};
+
 #[derive(Deserialize, ToSchema)]
 pub struct NewUserRequest {
     pub username: String,
@@ -12,3 +20,139 @@ pub struct UserResponse {
     pub username: String,
     pub user_code: String,
 }
+
+/// Api: aggregate stats of the current user
+#[derive(Serialize, ToSchema)]
+pub struct UserStatsResponse {
+    pub joined_groups: i64,
+    pub owned_groups: i64,
+    pub messages: i64,
+    pub attachments: i64,
+}
+
+/// Api: a guest session, the user code stops working after `expires_at`
+#[derive(Serialize, ToSchema)]
+pub struct GuestResponse {
+    pub user_id: i32,
+    pub username: String,
+    pub user_code: String,
+    #[serde(serialize_with = "serialize_with_date_time_utc_option")]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Api: turn the current guest into a permanent user
+#[derive(Deserialize, ToSchema)]
+pub struct UpgradeGuestRequest {
+    pub username: String,
+}
+
+impl Validate for UpgradeGuestRequest {
+    fn validate(&self) -> Result<(), Vec<FieldError>> {
+        let mut errors = Vec::new();
+        validate_required_text(&mut errors, "username", &self.username, MAX_USERNAME_LENGTH);
+        into_validation_result(errors)
+    }
+}
+
+/// Api: change the username of the current user
+#[derive(Deserialize, ToSchema)]
+pub struct UpdateUsernameRequest {
+    pub username: String,
+}
+
+impl Validate for UpdateUsernameRequest {
+    fn validate(&self) -> Result<(), Vec<FieldError>> {
+        let mut errors = Vec::new();
+        validate_required_text(&mut errors, "username", &self.username, MAX_USERNAME_LENGTH);
+        into_validation_result(errors)
+    }
+}
+
+/// Api: get public information of several users at once
+#[derive(Deserialize, ToSchema)]
+pub struct UsersBatchRequest {
+    pub user_ids: Vec<i32>,
+}
+
+impl Validate for UsersBatchRequest {
+    fn validate(&self) -> Result<(), Vec<FieldError>> {
+        let mut errors = Vec::new();
+        if self.user_ids.is_empty() {
+            errors.push(FieldError::new("user_ids", "must not be empty"));
+        } else if self.user_ids.len() > MAX_USERS_BATCH_IDS {
+            errors.push(FieldError::new(
+                "user_ids",
+                &format!("must contain at most {} ids", MAX_USERS_BATCH_IDS),
+            ));
+        }
+        into_validation_result(errors)
+    }
+}
+
+/// Api: public information of an user, the user code is never exposed
+#[derive(Serialize, ToSchema)]
+pub struct PublicUserResponse {
+    pub user_id: i32,
+    pub username: String,
+}
+
+/// Api: profile of the current user in a data export, the user code is left out
+#[derive(Serialize, ToSchema)]
+pub struct ExportProfile {
+    pub user_id: i32,
+    pub username: String,
+    #[serde(serialize_with = "serialize_with_date_time_utc")]
+    pub created_at: DateTime<Utc>,
+    pub is_guest: bool,
+}
+
+/// Api: a group joined by the current user in a data export
+#[derive(Serialize, ToSchema)]
+pub struct ExportGroup {
+    pub group_id: i32,
+    pub group_name: String,
+    pub is_owner: bool,
+    pub alias: Option<String>,
+    pub pinned: bool,
+}
+
+/// Api: a message sent by the current user in a data export, with the manifest of its attachments
+#[derive(Serialize, ToSchema)]
+pub struct ExportMessage {
+    pub group_id: i32,
+    #[serde(flatten)]
+    pub message: MessageWithUser,
+}
+
+/// Api: all data of the current user, returned by `GET /me/export`
+///
+/// Only used to document the api, the response body is streamed
+#[derive(Serialize, ToSchema)]
+pub struct UserExportResponse {
+    pub profile: ExportProfile,
+    pub groups: Vec<ExportGroup>,
+    pub messages: Vec<ExportMessage>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn username_requests_require_username() {
+        assert!(UpgradeGuestRequest { username: "alice".into() }.validate().is_ok());
+        assert!(UpgradeGuestRequest { username: " ".into() }.validate().is_err());
+        assert!(UpdateUsernameRequest { username: "alice".into() }.validate().is_ok());
+        assert!(UpdateUsernameRequest { username: "".into() }.validate().is_err());
+        let username = "a".repeat(MAX_USERNAME_LENGTH + 1);
+        assert!(UpdateUsernameRequest { username }.validate().is_err());
+    }
+
+    #[test]
+    fn users_batch_request_limits_ids() {
+        assert!(UsersBatchRequest { user_ids: vec![1, 2] }.validate().is_ok());
+        assert!(UsersBatchRequest { user_ids: vec![] }.validate().is_err());
+        let user_ids = vec![1; MAX_USERS_BATCH_IDS + 1];
+        assert!(UsersBatchRequest { user_ids }.validate().is_err());
+    }
+}
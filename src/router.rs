@@ -1,7 +1,7 @@
 use std::{env, sync::Arc, time::Duration};
 
 use axum::{
-  extract::DefaultBodyLimit, routing::{any, delete, get, post}, Router
+  extract::DefaultBodyLimit, routing::{any, delete, get, patch, post}, Router
 };
 use axum::http::{HeaderValue, Method};
 use dotenvy::dotenv;
@@ -13,8 +13,11 @@ use tower_http::cors::{CorsLayer, Any};
 use crate::{
   handlers,
   payloads::{
-    common::{OrderBy, CommonResponse, ListResponse},
-    groups::*, messages::*, user::{NewUserRequest, UserResponse}
+    common::{OrderBy, CommonResponse, HealthResponse, ListResponse},
+    groups::*, messages::*, user::{
+      GuestResponse, NewUserRequest, UpdateUsernameRequest, UpgradeGuestRequest, UserResponse,
+      UserStatsResponse
+    }
   },
   AppState,
 };
@@ -23,9 +26,11 @@ use crate::{
 #[openapi(
   paths(
     handlers::common::home,
+    handlers::common::health,
     handlers::group::get_list_groups_by_user_id,
     handlers::group::create_user_and_group,
     handlers::group::join_group,
+    handlers::group::join_group_v1,
     handlers::group::get_waiting_list,
     handlers::group::process_joining_request,
     handlers::group::del_gr_req,
@@ -33,28 +38,83 @@ use crate::{
     handlers::group::rm_user_from_gr,
     handlers::group::user_leave_gr,
     handlers::group::get_group_detail_with_extra_info, 
+    handlers::group::rename_group,
+    handlers::group::preview_group,
+    handlers::group::validate_group_code,
+    handlers::group::get_group_summaries,
+    handlers::group::remove_members,
+    handlers::group::unban_user,
+    handlers::group::archive_group,
+    handlers::group::unarchive_group,
+    handlers::group::get_group_reports,
+    handlers::group::get_pinned_messages,
+    handlers::group::get_my_permissions,
+    handlers::group::get_message_stats,
+    handlers::group::get_draft,
+    handlers::group::save_draft,
+    handlers::group::delete_draft,
+    handlers::group::pin_group,
+    handlers::group::set_slow_mode,
+    handlers::group::set_post_policy,
+    handlers::group::set_moderation,
+    handlers::group::set_max_message_length,
+    handlers::file::get_attachment,
+    handlers::user::get_my_stats,
+    handlers::user::export_my_data,
+    handlers::user::get_my_feed,
+    handlers::user::create_guest,
+    handlers::user::upgrade_guest,
+    handlers::user::update_username,
+    handlers::user::get_users_batch,
     handlers::message::send_msg,
     handlers::message::get_messages,
     handlers::message::update_message,
     handlers::message::delete_message,
+    handlers::message::get_message_attachments,
+    handlers::message::translate_message,
+    handlers::message::get_message_by_uuid,
+    handlers::message::report_message,
+    handlers::message::pin_message,
+    handlers::message::read_all_messages,
+    handlers::message::export_messages,
+    handlers::message::get_message_ids,
+    handlers::message::get_message_context,
     handlers::user::add_user_docs,
     handlers::file::upload_file,
     handlers::file::serve_file
     
   ),
   components(schemas(
-    OrderBy,
-    NewGroupForm, NewUserRequest,
+    OrderBy, HealthResponse,
+    NewGroupForm, NewUserRequest, JoinGroupForm, GroupResult, CommonResponse<GroupResult>,
     UserResponse, CommonResponse<UserResponse>,
     GroupListResponse, GroupInfo,
     ListResponse<WaitingListResponse>,
     DelGroupRequest, DelGroupResponse,
     GrDetailSettingResponse, 
     SendMessageRequest, SendMessageResponse,
-    AttachmentPayload,
+    AttachmentPayload, ListResponse<AttachmentPayload>, AttachmentDetailResponse,
     MessageResponse,
-    ListResponse<MessageWithUser>,
-    RmUserRequest, RmUserResponse
+    ListResponse<MessageWithUser>, QuotedMessage,
+    FeedItem, ListResponse<FeedItem>,
+    ListResponse<MessageReportResponse>,
+    ReadAllResponse,
+    TranslationResponse,
+    RmUserRequest, RmUserResponse,
+    RenameGroupRequest, GroupResponse, CommonResponse<GroupResponse>,
+    GroupPreviewResponse,
+    GroupSummariesRequest, GroupSummary, GroupSummariesResponse,
+    RemoveMembersRequest, RemoveMemberResult, RemoveMembersResponse,
+    CommonResponse<RemoveMembersResponse>,
+    PinGroupRequest, PinGroupResponse, CommonResponse<PinGroupResponse>,
+    ArchiveGroupResponse, CommonResponse<ArchiveGroupResponse>,
+    SlowModeRequest, SlowModeResponse, CommonResponse<SlowModeResponse>,
+    PostPolicyRequest, PostPolicyResponse, CommonResponse<PostPolicyResponse>,
+    ModerationRequest, ModerationResponse, CommonResponse<ModerationResponse>,
+    MaxMessageLengthRequest, MaxMessageLengthResponse, CommonResponse<MaxMessageLengthResponse>,
+    UserStatsResponse,
+    GuestResponse, CommonResponse<GuestResponse>, UpgradeGuestRequest,
+    UpdateUsernameRequest
     
   ))
 )]
@@ -78,11 +138,12 @@ pub fn init_router() -> Router<Arc<AppState>> {
   // Configure CORS to allow requests from the web client
   let cors = CorsLayer::new()
       .allow_origin(web_client_origin)
-      .allow_methods(vec![Method::GET, Method::POST, Method::OPTIONS])
+      .allow_methods(vec![Method::GET, Method::POST, Method::PATCH, Method::OPTIONS])
       .allow_headers(Any);
 
   Router::new()
     .route("/", get(handlers::common::home))
+    .route("/health", get(handlers::common::health))
     .route("/del-gr", post(handlers::group::del_gr_req))
     .route("/rm-rf-group", post(handlers::group::rm_rf_group))
     .route("/rm-u-from-gr", post(handlers::group::rm_user_from_gr))
@@ -90,19 +151,55 @@ pub fn init_router() -> Router<Arc<AppState>> {
     .route("/add-user-group",post(handlers::group::create_user_and_group))
     .route("/v1/add-user-group",post(handlers::group::create_user_and_group_v1))
     .route("/join-group", post(handlers::group::join_group))
+    .route("/v1/join-group", post(handlers::group::join_group_v1))
     .route("/gr/list/:user_id", get(handlers::group::get_list_groups_by_user_id))
+    .route("/groups/preview", get(handlers::group::preview_group))
+    .route("/groups/validate-code", get(handlers::group::validate_group_code))
+    .route("/groups/summaries", post(handlers::group::get_group_summaries))
+    .route("/groups/:group_id/name", patch(handlers::group::rename_group))
+    .route("/groups/:group_id/pin", patch(handlers::group::pin_group))
+    .route("/groups/:group_id/remove-members", post(handlers::group::remove_members))
+    .route("/groups/:group_id/bans/:user_id", delete(handlers::group::unban_user))
+    .route("/groups/:group_id/archive", post(handlers::group::archive_group))
+    .route("/groups/:group_id/unarchive", post(handlers::group::unarchive_group))
+    .route("/groups/:group_id/reports", get(handlers::group::get_group_reports))
+    .route("/groups/:group_id/pinned-messages", get(handlers::group::get_pinned_messages))
+    .route("/groups/:group_id/my-permissions", get(handlers::group::get_my_permissions))
+    .route("/groups/:group_id/message-stats", get(handlers::group::get_message_stats))
+    .route("/groups/:group_id/draft", get(handlers::group::get_draft).put(handlers::group::save_draft).delete(handlers::group::delete_draft))
+    .route("/groups/:group_id/slow-mode", patch(handlers::group::set_slow_mode))
+    .route("/groups/:group_id/post-policy", patch(handlers::group::set_post_policy))
+    .route("/groups/:group_id/moderation", patch(handlers::group::set_moderation))
+    .route("/groups/:group_id/max-message-length", patch(handlers::group::set_max_message_length))
     .route("/groups/:group_id/waiting-list", get(handlers::group::get_waiting_list))
     .route("/waiting-list/:request_id", post(handlers::group::process_joining_request))
     .route("/add-user", post(handlers::user::add_user)) //first: create a new user
+    .route("/me/stats", get(handlers::user::get_my_stats))
+    .route("/me/export", get(handlers::user::export_my_data))
+    .route("/me/feed", get(handlers::user::get_my_feed))
+    .route("/guest", post(handlers::user::create_guest))
+    .route("/me/upgrade", post(handlers::user::upgrade_guest))
+    .route("/me/username", patch(handlers::user::update_username))
+    .route("/users/batch", post(handlers::user::get_users_batch))
     .route("/create-group",post(handlers::group::create_group_with_user))
     .route("/messages", post(handlers::message::send_msg))
     .route("/messages/:message_id", delete(handlers::message::delete_message).put(handlers::message::update_message))
+    .route("/messages/:message_id/attachments", get(handlers::message::get_message_attachments))
+    .route("/messages/:message_id/translate", get(handlers::message::translate_message))
+    .route("/messages/by-uuid/:message_uuid", get(handlers::message::get_message_by_uuid))
+    .route("/messages/:message_id/report", post(handlers::message::report_message))
+    .route("/messages/:message_id/pin", patch(handlers::message::pin_message))
     .route("/groups/:group_id/messages", get(handlers::message::get_messages))
+    .route("/groups/:group_id/read-all", post(handlers::message::read_all_messages))
+    .route("/groups/:group_id/messages/export", get(handlers::message::export_messages))
+    .route("/groups/:group_id/messages/:message_id/context", get(handlers::message::get_message_context))
+    .route("/groups/:group_id/message-ids", get(handlers::message::get_message_ids))
     .route("/group-detail/:group_id", get(handlers::group::get_group_detail_with_extra_info))
     .route("/group-detail/setting/:gr_id", get(handlers::group::get_gr_setting_v1))
     .route("/add-user-doc", post(handlers::user::add_user_docs))
     .route("/files", post(handlers::file::upload_file))
     .route("/files/:filename", get(handlers::file::serve_file))
+    .route("/attachments/:attachment_id", get(handlers::file::get_attachment))
     .route("/ws", any(handlers::socket::handler::ws_handler))
     .fallback(handlers::common::fallback)
     .merge(get_swagger_ui())
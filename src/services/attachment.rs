@@ -1,8 +1,18 @@
-use diesel::{RunQueryDsl, SelectableHelper};
+use std::collections::HashSet;
+
+use diesel::{
+  dsl::sql,
+  sql_types::{BigInt, Bool},
+  ExpressionMethods, JoinOnDsl, OptionalExtension, QueryDsl, RunQueryDsl, SelectableHelper,
+};
 
 use crate::{
-  database::models::{self, Attachment, NewAttachment},
+  database::{
+    models::{self, Attachment, NewAttachment},
+    schema::{attachments, messages},
+  },
   errors::DBError,
+  payloads::common::PageRequest,
   PoolPGConnectionType,
 };
 #[allow(dead_code)]
@@ -37,3 +47,206 @@ pub fn create_attachments(
     })?;
   Ok(attachment)
 }
+
+/// Get names of uploaded files referenced by any attachment
+///
+/// The file name is the last path segment of the attachment url
+pub fn get_referenced_file_names(
+  conn: &mut PoolPGConnectionType,
+) -> Result<HashSet<String>, DBError> {
+  let urls = attachments::table
+    .select(attachments::url)
+    .load::<String>(conn)
+    .map_err(|err| {
+      tracing::error!("Failed to get attachment urls: {}", err.to_string());
+      DBError::QueryError("Failed to get attachment urls".into())
+    })?;
+  Ok(
+    urls
+      .iter()
+      .filter_map(|url| url.rsplit('/').next())
+      .map(|file_name| file_name.to_owned())
+      .collect(),
+  )
+}
+
+pub fn get_attachments_of_messages(
+  conn: &mut PoolPGConnectionType,
+  message_ids: &[i32],
+) -> Result<Vec<Attachment>, DBError> {
+  attachments::table
+    .filter(attachments::message_id.eq_any(message_ids))
+    .order((attachments::message_id.asc(), attachments::id.asc()))
+    .select(Attachment::as_select())
+    .load::<Attachment>(conn)
+    .map_err(|err| {
+      tracing::error!("Failed to get attachments of messages: {}", err.to_string());
+      DBError::QueryError("Failed to get attachments of messages".into())
+    })
+}
+
+/// Get at most `limit` first attachments of each message
+pub fn get_first_attachments_of_messages(
+  conn: &mut PoolPGConnectionType,
+  message_ids: &[i32],
+  limit: i64,
+) -> Result<Vec<Attachment>, DBError> {
+  attachments::table
+    .filter(attachments::message_id.eq_any(message_ids))
+    .filter(
+      sql::<Bool>(
+        "attachments.id IN (SELECT first_attachments.id FROM attachments AS first_attachments \
+         WHERE first_attachments.message_id = attachments.message_id \
+         ORDER BY first_attachments.id LIMIT ",
+      )
+      .bind::<BigInt, _>(limit)
+      .sql(")"),
+    )
+    .order((attachments::message_id.asc(), attachments::id.asc()))
+    .select(Attachment::as_select())
+    .load::<Attachment>(conn)
+    .map_err(|err| {
+      tracing::error!("Failed to get attachments of messages: {}", err.to_string());
+      DBError::QueryError("Failed to get attachments of messages".into())
+    })
+}
+
+pub fn get_attachments_of_message(
+  conn: &mut PoolPGConnectionType,
+  message_id: i32,
+  page: &PageRequest,
+) -> Result<Vec<Attachment>, DBError> {
+  let (offset, limit) = page.get_offset_and_limit();
+  attachments::table
+    .filter(attachments::message_id.eq(message_id))
+    .order(attachments::id.asc())
+    .offset(offset as i64)
+    .limit(limit)
+    .select(Attachment::as_select())
+    .load::<Attachment>(conn)
+    .map_err(|err| {
+      tracing::error!(
+        "Failed to get attachments of message {}: {}",
+        message_id,
+        err.to_string()
+      );
+      DBError::QueryError("Failed to get attachments of message".into())
+    })
+}
+
+pub fn get_count_attachments_of_message(
+  conn: &mut PoolPGConnectionType,
+  message_id: i32,
+) -> Result<i64, DBError> {
+  attachments::table
+    .filter(attachments::message_id.eq(message_id))
+    .count()
+    .get_result::<i64>(conn)
+    .map_err(|err| {
+      tracing::error!(
+        "Failed to count attachments of message {}: {}",
+        message_id,
+        err.to_string()
+      );
+      DBError::QueryError("Failed to count attachments of message".into())
+    })
+}
+
+/// Get an attachment together with the group of its message, attachments of deleted messages are left out
+pub fn get_attachment_with_group(
+  conn: &mut PoolPGConnectionType,
+  attachment_id: i32,
+) -> Result<Option<(Attachment, i32)>, DBError> {
+  attachments::table
+    .inner_join(messages::table.on(messages::id.eq(attachments::message_id)))
+    .filter(attachments::id.eq(attachment_id))
+    .filter(messages::deleted_at.is_null())
+    .select((Attachment::as_select(), messages::group_id))
+    .first::<(Attachment, i32)>(conn)
+    .optional()
+    .map_err(|err| {
+      tracing::error!(
+        "Failed to get attachment {}: {}",
+        attachment_id,
+        err.to_string()
+      );
+      DBError::QueryError("Failed to get attachment".into())
+    })
+}
+
+/// Get the groups of messages having an attachment with the stored url, each with whether
+/// the message is deleted
+pub fn get_groups_of_url(
+  conn: &mut PoolPGConnectionType,
+  url: &str,
+) -> Result<Vec<(i32, bool)>, DBError> {
+  attachments::table
+    .inner_join(messages::table.on(messages::id.eq(attachments::message_id)))
+    .filter(attachments::url.eq(url))
+    .select((messages::group_id, messages::deleted_at.is_not_null()))
+    .distinct()
+    .load::<(i32, bool)>(conn)
+    .map_err(|err| {
+      tracing::error!("Failed to get groups of attachment url {}: {}", url, err.to_string());
+      DBError::QueryError("Failed to get groups of attachment".into())
+    })
+}
+
+/// Get the total size in bytes of attachments of a group, including attachments of recalled messages
+pub fn get_storage_bytes_of_group(
+  conn: &mut PoolPGConnectionType,
+  group_id: i32,
+) -> Result<i64, DBError> {
+  attachments::table
+    .inner_join(messages::table.on(messages::id.eq(attachments::message_id)))
+    .filter(messages::group_id.eq(group_id))
+    // SUM of int8 is numeric, cast it back as bigint
+    .select(sql::<BigInt>("COALESCE(SUM(attachments.size_bytes), 0)::int8"))
+    .first::<i64>(conn)
+    .map_err(|err| {
+      tracing::error!(
+        "Failed to get storage usage of group {}: {}",
+        group_id,
+        err.to_string()
+      );
+      DBError::QueryError("Failed to get storage usage of group".into())
+    })
+}
+
+/// Get id and url of at most `limit` attachments whose file size is unknown
+pub fn get_attachments_without_size(
+  conn: &mut PoolPGConnectionType,
+  after_id: i32,
+  limit: i64,
+) -> Result<Vec<(i32, String)>, DBError> {
+  attachments::table
+    .filter(attachments::size_bytes.is_null())
+    .filter(attachments::id.gt(after_id))
+    .order(attachments::id.asc())
+    .limit(limit)
+    .select((attachments::id, attachments::url))
+    .load::<(i32, String)>(conn)
+    .map_err(|err| {
+      tracing::error!("Failed to get attachments without size: {}", err.to_string());
+      DBError::QueryError("Failed to get attachments without size".into())
+    })
+}
+
+pub fn update_attachment_size(
+  conn: &mut PoolPGConnectionType,
+  attachment_id: i32,
+  size_bytes: i64,
+) -> Result<(), DBError> {
+  diesel::update(attachments::table.find(attachment_id))
+    .set(attachments::size_bytes.eq(size_bytes))
+    .execute(conn)
+    .map(|_| ())
+    .map_err(|err| {
+      tracing::error!(
+        "Failed to update size of attachment {}: {}",
+        attachment_id,
+        err.to_string()
+      );
+      DBError::QueryError("Failed to update size of attachment".into())
+    })
+}
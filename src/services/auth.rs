@@ -0,0 +1,62 @@
+use chrono::Utc;
+use diesel::{ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl, SelectableHelper};
+
+use crate::{
+  database::{models::User, schema::users},
+  errors::{AuthError, DBError},
+  PoolPGConnectionType,
+};
+
+/// ### Resolve the user of a user code
+///
+/// Shared by the HTTP handlers and the websocket authentication, so both apply the same rules.
+/// Every path resolving a user code goes through this function or `find_user_by_code`
+pub fn authenticate_user_code(
+  conn: &mut PoolPGConnectionType,
+  user_code: Option<&str>,
+) -> Result<User, AuthError> {
+  let user_code = match user_code {
+    Some(user_code) if !user_code.is_empty() => user_code,
+    _ => return Err(AuthError::Missing),
+  };
+  let user = get_user_by_code(conn, user_code)
+    .map_err(|err| {
+      tracing::error!("Failed to get user by code: {}", err.to_string());
+      AuthError::DatabaseError(DBError::QueryError("Failed to retrieve user by code".into()))
+    })?
+    .ok_or(AuthError::Invalid)?;
+  if user
+    .user_code_expires_at
+    .is_some_and(|expires_at| expires_at <= Utc::now().naive_utc())
+  {
+    return Err(AuthError::Expired);
+  }
+  Ok(user)
+}
+
+/// ### Resolve the user of an optional user code
+///
+/// For endpoints creating a new user when the code is missing or unknown: `None` is returned
+/// in these cases, but an expired code still fails with `AuthError::Expired`
+pub fn find_user_by_code(
+  conn: &mut PoolPGConnectionType,
+  user_code: Option<&str>,
+) -> Result<Option<User>, AuthError> {
+  match authenticate_user_code(conn, user_code) {
+    Ok(user) => Ok(Some(user)),
+    Err(AuthError::Missing | AuthError::Invalid) => Ok(None),
+    Err(err) => Err(err),
+  }
+}
+
+/// Only used by `authenticate_user_code`, other paths must not skip its checks
+fn get_user_by_code(
+  conn: &mut PoolPGConnectionType,
+  secret_code: &str,
+) -> Result<Option<User>, diesel::result::Error> {
+  users::table
+    .filter(users::user_code.eq(secret_code))
+    .select(User::as_select())
+    .first(conn)
+    .optional()
+}
@@ -0,0 +1,75 @@
+use std::time::Duration;
+
+use diesel::{
+  r2d2::{ConnectionManager, Pool},
+  result::DatabaseErrorKind,
+  PgConnection,
+};
+
+use crate::{errors::DBError, PoolPGConnectionType};
+
+/// Retry of idempotent read queries failing with a transient error
+pub struct RetryPolicy {
+  /// Number of retries after the first attempt, 0 disables retrying
+  pub retries: u32,
+  /// Delay before the first retry, doubled before each following retry
+  pub backoff: Duration,
+}
+
+/// Map a failed query to `DBError::ConnectionLost` if the connection was closed,
+/// otherwise to `DBError::QueryError` with `message`
+pub fn query_error(err: &diesel::result::Error, message: String) -> DBError {
+  match err {
+    diesel::result::Error::DatabaseError(DatabaseErrorKind::ClosedConnection, info) => {
+      DBError::ConnectionLost(info.message().to_string())
+    }
+    _ => DBError::QueryError(message),
+  }
+}
+
+/// A query failing with a transient error may succeed with another connection
+fn is_transient(err: &DBError) -> bool {
+  matches!(err, DBError::ConnectionError(_) | DBError::ConnectionLost(_))
+}
+
+/// ### Run a read query, retrying it with a new connection on transient errors
+///
+/// `conn` is replaced by a new connection of `db_pool` before each retry, with an exponential
+/// backoff between attempts. Only idempotent queries may be retried, never pass writes here:
+/// a write might have been applied before the connection was lost
+pub async fn with_retry<T, F>(
+  conn: &mut PoolPGConnectionType,
+  db_pool: &Pool<ConnectionManager<PgConnection>>,
+  policy: &RetryPolicy,
+  mut query: F,
+) -> Result<T, DBError>
+where
+  F: FnMut(&mut PoolPGConnectionType) -> Result<T, DBError>,
+{
+  let mut backoff = policy.backoff;
+  let mut attempt = 0;
+  loop {
+    match query(conn) {
+      Err(err) if attempt < policy.retries && is_transient(&err) => {
+        attempt += 1;
+        tracing::warn!(
+          "Transient database error, retry {}/{} in {:?}: {}",
+          attempt,
+          policy.retries,
+          backoff,
+          err.to_string()
+        );
+        tokio::time::sleep(backoff).await;
+        backoff *= 2;
+        match db_pool.get() {
+          Ok(new_conn) => *conn = new_conn,
+          Err(err) if attempt < policy.retries => {
+            tracing::warn!("Failed to get a new connection: {}", err.to_string());
+          }
+          Err(err) => return Err(DBError::ConnectionError(err)),
+        }
+      }
+      result => return result,
+    }
+  }
+}
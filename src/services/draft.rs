@@ -0,0 +1,82 @@
+use chrono::Utc;
+use diesel::{upsert::excluded, ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl};
+
+use crate::{
+  database::{models::Draft, schema::drafts},
+  errors::DBError,
+  PoolPGConnectionType,
+};
+
+/// Get the draft of an user in a group, `None` if the user has no draft there
+pub fn get_draft(
+  conn: &mut PoolPGConnectionType,
+  user_id: i32,
+  group_id: i32,
+) -> Result<Option<Draft>, DBError> {
+  drafts::table
+    .find((user_id, group_id))
+    .first::<Draft>(conn)
+    .optional()
+    .map_err(|err| {
+      tracing::error!(
+        "Failed to get draft of user {} in group {}: {}",
+        user_id,
+        group_id,
+        err
+      );
+      DBError::QueryError("Failed to get draft".into())
+    })
+}
+
+/// Save the draft of an user in a group, replacing the previous draft
+pub fn save_draft(
+  conn: &mut PoolPGConnectionType,
+  user_id: i32,
+  group_id: i32,
+  content: String,
+) -> Result<Draft, DBError> {
+  let draft = Draft {
+    user_id,
+    group_id,
+    content,
+    updated_at: Utc::now().naive_utc(),
+  };
+  diesel::insert_into(drafts::table)
+    .values(&draft)
+    .on_conflict((drafts::user_id, drafts::group_id))
+    .do_update()
+    .set((
+      drafts::content.eq(excluded(drafts::content)),
+      drafts::updated_at.eq(excluded(drafts::updated_at)),
+    ))
+    .get_result::<Draft>(conn)
+    .map_err(|err| {
+      tracing::error!(
+        "Failed to save draft of user {} in group {}: {}",
+        user_id,
+        group_id,
+        err
+      );
+      DBError::QueryError("Failed to save draft".into())
+    })
+}
+
+/// Delete the draft of an user in a group, return false if the user had no draft there
+pub fn delete_draft(
+  conn: &mut PoolPGConnectionType,
+  user_id: i32,
+  group_id: i32,
+) -> Result<bool, DBError> {
+  diesel::delete(drafts::table.find((user_id, group_id)))
+    .execute(conn)
+    .map(|deleted| deleted > 0)
+    .map_err(|err| {
+      tracing::error!(
+        "Failed to delete draft of user {} in group {}: {}",
+        user_id,
+        group_id,
+        err
+      );
+      DBError::QueryError("Failed to delete draft".into())
+    })
+}
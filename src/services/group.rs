@@ -1,15 +1,24 @@
+use std::collections::HashMap;
+
+use chrono::{NaiveDateTime, Utc};
 use diesel::{
-  dsl::count, BoolExpressionMethods, ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl,
-  SelectableHelper,
+  dsl::{self, count, count_star, sql},
+  sql_types::BigInt,
+  upsert::excluded,
+  BoolExpressionMethods, ExpressionMethods, JoinOnDsl, NullableExpressionMethods,
+  OptionalExtension, QueryDsl, RunQueryDsl, SelectableHelper,
 };
 
 use crate::{
   database::{
-    models::{Group, WaitingList},
-    schema::{groups, participants, waiting_list},
+    models::{Group, GroupBan, PostPolicy, WaitingList},
+    schema::{
+      attachments, group_bans, groups, last_seen_messages, messages, participants, waiting_list,
+    },
   },
   errors::DBError,
-  PoolPGConnectionType,
+  payloads::groups::GroupSummary,
+  PoolPGConnectionType, MAX_MESSAGE_CONTENT_LENGTH,
 };
 
 pub fn check_user_join_group(
@@ -137,3 +146,574 @@ pub fn get_count_participants(
       })?,
   )
 }
+
+/// Get the group having `group_code`, including an expired group
+pub fn get_group_by_code(
+  conn: &mut PoolPGConnectionType,
+  group_code: &str,
+) -> Result<Option<Group>, DBError> {
+  groups::table
+    .filter(groups::group_code.eq(group_code))
+    .select(Group::as_select())
+    .first::<Group>(conn)
+    .optional()
+    .map_err(|err| {
+      tracing::error!("Failed to get group from group code: {:?}", err);
+      DBError::QueryError(format!("Error getting group from group code: {:?}", err))
+    })
+}
+
+/// Get the group having `group_code` if it is not expired yet
+pub fn get_active_group_by_code(
+  conn: &mut PoolPGConnectionType,
+  group_code: &str,
+) -> Result<Option<Group>, DBError> {
+  groups::table
+    .filter(groups::group_code.eq(group_code))
+    .filter(
+      groups::expired_at
+        .is_null()
+        .or(groups::expired_at.gt(Utc::now().naive_utc())),
+    )
+    .select(Group::as_select())
+    .first::<Group>(conn)
+    .optional()
+    .map_err(|err| {
+      tracing::error!("Failed to get group from group code: {:?}", err);
+      DBError::QueryError(format!("Error getting group from group code: {:?}", err))
+    })
+}
+
+/// Count groups owned by the user which are not expired yet
+pub fn get_count_active_groups_of_owner(
+  conn: &mut PoolPGConnectionType,
+  user_id: i32,
+) -> Result<i64, DBError> {
+  groups::table
+    .filter(groups::user_id.eq(user_id))
+    .filter(
+      groups::expired_at
+        .is_null()
+        .or(groups::expired_at.gt(Utc::now().naive_utc())),
+    )
+    .count()
+    .get_result::<i64>(conn)
+    .map_err(|err| {
+      tracing::error!(
+        "Failed to count active groups of user_id {}: {:?}",
+        user_id,
+        err
+      );
+      DBError::QueryError(format!("Error counting active groups: {:?}", err))
+    })
+}
+
+pub fn update_group_name(
+  conn: &mut PoolPGConnectionType,
+  group_id: i32,
+  name: &str,
+) -> Result<Group, DBError> {
+  diesel::update(groups::table.find(group_id))
+    .set(groups::name.eq(name))
+    .returning(Group::as_returning())
+    .get_result::<Group>(conn)
+    .map_err(|err| {
+      tracing::error!("Failed to update name of group {}: {:?}", group_id, err);
+      DBError::QueryError(format!("Error updating group name: {:?}", err))
+    })
+}
+
+/// ### Get summaries of the given groups which `user_id` joined, keyed by group id
+///
+/// Each count is computed by one grouped query for all groups
+pub fn get_group_summaries(
+  conn: &mut PoolPGConnectionType,
+  user_id: i32,
+  group_ids: &[i32],
+) -> Result<HashMap<i32, GroupSummary>, DBError> {
+  let joined_group_ids = participants::table
+    .filter(participants::user_id.eq(user_id))
+    .filter(participants::group_id.eq_any(group_ids))
+    .select(participants::group_id)
+    .load::<i32>(conn)
+    .map_err(|err| {
+      tracing::error!("Failed to get joined groups of user {}: {}", user_id, err);
+      DBError::QueryError("Failed to get joined groups".into())
+    })?;
+  let mut summaries = joined_group_ids
+    .iter()
+    .map(|group_id| (*group_id, GroupSummary::default()))
+    .collect::<HashMap<i32, GroupSummary>>();
+  if summaries.is_empty() {
+    return Ok(summaries);
+  }
+
+  let member_counts = participants::table
+    .filter(participants::group_id.eq_any(&joined_group_ids))
+    .group_by(participants::group_id)
+    .select((participants::group_id, count(participants::user_id)))
+    .load::<(i32, i64)>(conn)
+    .map_err(|err| {
+      tracing::error!("Failed to count members of groups: {}", err);
+      DBError::QueryError("Failed to count members of groups".into())
+    })?;
+  for (group_id, member_count) in member_counts {
+    if let Some(summary) = summaries.get_mut(&group_id) {
+      summary.member_count = member_count;
+    }
+  }
+
+  let last_activities = messages::table
+    .filter(messages::group_id.eq_any(&joined_group_ids))
+    .filter(messages::deleted_at.is_null())
+    .group_by(messages::group_id)
+    .select((messages::group_id, dsl::max(messages::created_at)))
+    .load::<(i32, Option<NaiveDateTime>)>(conn)
+    .map_err(|err| {
+      tracing::error!("Failed to get last activity of groups: {}", err);
+      DBError::QueryError("Failed to get last activity of groups".into())
+    })?;
+  for (group_id, last_activity) in last_activities {
+    if let Some(summary) = summaries.get_mut(&group_id) {
+      summary.last_activity = last_activity;
+    }
+  }
+
+  let unread_counts = messages::table
+    .left_join(
+      last_seen_messages::table.on(
+        last_seen_messages::group_id
+          .eq(messages::group_id)
+          .and(last_seen_messages::user_id.eq(user_id)),
+      ),
+    )
+    .filter(messages::group_id.eq_any(&joined_group_ids))
+    .filter(messages::deleted_at.is_null())
+    .filter(messages::user_id.ne(user_id))
+    .filter(
+      last_seen_messages::message_id
+        .is_null()
+        .or(messages::id.nullable().gt(last_seen_messages::message_id.nullable())),
+    )
+    .group_by(messages::group_id)
+    .select((messages::group_id, count_star()))
+    .load::<(i32, i64)>(conn)
+    .map_err(|err| {
+      tracing::error!("Failed to count unread messages of user {}: {}", user_id, err);
+      DBError::QueryError("Failed to count unread messages".into())
+    })?;
+  for (group_id, unread_count) in unread_counts {
+    if let Some(summary) = summaries.get_mut(&group_id) {
+      summary.unread_count = unread_count;
+    }
+  }
+
+  let storage_usages = attachments::table
+    .inner_join(messages::table.on(messages::id.eq(attachments::message_id)))
+    .filter(messages::group_id.eq_any(&joined_group_ids))
+    .group_by(messages::group_id)
+    // SUM of int8 is numeric, cast it back as bigint
+    .select((
+      messages::group_id,
+      sql::<BigInt>("COALESCE(SUM(attachments.size_bytes), 0)::int8"),
+    ))
+    .load::<(i32, i64)>(conn)
+    .map_err(|err| {
+      tracing::error!("Failed to get storage usage of groups: {}", err);
+      DBError::QueryError("Failed to get storage usage of groups".into())
+    })?;
+  for (group_id, storage_bytes) in storage_usages {
+    if let Some(summary) = summaries.get_mut(&group_id) {
+      summary.storage_bytes = storage_bytes;
+    }
+  }
+
+  Ok(summaries)
+}
+
+/// Remove members from a group in a single statement, return ids of the removed members
+///
+/// Users who are not members of the group are ignored
+pub fn remove_members(
+  conn: &mut PoolPGConnectionType,
+  group_id: i32,
+  user_ids: &[i32],
+) -> Result<Vec<i32>, DBError> {
+  diesel::delete(
+    participants::table
+      .filter(participants::group_id.eq(group_id))
+      .filter(participants::user_id.eq_any(user_ids)),
+  )
+  .returning(participants::user_id)
+  .get_results::<i32>(conn)
+  .map_err(|err| {
+    tracing::error!(
+      "Failed to remove members from group {}: {}",
+      group_id,
+      err.to_string()
+    );
+    DBError::QueryError("Failed to remove members from group".into())
+  })
+}
+
+/// Outcome of a user leaving a group
+pub enum LeaveGroupOutcome {
+  /// The participant row of the user was removed
+  Left,
+  /// The group does not exist
+  GroupNotFound,
+  /// The owner must stay a participant, the group should be deleted instead
+  IsOwner,
+  /// The user is not a member of the group
+  NotAMember,
+}
+
+/// Remove the current user from a group, unless the user owns the group
+pub fn leave_group(
+  conn: &mut PoolPGConnectionType,
+  user_id: i32,
+  group_id: i32,
+) -> Result<LeaveGroupOutcome, DBError> {
+  let Some(group) = get_group_info(conn, group_id)? else {
+    return Ok(LeaveGroupOutcome::GroupNotFound);
+  };
+  if group.user_id == user_id {
+    return Ok(LeaveGroupOutcome::IsOwner);
+  }
+  let removed = remove_members(conn, group_id, &[user_id])?;
+  if removed.is_empty() {
+    return Ok(LeaveGroupOutcome::NotAMember);
+  }
+  Ok(LeaveGroupOutcome::Left)
+}
+
+/// Ban users from rejoining a group until `banned_until`, replacing their previous bans
+pub fn ban_users(
+  conn: &mut PoolPGConnectionType,
+  group_id: i32,
+  user_ids: &[i32],
+  banned_until: NaiveDateTime,
+) -> Result<(), DBError> {
+  let created_at = Utc::now().naive_utc();
+  let bans = user_ids
+    .iter()
+    .map(|user_id| GroupBan {
+      group_id,
+      user_id: *user_id,
+      banned_until,
+      created_at,
+    })
+    .collect::<Vec<GroupBan>>();
+  diesel::insert_into(group_bans::table)
+    .values(&bans)
+    .on_conflict((group_bans::group_id, group_bans::user_id))
+    .do_update()
+    .set((
+      group_bans::banned_until.eq(excluded(group_bans::banned_until)),
+      group_bans::created_at.eq(excluded(group_bans::created_at)),
+    ))
+    .execute(conn)
+    .map(|_| ())
+    .map_err(|err| {
+      tracing::error!(
+        "Failed to ban users from group {}: {}",
+        group_id,
+        err.to_string()
+      );
+      DBError::QueryError("Failed to ban users from group".into())
+    })
+}
+
+/// Get the end of the ban of an user from a group, `None` if the user is not banned anymore
+pub fn get_active_ban(
+  conn: &mut PoolPGConnectionType,
+  group_id: i32,
+  user_id: i32,
+) -> Result<Option<NaiveDateTime>, DBError> {
+  group_bans::table
+    .find((group_id, user_id))
+    .filter(group_bans::banned_until.gt(Utc::now().naive_utc()))
+    .select(group_bans::banned_until)
+    .first::<NaiveDateTime>(conn)
+    .optional()
+    .map_err(|err| {
+      tracing::error!(
+        "Failed to get ban of user {} from group {}: {}",
+        user_id,
+        group_id,
+        err.to_string()
+      );
+      DBError::QueryError("Failed to get ban of user".into())
+    })
+}
+
+/// Lift the ban of an user from a group, return false if the user was not banned
+pub fn unban_user(
+  conn: &mut PoolPGConnectionType,
+  group_id: i32,
+  user_id: i32,
+) -> Result<bool, DBError> {
+  diesel::delete(group_bans::table.find((group_id, user_id)))
+    .execute(conn)
+    .map(|count| count > 0)
+    .map_err(|err| {
+      tracing::error!(
+        "Failed to unban user {} from group {}: {}",
+        user_id,
+        group_id,
+        err.to_string()
+      );
+      DBError::QueryError("Failed to unban user".into())
+    })
+}
+
+/// Pin or unpin a group in the group list of a member
+///
+/// Return false if the user is not a member of the group
+pub fn update_pinned(
+  conn: &mut PoolPGConnectionType,
+  user_id: i32,
+  group_id: i32,
+  pinned: bool,
+) -> Result<bool, DBError> {
+  use crate::database::schema::participants;
+  diesel::update(
+    participants::table
+      .filter(participants::user_id.eq(user_id))
+      .filter(participants::group_id.eq(group_id)),
+  )
+  .set(participants::pinned.eq(pinned))
+  .execute(conn)
+  .map(|count| count > 0)
+  .map_err(|err| {
+    tracing::error!(
+      "Failed to update pinned group {} of user {}: {}",
+      group_id,
+      user_id,
+      err.to_string()
+    );
+    DBError::QueryError("Failed to update pinned group".into())
+  })
+}
+
+/// Set the slow mode interval of a group, `None` disables slow mode
+pub fn update_slow_mode(
+  conn: &mut PoolPGConnectionType,
+  group_id: i32,
+  slow_mode_seconds: Option<i32>,
+) -> Result<Group, DBError> {
+  diesel::update(groups::table.find(group_id))
+    .set(groups::slow_mode_seconds.eq(slow_mode_seconds))
+    .returning(Group::as_returning())
+    .get_result::<Group>(conn)
+    .map_err(|err| {
+      tracing::error!(
+        "Failed to update slow mode of group {}: {}",
+        group_id,
+        err.to_string()
+      );
+      DBError::QueryError("Failed to update slow mode of group".into())
+    })
+}
+
+/// Set the alias of a member in a group, return false if the user is not a member of the group
+pub fn update_alias(
+  conn: &mut PoolPGConnectionType,
+  user_id: i32,
+  group_id: i32,
+  alias: &str,
+) -> Result<bool, DBError> {
+  use crate::database::schema::participants;
+  diesel::update(
+    participants::table
+      .filter(participants::user_id.eq(user_id))
+      .filter(participants::group_id.eq(group_id)),
+  )
+  .set(participants::alias.eq(alias))
+  .execute(conn)
+  .map(|count| count > 0)
+  .map_err(|err| {
+    tracing::error!(
+      "Failed to update alias of user {} in group {}: {}",
+      user_id,
+      group_id,
+      err.to_string()
+    );
+    DBError::QueryError("Failed to update alias".into())
+  })
+}
+
+/// Archive or unarchive a group, an archived group is read-only
+pub fn update_archived(
+  conn: &mut PoolPGConnectionType,
+  group_id: i32,
+  archived: bool,
+) -> Result<Group, DBError> {
+  diesel::update(groups::table.find(group_id))
+    .set(groups::archived.eq(archived))
+    .returning(Group::as_returning())
+    .get_result::<Group>(conn)
+    .map_err(|err| {
+      tracing::error!(
+        "Failed to update archived of group {}: {}",
+        group_id,
+        err.to_string()
+      );
+      DBError::QueryError("Failed to update archived of group".into())
+    })
+}
+
+/// Check whether a group is archived, a missing group is not archived
+pub fn is_group_archived(conn: &mut PoolPGConnectionType, group_id: i32) -> Result<bool, DBError> {
+  groups::table
+    .find(group_id)
+    .select(groups::archived)
+    .first::<bool>(conn)
+    .optional()
+    .map(|archived| archived.unwrap_or(false))
+    .map_err(|err| {
+      tracing::error!(
+        "Failed to check archived of group {}: {}",
+        group_id,
+        err.to_string()
+      );
+      DBError::QueryError("Failed to check archived of group".into())
+    })
+}
+
+pub fn update_post_policy(
+  conn: &mut PoolPGConnectionType,
+  group_id: i32,
+  post_policy: PostPolicy,
+) -> Result<Group, DBError> {
+  diesel::update(groups::table.find(group_id))
+    .set(groups::post_policy.eq(post_policy))
+    .returning(Group::as_returning())
+    .get_result::<Group>(conn)
+    .map_err(|err| {
+      tracing::error!(
+        "Failed to update post policy of group {}: {}",
+        group_id,
+        err.to_string()
+      );
+      DBError::QueryError("Failed to update post policy of group".into())
+    })
+}
+
+/// Check whether the post policy of a group allows a member to send messages,
+/// anyone can post in a missing group
+pub fn can_post_in_group(
+  conn: &mut PoolPGConnectionType,
+  user_id: i32,
+  group_id: i32,
+) -> Result<bool, DBError> {
+  groups::table
+    .find(group_id)
+    .select((groups::post_policy, groups::user_id))
+    .first::<(PostPolicy, i32)>(conn)
+    .optional()
+    .map(|group| {
+      group.is_none_or(|(post_policy, owner_id)| post_policy.allows(owner_id == user_id))
+    })
+    .map_err(|err| {
+      tracing::error!(
+        "Failed to check post policy of group {}: {}",
+        group_id,
+        err.to_string()
+      );
+      DBError::QueryError("Failed to check post policy of group".into())
+    })
+}
+
+/// Set the maximum message length of a group, `None` falls back to the global limit
+pub fn update_max_message_length(
+  conn: &mut PoolPGConnectionType,
+  group_id: i32,
+  max_message_length: Option<i32>,
+) -> Result<Group, DBError> {
+  diesel::update(groups::table.find(group_id))
+    .set(groups::max_message_length.eq(max_message_length))
+    .returning(Group::as_returning())
+    .get_result::<Group>(conn)
+    .map_err(|err| {
+      tracing::error!(
+        "Failed to update max message length of group {}: {}",
+        group_id,
+        err.to_string()
+      );
+      DBError::QueryError("Failed to update max message length of group".into())
+    })
+}
+
+/// ### Get the maximum number of characters of a message content in a group
+///
+/// The group limit never exceeds the global `MAX_MESSAGE_CONTENT_LENGTH`, which is used when
+/// the group has no limit of its own
+pub fn get_max_message_length(
+  conn: &mut PoolPGConnectionType,
+  group_id: i32,
+) -> Result<usize, DBError> {
+  let max_message_length = groups::table
+    .find(group_id)
+    .select(groups::max_message_length)
+    .first::<Option<i32>>(conn)
+    .optional()
+    .map_err(|err| {
+      tracing::error!(
+        "Failed to get max message length of group {}: {}",
+        group_id,
+        err.to_string()
+      );
+      DBError::QueryError("Failed to get max message length of group".into())
+    })?
+    .flatten();
+  Ok(
+    max_message_length
+      .map(|length| (length.max(0) as usize).min(MAX_MESSAGE_CONTENT_LENGTH))
+      .unwrap_or(MAX_MESSAGE_CONTENT_LENGTH),
+  )
+}
+
+/// ### Get the remaining seconds before `user_id` can send a new message to the group
+///
+/// `None` is returned if the user can send a message now. The owner is not limited by slow mode
+pub fn get_slow_mode_remaining(
+  conn: &mut PoolPGConnectionType,
+  user_id: i32,
+  group_id: i32,
+) -> Result<Option<i64>, DBError> {
+  let Some(group) = get_group_info(conn, group_id)? else {
+    return Ok(None);
+  };
+  let slow_mode_seconds = match group.slow_mode_seconds {
+    Some(seconds) if seconds > 0 && group.user_id != user_id => seconds as i64,
+    _ => return Ok(None),
+  };
+  // recalled messages are counted too, otherwise recalling would bypass slow mode
+  let last_message_at = messages::table
+    .filter(messages::group_id.eq(group_id))
+    .filter(messages::user_id.eq(user_id))
+    .select(dsl::max(messages::created_at))
+    .first::<Option<NaiveDateTime>>(conn)
+    .map_err(|err| {
+      tracing::error!(
+        "Failed to get last message time of user {} in group {}: {}",
+        user_id,
+        group_id,
+        err.to_string()
+      );
+      DBError::QueryError("Failed to get last message time".into())
+    })?;
+  let Some(last_message_at) = last_message_at else {
+    return Ok(None);
+  };
+  let elapsed = Utc::now()
+    .naive_utc()
+    .signed_duration_since(last_message_at)
+    .num_seconds();
+  if elapsed < slow_mode_seconds {
+    Ok(Some(slow_mode_seconds - elapsed))
+  } else {
+    Ok(None)
+  }
+}
@@ -1,28 +1,38 @@
+use std::collections::HashMap;
+
 use chrono::{NaiveDateTime, NaiveTime, Utc};
 use diesel::{
-  pg::Pg, prelude::Queryable, BoolExpressionMethods, ExpressionMethods, JoinOnDsl,
-  NullableExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl, SelectableHelper,
-  TextExpressionMethods,
+  dsl::{self, sql},
+  pg::{Pg, PgRowByRowLoadingMode},
+  prelude::Queryable,
+  sql_types::{Bool, Integer},
+  upsert::excluded,
+  BoolExpressionMethods, Connection, ExpressionMethods, IntoSql, JoinOnDsl, NullableExpressionMethods,
+  OptionalExtension, QueryDsl, RunQueryDsl, SelectableHelper, TextExpressionMethods,
 };
 use uuid::Uuid;
 
 use crate::{
   database::{
-    models::{self, AttachmentTypeEnum, Message, MessageStatus, MessageTypeEnum, NewMessage},
+    models::{
+      self, Attachment, AttachmentTypeEnum, LastSeenMessage, Message, MessageStatus, MessageTypeEnum,
+      NewMessage,
+    },
     schema::{
-      self, attachments,
+      self, attachments, groups, last_seen_messages,
       messages::{self},
-      users,
+      participants, users,
     },
   },
   errors::DBError,
   payloads::{
     common::PageRequest,
     messages::{
-      AttachmentPayload, MessageFilterParams, MessageSortParams, MessageWithUser, UpdateMessage,
+      AttachmentPayload, FeedItem, MessageFilterParams, MessageSortParams, MessageWithUser, QuotedMessage,
+      UpdateMessage,
     },
   },
-  PoolPGConnectionType,
+  services, PoolPGConnectionType, MAX_ATTACHMENTS_PREVIEW, MAX_QUOTED_CONTENT_LENGTH,
 };
 
 pub fn create_new_message(
@@ -41,20 +51,62 @@ pub fn create_new_message(
   Ok(message)
 }
 
+/// ### Insert a message together with its attachments
+///
+/// Both are inserted in a single transaction, so a failure never leaves a message without its attachments
+pub fn create_message_with_attachments(
+  conn: &mut PoolPGConnectionType,
+  new_message: NewMessage,
+  attachments: &[AttachmentPayload],
+) -> Result<(Message, Vec<Attachment>), DBError> {
+  conn.transaction::<_, DBError, _>(|conn| {
+    let message = create_new_message(conn, new_message)?;
+    let inserted_attachments = if attachments.is_empty() {
+      Vec::new()
+    } else {
+      let new_attachments = attachments
+        .iter()
+        .map(|attachment| attachment.into_new(message.id))
+        .collect();
+      services::attachment::create_attachments(conn, new_attachments)?
+    };
+    Ok((message, inserted_attachments))
+  })
+}
+
 #[derive(Queryable, Debug, Clone)]
 pub struct MessageWithAttachmentRaw {
   pub message_uuid: Uuid,
   pub id: i32,
+  pub group_id: i32,
   pub content: Option<String>,
   pub message_type: MessageTypeEnum,
   pub status: MessageStatus,
   pub created_at: NaiveDateTime,
   pub updated_at: Option<NaiveDateTime>,
+  pub version: i32,
   pub user_id: i32,
   pub user_name: String,
+  pub reply_to: Option<i32>,
   pub attachment_id: Option<i32>,
   pub url: Option<String>,
   pub attachment_type: Option<AttachmentTypeEnum>,
+  pub size_bytes: Option<i64>,
+}
+
+#[derive(Queryable, Debug, Clone)]
+struct MessageWithUserRaw {
+  message_uuid: Uuid,
+  id: i32,
+  content: Option<String>,
+  message_type: MessageTypeEnum,
+  status: MessageStatus,
+  created_at: NaiveDateTime,
+  updated_at: Option<NaiveDateTime>,
+  version: i32,
+  user_id: i32,
+  user_name: String,
+  reply_to: Option<i32>,
 }
 
 pub fn get_messages(
@@ -62,11 +114,13 @@ pub fn get_messages(
   group_id: i32,
   page: &PageRequest,
   message_filters: &MessageFilterParams,
-  message_sorts: MessageSortParams,
+  message_sorts: &MessageSortParams,
 ) -> Result<Vec<MessageWithUser>, DBError> {
   let mut query = messages::table.into_boxed();
 
-  query = query.filter(messages::group_id.eq(group_id));
+  query = query
+    .filter(messages::group_id.eq(group_id))
+    .filter(messages::deleted_at.is_null());
 
   if let Some(content_type_val) = &message_filters.message_type {
     query = query.filter(messages::message_type.eq(content_type_val));
@@ -87,23 +141,33 @@ pub fn get_messages(
     let naive_datetime = NaiveDateTime::new(to, NaiveTime::from_hms_opt(23, 59, 59).unwrap());
     query = query.filter(messages::created_at.le(naive_datetime));
   }
+  if let Some(has_attachments) = message_filters.has_attachments {
+    let with_attachments =
+      dsl::exists(attachments::table.filter(attachments::message_id.eq(messages::id)));
+    if has_attachments {
+      query = query.filter(with_attachments);
+    } else {
+      query = query.filter(dsl::not(with_attachments));
+    }
+  }
 
   let (offset, limit) = page.get_offset_and_limit();
   query = query.limit(limit as i64).offset(offset as i64);
 
-  if let Some(created_at_sort) = message_sorts.created_at_sort {
-    match created_at_sort {
-      crate::payloads::common::OrderBy::ASC => query = query.order_by(messages::created_at.asc()),
-      crate::payloads::common::OrderBy::DESC => query = query.order_by(messages::created_at.desc()),
+  // messages created at the same time are ordered by id, so pages never overlap or skip messages
+  query = match &message_sorts.created_at_sort {
+    Some(crate::payloads::common::OrderBy::ASC) => {
+      query.order_by((messages::created_at.asc(), messages::id.asc()))
     }
-  }
+    Some(crate::payloads::common::OrderBy::DESC) => {
+      query.order_by((messages::created_at.desc(), messages::id.desc()))
+    }
+    None => query.order_by(messages::id.asc()),
+  };
   tracing::debug!("{}", diesel::debug_query::<Pg, _>(&query));
 
-  let raw_results: Vec<MessageWithAttachmentRaw> = query
+  let raw_results: Vec<MessageWithUserRaw> = query
     .inner_join(users::table.on(users::id.eq(messages::user_id)))
-    .left_join(
-      schema::attachments::table.on(schema::messages::id.eq(schema::attachments::message_id)),
-    )
     .select((
       messages::message_uuid,
       messages::id,
@@ -112,64 +176,496 @@ pub fn get_messages(
       messages::status,
       messages::created_at,
       messages::updated_at,
+      messages::version,
       messages::user_id,
       users::username,
-      attachments::id.nullable(),
-      attachments::url.nullable(),
-      attachments::attachment_type.nullable(),
+      messages::reply_to,
     ))
-    .load::<MessageWithAttachmentRaw>(conn)
+    .load::<MessageWithUserRaw>(conn)
     .map_err(|err| {
       tracing::error!(
         "Failed to load messages for group_id {}: {:?}",
         group_id,
         err
       );
-      DBError::QueryError(format!("Error loading messages: {:?}", err))
+      services::db::query_error(&err, format!("Error loading messages: {:?}", err))
     })?;
 
-  let rs = map_raw_messages_to_payload(raw_results);
-  Ok(rs)
+  map_raw_messages_to_payload(conn, raw_results)
 }
 
-fn map_raw_messages_to_payload(raw_results: Vec<MessageWithAttachmentRaw>) -> Vec<MessageWithUser> {
-  let mut grouped_messages: std::collections::HashMap<i32, MessageWithUser> =
-    std::collections::HashMap::new();
+/// Build message payloads with a preview of at most `MAX_ATTACHMENTS_PREVIEW` attachments
+///
+/// Attachments are loaded by a separate query, so paginating messages is not affected
+/// by the number of attachments of each message
+fn map_raw_messages_to_payload(
+  conn: &mut PoolPGConnectionType,
+  raw_results: Vec<MessageWithUserRaw>,
+) -> Result<Vec<MessageWithUser>, DBError> {
+  let message_ids = raw_results.iter().map(|row| row.id).collect::<Vec<i32>>();
+  // load one more attachment to know if a message has more attachments than the preview
+  let attachments = services::attachment::get_first_attachments_of_messages(
+    conn,
+    &message_ids,
+    MAX_ATTACHMENTS_PREVIEW + 1,
+  )?;
+  let mut grouped_attachments: HashMap<i32, Vec<AttachmentPayload>> = HashMap::new();
+  for attachment in attachments {
+    grouped_attachments
+      .entry(attachment.message_id)
+      .or_default()
+      .push(AttachmentPayload::from(attachment));
+  }
+  let reply_ids = raw_results
+    .iter()
+    .filter_map(|row| row.reply_to)
+    .collect::<Vec<i32>>();
+  let quoted_messages = get_quoted_messages(conn, &reply_ids)?;
+
+  Ok(
+    raw_results
+      .into_iter()
+      .map(|row| {
+        let mut attachments = grouped_attachments.remove(&row.id).unwrap_or_default();
+        let has_more_attachments = attachments.len() as i64 > MAX_ATTACHMENTS_PREVIEW;
+        attachments.truncate(MAX_ATTACHMENTS_PREVIEW as usize);
+        MessageWithUser {
+          message_uuid: row.message_uuid,
+          id: row.id,
+          content: row.content,
+          message_type: row.message_type,
+          attachments: Some(attachments),
+          has_more_attachments,
+          status: row.status,
+          created_at: row.created_at,
+          updated_at: row.updated_at,
+          version: row.version,
+          user_id: row.user_id,
+          user_name: row.user_name,
+          reply_to: row.reply_to,
+          quoted: row.reply_to.and_then(|id| quoted_messages.get(&id).cloned()),
+        }
+      })
+      .collect(),
+  )
+}
 
-  for ref row in raw_results {
-    let entry = grouped_messages.entry(row.id).or_insert_with(|| {
+/// ### Get previews of the given messages, keyed by message id
+///
+/// Deleted messages are left out, their content is truncated to `MAX_QUOTED_CONTENT_LENGTH` characters
+pub fn get_quoted_messages(
+  conn: &mut PoolPGConnectionType,
+  message_ids: &[i32],
+) -> Result<HashMap<i32, QuotedMessage>, DBError> {
+  if message_ids.is_empty() {
+    return Ok(HashMap::new());
+  }
+  let rows = messages::table
+    .inner_join(users::table.on(users::id.eq(messages::user_id)))
+    .filter(messages::id.eq_any(message_ids))
+    .filter(messages::deleted_at.is_null())
+    .select((
+      messages::id,
+      messages::user_id,
+      users::username,
+      messages::content.nullable(),
+      messages::message_type,
+    ))
+    .load::<(i32, i32, String, Option<String>, MessageTypeEnum)>(conn)
+    .map_err(|err| {
+      tracing::error!("Failed to get quoted messages: {}", err.to_string());
+      DBError::QueryError("Failed to get quoted messages".into())
+    })?;
+
+  Ok(
+    rows
+      .into_iter()
+      .map(|(message_id, user_id, user_name, content, message_type)| {
+        let content = content.map(|content| {
+          if content.chars().count() > MAX_QUOTED_CONTENT_LENGTH {
+            let mut truncated = content.chars().take(MAX_QUOTED_CONTENT_LENGTH).collect::<String>();
+            truncated.push('…');
+            truncated
+          } else {
+            content
+          }
+        });
+        (
+          message_id,
+          QuotedMessage {
+            message_id,
+            user_id,
+            user_name,
+            content,
+            message_type,
+          },
+        )
+      })
+      .collect(),
+  )
+}
+
+/// ### Get a page of the latest messages across all groups joined by an user, newest first
+pub fn get_feed_of_user(
+  conn: &mut PoolPGConnectionType,
+  user_id: i32,
+  page: &PageRequest,
+) -> Result<Vec<FeedItem>, DBError> {
+  let group_ids = participants::table
+    .filter(participants::user_id.eq(user_id))
+    .select(participants::group_id);
+  let (offset, limit) = page.get_offset_and_limit();
+  messages::table
+    .inner_join(groups::table.on(groups::id.eq(messages::group_id)))
+    .inner_join(users::table.on(users::id.eq(messages::user_id)))
+    .filter(messages::group_id.eq_any(group_ids))
+    .filter(messages::deleted_at.is_null())
+    .order((messages::created_at.desc(), messages::id.desc()))
+    .limit(limit)
+    .offset(offset as i64)
+    .select((
+      groups::id,
+      groups::name,
+      messages::message_uuid,
+      messages::id,
+      messages::content,
+      messages::message_type,
+      messages::status,
+      messages::created_at,
+      messages::updated_at,
+      users::id,
+      users::username,
+    ))
+    .load::<FeedItem>(conn)
+    .map_err(|err| {
+      tracing::error!("Failed to get feed of user {}: {}", user_id, err.to_string());
+      DBError::QueryError("Failed to get feed".into())
+    })
+}
+
+/// ### Count messages across all groups joined by an user
+pub fn get_count_feed_of_user(
+  conn: &mut PoolPGConnectionType,
+  user_id: i32,
+) -> Result<i64, DBError> {
+  let group_ids = participants::table
+    .filter(participants::user_id.eq(user_id))
+    .select(participants::group_id);
+  messages::table
+    .filter(messages::group_id.eq_any(group_ids))
+    .filter(messages::deleted_at.is_null())
+    .count()
+    .get_result::<i64>(conn)
+    .map_err(|err| {
+      tracing::error!("Failed to count feed of user {}: {}", user_id, err.to_string());
+      DBError::QueryError("Failed to count feed".into())
+    })
+}
+
+/// ### Check a message exists in a group and is not deleted
+pub fn check_message_in_group(
+  conn: &mut PoolPGConnectionType,
+  message_id: i32,
+  group_id: i32,
+) -> Result<bool, DBError> {
+  dsl::select(dsl::exists(
+    messages::table
+      .filter(messages::id.eq(message_id))
+      .filter(messages::group_id.eq(group_id))
+      .filter(messages::deleted_at.is_null()),
+  ))
+  .get_result::<bool>(conn)
+  .map_err(|err| {
+    tracing::error!(
+      "Failed to check message {} in group {}: {}",
+      message_id,
+      group_id,
+      err.to_string()
+    );
+    DBError::QueryError("Failed to check message in group".into())
+  })
+}
+
+/// ### Check a message with the uuid already exists, including deleted messages
+pub fn check_message_uuid_exists(
+  conn: &mut PoolPGConnectionType,
+  message_uuid: Uuid,
+) -> Result<bool, DBError> {
+  dsl::select(dsl::exists(
+    messages::table.filter(messages::message_uuid.eq(message_uuid)),
+  ))
+  .get_result::<bool>(conn)
+  .map_err(|err| {
+    tracing::error!(
+      "Failed to check message uuid {}: {}",
+      message_uuid,
+      err.to_string()
+    );
+    DBError::QueryError("Failed to check message uuid".into())
+  })
+}
+
+/// ### Get at most `limit` messages of a group older than `before_id`, newest first
+///
+/// The latest messages are returned if `before_id` is `None`
+pub fn get_messages_before(
+  conn: &mut PoolPGConnectionType,
+  group_id: i32,
+  before_id: Option<i32>,
+  limit: i64,
+) -> Result<Vec<(Message, String)>, DBError> {
+  let mut query = messages::table
+    .inner_join(users::table.on(users::id.eq(messages::user_id)))
+    .filter(messages::group_id.eq(group_id))
+    .filter(messages::deleted_at.is_null())
+    .into_boxed();
+  if let Some(before_id) = before_id {
+    query = query.filter(messages::id.lt(before_id));
+  }
+  query
+    .order(messages::id.desc())
+    .limit(limit)
+    .select((Message::as_select(), users::username))
+    .load::<(Message, String)>(conn)
+    .map_err(|err| {
+      tracing::error!(
+        "Failed to load messages before {:?} for group_id {}: {:?}",
+        before_id,
+        group_id,
+        err
+      );
+      DBError::QueryError(format!("Error loading messages: {:?}", err))
+    })
+}
+
+/// Get at most `limit` messages of a group whose id is in `from_id..=to_id`, oldest first
+pub fn get_messages_in_range(
+  conn: &mut PoolPGConnectionType,
+  group_id: i32,
+  from_id: i32,
+  to_id: i32,
+  limit: i64,
+) -> Result<Vec<(Message, String)>, DBError> {
+  messages::table
+    .inner_join(users::table.on(users::id.eq(messages::user_id)))
+    .filter(messages::group_id.eq(group_id))
+    .filter(messages::deleted_at.is_null())
+    .filter(messages::id.between(from_id, to_id))
+    .order(messages::id.asc())
+    .limit(limit)
+    .select((Message::as_select(), users::username))
+    .load::<(Message, String)>(conn)
+    .map_err(|err| {
+      tracing::error!(
+        "Failed to load messages from {} to {} for group_id {}: {:?}",
+        from_id,
+        to_id,
+        group_id,
+        err
+      );
+      DBError::QueryError(format!("Error loading messages: {:?}", err))
+    })
+}
+
+/// ### Get a message with at most `radius` messages sent before and after it, oldest first
+///
+/// Messages of a group are sent in the order of their ids, deleted messages are left out
+pub fn get_messages_around(
+  conn: &mut PoolPGConnectionType,
+  group_id: i32,
+  message_id: i32,
+  radius: i64,
+) -> Result<Vec<MessageWithUser>, DBError> {
+  let load_error = |err: diesel::result::Error| {
+    tracing::error!(
+      "Failed to load messages around {} for group_id {}: {:?}",
+      message_id,
+      group_id,
+      err
+    );
+    services::db::query_error(&err, format!("Error loading messages: {:?}", err))
+  };
+  let mut message_ids = messages::table
+    .filter(messages::group_id.eq(group_id))
+    .filter(messages::deleted_at.is_null())
+    .filter(messages::id.lt(message_id))
+    .order(messages::id.desc())
+    .limit(radius)
+    .select(messages::id)
+    .load::<i32>(conn)
+    .map_err(load_error)?;
+  message_ids.push(message_id);
+  message_ids.extend(
+    messages::table
+      .filter(messages::group_id.eq(group_id))
+      .filter(messages::deleted_at.is_null())
+      .filter(messages::id.gt(message_id))
+      .order(messages::id.asc())
+      .limit(radius)
+      .select(messages::id)
+      .load::<i32>(conn)
+      .map_err(load_error)?,
+  );
+
+  let raw_results: Vec<MessageWithUserRaw> = messages::table
+    .inner_join(users::table.on(users::id.eq(messages::user_id)))
+    .filter(messages::group_id.eq(group_id))
+    .filter(messages::deleted_at.is_null())
+    .filter(messages::id.eq_any(&message_ids))
+    .order(messages::id.asc())
+    .select((
+      messages::message_uuid,
+      messages::id,
+      messages::content.nullable(),
+      messages::message_type,
+      messages::status,
+      messages::created_at,
+      messages::updated_at,
+      messages::version,
+      messages::user_id,
+      users::username,
+      messages::reply_to,
+    ))
+    .load::<MessageWithUserRaw>(conn)
+    .map_err(load_error)?;
+
+  map_raw_messages_to_payload(conn, raw_results)
+}
+
+/// Messages streamed by `stream_message_rows`
+enum MessageStreamScope {
+  /// All messages of a group
+  Group(i32),
+  /// All messages sent by an user, across groups
+  Author(i32),
+}
+
+/// ### Stream all messages of a group ordered by creation time
+///
+/// Rows are loaded one by one from the database and each message is handed to
+/// `on_message` as soon as all of its attachments are read, so the whole group history
+/// is never kept in memory. Streaming stops early when `on_message` returns `false`.
+pub fn stream_messages<F>(
+  conn: &mut PoolPGConnectionType,
+  group_id: i32,
+  mut on_message: F,
+) -> Result<(), DBError>
+where
+  F: FnMut(MessageWithUser) -> bool,
+{
+  stream_message_rows(conn, MessageStreamScope::Group(group_id), |_, message| {
+    on_message(message)
+  })
+}
+
+/// ### Stream all messages sent by an user ordered by creation time
+///
+/// Like `stream_messages`, `on_message` is given each message with the id of its group
+pub fn stream_messages_of_user<F>(
+  conn: &mut PoolPGConnectionType,
+  user_id: i32,
+  on_message: F,
+) -> Result<(), DBError>
+where
+  F: FnMut(i32, MessageWithUser) -> bool,
+{
+  stream_message_rows(conn, MessageStreamScope::Author(user_id), on_message)
+}
+
+fn stream_message_rows<F>(
+  conn: &mut PoolPGConnectionType,
+  scope: MessageStreamScope,
+  mut on_message: F,
+) -> Result<(), DBError>
+where
+  F: FnMut(i32, MessageWithUser) -> bool,
+{
+  let mut query = messages::table
+    .inner_join(users::table.on(users::id.eq(messages::user_id)))
+    .left_join(
+      schema::attachments::table.on(schema::messages::id.eq(schema::attachments::message_id)),
+    )
+    .filter(messages::deleted_at.is_null())
+    .into_boxed();
+  query = match scope {
+    MessageStreamScope::Group(group_id) => query.filter(messages::group_id.eq(group_id)),
+    MessageStreamScope::Author(user_id) => query.filter(messages::user_id.eq(user_id)),
+  };
+  let scope_name = match scope {
+    MessageStreamScope::Group(group_id) => format!("group_id {}", group_id),
+    MessageStreamScope::Author(user_id) => format!("user_id {}", user_id),
+  };
+  let rows = query
+    .order((messages::created_at.asc(), messages::id.asc()))
+    .select((
+      messages::message_uuid,
+      messages::id,
+      messages::group_id,
+      messages::content.nullable(),
+      messages::message_type,
+      messages::status,
+      messages::created_at,
+      messages::updated_at,
+      messages::version,
+      messages::user_id,
+      users::username,
+      messages::reply_to,
+      attachments::id.nullable(),
+      attachments::url.nullable(),
+      attachments::attachment_type.nullable(),
+      attachments::size_bytes.nullable(),
+    ))
+    .load_iter::<MessageWithAttachmentRaw, PgRowByRowLoadingMode>(conn)
+    .map_err(|err| {
+      tracing::error!("Failed to stream messages for {}: {:?}", scope_name, err);
+      DBError::QueryError(format!("Error streaming messages: {:?}", err))
+    })?;
+
+  // rows of the same message are adjacent thanks to the ordering
+  let mut current: Option<(i32, MessageWithUser)> = None;
+  for row in rows {
+    let row = row.map_err(|err| {
+      tracing::error!("Failed to read streamed message for {}: {:?}", scope_name, err);
+      DBError::QueryError(format!("Error streaming messages: {:?}", err))
+    })?;
+    if current.as_ref().is_some_and(|(_, message)| message.id != row.id) {
+      let (group_id, message) = current.take().unwrap();
+      if !on_message(group_id, message) {
+        return Ok(());
+      }
+    }
+    let (_, message) = current.get_or_insert_with(|| {
       let mut message = MessageWithUser::from(row.clone());
       message.attachments = Some(Vec::new());
-      message
+      (row.group_id, message)
     });
-
-    // If the row has an attachment, add it to the message's attachments
     if let Some(attachment_id) = row.attachment_id {
-      entry.attachments.as_mut().unwrap().push(AttachmentPayload {
+      message.attachments.as_mut().unwrap().push(AttachmentPayload {
         id: attachment_id,
-        url: row.url.clone().unwrap_or_default(),
-        attachment_type: row.attachment_type.clone().unwrap_or_default(),
+        url: row.url.as_deref().map(services::storage::to_public_url).unwrap_or_default(),
+        attachment_type: row.attachment_type.unwrap_or_default(),
+        size_bytes: row.size_bytes,
       });
     }
   }
-
-  let rs: Vec<MessageWithUser> = grouped_messages
-    .values()
-    .map(|value| value.clone())
-    .collect();
-  rs
+  if let Some((group_id, message)) = current {
+    on_message(group_id, message);
+  }
+  Ok(())
 }
 
 pub fn get_count_messages(
   conn: &mut PoolPGConnectionType,
   group_id: i32,
-  message_filters: MessageFilterParams,
+  message_filters: &MessageFilterParams,
 ) -> Result<i64, DBError> {
   let mut query = messages::table
     .inner_join(users::table.on(users::id.eq(messages::user_id)))
     .into_boxed();
 
-  query = query.filter(messages::group_id.eq(group_id));
+  query = query
+    .filter(messages::group_id.eq(group_id))
+    .filter(messages::deleted_at.is_null());
   // Filter by content type if provided
   if let Some(content_type_val) = &message_filters.message_type {
     query = query.filter(messages::message_type.eq(content_type_val));
@@ -190,6 +686,17 @@ pub fn get_count_messages(
     query = query.filter(messages::created_at.le(naive_datetime));
   }
 
+  // Filter by having attachments if provided
+  if let Some(has_attachments) = message_filters.has_attachments {
+    let with_attachments =
+      dsl::exists(attachments::table.filter(attachments::message_id.eq(messages::id)));
+    if has_attachments {
+      query = query.filter(with_attachments);
+    } else {
+      query = query.filter(dsl::not(with_attachments));
+    }
+  }
+
   tracing::debug!("{}", diesel::debug_query::<Pg, _>(&query));
 
   let messages_count = query.count().get_result::<i64>(conn).map_err(|err| {
@@ -198,12 +705,97 @@ pub fn get_count_messages(
       group_id,
       err
     );
-    DBError::QueryError(format!("Error get messages count: {:?}", err))
+    services::db::query_error(&err, format!("Error get messages count: {:?}", err))
   })?;
 
   Ok(messages_count)
 }
 
+/// Count messages of a group and get the creation time of the earliest and latest ones
+/// with a single aggregate query, times are `None` for a group without messages
+pub fn get_message_stats(
+  conn: &mut PoolPGConnectionType,
+  group_id: i32,
+) -> Result<(i64, Option<NaiveDateTime>, Option<NaiveDateTime>), DBError> {
+  messages::table
+    .filter(messages::group_id.eq(group_id))
+    .filter(messages::deleted_at.is_null())
+    .select((
+      dsl::count_star(),
+      dsl::min(messages::created_at),
+      dsl::max(messages::created_at),
+    ))
+    .get_result::<(i64, Option<NaiveDateTime>, Option<NaiveDateTime>)>(conn)
+    .map_err(|err| {
+      tracing::error!("Failed to get message stats of group {}: {}", group_id, err.to_string());
+      services::db::query_error(&err, "Failed to get message stats of group".into())
+    })
+}
+
+/// Get ids and edit times of the first `limit` messages of a group after `since_id`,
+/// ordered by id. Deleted messages are excluded
+pub fn get_message_ids_since(
+  conn: &mut PoolPGConnectionType,
+  group_id: i32,
+  since_id: Option<i32>,
+  limit: i64,
+) -> Result<Vec<(i32, Option<NaiveDateTime>)>, DBError> {
+  messages::table
+    .filter(messages::group_id.eq(group_id))
+    .filter(messages::deleted_at.is_null())
+    .filter(messages::id.gt(since_id.unwrap_or(0)))
+    .order(messages::id.asc())
+    .limit(limit)
+    .select((messages::id, messages::updated_at))
+    .get_results::<(i32, Option<NaiveDateTime>)>(conn)
+    .map_err(|err| {
+      tracing::error!("Failed to get message ids of group {}: {}", group_id, err);
+      services::db::query_error(&err, "Failed to get message ids of group".into())
+    })
+}
+
+/// ### Get a message by the uuid assigned by its sender, with the id of its group
+///
+/// `None` if no message has the uuid or the message was deleted
+pub fn get_message_by_uuid(
+  conn: &mut PoolPGConnectionType,
+  message_uuid: Uuid,
+) -> Result<Option<(i32, MessageWithUser)>, DBError> {
+  let row = messages::table
+    .inner_join(users::table.on(users::id.eq(messages::user_id)))
+    .filter(messages::message_uuid.eq(message_uuid))
+    .filter(messages::deleted_at.is_null())
+    .select((
+      messages::group_id,
+      (
+        messages::message_uuid,
+        messages::id,
+        messages::content.nullable(),
+        messages::message_type,
+        messages::status,
+        messages::created_at,
+        messages::updated_at,
+        messages::version,
+        messages::user_id,
+        users::username,
+        messages::reply_to,
+      ),
+    ))
+    .first::<(i32, MessageWithUserRaw)>(conn)
+    .optional()
+    .map_err(|err| {
+      tracing::error!("Failed to get message by uuid {}: {}", message_uuid, err);
+      services::db::query_error(&err, "Failed to get message by uuid".into())
+    })?;
+  let Some((group_id, raw_message)) = row else {
+    return Ok(None);
+  };
+  let message = map_raw_messages_to_payload(conn, vec![raw_message])?
+    .pop()
+    .map(|message| (group_id, message));
+  Ok(message)
+}
+
 pub fn get_latest_messages_from_group(
   conn: &mut PoolPGConnectionType,
   group_id: i32,
@@ -211,11 +803,9 @@ pub fn get_latest_messages_from_group(
   // Fetch messages (limit to latest messages if needed)
   let raw_results = messages::table
     .filter(messages::group_id.eq(group_id))
+    .filter(messages::deleted_at.is_null())
     .inner_join(users::table.on(users::id.eq(messages::user_id)))
-    .left_join(
-      schema::attachments::table.on(schema::messages::id.eq(schema::attachments::message_id)),
-    )
-    .order(messages::created_at.asc())
+    .order((messages::created_at.asc(), messages::id.asc()))
     .limit(10)
     .select((
       messages::message_uuid,
@@ -225,13 +815,12 @@ pub fn get_latest_messages_from_group(
       messages::status,
       messages::created_at,
       messages::updated_at,
+      messages::version,
       messages::user_id,
       users::username,
-      attachments::id.nullable(),
-      attachments::url.nullable(),
-      attachments::attachment_type.nullable(),
+      messages::reply_to,
     ))
-    .load::<MessageWithAttachmentRaw>(conn)
+    .load::<MessageWithUserRaw>(conn)
     .map_err(|err| {
       tracing::error!(
         "Failed to load messages for group_id {}: {:?}",
@@ -241,8 +830,7 @@ pub fn get_latest_messages_from_group(
       DBError::QueryError(format!("Error loading messages: {:?}", err))
     })?;
 
-  let rs = map_raw_messages_to_payload(raw_results);
-  Ok(rs)
+  map_raw_messages_to_payload(conn, raw_results)
 }
 
 pub fn delete_message(conn: &mut PoolPGConnectionType, message_id: i32) -> Result<bool, DBError> {
@@ -273,6 +861,7 @@ pub fn get_message(
   Ok(
     messages::table
       .find(message_id)
+      .filter(messages::deleted_at.is_null())
       .select(Message::as_select())
       .get_result::<Message>(conn)
       .optional()
@@ -304,37 +893,106 @@ pub fn get_messages_from_ids(
   )
 }
 
+/// Check whether a message can be changed to `message_type`,
+/// an `ATTACHMENT` message must have attachments
+pub fn is_valid_type_transition(
+  conn: &mut PoolPGConnectionType,
+  message: &Message,
+  message_type: &MessageTypeEnum,
+) -> Result<bool, DBError> {
+  if *message_type == message.message_type {
+    return Ok(true);
+  }
+  match message_type {
+    MessageTypeEnum::TEXT => Ok(true),
+    MessageTypeEnum::ATTACHMENT => {
+      Ok(services::attachment::get_count_attachments_of_message(conn, message.id)? > 0)
+    }
+  }
+}
+
+/// ### Update a message and increment its version
+///
+/// `message` is the current message, fields of `update_data` equal to its values are ignored.
+/// The message is left unchanged, including `updated_at` and the version, if nothing changes.
+///
+/// When `update_data.version` is given, the message is only updated if its version still
+/// matches, so concurrent edits can't silently overwrite each other. Only the author
+/// `editor_id` can update the message, checked in the same statement as the version.
+/// Return `None` if the message is deleted, its version doesn't match or `editor_id` is not its author
 pub fn update_message(
   conn: &mut PoolPGConnectionType,
-  message_id: i32,
+  editor_id: i32,
+  message: Message,
   update_data: UpdateMessage,
-) -> Result<Message, DBError> {
+) -> Result<Option<Message>, DBError> {
   use crate::database::schema::messages;
-  let mut updated_at_datetime = None;
-  if update_data.content.is_some() || update_data.message_type.is_some() {
-    updated_at_datetime = Some(Utc::now().naive_utc());
+  if message.user_id != editor_id {
+    return Ok(None);
   }
-  let message = diesel::update(messages::table.find(message_id))
+  let message_id = message.id;
+  let expected_version = update_data.version;
+  let content = update_data
+    .content
+    .filter(|content| message.content.as_ref() != Some(content));
+  let message_type = update_data
+    .message_type
+    .filter(|message_type| *message_type != message.message_type);
+  if content.is_none() && message_type.is_none() {
+    return Ok(
+      expected_version
+        .is_none_or(|version| version == message.version)
+        .then_some(message),
+    );
+  }
+  diesel::update(messages::table.find(message_id))
+    .filter(messages::deleted_at.is_null())
+    .filter(messages::user_id.eq(editor_id))
+    .filter(
+      messages::version
+        .nullable()
+        .eq(expected_version)
+        .or(expected_version.is_none().into_sql::<Bool>()),
+    )
     .set((
-      update_data
-        .content
-        .map(|content| messages::content.eq(content)),
-      update_data
-        .message_type
-        .map(|mt| messages::message_type.eq(mt)),
-      updated_at_datetime.map(|datetime| messages::updated_at.eq(datetime)),
+      content.map(|content| messages::content.eq(content)),
+      message_type.map(|mt| messages::message_type.eq(mt)),
+      messages::updated_at.eq(Utc::now().naive_utc()),
+      messages::version.eq(messages::version + 1),
     ))
     .returning(Message::as_returning())
     .get_result::<Message>(conn)
+    .optional()
     .map_err(|err| {
       tracing::error!(
         "Failed to update message {}: {}",
         message_id,
         err.to_string()
       );
-      return DBError::QueryError("Failed to delete message".into());
-    })?;
-  Ok(message)
+      DBError::QueryError("Failed to update message".into())
+    })
+}
+
+/// ### Soft-delete a message recalled by its sender
+///
+/// The content is cleared and the message is hidden from message lists
+pub fn recall_message(conn: &mut PoolPGConnectionType, message_id: i32) -> Result<Message, DBError> {
+  diesel::update(messages::table.find(message_id))
+    .filter(messages::deleted_at.is_null())
+    .set((
+      messages::content.eq(None::<String>),
+      messages::deleted_at.eq(Some(Utc::now().naive_utc())),
+    ))
+    .returning(Message::as_returning())
+    .get_result::<Message>(conn)
+    .map_err(|err| {
+      tracing::error!(
+        "Failed to recall message {}: {}",
+        message_id,
+        err.to_string()
+      );
+      DBError::QueryError("Failed to recall message".into())
+    })
 }
 
 pub fn delete_messages(
@@ -393,3 +1051,165 @@ pub fn change_messages_status(
     })?;
   Ok(())
 }
+
+pub fn get_latest_message_id(
+  conn: &mut PoolPGConnectionType,
+  group_id: i32,
+) -> Result<Option<i32>, DBError> {
+  messages::table
+    .filter(messages::group_id.eq(group_id))
+    .filter(messages::deleted_at.is_null())
+    .select(dsl::max(messages::id))
+    .get_result::<Option<i32>>(conn)
+    .map_err(|err| {
+      tracing::error!(
+        "Failed to get latest message id of group {}: {}",
+        group_id,
+        err.to_string()
+      );
+      DBError::QueryError("Failed to get latest message id".into())
+    })
+}
+
+/// ### Advance the last seen message pointer of an user in a group
+///
+/// The pointer only moves forward, an older `message_id` leaves the stored one untouched
+pub fn update_last_seen_message(
+  conn: &mut PoolPGConnectionType,
+  user_id: i32,
+  group_id: i32,
+  message_id: i32,
+) -> Result<LastSeenMessage, DBError> {
+  let last_seen = LastSeenMessage {
+    user_id,
+    group_id,
+    message_id,
+    updated_at: Utc::now().naive_utc(),
+  };
+  diesel::insert_into(last_seen_messages::table)
+    .values(&last_seen)
+    .on_conflict((last_seen_messages::user_id, last_seen_messages::group_id))
+    .do_update()
+    .set((
+      last_seen_messages::message_id.eq(sql::<Integer>(
+        "GREATEST(last_seen_messages.message_id, excluded.message_id)",
+      )),
+      last_seen_messages::updated_at.eq(excluded(last_seen_messages::updated_at)),
+    ))
+    .returning(LastSeenMessage::as_returning())
+    .get_result::<LastSeenMessage>(conn)
+    .map_err(|err| {
+      tracing::error!(
+        "Failed to update last seen message of user {} in group {}: {}",
+        user_id,
+        group_id,
+        err.to_string()
+      );
+      DBError::QueryError("Failed to update last seen message".into())
+    })
+}
+
+/// ### Count messages of other members in a group after the last seen message of an user
+///
+/// Every message of other members is unseen if the user hasn't seen any message of the group
+pub fn count_unseen_messages(
+  conn: &mut PoolPGConnectionType,
+  user_id: i32,
+  group_id: i32,
+) -> Result<i64, DBError> {
+  let last_seen_message_id = get_last_seen_message_id(conn, user_id, group_id)?;
+
+  messages::table
+    .filter(messages::group_id.eq(group_id))
+    .filter(messages::deleted_at.is_null())
+    .filter(messages::user_id.ne(user_id))
+    .filter(messages::id.gt(last_seen_message_id))
+    .count()
+    .get_result::<i64>(conn)
+    .map_err(|err| {
+      tracing::error!(
+        "Failed to count unseen messages of user {} in group {}: {}",
+        user_id,
+        group_id,
+        err.to_string()
+      );
+      DBError::QueryError("Failed to count unseen messages".into())
+    })
+}
+
+/// ### Get ids of messages of other members in a group after the last seen message of an user
+///
+/// At most `limit` ids are returned in ascending order, so the oldest unseen messages come first
+pub fn get_unseen_message_ids(
+  conn: &mut PoolPGConnectionType,
+  user_id: i32,
+  group_id: i32,
+  limit: i64,
+) -> Result<Vec<i32>, DBError> {
+  let last_seen_message_id = get_last_seen_message_id(conn, user_id, group_id)?;
+
+  messages::table
+    .filter(messages::group_id.eq(group_id))
+    .filter(messages::deleted_at.is_null())
+    .filter(messages::user_id.ne(user_id))
+    .filter(messages::id.gt(last_seen_message_id))
+    .order(messages::id.asc())
+    .limit(limit)
+    .select(messages::id)
+    .load::<i32>(conn)
+    .map_err(|err| {
+      tracing::error!(
+        "Failed to get unseen messages of user {} in group {}: {}",
+        user_id,
+        group_id,
+        err.to_string()
+      );
+      DBError::QueryError("Failed to get unseen messages".into())
+    })
+}
+
+/// Get the id of the last seen message of an user in a group, 0 if the user hasn't seen any message
+fn get_last_seen_message_id(
+  conn: &mut PoolPGConnectionType,
+  user_id: i32,
+  group_id: i32,
+) -> Result<i32, DBError> {
+  last_seen_messages::table
+    .find((user_id, group_id))
+    .select(last_seen_messages::message_id)
+    .get_result::<i32>(conn)
+    .optional()
+    .map(Option::unwrap_or_default)
+    .map_err(|err| {
+      tracing::error!(
+        "Failed to get last seen message of user {} in group {}: {}",
+        user_id,
+        group_id,
+        err.to_string()
+      );
+      DBError::QueryError("Failed to get last seen message".into())
+    })
+}
+
+/// Get status of the given messages which were sent by `user_id`, other ids are ignored
+pub fn get_status_of_sent_messages(
+  conn: &mut PoolPGConnectionType,
+  user_id: i32,
+  message_ids: &[i32],
+) -> Result<Vec<(i32, MessageStatus)>, DBError> {
+  messages::table
+    .filter(messages::id.eq_any(message_ids))
+    .filter(messages::user_id.eq(user_id))
+    .filter(messages::deleted_at.is_null())
+    .order(messages::id.asc())
+    .select((messages::id, messages::status))
+    .load::<(i32, MessageStatus)>(conn)
+    .map_err(|err| {
+      tracing::error!(
+        "Failed to get status of messages of user {}: {}",
+        user_id,
+        err.to_string()
+      );
+      DBError::QueryError("Failed to get status of messages".into())
+    })
+}
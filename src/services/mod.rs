@@ -1,4 +1,13 @@
 pub(crate) mod attachment;
+pub(crate) mod auth;
+pub(crate) mod db;
+pub(crate) mod draft;
 pub(crate) mod group;
 pub(crate) mod message;
+pub(crate) mod moderation;
+pub(crate) mod pin;
+pub(crate) mod report;
+pub(crate) mod storage;
+pub(crate) mod translation;
+pub(crate) mod upload;
 pub(crate) mod user;
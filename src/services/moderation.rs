@@ -0,0 +1,180 @@
+use std::{collections::HashSet, fs, io, path::Path};
+
+use diesel::{ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl, SelectableHelper};
+
+use crate::{
+  database::{
+    models::{Group, Moderation},
+    schema::groups,
+  },
+  errors::DBError,
+  PoolPGConnectionType,
+};
+
+/// ### Bad-word filter of message contents
+///
+/// The word list is loaded from the file set to `BAD_WORDS_FILE` in `main`, one word per line.
+/// Blank lines and lines starting with `#` are ignored. Words are matched case-insensitively
+/// against whole words of a content, so a flagged word inside a longer word is not matched
+pub struct WordFilter {
+  words: HashSet<String>,
+}
+
+impl WordFilter {
+  pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+    let words = fs::read_to_string(path)?
+      .lines()
+      .map(str::trim)
+      .filter(|line| !line.is_empty() && !line.starts_with('#'))
+      .map(str::to_lowercase)
+      .collect();
+    Ok(Self { words })
+  }
+
+  /// Replace every character of flagged words by `*`, `None` if the content has no flagged word
+  pub fn mask(&self, content: &str) -> Option<String> {
+    let mut masked = String::with_capacity(content.len());
+    let mut flagged = false;
+    let mut word_start = None;
+    for (index, char) in content.char_indices().chain(std::iter::once((content.len(), ' '))) {
+      if char.is_alphanumeric() {
+        word_start.get_or_insert(index);
+        continue;
+      }
+      if let Some(start) = word_start.take() {
+        let word = &content[start..index];
+        if self.words.contains(&word.to_lowercase()) {
+          flagged = true;
+          masked.extend(std::iter::repeat_n('*', word.chars().count()));
+        } else {
+          masked.push_str(word);
+        }
+      }
+      if index < content.len() {
+        masked.push(char);
+      }
+    }
+    flagged.then_some(masked)
+  }
+
+  /// Apply `moderation` to a content
+  pub fn moderate(&self, content: &str, moderation: Moderation) -> ModerationOutcome {
+    if moderation == Moderation::Off {
+      return ModerationOutcome::Allowed;
+    }
+    match (self.mask(content), moderation) {
+      (None, _) => ModerationOutcome::Allowed,
+      (Some(_), Moderation::Reject) => ModerationOutcome::Rejected,
+      (Some(masked), _) => ModerationOutcome::Masked(masked),
+    }
+  }
+}
+
+/// Outcome of the moderation of a message content
+#[derive(Debug, PartialEq)]
+pub enum ModerationOutcome {
+  /// The content is saved as sent
+  Allowed,
+  /// The content is saved with flagged words masked
+  Masked(String),
+  /// The content has flagged words and must not be saved
+  Rejected,
+}
+
+/// Apply the moderation of a group to a message content.
+/// Every content is allowed if no word filter is configured
+pub fn moderate_content(
+  conn: &mut PoolPGConnectionType,
+  word_filter: Option<&WordFilter>,
+  group_id: i32,
+  content: Option<&str>,
+) -> Result<ModerationOutcome, DBError> {
+  let (Some(word_filter), Some(content)) = (word_filter, content) else {
+    return Ok(ModerationOutcome::Allowed);
+  };
+  let moderation = get_moderation(conn, group_id)?;
+  Ok(word_filter.moderate(content, moderation))
+}
+
+/// Get the moderation of a group, moderation is off for a missing group
+pub fn get_moderation(conn: &mut PoolPGConnectionType, group_id: i32) -> Result<Moderation, DBError> {
+  groups::table
+    .find(group_id)
+    .select(groups::moderation)
+    .first::<Moderation>(conn)
+    .optional()
+    .map(Option::unwrap_or_default)
+    .map_err(|err| {
+      tracing::error!("Failed to get moderation of group {}: {}", group_id, err);
+      DBError::QueryError("Failed to get moderation of group".into())
+    })
+}
+
+pub fn update_moderation(
+  conn: &mut PoolPGConnectionType,
+  group_id: i32,
+  moderation: Moderation,
+) -> Result<Group, DBError> {
+  diesel::update(groups::table.find(group_id))
+    .set(groups::moderation.eq(moderation))
+    .returning(Group::as_returning())
+    .get_result::<Group>(conn)
+    .map_err(|err| {
+      tracing::error!("Failed to update moderation of group {}: {}", group_id, err);
+      DBError::QueryError("Failed to update moderation of group".into())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn word_filter(words: &[&str]) -> WordFilter {
+    WordFilter {
+      words: words.iter().map(|word| word.to_lowercase()).collect(),
+    }
+  }
+
+  #[test]
+  fn mask_ignores_case() {
+    let filter = word_filter(&["bad"]);
+    assert_eq!(filter.mask("BAD and Bad"), Some("*** and ***".into()));
+    assert_eq!(word_filter(&["BAD"]).mask("bad"), Some("***".into()));
+  }
+
+  #[test]
+  fn mask_matches_whole_words_only() {
+    let filter = word_filter(&["bad"]);
+    assert_eq!(filter.mask("badge of a badminton player"), None);
+    assert_eq!(filter.mask("bad, (bad) bad!"), Some("***, (***) ***!".into()));
+    assert_eq!(filter.mask("not_bad"), Some("not_***".into()));
+  }
+
+  #[test]
+  fn mask_handles_multibyte_content() {
+    let filter = word_filter(&["café", "bad"]);
+    assert_eq!(filter.mask("Un CAFÉ s'il vous plaît"), Some("Un **** s'il vous plaît".into()));
+    assert_eq!(filter.mask("bad🙂 día"), Some("***🙂 día".into()));
+    assert_eq!(filter.mask("cafés"), None);
+    assert_eq!(filter.mask("über straße 日本語"), None);
+  }
+
+  #[test]
+  fn mask_keeps_content_without_flagged_word() {
+    let filter = word_filter(&["bad"]);
+    assert_eq!(filter.mask(""), None);
+    assert_eq!(filter.mask("all good here"), None);
+  }
+
+  #[test]
+  fn moderate_applies_moderation_of_group() {
+    let filter = word_filter(&["bad"]);
+    assert_eq!(filter.moderate("so bad", Moderation::Off), ModerationOutcome::Allowed);
+    assert_eq!(
+      filter.moderate("so bad", Moderation::Mask),
+      ModerationOutcome::Masked("so ***".into())
+    );
+    assert_eq!(filter.moderate("so BAD", Moderation::Reject), ModerationOutcome::Rejected);
+    assert_eq!(filter.moderate("so good", Moderation::Reject), ModerationOutcome::Allowed);
+  }
+}
@@ -0,0 +1,65 @@
+use chrono::Utc;
+use diesel::{ExpressionMethods, JoinOnDsl, QueryDsl, RunQueryDsl, SelectableHelper};
+
+use crate::{
+  database::{
+    models::PinnedMessage,
+    schema::{messages, pinned_messages},
+  },
+  errors::DBError,
+  PoolPGConnectionType,
+};
+
+/// Pin a message of a group, return `None` if the message is already pinned
+pub fn pin_message(
+  conn: &mut PoolPGConnectionType,
+  message_id: i32,
+  group_id: i32,
+  pinned_by: i32,
+) -> Result<Option<PinnedMessage>, DBError> {
+  let pinned_message = PinnedMessage {
+    message_id,
+    group_id,
+    pinned_by,
+    pinned_at: Utc::now().naive_utc(),
+  };
+  let inserted = diesel::insert_into(pinned_messages::table)
+    .values(&pinned_message)
+    .on_conflict_do_nothing()
+    .execute(conn)
+    .map_err(|err| {
+      tracing::error!("Failed to pin message {}: {}", message_id, err.to_string());
+      DBError::QueryError("Failed to pin message".into())
+    })?;
+  Ok((inserted > 0).then_some(pinned_message))
+}
+
+/// Unpin a message, return false if the message was not pinned
+pub fn unpin_message(conn: &mut PoolPGConnectionType, message_id: i32) -> Result<bool, DBError> {
+  diesel::delete(pinned_messages::table.find(message_id))
+    .execute(conn)
+    .map(|deleted| deleted > 0)
+    .map_err(|err| {
+      tracing::error!("Failed to unpin message {}: {}", message_id, err.to_string());
+      DBError::QueryError("Failed to unpin message".into())
+    })
+}
+
+/// Get pinned messages of a group with the message content, latest pinned first.
+/// Deleted messages are excluded
+pub fn get_pinned_messages_of_group(
+  conn: &mut PoolPGConnectionType,
+  group_id: i32,
+) -> Result<Vec<(PinnedMessage, Option<String>)>, DBError> {
+  pinned_messages::table
+    .inner_join(messages::table.on(messages::id.eq(pinned_messages::message_id)))
+    .filter(pinned_messages::group_id.eq(group_id))
+    .filter(messages::deleted_at.is_null())
+    .order(pinned_messages::pinned_at.desc())
+    .select((PinnedMessage::as_select(), messages::content))
+    .load::<(PinnedMessage, Option<String>)>(conn)
+    .map_err(|err| {
+      tracing::error!("Failed to get pinned messages of group {}: {}", group_id, err.to_string());
+      DBError::QueryError("Failed to get pinned messages of group".into())
+    })
+}
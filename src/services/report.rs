@@ -0,0 +1,85 @@
+use chrono::Utc;
+use diesel::{
+  result::DatabaseErrorKind, ExpressionMethods, JoinOnDsl, QueryDsl, RunQueryDsl,
+  SelectableHelper,
+};
+
+use crate::{
+  database::{
+    models::{MessageReport, NewMessageReport},
+    schema::{message_reports, messages, users},
+  },
+  errors::DBError,
+  payloads::common::PageRequest,
+  PoolPGConnectionType,
+};
+
+/// Report a message, return `None` if the user already reported the message
+pub fn create_report(
+  conn: &mut PoolPGConnectionType,
+  reporter_id: i32,
+  message_id: i32,
+  reason: &str,
+) -> Result<Option<MessageReport>, DBError> {
+  let new_report = NewMessageReport {
+    reporter_id,
+    message_id,
+    reason,
+    created_at: Utc::now().naive_utc(),
+  };
+  match diesel::insert_into(message_reports::table)
+    .values(&new_report)
+    .returning(MessageReport::as_returning())
+    .get_result::<MessageReport>(conn)
+  {
+    Ok(report) => Ok(Some(report)),
+    Err(diesel::result::Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => Ok(None),
+    Err(err) => {
+      tracing::error!(
+        "Failed to report message {} by user {}: {}",
+        message_id,
+        reporter_id,
+        err.to_string()
+      );
+      Err(DBError::QueryError("Failed to report message".into()))
+    }
+  }
+}
+
+/// Get a page of reports of messages of a group with the reporter name and the message content,
+/// newest first
+pub fn get_reports_of_group(
+  conn: &mut PoolPGConnectionType,
+  group_id: i32,
+  page_request: &PageRequest,
+) -> Result<Vec<(MessageReport, String, Option<String>)>, DBError> {
+  let (offset, limit) = page_request.get_offset_and_limit();
+  message_reports::table
+    .inner_join(messages::table.on(messages::id.eq(message_reports::message_id)))
+    .inner_join(users::table.on(users::id.eq(message_reports::reporter_id)))
+    .filter(messages::group_id.eq(group_id))
+    .order(message_reports::id.desc())
+    .offset(offset as i64)
+    .limit(limit)
+    .select((MessageReport::as_select(), users::username, messages::content))
+    .load::<(MessageReport, String, Option<String>)>(conn)
+    .map_err(|err| {
+      tracing::error!("Failed to get reports of group {}: {}", group_id, err.to_string());
+      DBError::QueryError("Failed to get reports of group".into())
+    })
+}
+
+pub fn get_count_reports_of_group(
+  conn: &mut PoolPGConnectionType,
+  group_id: i32,
+) -> Result<i64, DBError> {
+  message_reports::table
+    .inner_join(messages::table.on(messages::id.eq(message_reports::message_id)))
+    .filter(messages::group_id.eq(group_id))
+    .count()
+    .get_result::<i64>(conn)
+    .map_err(|err| {
+      tracing::error!("Failed to count reports of group {}: {}", group_id, err.to_string());
+      DBError::QueryError("Failed to count reports of group".into())
+    })
+}
@@ -0,0 +1,253 @@
+use std::{
+  fs,
+  path::PathBuf,
+  time::{Duration, SystemTime},
+};
+
+use diesel::{
+  r2d2::{ConnectionManager, Pool},
+  PgConnection,
+};
+
+use crate::{
+  errors::DBError, services, utils::minors::get_server_url, ATTACHMENT_SIZE_BACKFILL_BATCH, UPLOADS_DIRECTORY, UPLOADS_EVICTION_INTERVAL_SECS,
+  UPLOADS_EVICTION_MIN_AGE_SECS,
+};
+
+/// Path of uploaded files relative to the server url, see `serve_file`
+const UPLOADED_FILES_PATH: &str = "files/";
+
+/// Convert an attachment url to the url stored in the database
+///
+/// Urls of uploaded files are stored relative to the server url, e.g. `files/<name>`, so stored
+/// urls stay valid when the server moves or `PUBLIC_BASE_URL` changes. Other urls are stored as is
+pub fn to_stored_url(url: &str) -> String {
+  let url = url.trim();
+  let server_url = get_server_url();
+  let path = url
+    .strip_prefix(server_url.as_str())
+    .and_then(|path| path.strip_prefix('/'))
+    .unwrap_or(url.trim_start_matches('/'));
+  if path.starts_with(UPLOADED_FILES_PATH) {
+    return path.to_string();
+  }
+  url.to_string()
+}
+
+/// Convert a stored attachment url to an absolute url using the current server url
+pub fn to_public_url(stored_url: &str) -> String {
+  if stored_url.starts_with(UPLOADED_FILES_PATH) {
+    return format!("{}/{}", get_server_url(), stored_url);
+  }
+  stored_url.to_string()
+}
+
+/// Get the stored url of an uploaded file from its name
+pub fn uploaded_file_url(file_name: &str) -> String {
+  format!("{}{}", UPLOADED_FILES_PATH, file_name)
+}
+
+/// Get the name of the uploaded file of an attachment url, which is its last path segment
+pub fn uploaded_file_name(url: &str) -> Option<&str> {
+  url
+    .rsplit('/')
+    .next()
+    .filter(|file_name| !file_name.is_empty() && *file_name != "." && *file_name != "..")
+}
+
+/// Get the size in bytes of the uploaded file of an attachment url, `None` if there is no such file
+pub fn uploaded_file_size(url: &str) -> Option<u64> {
+  let file_name = uploaded_file_name(url)?;
+  fs::metadata(PathBuf::from(UPLOADS_DIRECTORY).join(file_name))
+    .ok()
+    .filter(|metadata| metadata.is_file())
+    .map(|metadata| metadata.len())
+}
+
+/// Fill the size of attachments created before sizes were recorded, in the background
+///
+/// Attachments whose file is missing keep an unknown size
+pub fn spawn_attachment_size_backfill_task(db_pool: Pool<ConnectionManager<PgConnection>>) {
+  tokio::task::spawn_blocking(move || match backfill_attachment_sizes(&db_pool) {
+    Ok(0) => {}
+    Ok(count) => tracing::info!("Filled the size of {count} attachments"),
+    Err(err) => tracing::error!("Failed to fill attachment sizes: {}", err.to_string()),
+  });
+}
+
+/// Return the number of attachments whose size was filled
+fn backfill_attachment_sizes(db_pool: &Pool<ConnectionManager<PgConnection>>) -> Result<usize, DBError> {
+  let conn = &mut db_pool.get().map_err(DBError::ConnectionError)?;
+  let mut count = 0;
+  let mut after_id = 0;
+  loop {
+    let batch = services::attachment::get_attachments_without_size(
+      conn,
+      after_id,
+      ATTACHMENT_SIZE_BACKFILL_BATCH,
+    )?;
+    let Some((last_id, _)) = batch.last() else {
+      return Ok(count);
+    };
+    after_id = *last_id;
+    for (attachment_id, url) in batch {
+      if let Some(size) = uploaded_file_size(&url) {
+        services::attachment::update_attachment_size(conn, attachment_id, size as i64)?;
+        count += 1;
+      }
+    }
+  }
+}
+
+struct UploadedFile {
+  path: PathBuf,
+  file_name: String,
+  size: u64,
+  modified_at: SystemTime,
+}
+
+/// Periodically keep the total size of the uploads directory under `max_size` bytes
+///
+/// When the directory is too large, the oldest files not referenced by any attachment are
+/// deleted until the total size is under the threshold
+pub fn spawn_uploads_eviction_task(db_pool: Pool<ConnectionManager<PgConnection>>, max_size: u64) {
+  tokio::spawn(async move {
+    let mut interval = tokio::time::interval(Duration::from_secs(UPLOADS_EVICTION_INTERVAL_SECS));
+    loop {
+      interval.tick().await;
+      let db_pool = db_pool.clone();
+      let eviction_rs =
+        tokio::task::spawn_blocking(move || evict_unreferenced_uploads(&db_pool, max_size)).await;
+      match eviction_rs {
+        Ok(Ok(0)) => {}
+        Ok(Ok(count)) => tracing::info!("Evicted {count} unreferenced uploaded files"),
+        Ok(Err(err)) => tracing::error!("Failed to evict uploaded files: {}", err.to_string()),
+        Err(err) => tracing::error!("Uploads eviction task panicked: {}", err.to_string()),
+      }
+    }
+  });
+}
+
+/// Delete the oldest unreferenced uploaded files while the uploads directory exceeds `max_size`
+///
+/// Return the number of deleted files
+fn evict_unreferenced_uploads(
+  db_pool: &Pool<ConnectionManager<PgConnection>>,
+  max_size: u64,
+) -> Result<usize, DBError> {
+  let mut uploaded_files = list_uploaded_files();
+  let mut total_size = uploaded_files.iter().map(|file| file.size).sum::<u64>();
+  if total_size <= max_size {
+    return Ok(0);
+  }
+  tracing::info!("Uploads directory size {total_size} bytes exceeds limit {max_size} bytes");
+
+  let conn = &mut db_pool.get().map_err(DBError::ConnectionError)?;
+  let referenced_file_names = services::attachment::get_referenced_file_names(conn)?;
+
+  let min_age = Duration::from_secs(UPLOADS_EVICTION_MIN_AGE_SECS);
+  let now = SystemTime::now();
+  uploaded_files.sort_by_key(|file| file.modified_at);
+
+  let mut count = 0;
+  for file in uploaded_files {
+    if total_size <= max_size {
+      break;
+    }
+    if referenced_file_names.contains(&file.file_name)
+      || now.duration_since(file.modified_at).unwrap_or_default() < min_age
+    {
+      continue;
+    }
+    match fs::remove_file(&file.path) {
+      Ok(_) => {
+        tracing::info!("Evicted uploaded file {} ({} bytes)", file.file_name, file.size);
+        total_size -= file.size;
+        count += 1;
+      }
+      Err(err) => {
+        tracing::error!("Failed to evict uploaded file {}: {}", file.file_name, err.to_string())
+      }
+    }
+  }
+  if total_size > max_size {
+    tracing::warn!(
+      "Uploads directory size {total_size} bytes still exceeds limit {max_size} bytes, remaining files are referenced or too recent"
+    );
+  }
+  Ok(count)
+}
+
+fn list_uploaded_files() -> Vec<UploadedFile> {
+  let entries = match fs::read_dir(UPLOADS_DIRECTORY) {
+    Ok(entries) => entries,
+    Err(err) => {
+      tracing::error!("Failed to read uploads directory: {}", err.to_string());
+      return Vec::new();
+    }
+  };
+  entries
+    .filter_map(|entry| entry.ok())
+    .filter_map(|entry| {
+      let metadata = entry.metadata().ok()?;
+      if !metadata.is_file() {
+        return None;
+      }
+      Some(UploadedFile {
+        path: entry.path(),
+        file_name: entry.file_name().to_string_lossy().into_owned(),
+        size: metadata.len(),
+        modified_at: metadata.modified().ok()?,
+      })
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn stored_url_of_uploaded_file_is_relative() {
+    let absolute_url = format!("{}/files/photo.png", get_server_url());
+    assert_eq!(to_stored_url(&absolute_url), "files/photo.png");
+    assert_eq!(to_stored_url(" /files/photo.png "), "files/photo.png");
+    assert_eq!(to_stored_url("files/photo.png"), "files/photo.png");
+  }
+
+  #[test]
+  fn stored_url_keeps_external_urls() {
+    assert_eq!(
+      to_stored_url("https://cdn.example.com/files/photo.png"),
+      "https://cdn.example.com/files/photo.png"
+    );
+    let other_path = format!("{}/static/photo.png", get_server_url());
+    assert_eq!(to_stored_url(&other_path), other_path);
+  }
+
+  #[test]
+  fn public_url_resolves_stored_url() {
+    let absolute_url = format!("{}/files/photo.png", get_server_url());
+    assert_eq!(to_public_url("files/photo.png"), absolute_url);
+    assert_eq!(to_public_url(&to_stored_url(&absolute_url)), absolute_url);
+    assert_eq!(
+      to_public_url("https://cdn.example.com/photo.png"),
+      "https://cdn.example.com/photo.png"
+    );
+  }
+
+  #[test]
+  fn uploaded_file_name_is_last_path_segment() {
+    assert_eq!(uploaded_file_name("files/photo.png"), Some("photo.png"));
+    assert_eq!(uploaded_file_name(&uploaded_file_url("photo.png")), Some("photo.png"));
+    assert_eq!(uploaded_file_name("photo.png"), Some("photo.png"));
+    assert_eq!(uploaded_file_name("files/../../etc/passwd"), Some("passwd"));
+  }
+
+  #[test]
+  fn uploaded_file_name_rejects_directories() {
+    for url in ["", "files/", "files/.", "files/..", "..", "."] {
+      assert_eq!(uploaded_file_name(url), None, "{}", url);
+    }
+  }
+}
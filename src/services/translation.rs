@@ -0,0 +1,95 @@
+use chrono::Utc;
+use diesel::{upsert::excluded, ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl};
+use thiserror::Error;
+
+use crate::{
+  database::{models::MessageTranslation, schema::message_translations},
+  errors::DBError,
+  PoolPGConnectionType,
+};
+
+#[derive(Error, Debug)]
+pub enum TranslationError {
+  #[error("The language {0} is not supported")]
+  UnsupportedLanguage(String),
+
+  #[error("Translation provider failed: {0}")]
+  ProviderError(String),
+}
+
+/// ### Pluggable translation of message contents
+///
+/// Translation is disabled unless `TRANSLATION_ENABLED` is true, the provider is then
+/// set to `AppState::translation_provider` in `main`. A deployment plugs in its own provider
+/// (e.g. calling an external translation api) by implementing this trait and setting it there.
+///
+/// `translate` is called from a blocking thread, so a provider may perform blocking io.
+/// Results are cached in `message_translations`, the provider is only called again after
+/// the message is edited
+pub trait TranslationProvider: Send + Sync {
+  /// Translate `content` into `lang`, a BCP 47 language tag such as `en` or `pt-BR`
+  fn translate(&self, content: &str, lang: &str) -> Result<String, TranslationError>;
+}
+
+/// Default provider returning contents unchanged
+pub struct NoopTranslationProvider;
+
+impl TranslationProvider for NoopTranslationProvider {
+  fn translate(&self, content: &str, _lang: &str) -> Result<String, TranslationError> {
+    Ok(content.to_string())
+  }
+}
+
+/// Get the cached translation of a message, `None` if the message was not translated into `lang`
+pub fn get_cached_translation(
+  conn: &mut PoolPGConnectionType,
+  message_id: i32,
+  lang: &str,
+) -> Result<Option<MessageTranslation>, DBError> {
+  message_translations::table
+    .find((message_id, lang))
+    .first::<MessageTranslation>(conn)
+    .optional()
+    .map_err(|err| {
+      tracing::error!(
+        "Failed to get translation of message {} into {}: {}",
+        message_id,
+        lang,
+        err.to_string()
+      );
+      DBError::QueryError("Failed to get translation of message".into())
+    })
+}
+
+/// Cache the translation of a message, replacing a previous translation into the same language
+pub fn save_translation(
+  conn: &mut PoolPGConnectionType,
+  message_id: i32,
+  lang: &str,
+  content: String,
+) -> Result<MessageTranslation, DBError> {
+  let translation = MessageTranslation {
+    message_id,
+    lang: lang.to_string(),
+    content,
+    created_at: Utc::now().naive_utc(),
+  };
+  diesel::insert_into(message_translations::table)
+    .values(&translation)
+    .on_conflict((message_translations::message_id, message_translations::lang))
+    .do_update()
+    .set((
+      message_translations::content.eq(excluded(message_translations::content)),
+      message_translations::created_at.eq(excluded(message_translations::created_at)),
+    ))
+    .get_result::<MessageTranslation>(conn)
+    .map_err(|err| {
+      tracing::error!(
+        "Failed to save translation of message {} into {}: {}",
+        message_id,
+        lang,
+        err.to_string()
+      );
+      DBError::QueryError("Failed to save translation of message".into())
+    })
+}
@@ -0,0 +1,116 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use diesel::{
+  r2d2::{ConnectionManager, Pool},
+  BoolExpressionMethods, ExpressionMethods, OptionalExtension, PgConnection, QueryDsl, RunQueryDsl,
+  SelectableHelper,
+};
+use uuid::Uuid;
+
+use crate::{
+  database::{models::Upload, schema::uploads},
+  errors::DBError,
+  PoolPGConnectionType, COMPLETED_UPLOAD_TTL_SECS, INCOMPLETE_UPLOAD_TTL_SECS,
+  UPLOADS_CLEANUP_INTERVAL_SECS,
+};
+
+pub fn get_upload(conn: &mut PoolPGConnectionType, upload_id: Uuid) -> Result<Option<Upload>, DBError> {
+  uploads::table
+    .find(upload_id)
+    .select(Upload::as_select())
+    .first::<Upload>(conn)
+    .optional()
+    .map_err(|err| {
+      tracing::error!("Failed to get upload {}: {}", upload_id, err.to_string());
+      DBError::QueryError("Failed to get upload".into())
+    })
+}
+
+/// Record the start of an upload, an upload with the same id is left unchanged
+pub fn start_upload(
+  conn: &mut PoolPGConnectionType,
+  upload_id: Uuid,
+  user_id: i32,
+) -> Result<(), DBError> {
+  diesel::insert_into(uploads::table)
+    .values((
+      uploads::id.eq(upload_id),
+      uploads::user_id.eq(user_id),
+      uploads::created_at.eq(Utc::now().naive_utc()),
+    ))
+    .on_conflict_do_nothing()
+    .execute(conn)
+    .map(|_| ())
+    .map_err(|err| {
+      tracing::error!("Failed to start upload {}: {}", upload_id, err.to_string());
+      DBError::QueryError("Failed to start upload".into())
+    })
+}
+
+/// Record the stored file of an upload, replacing the file of a previous attempt
+pub fn complete_upload(
+  conn: &mut PoolPGConnectionType,
+  upload_id: Uuid,
+  file_name: &str,
+  content_type: &str,
+) -> Result<(), DBError> {
+  diesel::update(uploads::table.find(upload_id))
+    .set((
+      uploads::file_name.eq(file_name),
+      uploads::content_type.eq(content_type),
+      uploads::completed_at.eq(Utc::now().naive_utc()),
+    ))
+    .execute(conn)
+    .map(|_| ())
+    .map_err(|err| {
+      tracing::error!("Failed to complete upload {}: {}", upload_id, err.to_string());
+      DBError::QueryError("Failed to complete upload".into())
+    })
+}
+
+/// Periodically delete incomplete uploads older than `INCOMPLETE_UPLOAD_TTL_SECS` and
+/// completed uploads older than `COMPLETED_UPLOAD_TTL_SECS`
+///
+/// Stored files are not deleted, files not attached to any message are evicted by
+/// `spawn_uploads_eviction_task`
+pub fn spawn_uploads_cleanup_task(db_pool: Pool<ConnectionManager<PgConnection>>) {
+  tokio::spawn(async move {
+    let mut interval = tokio::time::interval(Duration::from_secs(UPLOADS_CLEANUP_INTERVAL_SECS));
+    loop {
+      interval.tick().await;
+      let db_pool = db_pool.clone();
+      let cleanup_rs = tokio::task::spawn_blocking(move || {
+        let conn = &mut db_pool.get().map_err(DBError::ConnectionError)?;
+        delete_expired_uploads(conn)
+      })
+      .await;
+      match cleanup_rs {
+        Ok(Ok(0)) => {}
+        Ok(Ok(count)) => tracing::info!("Deleted {count} expired upload ids"),
+        Ok(Err(err)) => tracing::error!("Failed to delete expired upload ids: {}", err.to_string()),
+        Err(err) => tracing::error!("Uploads cleanup task panicked: {}", err.to_string()),
+      }
+    }
+  });
+}
+
+/// Return the number of deleted uploads
+pub fn delete_expired_uploads(conn: &mut PoolPGConnectionType) -> Result<usize, DBError> {
+  let now = Utc::now().naive_utc();
+  let incomplete_before = now - chrono::Duration::seconds(INCOMPLETE_UPLOAD_TTL_SECS);
+  let completed_before = now - chrono::Duration::seconds(COMPLETED_UPLOAD_TTL_SECS);
+  diesel::delete(
+    uploads::table.filter(
+      uploads::completed_at
+        .is_null()
+        .and(uploads::created_at.lt(incomplete_before))
+        .or(uploads::completed_at.lt(completed_before)),
+    ),
+  )
+  .execute(conn)
+  .map_err(|err| {
+    tracing::error!("Failed to delete expired uploads: {}", err.to_string());
+    DBError::QueryError("Failed to delete expired uploads".into())
+  })
+}
@@ -1,13 +1,21 @@
+use std::time::Duration;
+
 use chrono::Utc;
-use diesel::{ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl, SelectableHelper};
+use diesel::{
+  r2d2::{ConnectionManager, Pool},
+  BoolExpressionMethods, Connection, ExpressionMethods, JoinOnDsl, OptionalExtension, PgConnection,
+  QueryDsl, RunQueryDsl, SelectableHelper,
+};
 
 use crate::{
   database::{
     models::{self, User},
     schema::{self},
   },
-  utils::crypto::generate_secret_code,
-  PoolPGConnectionType,
+  errors::DBError,
+  payloads::user::{ExportGroup, UserStatsResponse},
+  utils::crypto::{generate_random_salt, generate_secret_code},
+  PoolPGConnectionType, GUEST_CODE_DURATION_SECS, GUEST_PURGE_INTERVAL_SECS,
 };
 
 pub fn create_user(
@@ -27,6 +35,150 @@ pub fn create_user(
   Ok(user_result)
 }
 
+/// Create a guest with a random username, its user code expires after `GUEST_CODE_DURATION_SECS`
+pub fn create_guest_user(conn: &mut PoolPGConnectionType) -> Result<User, diesel::result::Error> {
+  let username = format!("guest-{}", generate_random_salt(10).to_lowercase());
+  let new_guest = models::NewGuestUser {
+    username: &username,
+    created_at: Utc::now().naive_local(),
+    user_code: &generate_secret_code(&username),
+    is_guest: true,
+    user_code_expires_at: Utc::now().naive_utc()
+      + chrono::Duration::seconds(GUEST_CODE_DURATION_SECS),
+  };
+
+  diesel::insert_into(schema::users::table)
+    .values(&new_guest)
+    .returning(models::User::as_returning())
+    .get_result::<models::User>(conn)
+}
+
+pub fn username_exists(
+  conn: &mut PoolPGConnectionType,
+  username: &str,
+) -> Result<bool, diesel::result::Error> {
+  let count = schema::users::table
+    .filter(schema::users::username.eq(username))
+    .count()
+    .get_result::<i64>(conn)?;
+  Ok(count > 0)
+}
+
+/// Turn a guest into a permanent user with a new username, clearing the guest flag and expiration
+pub fn upgrade_guest_user(
+  conn: &mut PoolPGConnectionType,
+  user_id: i32,
+  username: &str,
+) -> Result<User, diesel::result::Error> {
+  use schema::users;
+  diesel::update(users::table.find(user_id))
+    .set((
+      users::username.eq(username),
+      users::is_guest.eq(false),
+      users::user_code_expires_at.eq(None::<chrono::NaiveDateTime>),
+    ))
+    .returning(User::as_returning())
+    .get_result(conn)
+}
+
+/// Change the username of an user, fail with an unique violation if the username is taken
+pub fn update_username(
+  conn: &mut PoolPGConnectionType,
+  user_id: i32,
+  username: &str,
+) -> Result<User, diesel::result::Error> {
+  use schema::users;
+  diesel::update(users::table.find(user_id))
+    .set(users::username.eq(username))
+    .returning(User::as_returning())
+    .get_result(conn)
+}
+
+/// Periodically delete expired guests with their authored messages and owned groups
+pub fn spawn_guest_purge_task(db_pool: Pool<ConnectionManager<PgConnection>>) {
+  tokio::spawn(async move {
+    let mut interval = tokio::time::interval(Duration::from_secs(GUEST_PURGE_INTERVAL_SECS));
+    loop {
+      interval.tick().await;
+      let db_pool = db_pool.clone();
+      let purge_rs = tokio::task::spawn_blocking(move || {
+        let conn = &mut db_pool.get().map_err(DBError::ConnectionError)?;
+        purge_expired_guests(conn)
+      })
+      .await;
+      match purge_rs {
+        Ok(Ok(0)) => {}
+        Ok(Ok(count)) => tracing::info!("Purged {count} expired guests"),
+        Ok(Err(err)) => tracing::error!("Failed to purge expired guests: {}", err.to_string()),
+        Err(err) => tracing::error!("Guest purge task panicked: {}", err.to_string()),
+      }
+    }
+  });
+}
+
+/// Delete expired guests, their authored messages, their owned groups with all of their data
+/// and their memberships in a single transaction
+///
+/// Return the number of deleted guests
+pub fn purge_expired_guests(conn: &mut PoolPGConnectionType) -> Result<usize, DBError> {
+  use schema::{groups, last_seen_messages, messages, participants, users, waiting_list};
+  conn
+    .transaction::<_, diesel::result::Error, _>(|conn| {
+      let guest_ids = users::table
+        .filter(users::is_guest.eq(true))
+        .filter(users::user_code_expires_at.le(Utc::now().naive_utc()))
+        .select(users::id)
+        .get_results::<i32>(conn)?;
+      if guest_ids.is_empty() {
+        return Ok(0);
+      }
+      let group_ids = groups::table
+        .filter(groups::user_id.eq_any(&guest_ids))
+        .select(groups::id)
+        .get_results::<i32>(conn)?;
+
+      diesel::delete(
+        last_seen_messages::table.filter(
+          last_seen_messages::user_id
+            .eq_any(&guest_ids)
+            .or(last_seen_messages::group_id.eq_any(&group_ids)),
+        ),
+      )
+      .execute(conn)?;
+      // Attachments are deleted by cascading
+      diesel::delete(
+        messages::table.filter(
+          messages::user_id
+            .eq_any(&guest_ids)
+            .or(messages::group_id.eq_any(&group_ids)),
+        ),
+      )
+      .execute(conn)?;
+      diesel::delete(
+        participants::table.filter(
+          participants::user_id
+            .eq_any(&guest_ids)
+            .or(participants::group_id.eq_any(&group_ids)),
+        ),
+      )
+      .execute(conn)?;
+      diesel::delete(
+        waiting_list::table.filter(
+          waiting_list::user_id
+            .eq_any(&guest_ids)
+            .or(waiting_list::group_id.eq_any(&group_ids)),
+        ),
+      )
+      .execute(conn)?;
+      diesel::delete(groups::table.filter(groups::id.eq_any(&group_ids))).execute(conn)?;
+      diesel::delete(users::table.filter(users::id.eq_any(&guest_ids))).execute(conn)
+    })
+    .map_err(|err| {
+      tracing::error!("Failed to purge expired guests: {}", err.to_string());
+      DBError::QueryError("Failed to purge expired guests".into())
+    })
+}
+
 #[allow(dead_code)]
 pub fn user_exists(
   conn: &mut PoolPGConnectionType,
@@ -43,13 +195,13 @@ pub fn user_exists(
   }
 }
 
-pub fn get_user_by_code(
+pub fn get_username(
   conn: &mut PoolPGConnectionType,
-  secret_code: &str,
-) -> Result<Option<User>, diesel::result::Error> {
+  user_id: i32,
+) -> Result<Option<String>, diesel::result::Error> {
   schema::users::table
-    .filter(schema::users::user_code.eq(secret_code))
-    .select(User::as_select())
+    .find(user_id)
+    .select(schema::users::username)
     .first(conn)
     .optional()
 }
@@ -65,3 +217,125 @@ pub fn get_user_ids_from_group(
     .get_results::<i32>(conn)?;
   Ok(user_ids)
 }
+
+/// Get ids of other users sharing at least one group with an user
+pub fn get_user_ids_sharing_group(
+  conn: &mut PoolPGConnectionType,
+  user_id: i32,
+) -> Result<Vec<i32>, diesel::result::Error> {
+  use schema::participants;
+  let group_ids = participants::table
+    .filter(participants::user_id.eq(user_id))
+    .select(participants::group_id)
+    .get_results::<i32>(conn)?;
+  participants::table
+    .filter(participants::group_id.eq_any(group_ids))
+    .filter(participants::user_id.ne(user_id))
+    .select(participants::user_id)
+    .distinct()
+    .get_results::<i32>(conn)
+}
+
+/// Get id and username of the users among `user_ids` visible to an user, ordered by id.
+/// An user sees itself and the users sharing at least one group with it
+pub fn get_visible_users(
+  conn: &mut PoolPGConnectionType,
+  user_id: i32,
+  user_ids: &[i32],
+) -> Result<Vec<(i32, String)>, diesel::result::Error> {
+  use schema::{participants, users};
+  let group_ids = participants::table
+    .filter(participants::user_id.eq(user_id))
+    .select(participants::group_id)
+    .get_results::<i32>(conn)?;
+  let sharing_user_ids = participants::table
+    .filter(participants::group_id.eq_any(group_ids))
+    .select(participants::user_id);
+  users::table
+    .filter(users::id.eq_any(user_ids))
+    .filter(users::id.eq(user_id).or(users::id.eq_any(sharing_user_ids)))
+    .order(users::id.asc())
+    .select((users::id, users::username))
+    .get_results::<(i32, String)>(conn)
+}
+
+/// Get id and username of the first `limit` members of a group, ordered by joining order
+pub fn get_members_of_group(
+  conn: &mut PoolPGConnectionType,
+  group_id: i32,
+  limit: i64,
+) -> Result<Vec<(i32, String, Option<String>)>, diesel::result::Error> {
+  use schema::{participants, users};
+  participants::table
+    .inner_join(users::table.on(users::id.eq(participants::user_id)))
+    .filter(participants::group_id.eq(group_id))
+    .order(participants::id.asc())
+    .limit(limit)
+    .select((users::id, users::username, participants::alias))
+    .get_results::<(i32, String, Option<String>)>(conn)
+}
+
+/// Count joined groups, owned groups, authored messages and uploaded attachments of an user
+/// Get the groups joined by an user with the alias and the pin of the user in each group
+pub fn get_memberships_of_user(
+  conn: &mut PoolPGConnectionType,
+  user_id: i32,
+) -> Result<Vec<ExportGroup>, diesel::result::Error> {
+  use schema::{groups, participants};
+  let memberships = participants::table
+    .inner_join(groups::table.on(groups::id.eq(participants::group_id)))
+    .filter(participants::user_id.eq(user_id))
+    .order(groups::id.asc())
+    .select((
+      groups::id,
+      groups::name,
+      groups::user_id,
+      participants::alias,
+      participants::pinned,
+    ))
+    .load::<(i32, String, i32, Option<String>, bool)>(conn)?;
+  Ok(
+    memberships
+      .into_iter()
+      .map(|(group_id, group_name, owner_id, alias, pinned)| ExportGroup {
+        group_id,
+        group_name,
+        is_owner: owner_id == user_id,
+        alias,
+        pinned,
+      })
+      .collect(),
+  )
+}
+
+pub fn get_user_stats(
+  conn: &mut PoolPGConnectionType,
+  user_id: i32,
+) -> Result<UserStatsResponse, diesel::result::Error> {
+  use schema::{attachments, groups, messages, participants};
+  let joined_groups = participants::table
+    .filter(participants::user_id.eq(user_id))
+    .count()
+    .get_result::<i64>(conn)?;
+  let owned_groups = groups::table
+    .filter(groups::user_id.eq(user_id))
+    .count()
+    .get_result::<i64>(conn)?;
+  let messages = messages::table
+    .filter(messages::user_id.eq(user_id))
+    .filter(messages::deleted_at.is_null())
+    .count()
+    .get_result::<i64>(conn)?;
+  let attachments = attachments::table
+    .inner_join(messages::table.on(messages::id.eq(attachments::message_id)))
+    .filter(messages::user_id.eq(user_id))
+    .filter(messages::deleted_at.is_null())
+    .count()
+    .get_result::<i64>(conn)?;
+  Ok(UserStatsResponse {
+    joined_groups,
+    owned_groups,
+    messages,
+    attachments,
+  })
+}
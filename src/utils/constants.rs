@@ -1,6 +1,97 @@
+pub const DEFAULT_LOG_LEVEL: &str = "info";
 pub const DEFAULT_SERVER_ADDRESS: &str = "0.0.0.0";
 pub const DEFAULT_SERVER_PORT: u16 = 8080;
+pub const DEFAULT_SERVER_SCHEME: &str = "http";
 pub const DEFAULT_POOL_SIZE: u32 = 5;
 pub const DEFAULT_PAGE_SIZE: u32 = 10;
 pub const DEFAULT_PAGE_START: u16 = 1;
 pub const UPLOADS_DIRECTORY: &str = "assets";
+pub const DEFAULT_MAXIMUM_GROUPS_PER_USER: i64 = 50;
+pub const MAX_USERNAME_LENGTH: usize = 255;
+pub const MAX_GROUP_NAME_LENGTH: usize = 255;
+pub const MAX_GROUP_CODE_LENGTH: usize = 255;
+pub const MAX_MESSAGE_CONTENT_LENGTH: usize = 1000;
+/// Maximum number of characters of the reason of a message report
+pub const MAX_REPORT_REASON_LENGTH: usize = 1000;
+/// Maximum number of characters of the alias of a member in a group
+pub const MAX_ALIAS_LENGTH: usize = 50;
+/// Maximum number of characters of the content of a quoted message
+pub const MAX_QUOTED_CONTENT_LENGTH: usize = 100;
+/// Maximum number of groups requested at once by `POST /groups/summaries`
+pub const MAX_GROUP_SUMMARIES_IDS: usize = 100;
+/// Maximum number of users requested at once by `POST /users/batch`
+pub const MAX_USERS_BATCH_IDS: usize = 100;
+/// Maximum number of members removed by a single request
+pub const MAX_REMOVE_MEMBERS_IDS: usize = 100;
+/// Maximum duration in minutes of a ban from a group (30 days)
+pub const MAX_GROUP_BAN_MINUTES: u32 = 30 * 24 * 60;
+/// Consecutive failed websocket authentications of an ip address before it is blocked
+pub const DEFAULT_MAXIMUM_WS_AUTH_FAILURES: u32 = 5;
+/// Time in seconds an ip address is blocked after too many failed websocket authentications
+pub const WS_AUTH_COOLDOWN_SECS: u64 = 5 * 60;
+/// Default maximum number of open websocket connections of an ip address
+pub const DEFAULT_MAXIMUM_WS_CONNECTIONS_PER_IP: usize = 20;
+/// Seconds a client should wait before retrying a websocket connection rejected by the limit per ip
+pub const WS_IP_LIMIT_RETRY_SECS: u64 = 5;
+pub const MAX_JOIN_MESSAGE_LENGTH: usize = 1000;
+/// Maximum lifetime of a group in minutes (30 days)
+pub const MAX_GROUP_DURATION: u32 = 30 * 24 * 60;
+/// Maximum number of members returned by the websocket `GetMembers` message
+pub const MAX_SOCKET_MEMBERS_LIST: i64 = 200;
+/// Window in milliseconds in which seen events of a group are merged into a single event
+pub const SEEN_EVENTS_COALESCE_MILLIS: u64 = 300;
+/// Default maximum total size in bytes of the uploads directory (5 GiB)
+pub const DEFAULT_UPLOADS_MAX_SIZE: u64 = 5 * 1024 * 1024 * 1024;
+/// Default maximum total size in bytes of attachments of a group (1 GiB)
+pub const DEFAULT_GROUP_STORAGE_QUOTA: u64 = 1024 * 1024 * 1024;
+/// Default number of retries of a read query failing with a transient database error
+pub const DEFAULT_DB_RETRY_ATTEMPTS: u32 = 2;
+/// Default delay in milliseconds before the first retry of a failed read query
+pub const DEFAULT_DB_RETRY_BACKOFF_MILLIS: u64 = 100;
+/// Number of attachments loaded at once when filling sizes of existing attachments
+pub const ATTACHMENT_SIZE_BACKFILL_BATCH: i64 = 500;
+/// Interval in seconds between two checks of the uploads directory size
+pub const UPLOADS_EVICTION_INTERVAL_SECS: u64 = 10 * 60;
+/// Files younger than this age in seconds are never evicted, they may not be attached to a message yet
+pub const UPLOADS_EVICTION_MIN_AGE_SECS: u64 = 60 * 60;
+/// Maximum number of attachments of each message returned by message list apis
+pub const MAX_ATTACHMENTS_PREVIEW: i64 = 5;
+/// Maximum number of messages returned by the websocket `FetchHistory` message
+pub const MAX_SOCKET_HISTORY_LIMIT: i64 = 50;
+/// Maximum number of ids covered by the range of a websocket `ReplayRange` message
+pub const MAX_REPLAY_RANGE: i32 = 1000;
+/// Maximum number of message ids returned by `GET /groups/:group_id/message-ids`
+pub const MAX_MESSAGE_IDS_LIMIT: i64 = 1000;
+/// Default number of messages on each side of the target of `GET /groups/:group_id/messages/:message_id/context`
+pub const DEFAULT_MESSAGE_CONTEXT_RADIUS: i64 = 10;
+/// Maximum number of messages on each side of the target of `GET /groups/:group_id/messages/:message_id/context`
+pub const MAX_MESSAGE_CONTEXT_RADIUS: i64 = 50;
+/// Maximum number of message ids returned by the websocket `UnreadMessages` message
+pub const MAX_UNREAD_MESSAGE_IDS: i64 = 500;
+/// Maximum number of message ids of a websocket `QueryStatus` message
+pub const MAX_QUERY_STATUS_IDS: usize = 100;
+/// Maximum number of group events held back for a websocket connection until it sends `Ready`
+pub const MAX_EVENTS_BEFORE_READY: usize = 500;
+/// A websocket connection not sending `Ready` in this time in seconds receives group events anyway
+pub const SOCKET_READY_TIMEOUT_SECS: u64 = 30;
+/// Maximum length of a language tag of a translation
+pub const MAX_LANGUAGE_TAG_LENGTH: usize = 35;
+/// Maximum number of messages of a websocket `SendBatch` message
+pub const MAX_SEND_BATCH_SIZE: usize = 50;
+/// Time window in minutes in which the sender can recall a message
+pub const MESSAGE_RECALL_WINDOW_MINUTES: i64 = 15;
+/// Maximum slow mode interval of a group in seconds
+pub const MAX_SLOW_MODE_SECONDS: i32 = 60 * 60;
+/// Lifetime in seconds of the user code of a guest
+pub const GUEST_CODE_DURATION_SECS: i64 = 60 * 60;
+/// Interval in seconds between two purges of expired guests
+pub const GUEST_PURGE_INTERVAL_SECS: u64 = 5 * 60;
+/// Interval in seconds between two cleanups of upload ids
+pub const UPLOADS_CLEANUP_INTERVAL_SECS: u64 = 10 * 60;
+/// Incomplete uploads with a client supplied id are forgotten after this time in seconds
+pub const INCOMPLETE_UPLOAD_TTL_SECS: i64 = 60 * 60;
+/// A completed upload is returned again for a retry with the same id during this time in seconds
+pub const COMPLETED_UPLOAD_TTL_SECS: i64 = 24 * 60 * 60;
+pub const DEFAULT_MAXIMUM_CONCURRENT_UPLOADS: usize = 16;
+/// Seconds a client should wait before retrying an upload rejected because of saturation
+pub const UPLOAD_RETRY_AFTER_SECS: u64 = 5;
@@ -1,5 +1,6 @@
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
 
+use hmac::{Hmac, Mac};
 use sha2::{Digest, Sha256};
 
 pub fn generate_random_salt(length: usize) -> String {
@@ -10,9 +11,38 @@ pub fn generate_random_salt(length: usize) -> String {
     .collect()
 }
 
+/// Source of the salt and timestamp mixed into generated secret codes
+///
+/// The default source is `SystemCodeSource`, a fixed source can be injected with
+/// `generate_secret_code_with` to produce deterministic codes
+pub trait SecretCodeSource {
+  fn salt(&self) -> String;
+  fn timestamp_millis(&self) -> i64;
+}
+
+/// Random salt and current system time
+pub struct SystemCodeSource;
+
+impl SecretCodeSource for SystemCodeSource {
+  fn salt(&self) -> String {
+    generate_random_salt(16)
+  }
+
+  fn timestamp_millis(&self) -> i64 {
+    chrono::Utc::now().timestamp_millis()
+  }
+}
+
 pub fn generate_secret_code(plain: &str) -> String {
-  let salt = generate_random_salt(16);
-  let timestamp = chrono::Utc::now().timestamp_millis();
+  generate_secret_code_with(plain, &SystemCodeSource)
+}
+
+pub fn generate_secret_code_with(plain: &str, source: &impl SecretCodeSource) -> String {
+  hash_secret_code(plain, source.timestamp_millis(), &source.salt())
+}
+
+/// Hash the plain text together with the timestamp and salt into an uppercase hex code
+pub fn hash_secret_code(plain: &str, timestamp: i64, salt: &str) -> String {
   let data = format!("{}{}{}", plain, timestamp, salt);
 
   let mut hasher = Sha256::new();
@@ -22,3 +52,144 @@ pub fn generate_secret_code(plain: &str) -> String {
   let result = format!("{:X}", hasher.finalize());
   result
 }
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Length of the hex encoded group id and message id of a cursor
+const CURSOR_PAYLOAD_LENGTH: usize = 16;
+/// Length of the hex encoded HMAC-SHA256 signature of a cursor
+const CURSOR_SIGNATURE_LENGTH: usize = 64;
+
+fn cursor_mac(secret: &str, payload: &str) -> HmacSha256 {
+  let mut mac =
+    HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+  mac.update(payload.as_bytes());
+  mac
+}
+
+/// Encode a pagination cursor pointing at `message_id` of `group_id`
+///
+/// The cursor is signed with `secret`, so clients can not forge a cursor of another group
+pub fn encode_cursor(secret: &str, group_id: i32, message_id: i32) -> String {
+  let payload = format!("{:08X}{:08X}", group_id as u32, message_id as u32);
+  let signature = cursor_mac(secret, &payload).finalize().into_bytes();
+  format!("{}{:X}", payload, signature)
+}
+
+/// Decode a cursor built by `encode_cursor` for `group_id`, return the message id
+///
+/// `None` is returned if the cursor is malformed, its signature does not match or it was
+/// issued for another group
+pub fn decode_cursor(secret: &str, group_id: i32, cursor: &str) -> Option<i32> {
+  if cursor.len() != CURSOR_PAYLOAD_LENGTH + CURSOR_SIGNATURE_LENGTH
+    || !cursor.bytes().all(|byte| byte.is_ascii_hexdigit())
+  {
+    return None;
+  }
+  let (payload, signature) = cursor.split_at(CURSOR_PAYLOAD_LENGTH);
+  let signature = (0..signature.len())
+    .step_by(2)
+    .map(|index| u8::from_str_radix(&signature[index..index + 2], 16).ok())
+    .collect::<Option<Vec<u8>>>()?;
+  // the signature is compared in constant time
+  cursor_mac(secret, payload).verify_slice(&signature).ok()?;
+  let cursor_group_id = u32::from_str_radix(&payload[..8], 16).ok()? as i32;
+  let message_id = u32::from_str_radix(&payload[8..], 16).ok()? as i32;
+  (cursor_group_id == group_id).then_some(message_id)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  struct FixedCodeSource;
+
+  impl SecretCodeSource for FixedCodeSource {
+    fn salt(&self) -> String {
+      "0123456789abcdef".into()
+    }
+
+    fn timestamp_millis(&self) -> i64 {
+      1_700_000_000_000
+    }
+  }
+
+  #[test]
+  fn secret_code_is_deterministic_with_fixed_source() {
+    let code = generate_secret_code_with("group", &FixedCodeSource);
+    assert_eq!(
+      code,
+      "538BDC65B3B769AB9FC0B09131C3138B7B538547432BC4A635C70D4ED348E941"
+    );
+    assert_eq!(code, generate_secret_code_with("group", &FixedCodeSource));
+  }
+
+  #[test]
+  fn secret_code_hashes_plain_text_timestamp_and_salt() {
+    let code = hash_secret_code("group", 1_700_000_000_000, "0123456789abcdef");
+    assert_eq!(code, generate_secret_code_with("group", &FixedCodeSource));
+    assert_ne!(code, hash_secret_code("group", 1_700_000_000_001, "0123456789abcdef"));
+    assert_ne!(code, hash_secret_code("other", 1_700_000_000_000, "0123456789abcdef"));
+  }
+
+  #[test]
+  fn secret_code_is_uppercase_hex() {
+    let code = generate_secret_code("group");
+    assert_eq!(code.len(), 64);
+    assert!(code.chars().all(|c| c.is_ascii_digit() || c.is_ascii_uppercase()));
+  }
+
+  const CURSOR_SECRET: &str = "cursor-secret";
+
+  #[test]
+  fn cursor_round_trip() {
+    let cursor = encode_cursor(CURSOR_SECRET, 24, 42);
+    assert_eq!(cursor.len(), CURSOR_PAYLOAD_LENGTH + CURSOR_SIGNATURE_LENGTH);
+    assert_eq!(decode_cursor(CURSOR_SECRET, 24, &cursor), Some(42));
+  }
+
+  #[test]
+  fn cursor_round_trip_of_extreme_ids() {
+    for (group_id, message_id) in [(0, 0), (i32::MAX, i32::MAX), (-1, i32::MIN)] {
+      let cursor = encode_cursor(CURSOR_SECRET, group_id, message_id);
+      assert_eq!(decode_cursor(CURSOR_SECRET, group_id, &cursor), Some(message_id));
+    }
+  }
+
+  #[test]
+  fn cursor_with_tampered_signature_is_rejected() {
+    let cursor = encode_cursor(CURSOR_SECRET, 24, 42);
+    let last = if cursor.ends_with('0') { "1" } else { "0" };
+    let tampered = format!("{}{}", &cursor[..cursor.len() - 1], last);
+    assert_eq!(decode_cursor(CURSOR_SECRET, 24, &tampered), None);
+    assert_eq!(decode_cursor("other-secret", 24, &cursor), None);
+  }
+
+  #[test]
+  fn cursor_with_tampered_message_id_is_rejected() {
+    let cursor = encode_cursor(CURSOR_SECRET, 24, 42);
+    let tampered = format!("{:08X}{:08X}{}", 24, 41, &cursor[CURSOR_PAYLOAD_LENGTH..]);
+    assert_eq!(decode_cursor(CURSOR_SECRET, 24, &tampered), None);
+  }
+
+  #[test]
+  fn cursor_of_another_group_is_rejected() {
+    let cursor = encode_cursor(CURSOR_SECRET, 24, 42);
+    assert_eq!(decode_cursor(CURSOR_SECRET, 25, &cursor), None);
+    // re-signing the payload of another group requires the secret
+    let forged = format!("{:08X}{}", 25, &cursor[8..]);
+    assert_eq!(decode_cursor(CURSOR_SECRET, 25, &forged), None);
+  }
+
+  #[test]
+  fn malformed_cursor_is_rejected() {
+    let cursor = encode_cursor(CURSOR_SECRET, 24, 42);
+    let too_short = &cursor[..cursor.len() - 2];
+    let too_long = format!("{}00", cursor);
+    let non_ascii = format!("é{}", &cursor[2..]);
+    let non_hex = format!("+{}", &cursor[1..]);
+    for malformed in ["", too_short, too_long.as_str(), non_ascii.as_str(), non_hex.as_str()] {
+      assert_eq!(decode_cursor(CURSOR_SECRET, 24, malformed), None, "{}", malformed);
+    }
+  }
+}
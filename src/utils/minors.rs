@@ -3,7 +3,7 @@ use std::{env, path::PathBuf};
 use axum_extra::extract::CookieJar;
 use chrono::Utc;
 
-use crate::{DEFAULT_SERVER_ADDRESS, DEFAULT_SERVER_PORT};
+use crate::{DEFAULT_SERVER_ADDRESS, DEFAULT_SERVER_PORT, DEFAULT_SERVER_SCHEME};
 
 #[allow(dead_code)]
 pub fn get_value_from_cookie(cookie_jar: CookieJar, key: &str) -> Option<String> {
@@ -43,14 +43,25 @@ pub fn generate_file_name_with_timestamp(file_name: &str) -> String {
   rs
 }
 
+/// Base url of generated file urls
+///
+/// `PUBLIC_BASE_URL` is used verbatim if it is set, e.g. `https://chat.example.com` behind
+/// a TLS-terminating reverse proxy, otherwise the url is built from `SERVER_SCHEME`,
+/// `SERVER_ADDRESS` and `SERVER_PORT`
 pub fn get_server_url() -> String {
+  if let Ok(public_base_url) = env::var("PUBLIC_BASE_URL") {
+    if !public_base_url.trim().is_empty() {
+      return public_base_url.trim().trim_end_matches('/').to_string();
+    }
+  }
+  let server_scheme = env::var("SERVER_SCHEME").unwrap_or(DEFAULT_SERVER_SCHEME.to_string());
   let server_addr = env::var("SERVER_ADDRESS").unwrap_or(DEFAULT_SERVER_ADDRESS.to_string());
   let server_port = if let Ok(value) = env::var("SERVER_PORT") {
     value.parse::<u16>().unwrap_or(DEFAULT_SERVER_PORT)
   } else {
     DEFAULT_SERVER_PORT
   };
-  format!("{proto}://{server_addr}:{server_port}", proto = "http")
+  format!("{server_scheme}://{server_addr}:{server_port}")
 }
 
 pub fn guess_mime_type_from_path(path: PathBuf) -> String {
@@ -61,6 +72,8 @@ pub fn guess_mime_type_from_path(path: PathBuf) -> String {
     Some("png") => "image/png",
     Some("jpg") | Some("jpeg") => "image/jpeg",
     Some("gif") => "image/gif",
+    Some("webp") => "image/webp",
+    Some("avif") => "image/avif",
     _ => "application/octet-stream",
   }
   .to_string()
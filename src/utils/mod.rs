@@ -2,3 +2,4 @@ pub mod constants;
 pub mod crypto;
 pub mod custom_serde;
 pub mod minors;
+pub mod validation;
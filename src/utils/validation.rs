@@ -0,0 +1,112 @@
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// Validation problem of a single request field
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct FieldError {
+  pub field: String,
+  pub message: String,
+}
+
+impl FieldError {
+  pub fn new(field: &str, message: &str) -> Self {
+    Self {
+      field: field.into(),
+      message: message.into(),
+    }
+  }
+}
+
+/// ### Validation of request payloads
+///
+/// Implementors collect every invalid field instead of stopping at the first one,
+/// so the client can fix all of them at once
+pub trait Validate {
+  fn validate(&self) -> Result<(), Vec<FieldError>>;
+}
+
+/// Check a text field is not blank and has at most `max_length` characters
+pub fn validate_required_text(
+  errors: &mut Vec<FieldError>,
+  field: &str,
+  value: &str,
+  max_length: usize,
+) {
+  if value.trim().is_empty() {
+    errors.push(FieldError::new(field, "must not be empty"));
+  } else {
+    validate_text_length(errors, field, value, max_length);
+  }
+}
+
+/// Check a text field has at most `max_length` characters
+pub fn validate_text_length(
+  errors: &mut Vec<FieldError>,
+  field: &str,
+  value: &str,
+  max_length: usize,
+) {
+  if value.chars().count() > max_length {
+    errors.push(FieldError::new(
+      field,
+      &format!("must be at most {} characters", max_length),
+    ));
+  }
+}
+
+/// Turn collected field errors into a validation result
+pub fn into_validation_result(errors: Vec<FieldError>) -> Result<(), Vec<FieldError>> {
+  if errors.is_empty() {
+    Ok(())
+  } else {
+    Err(errors)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn fields(errors: &[FieldError]) -> Vec<&str> {
+    errors.iter().map(|error| error.field.as_str()).collect()
+  }
+
+  #[test]
+  fn required_text_rejects_blank_value() {
+    let mut errors = Vec::new();
+    validate_required_text(&mut errors, "username", "  \t", 10);
+    assert_eq!(fields(&errors), ["username"]);
+    assert_eq!(errors[0].message, "must not be empty");
+  }
+
+  #[test]
+  fn required_text_checks_length() {
+    let mut errors = Vec::new();
+    validate_required_text(&mut errors, "username", "alice", 5);
+    assert!(errors.is_empty());
+    validate_required_text(&mut errors, "username", "alice!", 5);
+    assert_eq!(fields(&errors), ["username"]);
+    assert_eq!(errors[0].message, "must be at most 5 characters");
+  }
+
+  #[test]
+  fn text_length_counts_characters_not_bytes() {
+    let mut errors = Vec::new();
+    validate_text_length(&mut errors, "message", "héllo", 5);
+    validate_text_length(&mut errors, "message", "", 5);
+    assert!(errors.is_empty());
+    validate_text_length(&mut errors, "message", "héllo!", 5);
+    assert_eq!(fields(&errors), ["message"]);
+  }
+
+  #[test]
+  fn validation_result_collects_every_error() {
+    assert!(into_validation_result(Vec::new()).is_ok());
+    let errors = vec![
+      FieldError::new("username", "must not be empty"),
+      FieldError::new("group_name", "must not be empty"),
+    ];
+    let result = into_validation_result(errors).unwrap_err();
+    assert_eq!(fields(&result), ["username", "group_name"]);
+  }
+}